@@ -2,12 +2,14 @@
 
 use std::{
     fmt::{Debug, Display},
+    sync::Arc,
     time::Duration,
 };
 
 use beacon_api_types::custom_types::Slot;
 use moka::{future::Cache, ops::compute::Op};
 use reqwest::{Client, StatusCode};
+use rpc_failover::{Endpoints, FailoverConfig};
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use serde_json::{Value, value::RawValue};
 use tracing::{debug, info, instrument, trace};
@@ -31,16 +33,43 @@ pub type Result<T> = core::result::Result<T, Error>;
 #[derive(Debug, Clone)]
 pub struct BeaconApiClient {
     client: Client,
-    base_url: String,
+    base_urls: Arc<Endpoints<String>>,
     spec: Cache<(), Spec>,
     genesis: Cache<(), GenesisData>,
 }
 
 impl BeaconApiClient {
     pub fn new(base_url: impl AsRef<str>) -> Self {
+        Self::new_with_failover(base_url, &FailoverConfig::default())
+    }
+
+    /// Create a client for `base_url` and all fallback urls of `config`. Requests are sent to the
+    /// healthiest beacon node, failing over to the next one if it is unavailable.
+    ///
+    /// NOTE: If any fallback urls are configured, this must be called from within a tokio runtime,
+    /// as the health checks are spawned onto it.
+    pub fn new_with_failover(base_url: impl AsRef<str>, config: &FailoverConfig) -> Self {
+        let client = reqwest::Client::new();
+
+        let base_urls = Arc::new(Endpoints::new(
+            config.urls(base_url.as_ref()).map(|base_url| {
+                let base_url = base_url.trim_end_matches('/').to_owned();
+                (base_url.clone(), base_url)
+            }),
+            config,
+        ));
+
+        Endpoints::spawn_health_checks(&base_urls, config, {
+            let client = client.clone();
+            move |base_url: &String| {
+                let req = client.get(format!("{base_url}/eth/v1/node/health"));
+                async move { req.send().await?.error_for_status() }
+            }
+        });
+
         Self {
-            client: reqwest::Client::new(),
-            base_url: base_url.as_ref().trim_end_matches('/').into(),
+            client,
+            base_urls,
 
             // refresh these caches every 12 hours
             spec: moka::future::CacheBuilder::new(1)
@@ -75,12 +104,38 @@ impl BeaconApiClient {
             .into_value())
     }
 
+    /// Fetch the latest finality update.
+    ///
+    /// If a quorum is configured, the finality update is fetched from all beacon nodes and the
+    /// highest one that the quorum has reached is returned.
     #[instrument(skip_all)]
     pub async fn finality_update(
         &self,
     ) -> Result<VersionedResponse<LightClientFinalityUpdateResponseTypes>> {
-        self.get_json("/eth/v1/beacon/light_client/finality_update")
-            .await
+        const PATH: &str = "/eth/v1/beacon/light_client/finality_update";
+
+        if self.base_urls.quorum().is_none() {
+            return self.get_json(PATH).await;
+        }
+
+        Ok(self
+            .base_urls
+            .call_quorum(
+                |base_url| self.get_json_from(base_url, PATH),
+                |update: &VersionedResponse<LightClientFinalityUpdateResponseTypes>| {
+                    update.fold_ref(
+                        |f| match *f {},
+                        |f| f.finalized_header.beacon.slot.get(),
+                        |f| f.finalized_header.beacon.slot.get(),
+                        |f| f.finalized_header.beacon.slot.get(),
+                        |f| f.finalized_header.beacon.slot.get(),
+                        |f| f.finalized_header.beacon.slot.get(),
+                        |f| f.finalized_header.beacon.slot.get(),
+                    )
+                },
+                is_endpoint_failure,
+            )
+            .await?)
     }
 
     #[instrument(skip_all, fields(block_id))]
@@ -220,7 +275,18 @@ impl BeaconApiClient {
 
     #[instrument(skip_all, fields(path))]
     async fn get_json<T: DeserializeOwned>(&self, path: impl Into<String>) -> Result<T> {
-        let url = format!("{}{}", self.base_url, path.into());
+        let path = path.into();
+
+        self.base_urls
+            .call(
+                |base_url| self.get_json_from(base_url, &path),
+                is_endpoint_failure,
+            )
+            .await
+    }
+
+    async fn get_json_from<T: DeserializeOwned>(&self, base_url: &str, path: &str) -> Result<T> {
+        let url = format!("{base_url}{path}");
 
         debug!(%url, "get_json");
 
@@ -252,6 +318,21 @@ impl BeaconApiClient {
     }
 }
 
+/// Whether `err` was caused by the beacon node, rather than by the request.
+///
+/// Internal server errors are not considered to be endpoint failures, as beacon nodes return them
+/// for requests that can not be served (i.e. a bootstrap for a non-checkpoint block), which all
+/// other beacon nodes would fail the same way.
+fn is_endpoint_failure(err: &Error) -> bool {
+    match err {
+        Error::Http(_) | Error::Json(_) => true,
+        Error::Other { code, .. } => {
+            code.is_server_error() || *code == StatusCode::TOO_MANY_REQUESTS
+        }
+        Error::Internal(_) | Error::NotFound(_) | Error::Quorum(_) => false,
+    }
+}
+
 pub enum Encoding {
     Json,
     Ssz,
@@ -1,4 +1,5 @@
 use reqwest::StatusCode;
+use rpc_failover::QuorumError;
 use serde_json::Value;
 
 // REVIEW: Merge internal/not found with other?
@@ -14,4 +15,6 @@ pub enum Error {
     Json(#[from] serde_json::Error),
     #[error("unknown error ({code}): {text}")]
     Other { code: StatusCode, text: String },
+    #[error(transparent)]
+    Quorum(#[from] QuorumError<Error>),
 }
@@ -2,6 +2,7 @@ use core::fmt;
 use std::{
     fmt::Debug,
     num::{NonZeroU8, NonZeroU32, NonZeroU64},
+    sync::Arc,
     time::Duration,
 };
 
@@ -16,6 +17,8 @@ use jsonrpsee::{
     rpc_params,
     ws_client::{PingConfig, WsClientBuilder},
 };
+use rpc_failover::{Endpoints, FailoverConfig};
+use serde_json::value::RawValue;
 use tracing::{Instrument, debug, debug_span, instrument, trace};
 use unionlabs::{
     ErrorReporter,
@@ -36,68 +39,106 @@ mod tests;
 
 pub mod rpc_types;
 pub mod serde;
+pub mod subscription;
 pub use cometbft_types as types;
 
 pub type JsonRpcError = jsonrpsee::core::client::Error;
 
 #[derive(Debug, Clone)]
 pub struct Client {
-    inner: ClientInner,
+    endpoints: Arc<Endpoints<ClientInner>>,
 }
 
 impl Client {
     pub async fn new(url: impl AsRef<str>) -> Result<Self, JsonRpcError> {
-        let url = url.as_ref().to_owned();
-
-        let inner = match url.split_once("://") {
-            Some(("ws" | "wss", _)) => {
-                let client = reconnecting_jsonrpc_ws_client::Client::new(move || {
-                    WsClientBuilder::default()
-                        .enable_ws_ping(PingConfig::new())
-                        .build(url.clone())
-                        .instrument(debug_span!("cometbft_rpc_client", %url))
-                });
+        Self::new_with_failover(url, &FailoverConfig::default()).await
+    }
 
-                // TODO: Config
+    /// Connect to `url` and all fallback urls of `config`. Requests are sent to the healthiest
+    /// endpoint, failing over to the next one if it is unavailable.
+    ///
+    /// See [`rpc_failover::connect_all`] for how unavailable endpoints are handled.
+    pub async fn new_with_failover(
+        url: impl AsRef<str>,
+        config: &FailoverConfig,
+    ) -> Result<Self, JsonRpcError> {
+        let clients = rpc_failover::connect_all(url.as_ref(), config, ClientInner::new).await?;
+
+        let endpoints = Arc::new(Endpoints::new(clients, config));
+
+        Endpoints::spawn_health_checks(&endpoints, config, |client: &ClientInner| {
+            let client = client.clone();
+            async move {
                 client
-                    .wait_until_connected(Duration::from_secs(5))
+                    .request::<StatusResponse, _>("status", rpc_params!())
                     .await
-                    .map_err(|e| JsonRpcError::Custom(e.to_string()))?;
-
-                ClientInner::Ws(client)
             }
-            Some(("http" | "https", _)) => {
-                return Self::on_http(
-                    HttpClientBuilder::default()
-                        .max_response_size(100 * 1024 * 1024)
-                        .build(url)?,
-                );
-            }
-            _ => return Err(JsonRpcError::Custom(format!("invalid url {url}"))),
-        };
+        });
 
-        Ok(Self { inner })
+        Ok(Self { endpoints })
     }
 
     pub fn on_http(client: HttpClient) -> Result<Self, JsonRpcError> {
         Ok(Self {
-            inner: ClientInner::Http(Box::new(client)),
+            endpoints: Arc::new(Endpoints::new(
+                [("http".to_owned(), ClientInner::Http(Box::new(client)))],
+                &FailoverConfig::default(),
+            )),
         })
     }
 
+    async fn request<R, Params>(&self, method: &str, params: Params) -> Result<R, JsonRpcError>
+    where
+        R: DeserializeOwned,
+        Params: ToRpcParams + Send,
+    {
+        // serialize the params once, such that they can be sent to multiple endpoints
+        let params = RawParams(params.to_rpc_params().map_err(JsonRpcError::ParseError)?);
+
+        self.endpoints
+            .call(
+                |client| client.request(method, params.clone()),
+                is_endpoint_failure,
+            )
+            .await
+    }
+
+    /// Fetch the latest commit, or the commit at `height` if set.
+    ///
+    /// If a quorum is configured, the latest commit is fetched from all endpoints and the highest
+    /// one that the quorum has reached is returned.
     // TODO: This should be bounded correctly
     pub async fn commit(&self, height: Option<NonZeroU64>) -> Result<CommitResponse, JsonRpcError> {
-        self.inner
-            .request("commit", (height.map(|x| x.to_string()),))
+        if height.is_some() || self.endpoints.quorum().is_none() {
+            return self
+                .request("commit", (height.map(|x| x.to_string()),))
+                .await;
+        }
+
+        self.endpoints
+            .call_quorum(
+                |client| client.request("commit", (None::<String>,)),
+                |res: &CommitResponse| res.signed_header.header.height,
+                is_endpoint_failure,
+            )
             .await
+            .map_err(|err| {
+                JsonRpcError::Custom(format!(
+                    "{err}: {}",
+                    err.errors
+                        .iter()
+                        .map(|err| ErrorReporter(err).to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ))
+            })
     }
 
     pub async fn header(
         &self,
         height: Option<BoundedI64<1>>,
     ) -> Result<HeaderResponse, JsonRpcError> {
-        self.inner
-            .request("header", (height.map(|x| x.to_string()),))
+        self.request("header", (height.map(|x| x.to_string()),))
             .await
     }
 
@@ -106,16 +147,15 @@ impl Client {
         height: Option<NonZeroU64>,
         pagination: Option<rpc_types::ValidatorsPagination>,
     ) -> Result<ValidatorsResponse, JsonRpcError> {
-        self.inner
-            .request(
-                "validators",
-                (
-                    height.map(|x| x.to_string()),
-                    pagination.map(|x| x.page).map(|x| x.to_string()),
-                    pagination.and_then(|x| x.per_page).map(|x| x.to_string()),
-                ),
-            )
-            .await
+        self.request(
+            "validators",
+            (
+                height.map(|x| x.to_string()),
+                pagination.map(|x| x.page).map(|x| x.to_string()),
+                pagination.and_then(|x| x.per_page).map(|x| x.to_string()),
+            ),
+        )
+        .await
     }
 
     /// Auto-paginated version of [`Self::validators`].
@@ -167,7 +207,7 @@ impl Client {
     pub async fn abci_info(&self) -> Result<AbciInfoResponse, JsonRpcError> {
         debug!("fetching abci info");
 
-        let res: AbciInfoResponse = self.inner.request("abci_info", rpc_params!()).await?;
+        let res: AbciInfoResponse = self.request("abci_info", rpc_params!()).await?;
 
         debug!(
             data = %res.response.data,
@@ -200,7 +240,6 @@ impl Client {
         debug!("fetching abci query");
 
         let res: AbciQueryResponse = self
-            .inner
             // the rpc needs an un-prefixed hex string
             .request(
                 "abci_query",
@@ -275,22 +314,19 @@ impl Client {
     }
 
     pub async fn status(&self) -> Result<StatusResponse, JsonRpcError> {
-        self.inner.request("status", rpc_params!()).await
+        self.request("status", rpc_params!()).await
     }
 
     pub async fn block(
         &self,
         height: Option<BoundedI64<1>>,
     ) -> Result<BlockResponse, JsonRpcError> {
-        self.inner
-            .request("block", (height.map(|x| x.to_string()),))
+        self.request("block", (height.map(|x| x.to_string()),))
             .await
     }
 
     pub async fn block_by_hash(&self, hash: H256) -> Result<BlockResponse, JsonRpcError> {
-        self.inner
-            .request("block_by_hash", (hash.to_string(),))
-            .await
+        self.request("block_by_hash", (hash.to_string(),)).await
     }
 
     pub async fn blockchain(
@@ -298,12 +334,11 @@ impl Client {
         min_height: NonZeroU64,
         max_height: NonZeroU64,
     ) -> Result<BlockchainResponse, JsonRpcError> {
-        self.inner
-            .request(
-                "blockchain",
-                (min_height.to_string(), max_height.to_string()),
-            )
-            .await
+        self.request(
+            "blockchain",
+            (min_height.to_string(), max_height.to_string()),
+        )
+        .await
     }
 
     #[instrument(
@@ -327,7 +362,6 @@ impl Client {
         order_by: Order,
     ) -> Result<TxSearchResponse, JsonRpcError> {
         let response = self
-            .inner
             .request::<TxSearchResponse, _>(
                 "tx_search",
                 rpc_params![
@@ -365,7 +399,6 @@ impl Client {
         order_by: Order,
     ) -> Result<BlockSearchResponse, JsonRpcError> {
         let response = self
-            .inner
             .request::<BlockSearchResponse, _>(
                 "block_search",
                 rpc_params![
@@ -386,8 +419,7 @@ impl Client {
     pub async fn tx(&self, hash: H256, prove: bool) -> Result<TxResponse, JsonRpcError> {
         use base64::prelude::*;
 
-        self.inner
-            .request("tx", rpc_params![BASE64_STANDARD.encode(hash), prove])
+        self.request("tx", rpc_params![BASE64_STANDARD.encode(hash), prove])
             .await
     }
 
@@ -397,8 +429,7 @@ impl Client {
     ) -> Result<BroadcastTxSyncResponse, JsonRpcError> {
         use base64::prelude::*;
 
-        self.inner
-            .request("broadcast_tx_sync", rpc_params![BASE64_STANDARD.encode(tx)])
+        self.request("broadcast_tx_sync", rpc_params![BASE64_STANDARD.encode(tx)])
             .await
     }
 
@@ -406,8 +437,7 @@ impl Client {
         &self,
         height: Option<NonZeroU64>,
     ) -> Result<BlockResultsResponse, JsonRpcError> {
-        self.inner
-            .request("block_results", rpc_params![height.map(|x| x.to_string())])
+        self.request("block_results", rpc_params![height.map(|x| x.to_string())])
             .await
     }
 }
@@ -418,6 +448,51 @@ enum ClientInner {
     Ws(reconnecting_jsonrpc_ws_client::Client),
 }
 
+impl ClientInner {
+    async fn new(url: String) -> Result<Self, JsonRpcError> {
+        match url.split_once("://") {
+            Some(("ws" | "wss", _)) => {
+                let client = reconnecting_jsonrpc_ws_client::Client::new(move || {
+                    WsClientBuilder::default()
+                        .enable_ws_ping(PingConfig::new())
+                        .build(url.clone())
+                        .instrument(debug_span!("cometbft_rpc_client", %url))
+                });
+
+                // TODO: Config
+                client
+                    .wait_until_connected(Duration::from_secs(5))
+                    .await
+                    .map_err(|e| JsonRpcError::Custom(e.to_string()))?;
+
+                Ok(ClientInner::Ws(client))
+            }
+            Some(("http" | "https", _)) => Ok(ClientInner::Http(Box::new(
+                HttpClientBuilder::default()
+                    .max_response_size(100 * 1024 * 1024)
+                    .build(url)?,
+            ))),
+            _ => Err(JsonRpcError::Custom(format!("invalid url {url}"))),
+        }
+    }
+}
+
+/// Whether `err` was caused by the endpoint, rather than by the request. Error responses are
+/// returned as is, since the other endpoints would respond with the same error.
+fn is_endpoint_failure(err: &JsonRpcError) -> bool {
+    !matches!(err, JsonRpcError::Call(_))
+}
+
+/// Already serialized params, such that the same request can be sent to multiple endpoints.
+#[derive(Debug, Clone)]
+struct RawParams(Option<Box<RawValue>>);
+
+impl ToRpcParams for RawParams {
+    fn to_rpc_params(self) -> Result<Option<Box<RawValue>>, serde_json::Error> {
+        Ok(self.0)
+    }
+}
+
 impl ClientT for ClientInner {
     async fn notification<Params>(&self, method: &str, params: Params) -> Result<(), JsonRpcError>
     where
@@ -1,4 +1,5 @@
 use std::{
+    collections::BTreeMap,
     fmt::Display,
     num::{NonZeroU32, NonZeroU64},
     str::FromStr,
@@ -287,3 +288,62 @@ pub struct BroadcastTxSyncResponse {
     pub log: String,
     pub hash: H256<HexUnprefixed>,
 }
+
+/// An event pushed by a [subscription](crate::subscription::subscribe).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SubscriptionEvent {
+    pub query: String,
+    pub data: SubscriptionEventData,
+    /// The composite keys of the indexed attributes of the event, such as `tx.hash`.
+    #[serde(default)]
+    pub events: BTreeMap<String, Vec<String>>,
+}
+
+impl SubscriptionEvent {
+    /// The hash of the transaction this event was emitted by, if any.
+    pub fn tx_hash(&self) -> Option<H256<HexUnprefixed>> {
+        self.events
+            .get("tx.hash")
+            .and_then(|hashes| hashes.first())
+            .and_then(|hash| hash.parse().ok())
+    }
+}
+
+/// Only the event types that can be subscribed to by the relayer are supported.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value")]
+pub enum SubscriptionEventData {
+    #[serde(rename = "tendermint/event/NewBlockEvents")]
+    NewBlockEvents(NewBlockEvents),
+    #[serde(rename = "tendermint/event/Tx")]
+    Tx(TxEvent),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NewBlockEvents {
+    #[serde(with = "::serde_utils::string")]
+    pub height: u64,
+    #[serde(default)]
+    pub events: Vec<Event>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TxEvent {
+    #[serde(rename = "TxResult")]
+    pub tx_result: TxEventResult,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TxEventResult {
+    #[serde(with = "::serde_utils::string")]
+    pub height: u64,
+    #[serde(default)]
+    pub index: u32,
+    pub result: TxEventExecResult,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TxEventExecResult {
+    #[serde(default)]
+    pub events: Vec<Event>,
+}
@@ -0,0 +1,255 @@
+//! Event subscriptions over the CometBFT `/websocket` endpoint.
+//!
+//! CometBFT does not send subscription events as JSON-RPC notifications, but as responses to the
+//! original `subscribe` request. The jsonrpsee client drops these, so the websocket is read
+//! directly instead.
+
+use std::time::Duration;
+
+use jsonrpsee::{
+    client_transport::ws::{Url, WsHandshakeError, WsTransportClientBuilder},
+    core::client::{ReceivedMessage, TransportReceiverT, TransportSenderT},
+};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tokio::{sync::mpsc, time::sleep};
+use tracing::{debug, trace, warn};
+use unionlabs::ErrorReporter;
+
+use crate::rpc_types::SubscriptionEvent;
+
+const MAX_RETRY_MS: u64 = 8_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SubscriptionMessage {
+    /// The websocket has been (re)connected and all queries are subscribed to. Any events emitted
+    /// while the websocket was disconnected have been missed.
+    Connected,
+    Event(Box<SubscriptionEvent>),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SubscriptionError {
+    #[error("invalid url {0}")]
+    InvalidUrl(String),
+    #[error("websocket handshake failed")]
+    Handshake(#[from] WsHandshakeError),
+    #[error("websocket error")]
+    Ws(#[from] jsonrpsee::client_transport::ws::WsError),
+    #[error("subscription failed: {0}")]
+    Rpc(Value),
+}
+
+#[derive(Debug, Deserialize)]
+struct Response {
+    #[serde(default)]
+    result: Option<Value>,
+    #[serde(default)]
+    error: Option<Value>,
+}
+
+/// Subscribe to all events matching any of `queries` (i.e. `tm.event='Tx'`) on the websocket
+/// endpoint at `url` (i.e. `ws://localhost:26657/websocket`). The websocket is reconnected and
+/// resubscribed whenever the connection is lost, until the returned receiver is dropped.
+pub fn subscribe(
+    url: &str,
+    queries: Vec<String>,
+) -> Result<mpsc::UnboundedReceiver<SubscriptionMessage>, SubscriptionError> {
+    let url = Url::parse(url).map_err(|_| SubscriptionError::InvalidUrl(url.to_owned()))?;
+
+    let (tx, rx) = mpsc::unbounded_channel();
+
+    tokio::spawn(async move {
+        let mut retry_ms = 5;
+
+        while !tx.is_closed() {
+            if let Err(err) = run(&url, &queries, &tx, &mut retry_ms).await {
+                debug!(
+                    err = %ErrorReporter(err),
+                    %url,
+                    "subscription lost, reconnecting in {retry_ms} ms"
+                );
+            }
+
+            sleep(Duration::from_millis(retry_ms)).await;
+
+            retry_ms = std::cmp::min((retry_ms * 3) / 2, MAX_RETRY_MS);
+        }
+    });
+
+    Ok(rx)
+}
+
+async fn run(
+    url: &Url,
+    queries: &[String],
+    tx: &mpsc::UnboundedSender<SubscriptionMessage>,
+    retry_ms: &mut u64,
+) -> Result<(), SubscriptionError> {
+    let (mut sender, mut receiver) = WsTransportClientBuilder::default()
+        .build(url.clone())
+        .await?;
+
+    for (id, query) in queries.iter().enumerate() {
+        sender
+            .send(
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "method": "subscribe",
+                    "params": { "query": query },
+                })
+                .to_string(),
+            )
+            .await?;
+    }
+
+    let mut confirmed = 0;
+
+    loop {
+        let message = match receiver.receive().await? {
+            ReceivedMessage::Text(text) => text,
+            ReceivedMessage::Bytes(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+            ReceivedMessage::Pong => continue,
+        };
+
+        match parse_message(&message)? {
+            Some(Message::Subscribed) => {
+                confirmed += 1;
+
+                if confirmed == queries.len() {
+                    debug!(%url, "subscribed");
+
+                    *retry_ms = 5;
+
+                    if tx.send(SubscriptionMessage::Connected).is_err() {
+                        return Ok(());
+                    }
+                }
+            }
+            Some(Message::Event(event)) => {
+                trace!(query = %event.query, "received event");
+
+                if tx.send(SubscriptionMessage::Event(event)).is_err() {
+                    return Ok(());
+                }
+            }
+            None => {}
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Message {
+    /// A `subscribe` request was acknowledged.
+    Subscribed,
+    Event(Box<SubscriptionEvent>),
+}
+
+/// Parse a message received on the websocket. Messages that are invalid or not supported are logged
+/// and skipped.
+fn parse_message(message: &str) -> Result<Option<Message>, SubscriptionError> {
+    let response = match serde_json::from_str::<Response>(message) {
+        Ok(response) => response,
+        Err(err) => {
+            warn!(err = %ErrorReporter(err), %message, "invalid subscription message");
+            return Ok(None);
+        }
+    };
+
+    if let Some(error) = response.error {
+        return Err(SubscriptionError::Rpc(error));
+    }
+
+    let Some(result) = response.result else {
+        return Ok(None);
+    };
+
+    // the subscribe request itself is acknowledged with an empty result
+    if result.as_object().is_some_and(|object| object.is_empty()) {
+        return Ok(Some(Message::Subscribed));
+    }
+
+    match serde_json::from_value::<SubscriptionEvent>(result) {
+        Ok(event) => Ok(Some(Message::Event(Box::new(event)))),
+        Err(err) => {
+            warn!(err = %ErrorReporter(err), "unsupported subscription event");
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc_types::{NewBlockEvents, SubscriptionEventData};
+
+    #[test]
+    fn parse_ack() {
+        assert_eq!(
+            parse_message(r#"{"jsonrpc":"2.0","id":0,"result":{}}"#).unwrap(),
+            Some(Message::Subscribed)
+        );
+    }
+
+    #[test]
+    fn parse_event() {
+        let message = json!({
+            "jsonrpc": "2.0",
+            "id": 0,
+            "result": {
+                "query": "tm.event='NewBlockEvents'",
+                "data": {
+                    "type": "tendermint/event/NewBlockEvents",
+                    "value": {
+                        "height": "42",
+                        "events": [],
+                    },
+                },
+                "events": {
+                    "tm.event": ["NewBlockEvents"],
+                },
+            },
+        });
+
+        let Some(Message::Event(event)) = parse_message(&message.to_string()).unwrap() else {
+            panic!("expected an event");
+        };
+
+        assert_eq!(event.query, "tm.event='NewBlockEvents'");
+        assert_eq!(
+            event.data,
+            SubscriptionEventData::NewBlockEvents(NewBlockEvents {
+                height: 42,
+                events: vec![],
+            })
+        );
+        assert_eq!(event.tx_hash(), None);
+    }
+
+    #[test]
+    fn parse_error() {
+        assert!(matches!(
+            parse_message(
+                r#"{"jsonrpc":"2.0","id":0,"error":{"code":-32603,"message":"max subscriptions"}}"#
+            ),
+            Err(SubscriptionError::Rpc(_))
+        ));
+    }
+
+    #[test]
+    fn skip_unsupported_messages() {
+        // not json
+        assert_eq!(parse_message("not json").unwrap(), None);
+        // no result
+        assert_eq!(parse_message(r#"{"jsonrpc":"2.0","id":0}"#).unwrap(), None);
+        // unsupported event type
+        assert_eq!(
+            parse_message(
+                r#"{"jsonrpc":"2.0","id":0,"result":{"query":"tm.event='Vote'","data":{"type":"tendermint/event/Vote","value":{}}}}"#
+            )
+            .unwrap(),
+            None
+        );
+    }
+}
@@ -5,6 +5,7 @@ pub struct EventAttribute {
     pub key: String,
     pub value: String,
     /// nondeterministic
+    #[serde(default)]
     pub index: bool,
 }
 
@@ -1,9 +1,19 @@
 #![feature(trait_alias)]
 
+pub mod keystore;
+pub mod monitor;
+pub mod nonce;
 pub mod private_key;
+pub mod remote;
 
 use std::{
-    collections::HashMap, fmt::Display, hash::Hash, panic::UnwindSafe, path::PathBuf, sync::Arc,
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    hash::Hash,
+    num::NonZeroUsize,
+    panic::UnwindSafe,
+    path::PathBuf,
+    sync::{Arc, RwLock},
 };
 
 use crossbeam_queue::ArrayQueue;
@@ -20,6 +30,14 @@ pub trait ChainKeyring {
     fn keyring(&self) -> &ConcurrentKeyring<Self::Address, Self::Signer>;
 
     fn balances(&self) -> impl Future<Output = Vec<SignerBalance<Self::Address>>> + Send;
+
+    /// Transfer `amount` of the fee denom to `address` from the treasury key configured in
+    /// [`monitor::RefillConfig`].
+    fn refill(
+        &self,
+        address: &Self::Address,
+        amount: u128,
+    ) -> impl Future<Output = Result<(), Box<dyn std::error::Error + Send + Sync>>> + Send;
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -39,6 +57,10 @@ pub struct ConcurrentKeyring<A: Hash + Eq, S> {
     addresses_buffer: Arc<ArrayQueue<A>>,
 
     signers: Arc<HashMap<A, S>>,
+
+    /// Addresses that are skipped by [`ConcurrentKeyring::with`], i.e. because their balance is
+    /// below the configured floor.
+    excluded: Arc<RwLock<HashSet<A>>>,
 }
 
 pub struct KeyringEntry<A, S> {
@@ -51,29 +73,51 @@ impl<A: Hash + Eq + Clone + Display, S: 'static> ConcurrentKeyring<A, S> {
     pub fn new(
         name: impl Into<String>,
         entries: impl ExactSizeIterator<Item = KeyringEntry<A, S>>,
+    ) -> Self {
+        Self::new_with_max_in_flight(name, entries, NonZeroUsize::MIN)
+    }
+
+    /// Create a keyring where every signer can be used by up to `max_in_flight` concurrent calls
+    /// to [`ConcurrentKeyring::with`]. This should only be used with signers whose nonces are
+    /// managed locally (see [`nonce::NonceManager`]).
+    pub fn new_with_max_in_flight(
+        name: impl Into<String>,
+        entries: impl ExactSizeIterator<Item = KeyringEntry<A, S>>,
+        max_in_flight: NonZeroUsize,
     ) -> Self {
         // let mut address_to_key = HashMap::new();
         // let mut key_to_address = HashMap::new();
         let mut signers = HashMap::new();
-        let addresses_buffer = ArrayQueue::new(entries.len());
+        let addresses_buffer = ArrayQueue::new(entries.len() * max_in_flight.get());
 
         let mut rng = &mut rand::thread_rng();
 
         let mut entries = entries.collect::<Vec<_>>();
         entries.shuffle(&mut rng);
 
-        for key in entries {
-            signers.insert(key.address.clone(), key.signer);
-            addresses_buffer
-                .push(key.address)
-                .ok()
-                .expect("buffer is created with the expected length; qed;");
+        let addresses = entries
+            .into_iter()
+            .map(|key| {
+                signers.insert(key.address.clone(), key.signer);
+                key.address
+            })
+            .collect::<Vec<_>>();
+
+        // every signer is used once before any signer is used concurrently
+        for _ in 0..max_in_flight.get() {
+            for address in &addresses {
+                addresses_buffer
+                    .push(address.clone())
+                    .ok()
+                    .expect("buffer is created with the expected length; qed;");
+            }
         }
 
         Self {
             name: Arc::new(name.into()),
             addresses_buffer: Arc::new(addresses_buffer),
             signers: Arc::new(signers),
+            excluded: Arc::new(RwLock::new(HashSet::new())),
         }
     }
 
@@ -81,13 +125,55 @@ impl<A: Hash + Eq + Clone + Display, S: 'static> ConcurrentKeyring<A, S> {
         self.signers.keys()
     }
 
+    /// Exclude `address` from rotation. Returns `true` if the address was not already excluded.
+    pub fn exclude(&self, address: &A) -> bool {
+        self.excluded
+            .write()
+            .expect("lock is not poisoned; qed;")
+            .insert(address.clone())
+    }
+
+    /// Include `address` in rotation again. Returns `true` if the address was excluded.
+    pub fn include(&self, address: &A) -> bool {
+        self.excluded
+            .write()
+            .expect("lock is not poisoned; qed;")
+            .remove(address)
+    }
+
+    pub fn is_excluded(&self, address: &A) -> bool {
+        self.excluded
+            .read()
+            .expect("lock is not poisoned; qed;")
+            .contains(address)
+    }
+
+    /// Pop the next address that is not excluded from the buffer. Every address is checked at most
+    /// once, excluded addresses are pushed straight back.
+    fn pop_available(&self) -> Option<A> {
+        for _ in 0..self.addresses_buffer.capacity() {
+            let address = self.addresses_buffer.pop()?;
+
+            if !self.is_excluded(&address) {
+                return Some(address);
+            }
+
+            self.addresses_buffer
+                .push(address)
+                .ok()
+                .expect("no additional items are added; qed;");
+        }
+
+        None
+    }
+
     pub async fn with<'a, F, Fut>(&'a self, f: F) -> Option<Fut::Output>
     where
         F: FnOnce(&'a S) -> Fut + 'a,
         Fut: Future<Output: 'a> + Sized + UnwindSafe + 'a,
     {
-        let Some(address) = self.addresses_buffer.pop() else {
-            debug!(keyring = %self.name, "high traffic in keyring");
+        let Some(address) = self.pop_available() else {
+            debug!(keyring = %self.name, "high traffic in keyring or all signers are excluded");
             return None;
         };
 
@@ -130,9 +216,23 @@ impl<A: Hash + Eq + Clone + Display, S: 'static> ConcurrentKeyring<A, S> {
 pub struct KeyringConfig {
     pub name: String,
     pub keys: Vec<KeyringConfigEntry>,
+    /// The maximum amount of in-flight transactions per signer. This is only supported by plugins
+    /// that manage the nonces of their signers locally.
+    #[serde(default = "default_max_in_flight")]
+    pub max_in_flight: NonZeroUsize,
+}
+
+const fn default_max_in_flight() -> NonZeroUsize {
+    NonZeroUsize::MIN
 }
 
 impl KeyringConfigEntry {
+    /// The raw private key of this entry.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if the key cannot be read or decrypted, or if this is a
+    /// [`KeyringConfigEntry::Remote`] entry (use [`KeyringConfigEntry::remote`] for those instead).
     pub fn value(&self) -> Vec<u8> {
         match &self {
             KeyringConfigEntry::File { path } => std::fs::read_to_string(path)
@@ -142,6 +242,24 @@ impl KeyringConfigEntry {
                 .expect("key is in an invalid format")
                 .into(),
             KeyringConfigEntry::Raw { name: _, key } => key.clone(),
+            KeyringConfigEntry::Keystore { path, passphrase } => keystore::decrypt(
+                &std::fs::read_to_string(path).expect("keystore does not exist"),
+                &passphrase.value(),
+            )
+            .unwrap_or_else(|e| panic!("unable to decrypt keystore {}: {e}", path.display())),
+            KeyringConfigEntry::Remote { name, .. } => {
+                panic!("the private key of remote signer {name} is not available")
+            }
+        }
+    }
+
+    /// The remote signer of this entry, if this is a [`KeyringConfigEntry::Remote`] entry.
+    pub fn remote(&self) -> Option<remote::RemoteSigner> {
+        match self {
+            KeyringConfigEntry::Remote { url, key_id, .. } => {
+                Some(remote::RemoteSigner::new(url, key_id.clone()))
+            }
+            _ => None,
         }
     }
 }
@@ -157,4 +275,40 @@ pub enum KeyringConfigEntry {
         #[serde(with = "::serde_utils::hex_string")]
         key: Vec<u8>,
     },
+    /// An encrypted keystore file, either an Ethereum V3 keystore or a cosmos armored private key.
+    /// See [`keystore`] for the supported formats.
+    Keystore {
+        path: PathBuf,
+        passphrase: Passphrase,
+    },
+    /// A key held by a remote signer. See [`remote`] for the signing protocol.
+    Remote {
+        name: String,
+        url: String,
+        #[serde(default)]
+        key_id: Option<String>,
+    },
+}
+
+/// The source of the passphrase of a [`KeyringConfigEntry::Keystore`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum Passphrase {
+    Env { var: String },
+    File { path: PathBuf },
+}
+
+impl Passphrase {
+    pub fn value(&self) -> String {
+        match self {
+            Passphrase::Env { var } => std::env::var(var)
+                .unwrap_or_else(|e| panic!("unable to read passphrase from ${var}: {e}")),
+            Passphrase::File { path } => std::fs::read_to_string(path)
+                .unwrap_or_else(|e| {
+                    panic!("unable to read passphrase from {}: {e}", path.display())
+                })
+                .trim_end_matches(['\r', '\n'])
+                .to_owned(),
+        }
+    }
 }
@@ -0,0 +1,234 @@
+//! Periodic balance monitoring of the signers in a keyring.
+//!
+//! Signers with a balance below [`BalanceMonitorConfig::min_balance`] are excluded from rotation
+//! until their balance is back above the floor, instead of being picked for transactions that are
+//! going to run out of gas. If a [`RefillConfig`] is set, low signers are also topped up from the
+//! treasury key.
+
+use std::time::Duration;
+
+use opentelemetry::{KeyValue, metrics::Gauge};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+
+use crate::{ChainKeyring, KeyringConfigEntry, SignerBalance};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BalanceMonitorConfig {
+    /// How often the balances of the signers are polled.
+    #[serde(default = "default_poll_interval_seconds")]
+    pub poll_interval_seconds: u64,
+
+    /// Signers with a balance below this amount of the fee denom are excluded from rotation.
+    #[serde(with = "::serde_utils::string")]
+    pub min_balance: u128,
+
+    #[serde(default)]
+    pub refill: Option<RefillConfig>,
+}
+
+const fn default_poll_interval_seconds() -> u64 {
+    60
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RefillConfig {
+    /// The key that refills are sent from. This key is not part of the keyring.
+    pub treasury: KeyringConfigEntry,
+
+    /// Signers below [`BalanceMonitorConfig::min_balance`] are topped up to this balance.
+    #[serde(with = "::serde_utils::string")]
+    pub target_balance: u128,
+}
+
+/// Poll the balances of the signers of `chain` every
+/// [`BalanceMonitorConfig::poll_interval_seconds`], forever.
+pub async fn monitor_balances<K>(chain: K, config: BalanceMonitorConfig)
+where
+    K: ChainKeyring<Signer: Send + Sync> + Sync,
+{
+    let gauge = opentelemetry::global::meter("voyager")
+        .f64_gauge("keyring.signer_balance")
+        .build();
+
+    loop {
+        check_balances(&chain, &config, &gauge).await;
+
+        tokio::time::sleep(Duration::from_secs(config.poll_interval_seconds)).await;
+    }
+}
+
+/// Record the balances of the signers of `chain`, exclude or include them in rotation based on
+/// [`BalanceMonitorConfig::min_balance`], and refill any low signers if configured.
+pub async fn check_balances<K>(chain: &K, config: &BalanceMonitorConfig, gauge: &Gauge<f64>)
+where
+    K: ChainKeyring<Signer: Send + Sync> + Sync,
+{
+    let keyring = chain.keyring();
+
+    for SignerBalance {
+        key_name,
+        address,
+        balance,
+        denom,
+    } in chain.balances().await
+    {
+        gauge.record(
+            balance as f64,
+            &[
+                KeyValue::new("keyring", keyring.name.to_string()),
+                KeyValue::new("key_name", key_name),
+                KeyValue::new("address", address.to_string()),
+                KeyValue::new("denom", denom.clone()),
+            ],
+        );
+
+        if balance >= config.min_balance {
+            if keyring.include(&address) {
+                info!(
+                    keyring = %keyring.name,
+                    %address,
+                    %balance,
+                    %denom,
+                    "signer balance is above the floor, including signer in rotation"
+                );
+            }
+
+            continue;
+        }
+
+        if keyring.exclude(&address) {
+            warn!(
+                keyring = %keyring.name,
+                %address,
+                %balance,
+                %denom,
+                min_balance = %config.min_balance,
+                "signer balance is below the floor, excluding signer from rotation"
+            );
+        }
+
+        if let Some(refill) = &config.refill {
+            let amount = refill.target_balance.saturating_sub(balance);
+
+            match chain.refill(&address, amount).await {
+                Ok(()) => info!(
+                    keyring = %keyring.name,
+                    %address,
+                    %amount,
+                    %denom,
+                    "refilled signer"
+                ),
+                Err(err) => error!(
+                    keyring = %keyring.name,
+                    %address,
+                    %amount,
+                    %denom,
+                    "error refilling signer: {err}"
+                ),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::{ConcurrentKeyring, KeyringEntry};
+
+    struct Chain {
+        keyring: ConcurrentKeyring<u8, u8>,
+        balances: Vec<(u8, u128)>,
+        refills: Mutex<Vec<(u8, u128)>>,
+    }
+
+    impl ChainKeyring for Chain {
+        type Address = u8;
+        type Signer = u8;
+
+        fn keyring(&self) -> &ConcurrentKeyring<u8, u8> {
+            &self.keyring
+        }
+
+        async fn balances(&self) -> Vec<SignerBalance<u8>> {
+            self.balances
+                .iter()
+                .map(|(address, balance)| SignerBalance {
+                    key_name: address.to_string(),
+                    address: *address,
+                    balance: *balance,
+                    denom: "au".to_owned(),
+                })
+                .collect()
+        }
+
+        async fn refill(
+            &self,
+            address: &u8,
+            amount: u128,
+        ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+            self.refills.lock().unwrap().push((*address, amount));
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn low_signers_are_excluded_and_refilled() {
+        let mut chain = Chain {
+            keyring: ConcurrentKeyring::new(
+                "test",
+                [1, 2].into_iter().map(|address| KeyringEntry {
+                    address,
+                    signer: address,
+                }),
+            ),
+            balances: vec![(1, 5), (2, 100)],
+            refills: Mutex::new(vec![]),
+        };
+
+        let config = BalanceMonitorConfig {
+            poll_interval_seconds: 1,
+            min_balance: 10,
+            refill: Some(RefillConfig {
+                treasury: KeyringConfigEntry::Raw {
+                    name: "treasury".to_owned(),
+                    key: vec![],
+                },
+                target_balance: 50,
+            }),
+        };
+
+        let gauge = opentelemetry::global::meter("test")
+            .f64_gauge("balance")
+            .build();
+
+        check_balances(&chain, &config, &gauge).await;
+
+        assert!(chain.keyring.is_excluded(&1));
+        assert!(!chain.keyring.is_excluded(&2));
+        assert_eq!(*chain.refills.lock().unwrap(), [(1, 45)]);
+
+        // the excluded signer is never picked
+        for _ in 0..4 {
+            assert_eq!(chain.keyring.with(|a| async move { *a }).await, Some(2));
+        }
+
+        chain.balances = vec![(1, 50), (2, 0)];
+
+        check_balances(&chain, &config, &gauge).await;
+
+        assert!(!chain.keyring.is_excluded(&1));
+        assert!(chain.keyring.is_excluded(&2));
+        assert_eq!(chain.keyring.with(|a| async move { *a }).await, Some(1));
+
+        chain.balances = vec![(1, 0), (2, 0)];
+
+        check_balances(&chain, &config, &gauge).await;
+
+        assert_eq!(chain.keyring.with(|a| async move { *a }).await, None);
+    }
+}
@@ -0,0 +1,161 @@
+//! Local nonce (or account sequence) tracking for the signers of a keyring.
+//!
+//! Nonces are assigned optimistically from a local cache instead of being queried from the chain
+//! for every transaction, which allows for multiple in-flight transactions per signer (see
+//! [`KeyringConfig::max_in_flight`](crate::KeyringConfig::max_in_flight)). The cache is dropped
+//! and refetched on the next reservation whenever it is found to be out of sync with the chain.
+
+use std::{collections::HashMap, future::Future, hash::Hash, sync::Mutex};
+
+use tracing::debug;
+
+/// The next nonce of every signer that has been used so far, along with any additional per-signer
+/// data `D` that is fetched alongside the nonce (i.e. the account number of a cosmos account).
+#[derive(Debug)]
+pub struct NonceManager<A, D = ()> {
+    accounts: Mutex<HashMap<A, Account<D>>>,
+}
+
+#[derive(Debug)]
+struct Account<D> {
+    next: u64,
+    data: D,
+}
+
+impl<A, D> Default for NonceManager<A, D> {
+    fn default() -> Self {
+        Self {
+            accounts: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<A: Hash + Eq + Clone, D: Clone> NonceManager<A, D> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reserve the next nonce of `address`. If the nonce of `address` is not cached, the current
+    /// nonce is fetched from the chain with `fetch`.
+    ///
+    /// A reserved nonce that ends up not being used must be returned with
+    /// [`NonceManager::release`].
+    pub async fn reserve<E, Fut>(
+        &self,
+        address: &A,
+        fetch: impl FnOnce() -> Fut,
+    ) -> Result<(u64, D), E>
+    where
+        Fut: Future<Output = Result<(u64, D), E>>,
+    {
+        if let Some(reserved) = self.reserve_cached(address) {
+            return Ok(reserved);
+        }
+
+        let (next, data) = fetch().await?;
+
+        debug!(next, "fetched nonce");
+
+        let mut accounts = self.accounts.lock().expect("mutex is not poisoned; qed;");
+
+        // if another reservation fetched the nonce concurrently, the first one wins
+        let account = accounts
+            .entry(address.clone())
+            .or_insert(Account { next, data });
+
+        let nonce = account.next;
+        account.next += 1;
+
+        Ok((nonce, account.data.clone()))
+    }
+
+    fn reserve_cached(&self, address: &A) -> Option<(u64, D)> {
+        let mut accounts = self.accounts.lock().expect("mutex is not poisoned; qed;");
+
+        let account = accounts.get_mut(address)?;
+
+        let nonce = account.next;
+        account.next += 1;
+
+        Some((nonce, account.data.clone()))
+    }
+
+    /// Return a reserved `nonce` that was not used (i.e. the transaction was never broadcast). If
+    /// this was the most recently reserved nonce of `address` it will be handed out again,
+    /// otherwise the cache is dropped since there is now a gap in the nonces.
+    pub fn release(&self, address: &A, nonce: u64) {
+        let mut accounts = self.accounts.lock().expect("mutex is not poisoned; qed;");
+
+        match accounts.get_mut(address) {
+            Some(account) if account.next == nonce + 1 => account.next = nonce,
+            Some(_) => {
+                debug!(nonce, "released nonce is not the latest, dropping cache");
+                accounts.remove(address);
+            }
+            None => {}
+        }
+    }
+
+    /// Drop the cached nonce of `address`, such that it is refetched on the next reservation.
+    pub fn resync(&self, address: &A) {
+        debug!("resyncing nonce");
+
+        self.accounts
+            .lock()
+            .expect("mutex is not poisoned; qed;")
+            .remove(address);
+    }
+
+    /// Set the next nonce of `address`, i.e. to the expected nonce returned in a nonce mismatch
+    /// error. This has no effect if the nonce of `address` is not cached.
+    pub fn set(&self, address: &A, next: u64) {
+        debug!(next, "setting nonce");
+
+        if let Some(account) = self
+            .accounts
+            .lock()
+            .expect("mutex is not poisoned; qed;")
+            .get_mut(address)
+        {
+            account.next = next;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use super::*;
+
+    async fn reserve(nonces: &NonceManager<u8, u64>, chain_nonce: u64) -> (u64, u64) {
+        nonces
+            .reserve(&1, || async { Ok::<_, Infallible>((chain_nonce, 7)) })
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn nonces_are_assigned_optimistically() {
+        let nonces = NonceManager::new();
+
+        assert_eq!(reserve(&nonces, 10).await, (10, 7));
+        // the chain is not queried again while the nonce is cached
+        assert_eq!(reserve(&nonces, 0).await, (11, 7));
+        assert_eq!(reserve(&nonces, 0).await, (12, 7));
+
+        // the latest nonce is handed out again
+        nonces.release(&1, 12);
+        assert_eq!(reserve(&nonces, 0).await, (12, 7));
+
+        // releasing an older nonce leaves a gap, which requires a resync
+        nonces.release(&1, 11);
+        assert_eq!(reserve(&nonces, 20).await, (20, 7));
+
+        nonces.set(&1, 15);
+        assert_eq!(reserve(&nonces, 0).await, (15, 7));
+
+        nonces.resync(&1);
+        assert_eq!(reserve(&nonces, 30).await, (30, 7));
+    }
+}
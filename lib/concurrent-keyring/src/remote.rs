@@ -0,0 +1,196 @@
+//! Client for remote signers, i.e. an HSM or KMS proxy holding the private keys.
+//!
+//! The protocol is intentionally minimal, such that it can be implemented in front of any signing
+//! backend:
+//!
+//! - `POST {url}/public_key` with `{"key_id": string | null}` returns `{"public_key": "0x.."}`.
+//! - `POST {url}/sign` with `{"key_id": string | null, "message": "0x.."}` returns
+//!   `{"signature": "0x.."}`.
+//!
+//! For secp256k1 keys, `message` is the 32 byte prehash of the payload, and `signature` is the
+//! 65 byte `r || s || v` recoverable signature (with a low `s`). For ed25519 keys, `message` is
+//! the full payload and `signature` is the 64 byte signature.
+
+use serde::{Deserialize, Serialize};
+use unionlabs::primitives::Bytes;
+
+#[derive(Debug, thiserror::Error)]
+pub enum RemoteSignerError {
+    #[error("error sending request to remote signer")]
+    Request(#[from] reqwest::Error),
+    #[error("remote signer returned an invalid {0}")]
+    InvalidResponse(&'static str),
+}
+
+#[derive(Debug, Clone)]
+pub struct RemoteSigner {
+    url: String,
+    key_id: Option<String>,
+    client: reqwest::Client,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PublicKeyRequest {
+    pub key_id: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PublicKeyResponse {
+    pub public_key: Bytes,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignRequest {
+    pub key_id: Option<String>,
+    pub message: Bytes,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignResponse {
+    pub signature: Bytes,
+}
+
+impl RemoteSigner {
+    pub fn new(url: impl Into<String>, key_id: Option<String>) -> Self {
+        Self {
+            url: url.into().trim_end_matches('/').to_owned(),
+            key_id,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn key_id(&self) -> Option<&str> {
+        self.key_id.as_deref()
+    }
+
+    /// Fetch the public key of this signer.
+    pub async fn public_key(&self) -> Result<Vec<u8>, RemoteSignerError> {
+        let res = self
+            .client
+            .post(format!("{}/public_key", self.url))
+            .json(&PublicKeyRequest {
+                key_id: self.key_id.clone(),
+            })
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<PublicKeyResponse>()
+            .await?;
+
+        if res.public_key.is_empty() {
+            return Err(RemoteSignerError::InvalidResponse("public key"));
+        }
+
+        Ok(res.public_key.into_vec())
+    }
+
+    /// Sign `message` with this signer. See the module level docs for the expected format of
+    /// `message` and the returned signature.
+    pub async fn sign(&self, message: &[u8]) -> Result<Vec<u8>, RemoteSignerError> {
+        let res = self
+            .client
+            .post(format!("{}/sign", self.url))
+            .json(&SignRequest {
+                key_id: self.key_id.clone(),
+                message: message.to_vec().into(),
+            })
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<SignResponse>()
+            .await?;
+
+        if !matches!(res.signature.len(), 64 | 65) {
+            return Err(RemoteSignerError::InvalidResponse("signature"));
+        }
+
+        Ok(res.signature.into_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Json, Router, extract::State, routing::post};
+    use bip32::secp256k1::ecdsa::{
+        RecoveryId, Signature, SigningKey, VerifyingKey, signature::hazmat::PrehashVerifier,
+    };
+    use hex_literal::hex;
+    use sha2::{Digest, Sha256};
+
+    use super::*;
+
+    const KEY: [u8; 32] = hex!("7a28b5ba57c53603b0b07b56bba752f7784bf506fa95edc395f5cf6c7514fe9d");
+
+    async fn public_key(
+        State(key): State<SigningKey>,
+        Json(req): Json<PublicKeyRequest>,
+    ) -> Json<PublicKeyResponse> {
+        assert_eq!(req.key_id.as_deref(), Some("relayer"));
+
+        Json(PublicKeyResponse {
+            public_key: key
+                .verifying_key()
+                .to_encoded_point(true)
+                .as_bytes()
+                .to_vec()
+                .into(),
+        })
+    }
+
+    async fn sign(
+        State(key): State<SigningKey>,
+        Json(req): Json<SignRequest>,
+    ) -> Json<SignResponse> {
+        let (signature, recovery_id): (Signature, RecoveryId) =
+            key.sign_prehash_recoverable(&req.message).unwrap();
+
+        Json(SignResponse {
+            signature: signature
+                .to_bytes()
+                .into_iter()
+                .chain([recovery_id.to_byte()])
+                .collect::<Vec<_>>()
+                .into(),
+        })
+    }
+
+    #[tokio::test]
+    async fn stand_in_signer() {
+        let key = SigningKey::from_slice(&KEY).unwrap();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+
+        tokio::spawn(
+            axum::serve(
+                listener,
+                Router::new()
+                    .route("/public_key", post(public_key))
+                    .route("/sign", post(sign))
+                    .with_state(key.clone()),
+            )
+            .into_future(),
+        );
+
+        let signer = RemoteSigner::new(url, Some("relayer".to_owned()));
+
+        let public_key = signer.public_key().await.unwrap();
+        assert_eq!(
+            public_key,
+            key.verifying_key().to_encoded_point(true).as_bytes()
+        );
+
+        let prehash = Sha256::digest(b"union");
+        let signature = signer.sign(&prehash).await.unwrap();
+        assert_eq!(signature.len(), 65);
+
+        VerifyingKey::from_sec1_bytes(&public_key)
+            .unwrap()
+            .verify_prehash(&prehash, &Signature::from_slice(&signature[..64]).unwrap())
+            .unwrap();
+    }
+}
@@ -1,15 +1,21 @@
+use cometbft_rpc::{JsonRpcError, rpc_types::GrpcAbciQueryError};
+use gas_oracle::GasOracleError;
 use num_rational::BigRational;
 use unionlabs::cosmos::tx::fee::Fee;
 
 pub mod any;
 pub mod feemarket;
 pub mod fixed;
+pub mod oracle;
 pub mod osmosis_eip1559_feemarket;
 
 pub trait GasFillerT {
     async fn max_gas(&self) -> u64;
 
-    async fn mk_fee(&self, gas: u64) -> Fee;
+    async fn mk_fee(&self, gas: u64) -> Result<Fee, GasOracleError>;
+
+    /// The denom that fees are paid in.
+    fn denom(&self) -> &str;
 }
 
 impl<T: GasFillerT> GasFillerT for &T {
@@ -17,9 +23,24 @@ impl<T: GasFillerT> GasFillerT for &T {
         (*self).max_gas().await
     }
 
-    async fn mk_fee(&self, gas: u64) -> Fee {
+    async fn mk_fee(&self, gas: u64) -> Result<Fee, GasOracleError> {
         (*self).mk_fee(gas).await
     }
+
+    fn denom(&self) -> &str {
+        (*self).denom()
+    }
+}
+
+/// An error constructing a gas filler.
+#[derive(Debug, thiserror::Error)]
+pub enum GasFillerError {
+    #[error("jsonrpc error")]
+    JsonRpc(#[from] JsonRpcError),
+    #[error("grpc abci query error")]
+    Query(#[from] GrpcAbciQueryError),
+    #[error("empty response when querying {0}")]
+    EmptyResponse(&'static str),
 }
 
 pub(crate) fn u128_saturating_mul_f64(u: u128, f: f64) -> u128 {
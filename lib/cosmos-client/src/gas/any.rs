@@ -1,13 +1,15 @@
+use gas_oracle::GasOracleError;
 use serde::{Deserialize, Serialize};
 use unionlabs::cosmos::tx::fee::Fee;
 
-use crate::gas::{GasFillerT, feemarket, fixed, osmosis_eip1559_feemarket};
+use crate::gas::{GasFillerT, feemarket, fixed, oracle, osmosis_eip1559_feemarket};
 
 #[derive(Debug)]
 pub enum GasFiller {
     Fixed(fixed::GasFiller),
     Feemarket(feemarket::GasFiller),
     OsmosisEip1559Feemarket(osmosis_eip1559_feemarket::GasFiller),
+    Oracle(oracle::GasFiller),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -17,6 +19,7 @@ pub enum Config {
     Fixed(fixed::GasFiller),
     Feemarket(feemarket::Config),
     OsmosisEip1559Feemarket(osmosis_eip1559_feemarket::Config),
+    Oracle(oracle::Config),
 }
 
 impl GasFillerT for GasFiller {
@@ -25,14 +28,25 @@ impl GasFillerT for GasFiller {
             Self::Fixed(f) => f.max_gas().await,
             Self::Feemarket(f) => f.max_gas().await,
             GasFiller::OsmosisEip1559Feemarket(f) => f.max_gas().await,
+            GasFiller::Oracle(f) => f.max_gas().await,
         }
     }
 
-    async fn mk_fee(&self, gas: u64) -> Fee {
+    async fn mk_fee(&self, gas: u64) -> Result<Fee, GasOracleError> {
         match self {
             Self::Fixed(f) => f.mk_fee(gas).await,
             Self::Feemarket(f) => f.mk_fee(gas).await,
             GasFiller::OsmosisEip1559Feemarket(f) => f.mk_fee(gas).await,
+            GasFiller::Oracle(f) => f.mk_fee(gas).await,
+        }
+    }
+
+    fn denom(&self) -> &str {
+        match self {
+            Self::Fixed(f) => f.denom(),
+            Self::Feemarket(f) => f.denom(),
+            GasFiller::OsmosisEip1559Feemarket(f) => f.denom(),
+            GasFiller::Oracle(f) => f.denom(),
         }
     }
 }
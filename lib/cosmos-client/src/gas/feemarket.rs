@@ -1,10 +1,11 @@
+use gas_oracle::{GasOracle, GasOracleError, GasPrice};
 use num_rational::BigRational;
 use num_traits::ToPrimitive;
 use serde::{Deserialize, Serialize};
 use tracing::{debug, instrument};
 use unionlabs::cosmos::{base::coin::Coin, tx::fee::Fee};
 
-use crate::gas::{GasFillerT, u128_saturating_mul_f64};
+use crate::gas::{GasFillerError, GasFillerT, u128_saturating_mul_f64};
 
 #[derive(Debug)]
 pub struct GasFiller {
@@ -22,9 +23,8 @@ pub struct Config {
     pub denom: Option<String>,
 }
 
-#[allow(clippy::unwrap_used)] // TODO: Better error handling here
 impl GasFiller {
-    pub async fn new(config: Config) -> Result<Self, cometbft_rpc::JsonRpcError> {
+    pub async fn new(config: Config) -> Result<Self, GasFillerError> {
         let client = cometbft_rpc::Client::new(config.rpc_url).await?;
 
         let denom = match config.denom {
@@ -38,11 +38,9 @@ impl GasFiller {
                         false,
                     )
                     .await?
-                    .into_result()
-                    .unwrap()
-                    .unwrap()
-                    .params
-                    .unwrap()
+                    .into_result()?
+                    .and_then(|response| response.params)
+                    .ok_or(GasFillerError::EmptyResponse("feemarket params"))?
                     .fee_denom
             }
         };
@@ -55,7 +53,8 @@ impl GasFiller {
         })
     }
 
-    pub(crate) async fn get_gas_price(&self) -> Coin {
+    /// The current gas price, with an amount scaled by 10^18.
+    pub(crate) async fn get_gas_price(&self) -> Result<Coin, GasOracleError> {
         let response = self
             .client
             .grpc_abci_query::<_, protos::feemarket::feemarket::v1::GasPriceResponse>(
@@ -67,17 +66,29 @@ impl GasFiller {
                 false,
             )
             .await
-            .unwrap()
+            .map_err(GasOracleError::other)?
             .into_result()
-            .unwrap()
-            .unwrap()
-            .price
-            .unwrap();
+            .map_err(GasOracleError::other)?
+            .and_then(|response| response.price)
+            .ok_or_else(|| GasOracleError::other("empty gas price response"))?;
 
-        Coin {
+        Ok(Coin {
             denom: response.denom,
-            amount: response.amount.parse().unwrap(),
-        }
+            amount: response.amount.parse().map_err(GasOracleError::other)?,
+        })
+    }
+}
+
+impl GasOracle for GasFiller {
+    async fn gas_price(&self) -> Result<GasPrice, GasOracleError> {
+        let coin = self.get_gas_price().await?;
+
+        Ok(GasPrice {
+            base_fee: BigRational::new(coin.amount.into(), 10_u128.pow(18).into())
+                .to_f64()
+                .expect("gas price is a valid f64"),
+            priority_fee: 0.0,
+        })
     }
 }
 
@@ -86,6 +97,10 @@ impl GasFillerT for GasFiller {
         self.max_gas
     }
 
+    fn denom(&self) -> &str {
+        &self.denom
+    }
+
     #[instrument(
         skip_all,
         fields(
@@ -95,13 +110,13 @@ impl GasFillerT for GasFiller {
             gas = %gas,
         )
     )]
-    async fn mk_fee(&self, gas: u64) -> Fee {
+    async fn mk_fee(&self, gas: u64) -> Result<Fee, GasOracleError> {
         // gas limit = provided gas * multiplier, clamped between min_gas and max_gas
         let gas_limit = u64::try_from(u128_saturating_mul_f64(gas.into(), self.gas_multiplier))
             .unwrap_or(self.max_gas)
             .min(self.max_gas);
 
-        let coin = self.get_gas_price().await;
+        let coin = self.get_gas_price().await?;
 
         let one = 10_u128.pow(18);
         let gas_price = BigRational::new(coin.amount.into(), one.into());
@@ -118,7 +133,7 @@ impl GasFillerT for GasFiller {
 
         debug!(amount, "fee");
 
-        Fee {
+        Ok(Fee {
             amount: vec![Coin {
                 amount,
                 denom: coin.denom,
@@ -126,6 +141,6 @@ impl GasFillerT for GasFiller {
             gas_limit,
             payer: String::new(),
             granter: String::new(),
-        }
+        })
     }
 }
@@ -1,3 +1,4 @@
+use gas_oracle::GasOracleError;
 use serde::{Deserialize, Serialize};
 use unionlabs::cosmos::{base::coin::Coin, tx::fee::Fee};
 
@@ -21,14 +22,18 @@ impl GasFillerT for GasFiller {
         self.max_gas
     }
 
-    async fn mk_fee(&self, gas: u64) -> Fee {
+    fn denom(&self) -> &str {
+        &self.gas_denom
+    }
+
+    async fn mk_fee(&self, gas: u64) -> Result<Fee, GasOracleError> {
         // gas limit = provided gas * multiplier, clamped between min_gas and max_gas
         let gas_limit = u128_saturating_mul_f64(gas.into(), self.gas_multiplier)
             .clamp(self.min_gas.into(), self.max_gas.into());
 
         let amount = u128_saturating_mul_f64(gas.into(), self.gas_price);
 
-        Fee {
+        Ok(Fee {
             amount: vec![Coin {
                 amount,
                 denom: self.gas_denom.clone(),
@@ -36,6 +41,6 @@ impl GasFillerT for GasFiller {
             gas_limit: gas_limit.try_into().unwrap_or(u64::MAX),
             payer: String::new(),
             granter: String::new(),
-        }
+        })
     }
 }
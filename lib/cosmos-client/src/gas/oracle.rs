@@ -0,0 +1,174 @@
+use gas_oracle::{GasOracle, GasOracleConfig, GasOracleError, GasOracleStack, GasPrice};
+use num_rational::BigRational;
+use num_traits::FromPrimitive;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, instrument};
+use unionlabs::cosmos::{base::coin::Coin, tx::fee::Fee};
+
+use crate::gas::{
+    GasFillerError, GasFillerT, feemarket, osmosis_eip1559_feemarket, u128_saturating_mul_f64,
+};
+
+/// A gas filler that prices gas with a [`GasOracleStack`], such that the gas price can be taken
+/// from multiple sources.
+#[derive(Debug)]
+pub struct GasFiller {
+    max_gas: u64,
+    gas_multiplier: f64,
+    denom: String,
+    oracle: GasOracleStack<Source>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Config {
+    pub max_gas: u64,
+    #[serde(with = "::serde_utils::string_opt", default)]
+    pub gas_multiplier: Option<f64>,
+    /// The denom that fees are paid in.
+    pub denom: String,
+    pub oracle: GasOracleConfig<SourceConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum SourceConfig {
+    /// A fixed gas price.
+    Fixed {
+        #[serde(with = "::serde_utils::string")]
+        gas_price: f64,
+    },
+    /// The gas price of the `x/feemarket` module.
+    Feemarket {
+        /// Defaults to the rpc url of the client.
+        #[serde(default)]
+        rpc_url: Option<String>,
+    },
+    /// The eip-1559 base fee of the osmosis `x/txfees` module.
+    OsmosisEip1559Feemarket {
+        /// Defaults to the rpc url of the client.
+        #[serde(default)]
+        rpc_url: Option<String>,
+        #[serde(with = "::serde_utils::string_opt", default)]
+        base_fee_multiplier: Option<f64>,
+    },
+}
+
+#[derive(Debug)]
+pub enum Source {
+    Fixed(GasPrice),
+    Feemarket(feemarket::GasFiller),
+    OsmosisEip1559Feemarket(osmosis_eip1559_feemarket::GasFiller),
+}
+
+impl Source {
+    pub async fn new(
+        config: SourceConfig,
+        rpc_url: &str,
+        denom: &str,
+    ) -> Result<Self, GasFillerError> {
+        Ok(match config {
+            SourceConfig::Fixed { gas_price } => Self::Fixed(GasPrice {
+                base_fee: gas_price,
+                priority_fee: 0.0,
+            }),
+            SourceConfig::Feemarket {
+                rpc_url: source_rpc_url,
+            } => Self::Feemarket(
+                feemarket::GasFiller::new(feemarket::Config {
+                    rpc_url: source_rpc_url.unwrap_or_else(|| rpc_url.to_owned()),
+                    max_gas: 0,
+                    gas_multiplier: None,
+                    denom: Some(denom.to_owned()),
+                })
+                .await?,
+            ),
+            SourceConfig::OsmosisEip1559Feemarket {
+                rpc_url: source_rpc_url,
+                base_fee_multiplier,
+            } => Self::OsmosisEip1559Feemarket(
+                osmosis_eip1559_feemarket::GasFiller::new(osmosis_eip1559_feemarket::Config {
+                    rpc_url: source_rpc_url.unwrap_or_else(|| rpc_url.to_owned()),
+                    max_gas: 0,
+                    gas_multiplier: None,
+                    base_fee_multiplier,
+                    denom: Some(denom.to_owned()),
+                })
+                .await?,
+            ),
+        })
+    }
+}
+
+impl GasOracle for Source {
+    async fn gas_price(&self) -> Result<GasPrice, GasOracleError> {
+        match self {
+            Self::Fixed(gas_price) => gas_price.gas_price().await,
+            Self::Feemarket(f) => f.gas_price().await,
+            Self::OsmosisEip1559Feemarket(f) => f.gas_price().await,
+        }
+    }
+}
+
+impl GasFiller {
+    /// Build the gas filler, using `rpc_url` for any sources that do not specify their own.
+    pub async fn new(config: Config, rpc_url: &str) -> Result<Self, GasFillerError> {
+        let mut sources = vec![];
+        for source in config.oracle.sources.clone() {
+            sources.push(Source::new(source, rpc_url, &config.denom).await?);
+        }
+
+        Ok(Self {
+            max_gas: config.max_gas,
+            gas_multiplier: config.gas_multiplier.unwrap_or(1.0),
+            oracle: GasOracleStack::from_config(&config.oracle, sources),
+            denom: config.denom,
+        })
+    }
+}
+
+impl GasFillerT for GasFiller {
+    async fn max_gas(&self) -> u64 {
+        self.max_gas
+    }
+
+    fn denom(&self) -> &str {
+        &self.denom
+    }
+
+    #[instrument(
+        skip_all,
+        fields(
+            self.max_gas = %self.max_gas,
+            self.gas_multiplier = %self.gas_multiplier,
+            self.denom = %self.denom,
+            gas = %gas,
+        )
+    )]
+    async fn mk_fee(&self, gas: u64) -> Result<Fee, GasOracleError> {
+        // gas limit = provided gas * multiplier, clamped to max_gas
+        let gas_limit = u64::try_from(u128_saturating_mul_f64(gas.into(), self.gas_multiplier))
+            .unwrap_or(self.max_gas)
+            .min(self.max_gas);
+
+        let gas_price = self.oracle.gas_price().await?;
+
+        debug!(gas_price = gas_price.total(), "gas price");
+
+        let amount = BigRational::from_integer(gas_limit.into())
+            * BigRational::from_f64(gas_price.total()).expect("gas price is rational");
+
+        let amount = amount.ceil().to_integer().try_into().unwrap_or(u128::MAX);
+
+        debug!(amount, "fee");
+
+        Ok(Fee {
+            amount: vec![Coin {
+                amount,
+                denom: self.denom.clone(),
+            }],
+            gas_limit,
+            payer: String::new(),
+            granter: String::new(),
+        })
+    }
+}
@@ -1,10 +1,11 @@
+use gas_oracle::{GasOracle, GasOracleError, GasPrice};
 use num_rational::BigRational;
 use num_traits::{FromPrimitive, ToPrimitive};
 use serde::{Deserialize, Serialize};
 use tracing::{debug, instrument};
 use unionlabs::cosmos::{base::coin::Coin, tx::fee::Fee};
 
-use crate::gas::{GasFillerT, u128_saturating_mul_f64};
+use crate::gas::{GasFillerError, GasFillerT, u128_saturating_mul_f64};
 
 #[derive(Debug)]
 pub struct GasFiller {
@@ -24,9 +25,8 @@ pub struct Config {
     pub denom: Option<String>,
 }
 
-#[allow(clippy::unwrap_used)] // TODO: Better error handling here
 impl GasFiller {
-    pub async fn new(config: Config) -> Result<Self, cometbft_rpc::JsonRpcError> {
+    pub async fn new(config: Config) -> Result<Self, GasFillerError> {
         let client = cometbft_rpc::Client::new(config.rpc_url).await?;
 
         let denom =
@@ -40,9 +40,8 @@ impl GasFiller {
                         false,
                     )
                     .await?
-                    .into_result()
-                    .unwrap()
-                    .unwrap()
+                    .into_result()?
+                    .ok_or(GasFillerError::EmptyResponse("txfees base denom"))?
                     .base_denom,
             };
 
@@ -57,8 +56,8 @@ impl GasFiller {
         })
     }
 
-    pub(crate) async fn get_base_fee(&self) -> BigRational {
-        let raw_base_fee = &self
+    pub(crate) async fn get_base_fee(&self) -> Result<BigRational, GasOracleError> {
+        let raw_base_fee = self
             .client
             .grpc_abci_query::<_, protos::osmosis::txfees::v1beta1::QueryEipBaseFeeResponse>(
                 "/osmosis.txfees.v1beta1.Query/GetEipBaseFee",
@@ -67,17 +66,32 @@ impl GasFiller {
                 false,
             )
             .await
-            .unwrap()
+            .map_err(GasOracleError::other)?
             .into_result()
-            .unwrap()
-            .unwrap()
+            .map_err(GasOracleError::other)?
+            .ok_or_else(|| GasOracleError::other("empty base fee response"))?
             .base_fee;
 
-        let denominator: u128 = raw_base_fee.parse().unwrap();
+        let denominator: u128 = raw_base_fee.parse().map_err(GasOracleError::other)?;
 
         let one = 10_u128.pow(18);
 
-        BigRational::new(denominator.into(), one.into())
+        Ok(BigRational::new(denominator.into(), one.into()))
+    }
+}
+
+impl GasOracle for GasFiller {
+    async fn gas_price(&self) -> Result<GasPrice, GasOracleError> {
+        let base_fee = self.get_base_fee().await?;
+
+        Ok(GasPrice {
+            base_fee: (BigRational::from_f64(self.base_fee_multiplier)
+                .expect("base fee multiplier is rational")
+                * base_fee)
+                .to_f64()
+                .expect("should be a valid f64"),
+            priority_fee: 0.0,
+        })
     }
 }
 
@@ -86,6 +100,10 @@ impl GasFillerT for GasFiller {
         self.max_gas
     }
 
+    fn denom(&self) -> &str {
+        &self.denom
+    }
+
     #[instrument(
         skip_all,
         fields(
@@ -96,13 +114,13 @@ impl GasFillerT for GasFiller {
             gas = %gas,
         )
     )]
-    async fn mk_fee(&self, gas: u64) -> Fee {
+    async fn mk_fee(&self, gas: u64) -> Result<Fee, GasOracleError> {
         // gas limit = provided gas * multiplier, clamped between min_gas and max_gas
         let gas_limit = u64::try_from(u128_saturating_mul_f64(gas.into(), self.gas_multiplier))
             .unwrap_or(self.max_gas)
             .min(self.max_gas);
 
-        let base_fee = self.get_base_fee().await;
+        let base_fee = self.get_base_fee().await?;
 
         let gas_price = BigRational::from_f64(self.base_fee_multiplier)
             .expect("base fee multiplier is rational")
@@ -120,7 +138,7 @@ impl GasFillerT for GasFiller {
 
         debug!(amount, "fee");
 
-        Fee {
+        Ok(Fee {
             amount: vec![Coin {
                 amount,
                 denom: self.denom.clone(),
@@ -128,6 +146,6 @@ impl GasFillerT for GasFiller {
             gas_limit,
             payer: String::new(),
             granter: String::new(),
-        }
+        })
     }
 }
@@ -8,6 +8,7 @@ use cometbft_rpc::{
     rpc_types::{GrpcAbciQueryError, TxResponse},
     types::code::Code,
 };
+use gas_oracle::GasOracleError;
 use protos::cosmos::base::abci;
 use sha2::Digest;
 use tracing::{debug, info, instrument};
@@ -29,7 +30,11 @@ use unionlabs::{
     prost::{self, Message},
 };
 
-use crate::{gas::GasFillerT, rpc::RpcT, wallet::WalletT};
+use crate::{
+    gas::GasFillerT,
+    rpc::RpcT,
+    wallet::{SignError, WalletT},
+};
 
 pub mod gas;
 pub mod rpc;
@@ -39,11 +44,26 @@ pub struct TxClient<W, Q, G> {
     wallet: W,
     rpc: Q,
     gas: G,
+    account: Option<BaseAccount>,
 }
 
 impl<W, Q, G> TxClient<W, Q, G> {
     pub fn new(wallet: W, rpc: Q, gas: G) -> Self {
-        Self { wallet, rpc, gas }
+        Self {
+            wallet,
+            rpc,
+            gas,
+            account: None,
+        }
+    }
+
+    /// Sign all transactions with the account number and sequence of `account`, instead of
+    /// querying the account before every transaction. This is used to assign sequences locally,
+    /// which allows for multiple in-flight transactions per signer.
+    #[must_use]
+    pub fn with_account(mut self, account: BaseAccount) -> Self {
+        self.account = Some(account);
+        self
     }
 
     pub fn wallet(&self) -> &W {
@@ -109,49 +129,56 @@ impl<W: WalletT, Q: RpcT, G: GasFillerT> TxClient<W, Q, G> {
         memo: impl AsRef<str>,
         simulate: bool,
     ) -> Result<TxResponse, BroadcastTxCommitError> {
-        let account = self
-            .account_info(self.wallet.address())
-            .await?
-            .unwrap_or_default();
+        let gas_used = if simulate {
+            let (_, _, simulation_gas_info) =
+                self.simulate_tx(messages.clone(), memo.as_ref()).await?;
 
-        let (tx_body, mut auth_info, simulation_gas_info) = if simulate {
-            self.simulate_tx(messages, memo).await?
+            info!(
+                gas_used = %simulation_gas_info.gas_used,
+                gas_wanted = %simulation_gas_info.gas_wanted,
+                "tx simulation successful"
+            );
+
+            simulation_gas_info.gas_used
         } else {
-            let (tx_body, auth_info) = self.tx_info(messages, memo, &account).await;
-
-            (
-                tx_body,
-                auth_info,
-                GasInfo {
-                    gas_wanted: self.gas.max_gas().await,
-                    gas_used: self.gas.max_gas().await,
-                },
-            )
+            self.gas.max_gas().await
         };
 
-        info!(
-            gas_used = %simulation_gas_info.gas_used,
-            gas_wanted = %simulation_gas_info.gas_wanted,
-            "tx simulation successful"
-        );
+        self.broadcast_tx_commit_with_gas(messages, memo, gas_used)
+            .await
+    }
+
+    /// Same as [`Self::broadcast_tx_commit`], but with the gas used by the tx already known (i.e.
+    /// from a previous simulation of the same messages), such that the tx isn't simulated again.
+    #[instrument(skip_all, fields(memo = %memo.as_ref(), %gas_used))]
+    pub async fn broadcast_tx_commit_with_gas(
+        &self,
+        messages: impl IntoIterator<Item: Into<RawAny>> + Clone,
+        memo: impl AsRef<str>,
+        gas_used: u64,
+    ) -> Result<TxResponse, BroadcastTxCommitError> {
+        let account = self.account().await?;
 
-        auth_info.fee = self.gas.mk_fee(simulation_gas_info.gas_used).await;
+        let (tx_body, auth_info) = self.tx_info(messages, memo, &account, gas_used).await?;
 
         info!(
             fee = %auth_info.fee.amount[0].amount,
             "submitting transaction with gas"
         );
 
-        // re-sign the new auth info with the simulated gas
-        let signature = self.wallet.sign(
-            &SignDoc {
-                body_bytes: tx_body.clone().encode_as::<Proto>(),
-                auth_info_bytes: auth_info.clone().encode_as::<Proto>(),
-                chain_id: self.rpc.chain_id().to_string(),
-                account_number: account.account_number,
-            }
-            .encode_as::<Proto>(),
-        );
+        // sign the auth info with the fee for the gas used
+        let signature = self
+            .wallet
+            .sign(
+                &SignDoc {
+                    body_bytes: tx_body.clone().encode_as::<Proto>(),
+                    auth_info_bytes: auth_info.clone().encode_as::<Proto>(),
+                    chain_id: self.rpc.chain_id().to_string(),
+                    account_number: account.account_number,
+                }
+                .encode_as::<Proto>(),
+            )
+            .await?;
 
         let tx_raw_bytes = TxRaw {
             body_bytes: tx_body.clone().encode_as::<Proto>(),
@@ -186,6 +213,7 @@ impl<W: WalletT, Q: RpcT, G: GasFillerT> TxClient<W, Q, G> {
                 codespace: response.codespace,
                 error_code,
                 log: response.log,
+                included: None,
             });
         };
 
@@ -209,9 +237,10 @@ impl<W: WalletT, Q: RpcT, G: GasFillerT> TxClient<W, Q, G> {
                     Code::Ok => break Ok(tx),
                     Code::Err(error_code) => {
                         return Err(BroadcastTxCommitError::TxFailed {
-                            codespace: response.codespace,
+                            codespace: tx.tx_result.codespace.clone(),
                             error_code,
-                            log: response.log,
+                            log: tx.tx_result.log.clone(),
+                            included: Some(Box::new(tx)),
                         });
                     }
                 },
@@ -239,22 +268,24 @@ impl<W: WalletT, Q: RpcT, G: GasFillerT> TxClient<W, Q, G> {
     ) -> Result<(TxBody, AuthInfo, GasInfo), BroadcastTxCommitError> {
         use protos::cosmos::tx;
 
-        let account = self
-            .account_info(self.wallet.address())
-            .await?
-            .unwrap_or_default();
+        let account = self.account().await?;
 
-        let (tx_body, auth_info) = self.tx_info(messages, memo, &account).await;
+        let (tx_body, auth_info) = self
+            .tx_info(messages, memo, &account, self.gas.max_gas().await)
+            .await?;
 
-        let simulation_signature = self.wallet.sign(
-            &SignDoc {
-                body_bytes: tx_body.clone().encode_as::<Proto>(),
-                auth_info_bytes: auth_info.clone().encode_as::<Proto>(),
-                chain_id: self.rpc.chain_id().to_string(),
-                account_number: account.account_number,
-            }
-            .encode_as::<Proto>(),
-        );
+        let simulation_signature = self
+            .wallet
+            .sign(
+                &SignDoc {
+                    body_bytes: tx_body.clone().encode_as::<Proto>(),
+                    auth_info_bytes: auth_info.clone().encode_as::<Proto>(),
+                    chain_id: self.rpc.chain_id().to_string(),
+                    account_number: account.account_number,
+                }
+                .encode_as::<Proto>(),
+            )
+            .await?;
 
         let simulate_response = self
             .rpc
@@ -289,7 +320,8 @@ impl<W: WalletT, Q: RpcT, G: GasFillerT> TxClient<W, Q, G> {
         messages: impl IntoIterator<Item: Into<RawAny>> + Clone,
         memo: impl AsRef<str>,
         account: &BaseAccount,
-    ) -> (TxBody, AuthInfo) {
+        gas: u64,
+    ) -> Result<(TxBody, AuthInfo), GasOracleError> {
         let tx_body = TxBody {
             // TODO: Use RawAny here
             messages: messages.clone().into_iter().map(Into::into).collect(),
@@ -312,10 +344,22 @@ impl<W: WalletT, Q: RpcT, G: GasFillerT> TxClient<W, Q, G> {
                 sequence: account.sequence,
             }]
             .to_vec(),
-            fee: self.gas.mk_fee(self.gas.max_gas().await).await,
+            fee: self.gas.mk_fee(gas).await?,
         };
 
-        (tx_body, auth_info)
+        Ok((tx_body, auth_info))
+    }
+
+    /// The account to sign with, either the one set with [`TxClient::with_account`] or the current
+    /// state of the account on chain.
+    async fn account(&self) -> Result<BaseAccount, BroadcastTxCommitError> {
+        match &self.account {
+            Some(account) => Ok(account.clone()),
+            None => Ok(self
+                .account_info(self.wallet.address())
+                .await?
+                .unwrap_or_default()),
+        }
     }
 
     pub async fn account_info<T: Clone + AsRef<[u8]>>(
@@ -370,11 +414,18 @@ pub enum BroadcastTxCommitError {
     Query(#[from] GrpcAbciQueryError),
     #[error("error decoding account")]
     AccountDecode(#[from] TryFromAnyError<BaseAccount>),
+    #[error("error signing tx")]
+    Sign(#[from] SignError),
+    #[error("error pricing gas")]
+    GasPrice(#[from] GasOracleError),
     #[error("tx failed: code={error_code}, codespace={codespace}, log={log}")]
     TxFailed {
         codespace: String,
         error_code: NonZeroU32,
         log: String,
+        /// The tx, if it was included in a block but failed to execute. If this is `None`, the tx
+        /// was rejected before it was included.
+        included: Option<Box<TxResponse>>,
     },
     #[error("tx inclusion couldn't be retrieved after {attempts} attempt(s) (tx hash: {tx_hash})")]
     Inclusion {
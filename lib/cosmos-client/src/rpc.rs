@@ -1,3 +1,5 @@
+use rpc_failover::FailoverConfig;
+
 pub trait RpcT {
     fn client(&self) -> &cometbft_rpc::Client;
 
@@ -13,7 +15,14 @@ pub struct Rpc {
 
 impl Rpc {
     pub async fn new(rpc_url: String) -> Result<Self, cometbft_rpc::JsonRpcError> {
-        let client = cometbft_rpc::Client::new(rpc_url).await?;
+        Self::new_with_failover(rpc_url, &FailoverConfig::default()).await
+    }
+
+    pub async fn new_with_failover(
+        rpc_url: String,
+        config: &FailoverConfig,
+    ) -> Result<Self, cometbft_rpc::JsonRpcError> {
+        let client = cometbft_rpc::Client::new_with_failover(rpc_url, config).await?;
 
         let chain_id = client.status().await?.node_info.network;
 
@@ -1,4 +1,9 @@
+use concurrent_keyring::{
+    KeyringConfigEntry,
+    remote::{RemoteSigner, RemoteSignerError},
+};
 use cosmos_signer::CosmosSigner;
+use sha2::Digest;
 use unionlabs::primitives::{Bech32, FixedBytes, H160, H256, H512};
 
 pub trait WalletT {
@@ -6,7 +11,17 @@ pub trait WalletT {
 
     fn public_key(&self) -> FixedBytes<33>;
 
-    fn sign(&self, bz: &[u8]) -> H512;
+    async fn sign(&self, bz: &[u8]) -> Result<H512, SignError>;
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SignError {
+    #[error("remote signer error")]
+    Remote(#[from] RemoteSignerError),
+    #[error(
+        "remote signer returned an invalid public key (expected a 33 byte compressed secp256k1 key, found {0} bytes)"
+    )]
+    InvalidPublicKey(usize),
 }
 
 #[derive(Debug)]
@@ -35,12 +50,100 @@ impl WalletT for LocalSigner {
         self.signer.public_key()
     }
 
-    fn sign(&self, bz: &[u8]) -> H512 {
-        self.signer
+    async fn sign(&self, bz: &[u8]) -> Result<H512, SignError> {
+        Ok(self
+            .signer
             .try_sign(bz)
             .expect("infallible")
             .to_bytes()
-            .into()
+            .into())
+    }
+}
+
+/// A secp256k1 key held by a [`RemoteSigner`].
+#[derive(Debug)]
+pub struct RemoteWallet {
+    signer: RemoteSigner,
+    address: Bech32<H160>,
+    public_key: FixedBytes<33>,
+}
+
+impl RemoteWallet {
+    /// Fetch the public key of `signer` and derive its address with `prefix`.
+    pub async fn new(signer: RemoteSigner, prefix: impl Into<String>) -> Result<Self, SignError> {
+        let public_key = signer.public_key().await?;
+
+        let public_key = FixedBytes::<33>::try_from(public_key.as_slice())
+            .map_err(|_| SignError::InvalidPublicKey(public_key.len()))?;
+
+        Ok(Self {
+            address: cosmos_signer::address(prefix.into(), public_key.get()),
+            signer,
+            public_key,
+        })
+    }
+}
+
+impl WalletT for RemoteWallet {
+    fn address(&self) -> Bech32<H160> {
+        self.address.clone()
+    }
+
+    fn public_key(&self) -> FixedBytes<33> {
+        self.public_key
+    }
+
+    async fn sign(&self, bz: &[u8]) -> Result<H512, SignError> {
+        let signature = self.signer.sign(&sha2::Sha256::digest(bz)).await?;
+
+        // the recovery id (if any) is not part of cosmos signatures
+        Ok(H512::try_from(&signature[..64]).expect("signature is at least 64 bytes; qed;"))
+    }
+}
+
+/// Either a [`LocalSigner`] or a [`RemoteWallet`], for keyrings that can contain both.
+#[derive(Debug)]
+pub enum Wallet {
+    Local(LocalSigner),
+    Remote(RemoteWallet),
+}
+
+impl Wallet {
+    /// Build the wallet for a keyring entry. Remote entries are queried for their public key.
+    pub async fn from_config(
+        entry: &KeyringConfigEntry,
+        prefix: impl Into<String>,
+    ) -> Result<Self, SignError> {
+        match entry.remote() {
+            Some(remote) => Ok(Self::Remote(RemoteWallet::new(remote, prefix).await?)),
+            None => Ok(Self::Local(LocalSigner::new(
+                entry.value().try_into().expect("invalid private key"),
+                prefix,
+            ))),
+        }
+    }
+}
+
+impl WalletT for Wallet {
+    fn address(&self) -> Bech32<H160> {
+        match self {
+            Wallet::Local(wallet) => wallet.address(),
+            Wallet::Remote(wallet) => wallet.address(),
+        }
+    }
+
+    fn public_key(&self) -> FixedBytes<33> {
+        match self {
+            Wallet::Local(wallet) => wallet.public_key(),
+            Wallet::Remote(wallet) => wallet.public_key(),
+        }
+    }
+
+    async fn sign(&self, bz: &[u8]) -> Result<H512, SignError> {
+        match self {
+            Wallet::Local(wallet) => wallet.sign(bz).await,
+            Wallet::Remote(wallet) => wallet.sign(bz).await,
+        }
     }
 }
 
@@ -53,7 +156,7 @@ impl<T: WalletT> WalletT for &T {
         (*self).public_key()
     }
 
-    fn sign(&self, bz: &[u8]) -> H512 {
-        (*self).sign(bz)
+    async fn sign(&self, bz: &[u8]) -> Result<H512, SignError> {
+        (*self).sign(bz).await
     }
 }
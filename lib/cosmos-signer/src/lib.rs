@@ -57,17 +57,9 @@ impl CosmosSigner {
     #[must_use]
     pub fn address(&self) -> Bech32<H160> {
         // TODO: benchmark this, and consider caching it in the struct
-        // bech32(prefix, ripemd(sha256(pubkey)))
-        Bech32::new(
+        address(
             self.prefix.clone(),
-            ripemd::Ripemd160::new()
-                .chain_update(
-                    sha2::Sha256::new()
-                        .chain_update(self.signing_key.public_key().to_bytes())
-                        .finalize(),
-                )
-                .finalize()
-                .into(),
+            &self.signing_key.public_key().to_bytes(),
         )
     }
 
@@ -77,6 +69,18 @@ impl CosmosSigner {
     }
 }
 
+/// The address of a secp256k1 public key, i.e. `bech32(prefix, ripemd(sha256(pubkey)))`.
+#[must_use]
+pub fn address(prefix: String, public_key: &[u8]) -> Bech32<H160> {
+    Bech32::new(
+        prefix,
+        ripemd::Ripemd160::new()
+            .chain_update(sha2::Sha256::new().chain_update(public_key).finalize())
+            .finalize()
+            .into(),
+    )
+}
+
 impl Display for CosmosSigner {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(f, "{}", self.address())
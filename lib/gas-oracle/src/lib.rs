@@ -0,0 +1,308 @@
+//! Chain-agnostic gas price oracles.
+//!
+//! A [`GasOracle`] is a single source of gas prices, i.e. an rpc endpoint or a fixed price.
+//! [`GasOracleStack`] combines multiple sources into one, taking the median of the sources that
+//! respond, caching the result, and refusing to return a price above a hard ceiling. Chain specific
+//! sources are implemented next to the clients that use them.
+
+#![allow(async_fn_in_trait)]
+
+use std::{
+    error::Error,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+use unionlabs::ErrorReporter;
+
+pub type BoxDynError = Box<dyn Error + Send + Sync + 'static>;
+
+/// The price of one unit of gas, denominated in the smallest unit of the fee token of the chain.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GasPrice {
+    /// The price that is required for a transaction to be included, i.e. the base fee on chains
+    /// with a fee market.
+    #[serde(with = "::serde_utils::string")]
+    pub base_fee: f64,
+    /// The additional price paid to the block producer, on chains that support priority fees.
+    #[serde(with = "::serde_utils::string", default)]
+    pub priority_fee: f64,
+}
+
+impl GasPrice {
+    /// The total price paid per unit of gas.
+    pub fn total(&self) -> f64 {
+        self.base_fee + self.priority_fee
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum GasOracleError {
+    #[error("gas price {price} is above the ceiling of {max}")]
+    TooHigh { price: f64, max: f64 },
+    #[error("no gas price sources are configured")]
+    NoSources,
+    #[error("all {} gas price sources failed", .0.len())]
+    AllSourcesFailed(Vec<GasOracleError>),
+    #[error("error fetching gas price")]
+    Source(#[source] BoxDynError),
+}
+
+impl GasOracleError {
+    pub fn other(err: impl Into<BoxDynError>) -> Self {
+        Self::Source(err.into())
+    }
+}
+
+pub trait GasOracle {
+    async fn gas_price(&self) -> Result<GasPrice, GasOracleError>;
+}
+
+impl GasOracle for GasPrice {
+    async fn gas_price(&self) -> Result<GasPrice, GasOracleError> {
+        Ok(*self)
+    }
+}
+
+impl<T: GasOracle> GasOracle for &T {
+    async fn gas_price(&self) -> Result<GasPrice, GasOracleError> {
+        (*self).gas_price().await
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GasOracleConfig<S> {
+    /// The sources that the gas price is fetched from. If more than one source is configured, all
+    /// of them are queried and the median price is used, such that a single unavailable or
+    /// misbehaving source does not affect the gas price.
+    pub sources: Vec<S>,
+
+    /// How long a fetched gas price is reused for.
+    #[serde(default)]
+    pub cache_seconds: u64,
+
+    /// The maximum total price per unit of gas. No transactions are sent while the gas price is
+    /// above this ceiling.
+    #[serde(with = "::serde_utils::string_opt", default)]
+    pub max_gas_price: Option<f64>,
+}
+
+#[derive(Debug)]
+pub struct GasOracleStack<O> {
+    sources: Vec<O>,
+    cache_duration: Duration,
+    max_gas_price: Option<f64>,
+    cache: Mutex<Option<(Instant, GasPrice)>>,
+}
+
+impl<O: GasOracle> GasOracleStack<O> {
+    pub fn new(sources: Vec<O>, cache_duration: Duration, max_gas_price: Option<f64>) -> Self {
+        Self {
+            sources,
+            cache_duration,
+            max_gas_price,
+            cache: Mutex::new(None),
+        }
+    }
+
+    /// Build the stack from a config, where `sources` are the sources built from
+    /// [`GasOracleConfig::sources`].
+    pub fn from_config<S>(config: &GasOracleConfig<S>, sources: Vec<O>) -> Self {
+        Self::new(
+            sources,
+            Duration::from_secs(config.cache_seconds),
+            config.max_gas_price,
+        )
+    }
+
+    pub fn max_gas_price(&self) -> Option<f64> {
+        self.max_gas_price
+    }
+
+    fn cached(&self) -> Option<GasPrice> {
+        self.cache
+            .lock()
+            .expect("mutex is not poisoned; qed;")
+            .filter(|(fetched_at, _)| fetched_at.elapsed() < self.cache_duration)
+            .map(|(_, gas_price)| gas_price)
+    }
+
+    async fn fetch(&self) -> Result<GasPrice, GasOracleError> {
+        let results =
+            futures::future::join_all(self.sources.iter().map(|source| source.gas_price())).await;
+
+        let mut prices = vec![];
+        let mut errors = vec![];
+
+        for (idx, result) in results.into_iter().enumerate() {
+            match result {
+                Ok(gas_price) => prices.push(gas_price),
+                Err(err) => {
+                    warn!(
+                        source = idx,
+                        err = %ErrorReporter(&*err),
+                        "error fetching gas price"
+                    );
+                    errors.push(err);
+                }
+            }
+        }
+
+        if prices.is_empty() {
+            return Err(if errors.is_empty() {
+                GasOracleError::NoSources
+            } else {
+                GasOracleError::AllSourcesFailed(errors)
+            });
+        }
+
+        let gas_price = GasPrice {
+            base_fee: median(prices.iter().map(|gas_price| gas_price.base_fee)),
+            priority_fee: median(prices.iter().map(|gas_price| gas_price.priority_fee)),
+        };
+
+        debug!(?prices, ?gas_price, "fetched gas price");
+
+        *self.cache.lock().expect("mutex is not poisoned; qed;") =
+            Some((Instant::now(), gas_price));
+
+        Ok(gas_price)
+    }
+}
+
+impl<O: GasOracle> GasOracle for GasOracleStack<O> {
+    async fn gas_price(&self) -> Result<GasPrice, GasOracleError> {
+        let gas_price = match self.cached() {
+            Some(gas_price) => gas_price,
+            None => self.fetch().await?,
+        };
+
+        match self.max_gas_price {
+            Some(max) if gas_price.total() > max => Err(GasOracleError::TooHigh {
+                price: gas_price.total(),
+                max,
+            }),
+            _ => Ok(gas_price),
+        }
+    }
+}
+
+/// The median of `values`, or 0 if there are none. The mean of the two middle values is used for
+/// an even amount of values.
+pub fn median(values: impl IntoIterator<Item = f64>) -> f64 {
+    let mut values = values.into_iter().collect::<Vec<_>>();
+
+    values.sort_by(f64::total_cmp);
+
+    let mid = values.len() / 2;
+
+    if values.is_empty() {
+        0.0
+    } else if values.len() & 1 == 0 {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    struct Source {
+        gas_price: Option<GasPrice>,
+        calls: AtomicUsize,
+    }
+
+    impl Source {
+        fn new(base_fee: f64, priority_fee: f64) -> Self {
+            Self {
+                gas_price: Some(GasPrice {
+                    base_fee,
+                    priority_fee,
+                }),
+                calls: AtomicUsize::new(0),
+            }
+        }
+
+        fn failing() -> Self {
+            Self {
+                gas_price: None,
+                calls: AtomicUsize::new(0),
+            }
+        }
+    }
+
+    impl GasOracle for Source {
+        async fn gas_price(&self) -> Result<GasPrice, GasOracleError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+
+            self.gas_price
+                .ok_or_else(|| GasOracleError::other("source is unavailable"))
+        }
+    }
+
+    #[tokio::test]
+    async fn median_of_sources() {
+        let stack = GasOracleStack::new(
+            vec![
+                Source::new(10.0, 1.0),
+                Source::failing(),
+                Source::new(1000.0, 3.0),
+                Source::new(12.0, 2.0),
+            ],
+            Duration::ZERO,
+            None,
+        );
+
+        assert_eq!(
+            stack.gas_price().await.unwrap(),
+            GasPrice {
+                base_fee: 12.0,
+                priority_fee: 2.0
+            }
+        );
+
+        let stack = GasOracleStack::new(
+            vec![Source::failing(), Source::failing()],
+            Duration::ZERO,
+            None,
+        );
+
+        assert!(matches!(
+            stack.gas_price().await,
+            Err(GasOracleError::AllSourcesFailed(errors)) if errors.len() == 2
+        ));
+    }
+
+    #[tokio::test]
+    async fn cache_and_ceiling() {
+        let stack = GasOracleStack::new(
+            vec![Source::new(10.0, 1.0)],
+            Duration::from_secs(60),
+            Some(10.0),
+        );
+
+        assert!(matches!(
+            stack.gas_price().await,
+            Err(GasOracleError::TooHigh { price, max }) if price == 11.0 && max == 10.0
+        ));
+        assert!(stack.gas_price().await.is_err());
+
+        // the price is only fetched once
+        assert_eq!(stack.sources[0].calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn median() {
+        assert_eq!(super::median([]), 0.0);
+        assert_eq!(super::median([3.0, 1.0, 2.0]), 2.0);
+        assert_eq!(super::median([4.0, 1.0, 2.0, 3.0]), 2.5);
+    }
+}
@@ -51,6 +51,10 @@ maybe_sol_attr! {
                 MsgUpdateClient calldata msg_
             ) external;
 
+            function misbehaviour(
+                MsgMisbehaviour calldata msg_
+            ) external;
+
             // CONNECTION
 
             function connectionOpenInit(
@@ -450,6 +454,12 @@ maybe_sol_attr! {
             address relayer;
         }
 
+        struct MsgMisbehaviour {
+            uint32 client_id;
+            bytes client_message;
+            address relayer;
+        }
+
         struct MsgForceUpdateClient {
             uint32 clientId;
             bytes clientStateBytes;
@@ -18,6 +18,7 @@ use crate::{
 pub enum Datagram {
     CreateClient(MsgCreateClient),
     UpdateClient(MsgUpdateClient),
+    Misbehaviour(MsgMisbehaviour),
     ConnectionOpenInit(MsgConnectionOpenInit),
     ConnectionOpenTry(MsgConnectionOpenTry),
     ConnectionOpenAck(MsgConnectionOpenAck),
@@ -46,6 +47,7 @@ impl Datagram {
         match self {
             Self::CreateClient(_) => None,
             Self::UpdateClient(_) => None,
+            Self::Misbehaviour(_) => None,
             Self::ConnectionOpenInit(_) => None,
             Self::ConnectionOpenTry(msg) => Some(Height::new(msg.proof_height)),
             Self::ConnectionOpenAck(msg) => Some(Height::new(msg.proof_height)),
@@ -72,6 +74,7 @@ impl Datagram {
         match self {
             Self::CreateClient(_) => "create_client",
             Self::UpdateClient(_) => "update_client",
+            Self::Misbehaviour(_) => "misbehaviour",
             Self::ConnectionOpenInit(_) => "connection_open_init",
             Self::ConnectionOpenTry(_) => "connection_open_try",
             Self::ConnectionOpenAck(_) => "connection_open_ack",
@@ -93,6 +96,34 @@ impl Datagram {
             Self::CommitPacketTimeout(_) => "commit_packet_timeout",
         }
     }
+
+    /// Returns the packets that this datagram operates on, if any.
+    pub fn packets(&self) -> &[Packet] {
+        match self {
+            Self::PacketRecv(msg) => &msg.packets,
+            Self::PacketAcknowledgement(msg) => &msg.packets,
+            Self::PacketTimeout(msg) => core::slice::from_ref(&msg.packet),
+            Self::IntentPacketRecv(msg) => &msg.packets,
+            Self::BatchSend(msg) => &msg.packets,
+            Self::BatchAcks(msg) => &msg.packets,
+            Self::CommitPacketTimeout(msg) => core::slice::from_ref(&msg.packet),
+            Self::CreateClient(_)
+            | Self::UpdateClient(_)
+            | Self::Misbehaviour(_)
+            | Self::ConnectionOpenInit(_)
+            | Self::ConnectionOpenTry(_)
+            | Self::ConnectionOpenAck(_)
+            | Self::ConnectionOpenConfirm(_)
+            | Self::ChannelOpenInit(_)
+            | Self::ChannelOpenTry(_)
+            | Self::ChannelOpenAck(_)
+            | Self::ChannelOpenConfirm(_)
+            | Self::ChannelCloseInit(_)
+            | Self::ChannelCloseConfirm(_)
+            | Self::CommitMembershipProof(_)
+            | Self::CommitNonMembershipProof(_) => &[],
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -120,6 +151,19 @@ pub struct MsgUpdateClient {
     pub client_message: Bytes,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case", deny_unknown_fields)
+)]
+pub struct MsgMisbehaviour {
+    pub client_id: ClientId,
+    /// The misbehaviour evidence, encoded by the client module of the client.
+    pub client_message: Bytes,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[cfg_attr(
@@ -0,0 +1,643 @@
+//! Checkpoints of the heights indexed by the event source plugins.
+//!
+//! Event sources fetch the blocks of a chunk concurrently, so a height is only *fully indexed* once
+//! it and all heights before it have been fetched. [`IndexProgress`] tracks the fetched heights of a
+//! chain, persists the highest fully indexed height to a [`CheckpointStore`], detects gaps in the
+//! heights scheduled for indexing, and reports how far indexing lags behind the chain as metrics.
+//!
+//! The end of the scheduled heights is persisted along with the checkpoint, such that heights that
+//! were scheduled before a restart (and are still in a persistent queue) are not mistaken for a gap.
+
+use std::{collections::BTreeSet, ops::Range, path::PathBuf, sync::Mutex, time::Duration};
+
+use futures::TryStreamExt;
+use opentelemetry::{KeyValue, metrics::Gauge};
+use serde::{Deserialize, Serialize};
+use sqlx::{Executor, PgPool, postgres::PgPoolOptions};
+use tracing::{debug, error, info, trace, warn};
+use unionlabs::ErrorReporter;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CheckpointConfig {
+    pub store: CheckpointStoreConfig,
+
+    /// Alert when the indexed height falls more than this many blocks behind the latest height.
+    #[serde(default = "default_max_lag")]
+    pub max_lag: u64,
+
+    /// The REST endpoint of voyager (i.e. `http://localhost:7177`). If set, an index op starting
+    /// after the checkpoint is enqueued when the plugin starts.
+    ///
+    /// Only set this when running with an in-memory queue. A persistent queue still contains the
+    /// index op after a restart, and resuming would index the chain twice.
+    ///
+    /// The op is enqueued with an idempotency key of the chain id and the checkpoint (see
+    /// [`resume_idempotency_key`]), such that restarting the plugin (i.e. when reloading the
+    /// config) without voyager restarting does not enqueue it again, as long as the checkpoint has
+    /// not advanced within the `idempotency_window_seconds` of the queue.
+    #[serde(default)]
+    pub resume_rest_url: Option<String>,
+}
+
+fn default_max_lag() -> u64 {
+    100
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type", deny_unknown_fields)]
+pub enum CheckpointStoreConfig {
+    /// Store checkpoints in the `index_checkpoints` table of a postgres database (usually the
+    /// database of the queue). The table is created if it does not exist.
+    Postgres { database_url: String },
+    /// Store the checkpoint in a local json file. Every plugin must use its own file.
+    File { path: PathBuf },
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CheckpointError {
+    #[error("database error")]
+    Database(#[from] sqlx::Error),
+    #[error("io error")]
+    Io(#[from] std::io::Error),
+    #[error("invalid checkpoint file")]
+    InvalidFile(#[from] serde_json::Error),
+    #[error("the checkpoint file is for chain {found}, but expected {expected}")]
+    ChainIdMismatch { expected: String, found: String },
+}
+
+/// A persisted checkpoint of a chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint {
+    /// All heights up to and including this height have been indexed.
+    pub height: u64,
+    /// The end (exclusive) of the heights scheduled for indexing, if it was persisted. The heights
+    /// between `height` and `scheduled` may still be in the queue.
+    pub scheduled: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileCheckpoint {
+    chain_id: String,
+    height: u64,
+    #[serde(default)]
+    scheduled: Option<u64>,
+}
+
+#[derive(Debug)]
+pub enum CheckpointStore {
+    Postgres(PgPool),
+    File { path: PathBuf },
+}
+
+impl CheckpointStore {
+    pub async fn new(config: CheckpointStoreConfig) -> Result<Self, CheckpointError> {
+        match config {
+            CheckpointStoreConfig::Postgres { database_url } => {
+                let db = PgPoolOptions::new().connect(&database_url).await?;
+
+                db.execute_many(
+                    r#"
+                    CREATE TABLE IF NOT EXISTS
+                      index_checkpoints (
+                        chain_id TEXT PRIMARY KEY,
+                        height BIGINT NOT NULL,
+                        updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+                      );
+
+                    ALTER TABLE index_checkpoints ADD COLUMN IF NOT EXISTS scheduled BIGINT;
+                    "#,
+                )
+                .try_for_each(|result| async move {
+                    trace!("rows affected: {}", result.rows_affected());
+                    Ok(())
+                })
+                .await?;
+
+                Ok(Self::Postgres(db))
+            }
+            CheckpointStoreConfig::File { path } => Ok(Self::File { path }),
+        }
+    }
+
+    /// The checkpoint of `chain_id`, if any.
+    pub async fn load(&self, chain_id: &str) -> Result<Option<Checkpoint>, CheckpointError> {
+        match self {
+            Self::Postgres(db) => Ok(sqlx::query_as::<_, (i64, Option<i64>)>(
+                r#"
+                SELECT
+                  height,
+                  scheduled
+                FROM
+                  index_checkpoints
+                WHERE
+                  chain_id = $1
+                "#,
+            )
+            .bind(chain_id)
+            .fetch_optional(db)
+            .await?
+            .map(|(height, scheduled)| Checkpoint {
+                height: height as u64,
+                scheduled: scheduled.map(|scheduled| scheduled as u64),
+            })),
+            Self::File { path } => {
+                let contents = match tokio::fs::read(path).await {
+                    Ok(contents) => contents,
+                    Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+                    Err(err) => return Err(err.into()),
+                };
+
+                let checkpoint = serde_json::from_slice::<FileCheckpoint>(&contents)?;
+
+                if checkpoint.chain_id != chain_id {
+                    return Err(CheckpointError::ChainIdMismatch {
+                        expected: chain_id.to_owned(),
+                        found: checkpoint.chain_id,
+                    });
+                }
+
+                Ok(Some(Checkpoint {
+                    height: checkpoint.height,
+                    scheduled: checkpoint.scheduled,
+                }))
+            }
+        }
+    }
+
+    pub async fn save(
+        &self,
+        chain_id: &str,
+        checkpoint: Checkpoint,
+    ) -> Result<(), CheckpointError> {
+        trace!(%chain_id, ?checkpoint, "saving checkpoint");
+
+        match self {
+            Self::Postgres(db) => {
+                sqlx::query(
+                    r#"
+                    INSERT INTO
+                      index_checkpoints (chain_id, height, scheduled)
+                    VALUES
+                      ($1, $2, $3)
+                    ON CONFLICT (chain_id) DO UPDATE
+                    SET
+                      height = EXCLUDED.height,
+                      scheduled = EXCLUDED.scheduled,
+                      updated_at = now()
+                    "#,
+                )
+                .bind(chain_id)
+                .bind(checkpoint.height as i64)
+                .bind(checkpoint.scheduled.map(|scheduled| scheduled as i64))
+                .execute(db)
+                .await?;
+            }
+            Self::File { path } => {
+                let contents = serde_json::to_vec(&FileCheckpoint {
+                    chain_id: chain_id.to_owned(),
+                    height: checkpoint.height,
+                    scheduled: checkpoint.scheduled,
+                })
+                .expect("serialization is infallible; qed;");
+
+                // write to a temporary file first, such that the checkpoint is never left half written
+                let tmp_path = path.with_extension("tmp");
+                tokio::fs::write(&tmp_path, contents).await?;
+                tokio::fs::rename(&tmp_path, path).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The indexing progress of a chain, as returned by the `indexingStatus` method of the event source
+/// plugins.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IndexingStatus {
+    pub chain_id: String,
+    /// All heights up to and including this height have been indexed.
+    pub indexed_height: Option<u64>,
+    /// The latest height of the chain, as of the last time it was queried by the event source.
+    pub latest_height: Option<u64>,
+    /// The amount of blocks up to `latest_height` that have not yet been indexed.
+    pub lag: Option<u64>,
+    /// Whether `lag` exceeds the configured `max_lag`.
+    pub lagging: bool,
+}
+
+#[derive(Debug)]
+pub struct IndexProgress {
+    chain_id: String,
+    store: CheckpointStore,
+    max_lag: u64,
+    state: Mutex<State>,
+    /// The last persisted checkpoint. This is held while saving, such that concurrent saves are not
+    /// reordered.
+    persisted: tokio::sync::Mutex<Option<Checkpoint>>,
+    indexed_height_metric: Gauge<u64>,
+    indexing_lag_metric: Gauge<u64>,
+    indexing_lag_alert_metric: Gauge<u64>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// The lowest height that has not yet been indexed.
+    next: Option<u64>,
+    /// Heights after `next` that have already been indexed, out of order.
+    pending: BTreeSet<u64>,
+    /// The end (exclusive) of the heights scheduled by the indexing unfold so far.
+    scheduled: Option<u64>,
+    latest: Option<u64>,
+    lagging: bool,
+}
+
+impl State {
+    fn lag(&self) -> Option<u64> {
+        Some((self.latest? + 1).saturating_sub(self.next?))
+    }
+}
+
+impl IndexProgress {
+    /// Load the checkpoint of `chain_id` from the configured store.
+    pub async fn new(
+        chain_id: impl Into<String>,
+        config: &CheckpointConfig,
+    ) -> Result<Self, CheckpointError> {
+        let chain_id = chain_id.into();
+
+        let store = CheckpointStore::new(config.store.clone()).await?;
+
+        let checkpoint = store.load(&chain_id).await?;
+
+        info!(%chain_id, ?checkpoint, "loaded checkpoint");
+
+        Ok(Self {
+            chain_id,
+            store,
+            max_lag: config.max_lag,
+            state: Mutex::new(State {
+                next: checkpoint.map(|checkpoint| checkpoint.height + 1),
+                // heights up to the persisted end of the scheduled heights may still be in the
+                // queue, so they are not a gap
+                scheduled: checkpoint.map(|checkpoint| {
+                    checkpoint
+                        .scheduled
+                        .unwrap_or_default()
+                        .max(checkpoint.height + 1)
+                }),
+                ..Default::default()
+            }),
+            persisted: tokio::sync::Mutex::new(checkpoint),
+            indexed_height_metric: opentelemetry::global::meter("voyager")
+                .u64_gauge("event_source.indexed_height")
+                .build(),
+            indexing_lag_metric: opentelemetry::global::meter("voyager")
+                .u64_gauge("event_source.indexing_lag")
+                .build(),
+            indexing_lag_alert_metric: opentelemetry::global::meter("voyager")
+                .u64_gauge("event_source.indexing_lag_alert")
+                .build(),
+        })
+    }
+
+    /// The last fully indexed height.
+    pub fn checkpoint(&self) -> Option<u64> {
+        self.state
+            .lock()
+            .expect("mutex is not poisoned; qed;")
+            .next
+            .and_then(|next| next.checked_sub(1))
+    }
+
+    /// The indexing unfold scheduled `heights` to be indexed. Returns the heights between the end of
+    /// the previously scheduled heights and `heights`, if any. These would otherwise never be
+    /// indexed, and must be backfilled.
+    ///
+    /// The new end of the scheduled heights is persisted along with the checkpoint before this
+    /// returns, such that it is known after a restart.
+    pub async fn schedule(&self, heights: Range<u64>) -> Option<Range<u64>> {
+        let (gap, scheduled) = {
+            let mut state = self.state.lock().expect("mutex is not poisoned; qed;");
+
+            let gap = state
+                .scheduled
+                .filter(|scheduled| *scheduled < heights.start)
+                .map(|scheduled| scheduled..heights.start);
+
+            if let Some(gap) = &gap {
+                warn!(
+                    chain_id = %self.chain_id,
+                    from = gap.start,
+                    to = gap.end,
+                    "gap in the indexed heights, {}..{} will be backfilled",
+                    gap.start,
+                    gap.end,
+                );
+            }
+
+            if state.next.is_none() {
+                state.next = Some(heights.start);
+            }
+
+            let scheduled = state.scheduled.unwrap_or_default().max(heights.end);
+
+            state.scheduled = Some(scheduled);
+
+            (gap, scheduled)
+        };
+
+        let mut persisted = self.persisted.lock().await;
+
+        // without a checkpoint, the scheduled heights are persisted along with the first one
+        let outdated = persisted.filter(|checkpoint| {
+            checkpoint
+                .scheduled
+                .is_none_or(|persisted| persisted < scheduled)
+        });
+
+        if let Some(checkpoint) = outdated {
+            self.save(
+                &mut persisted,
+                Checkpoint {
+                    height: checkpoint.height,
+                    scheduled: Some(scheduled),
+                },
+            )
+            .await;
+        }
+
+        gap
+    }
+
+    /// All events at `height` have been fetched. This advances and persists the checkpoint if all
+    /// heights before `height` have been fetched as well.
+    pub async fn complete(&self, height: u64) {
+        let (indexed, scheduled) = {
+            let mut state = self.state.lock().expect("mutex is not poisoned; qed;");
+
+            let Some(mut next) = state.next else {
+                return;
+            };
+
+            if height < next {
+                return;
+            }
+
+            state.pending.insert(height);
+
+            while state.pending.remove(&next) {
+                next += 1;
+            }
+
+            if Some(next) == state.next {
+                return;
+            }
+
+            state.next = Some(next);
+
+            self.record_lag(&mut state);
+
+            (next - 1, state.scheduled)
+        };
+
+        self.indexed_height_metric
+            .record(indexed, &self.attributes());
+
+        let mut persisted = self.persisted.lock().await;
+
+        if persisted.is_some_and(|persisted| persisted.height >= indexed) {
+            return;
+        }
+
+        self.save(
+            &mut persisted,
+            Checkpoint {
+                height: indexed,
+                scheduled,
+            },
+        )
+        .await;
+    }
+
+    /// Save `checkpoint` to the store, updating `persisted` if it was saved.
+    async fn save(&self, persisted: &mut Option<Checkpoint>, checkpoint: Checkpoint) {
+        match self.store.save(&self.chain_id, checkpoint).await {
+            Ok(()) => {
+                debug!(chain_id = %self.chain_id, ?checkpoint, "checkpoint saved");
+                *persisted = Some(checkpoint);
+            }
+            Err(err) => {
+                error!(
+                    err = %ErrorReporter(err),
+                    chain_id = %self.chain_id,
+                    ?checkpoint,
+                    "error saving checkpoint"
+                );
+            }
+        }
+    }
+
+    /// Record the latest height of the chain, updating the lag metrics.
+    pub fn latest_height(&self, height: u64) {
+        let mut state = self.state.lock().expect("mutex is not poisoned; qed;");
+
+        state.latest = Some(state.latest.unwrap_or_default().max(height));
+
+        self.record_lag(&mut state);
+    }
+
+    pub fn status(&self) -> IndexingStatus {
+        let state = self.state.lock().expect("mutex is not poisoned; qed;");
+
+        IndexingStatus {
+            chain_id: self.chain_id.clone(),
+            indexed_height: state.next.and_then(|next| next.checked_sub(1)),
+            latest_height: state.latest,
+            lag: state.lag(),
+            lagging: state.lagging,
+        }
+    }
+
+    fn record_lag(&self, state: &mut State) {
+        let Some(lag) = state.lag() else {
+            return;
+        };
+
+        let lagging = lag > self.max_lag;
+
+        if lagging && !state.lagging {
+            warn!(
+                chain_id = %self.chain_id,
+                %lag,
+                max_lag = self.max_lag,
+                "indexing is lagging behind the chain"
+            );
+        } else if !lagging && state.lagging {
+            info!(chain_id = %self.chain_id, %lag, "indexing caught up with the chain");
+        }
+
+        state.lagging = lagging;
+
+        let attributes = self.attributes();
+
+        self.indexing_lag_metric.record(lag, &attributes);
+        self.indexing_lag_alert_metric
+            .record(lagging.into(), &attributes);
+    }
+
+    fn attributes(&self) -> [KeyValue; 1] {
+        [KeyValue::new("chain_id", self.chain_id.clone())]
+    }
+}
+
+/// The idempotency key that the op resuming indexing from `checkpoint` is enqueued with.
+pub fn resume_idempotency_key(chain_id: &str, checkpoint: u64) -> String {
+    format!("index-checkpoint/resume/{chain_id}/{checkpoint}")
+}
+
+/// Enqueue `op` on the voyager instance serving its REST api at `rest_url` with `idempotency_key`,
+/// retrying until voyager is reachable.
+pub async fn enqueue<T: Serialize>(rest_url: &str, op: &T, idempotency_key: &str) {
+    const MAX_RETRY_MS: u64 = 30_000;
+
+    let client = reqwest::Client::new();
+
+    let mut retry_ms = 500;
+
+    loop {
+        match client
+            .post(format!("{rest_url}/enqueue"))
+            .query(&[("idempotency_key", idempotency_key)])
+            .json(op)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+        {
+            Ok(_) => {
+                info!(%rest_url, "enqueued op");
+                return;
+            }
+            Err(err) => {
+                debug!(
+                    err = %ErrorReporter(err),
+                    %rest_url,
+                    "error enqueueing op, trying again in {retry_ms} ms"
+                );
+
+                tokio::time::sleep(Duration::from_millis(retry_ms)).await;
+
+                retry_ms = std::cmp::min(retry_ms * 2, MAX_RETRY_MS);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn progress(name: &str) -> (IndexProgress, PathBuf) {
+        let path = std::env::temp_dir().join(format!(
+            "index-checkpoint-{name}-{}.json",
+            std::process::id()
+        ));
+
+        let progress = IndexProgress::new(
+            "chain",
+            &CheckpointConfig {
+                store: CheckpointStoreConfig::File { path: path.clone() },
+                max_lag: 5,
+                resume_rest_url: None,
+            },
+        )
+        .await
+        .unwrap();
+
+        (progress, path)
+    }
+
+    #[tokio::test]
+    async fn advances_contiguously() {
+        let (progress, path) = progress("contiguous").await;
+
+        assert_eq!(progress.schedule(10..13).await, None);
+
+        progress.complete(11).await;
+        progress.complete(12).await;
+
+        // nothing before the start of the unfold needs to be indexed
+        assert_eq!(progress.checkpoint(), Some(9));
+
+        progress.complete(10).await;
+
+        assert_eq!(progress.checkpoint(), Some(12));
+
+        // resumes from the persisted checkpoint
+        let (progress, _) = self::progress("contiguous").await;
+
+        assert_eq!(progress.checkpoint(), Some(12));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn detects_gaps_and_lag() {
+        let (progress, path) = progress("gaps").await;
+
+        assert_eq!(progress.schedule(10..13).await, None);
+        assert_eq!(progress.schedule(13..16).await, None);
+        assert_eq!(progress.schedule(20..23).await, Some(16..20));
+
+        for height in 10..13 {
+            progress.complete(height).await;
+        }
+
+        progress.latest_height(20);
+
+        let status = progress.status();
+
+        assert_eq!(status.indexed_height, Some(12));
+        assert_eq!(status.lag, Some(8));
+        assert!(status.lagging);
+
+        for height in 13..20 {
+            progress.complete(height).await;
+        }
+
+        let status = progress.status();
+
+        assert_eq!(status.lag, Some(1));
+        assert!(!status.lagging);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn scheduled_heights_survive_restarts() {
+        let (progress, path) = progress("restart").await;
+
+        assert_eq!(progress.schedule(10..13).await, None);
+
+        progress.complete(10).await;
+
+        assert_eq!(progress.schedule(13..16).await, None);
+
+        // the unfold in a persistent queue continues after the scheduled heights, this is not a gap
+        let (progress, _) = self::progress("restart").await;
+
+        assert_eq!(progress.checkpoint(), Some(10));
+        assert_eq!(progress.schedule(16..19).await, None);
+
+        // resuming from the checkpoint (i.e. with an in-memory queue) is not a gap either
+        let (progress, _) = self::progress("restart").await;
+
+        assert_eq!(progress.schedule(11..14).await, None);
+        assert_eq!(progress.schedule(20..23).await, Some(19..20));
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -2,11 +2,15 @@ use core::f64;
 use std::{
     borrow::Borrow,
     cmp::Eq,
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, HashMap, HashSet},
     fmt::Write,
     future::Future,
     hash::Hash,
     marker::PhantomData,
+    sync::{
+        Arc,
+        atomic::{AtomicU32, Ordering},
+    },
     time::{Duration, Instant},
 };
 
@@ -15,14 +19,16 @@ use itertools::Itertools;
 use opentelemetry::KeyValue;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use sha2::{Digest, Sha256};
 use sqlx::{
-    Either, Executor, PgPool, Postgres, Transaction, postgres::PgPoolOptions, prelude::FromRow,
-    types::Json,
+    Either, Executor, PgPool, Postgres, Row, Transaction, postgres::PgPoolOptions,
+    prelude::FromRow, types::Json,
 };
 use tracing::{Instrument, debug, error, info, info_span, instrument, trace, trace_span, warn};
 use voyager_vm::{
-    BoxDynError, Captures, EnqueueResult, ItemId, Op, QueueError, QueueMessage,
-    filter::{FilterResult, Interest, InterestFilter},
+    BoxDynError, Captures, DEFAULT_ANTI_STARVATION_INTERVAL, EnqueueResult, HistoryItem, ItemId,
+    ItemStatus, Op, Priority, QueueError, QueueMessage,
+    filter::{Admission, AdmissionKey, FilterResult, Interest, InterestFilter},
     pass::{Pass, PassResult},
 };
 
@@ -46,6 +52,10 @@ pub struct PgQueue<T> {
     optimize_batch_limit: Option<i64>,
     retryable_error_expo_backoff_max: f64,
     retryable_error_expo_backoff_multiplier: f64,
+    anti_starvation_interval: u32,
+    idempotency_window_seconds: u64,
+    /// Total amount of items taken from the queue, used to periodically ignore priorities.
+    processed: Arc<AtomicU32>,
 
     metrics: Metrics,
 
@@ -72,6 +82,26 @@ pub struct PgQueueConfig {
     pub retryable_error_expo_backoff_multiplier: f64,
     #[serde(default)]
     pub vacuum_on_boot: bool,
+    /// Take every nth item in FIFO order, see [`DEFAULT_ANTI_STARVATION_INTERVAL`]. Set to 0 to
+    /// always process by priority.
+    #[serde(default = "default_anti_starvation_interval")]
+    pub anti_starvation_interval: u32,
+    /// How long the idempotency key of an op is remembered for, in seconds. Any other ops with the
+    /// same key that are inserted within this window are dropped. Set to 0 to disable
+    /// deduplication.
+    ///
+    /// See [`InterestFilter::idempotency_key`].
+    #[serde(default)]
+    pub idempotency_window_seconds: u64,
+}
+
+/// How many ready items are checked per poll when looking for an item that is admitted by the
+/// [`InterestFilter`], see [`InterestFilter::admit`]. Held items are already skipped by the query,
+/// so this only bounds the items that are not admitted for other reasons.
+const MAX_ADMISSION_ATTEMPTS: usize = 64;
+
+pub const fn default_anti_starvation_interval() -> u32 {
+    DEFAULT_ANTI_STARVATION_INTERVAL
 }
 
 pub const fn default_max_connections() -> u32 {
@@ -113,19 +143,39 @@ struct QueueRecord {
     parents: Vec<i64>,
     item: String,
     created_at: time::OffsetDateTime,
+    handle_at: time::OffsetDateTime,
     attempt: i64,
+    priority: i16,
 }
 
 #[derive(Debug, FromRow)]
 struct OptimizeRecord {
     id: i64,
-    #[allow(dead_code)]
     parents: Vec<i64>,
     item: String,
-    #[allow(dead_code)]
     created_at: time::OffsetDateTime,
 }
 
+#[derive(Debug, FromRow)]
+struct RequeueRecord {
+    id: i64,
+    parents: Vec<i64>,
+    item: String,
+    created_at: time::OffsetDateTime,
+}
+
+/// The result of a single [`PgQueue::requeue_failed`] call.
+#[derive(Debug)]
+pub struct RequeueBatch<E> {
+    /// The ids of the items that were moved back into the queue.
+    pub requeued: Vec<i64>,
+    /// The items that the transform failed on. These are left in the failed table.
+    pub errors: Vec<(i64, E)>,
+    /// The highest id visited in this batch, to be passed as `after` to fetch the next batch. This
+    /// is `None` if there were no more matching items.
+    pub last_id: Option<i64>,
+}
+
 #[derive(Debug, FromRow, Serialize)]
 #[serde(bound(serialize = ""))]
 pub struct FailedRecord<T: QueueMessage> {
@@ -208,6 +258,141 @@ impl<T: QueueMessage> PgQueue<T> {
         .transpose()
     }
 
+    /// Count the failed items matching the provided filters. The filters are the same as in
+    /// [`Self::query_failed`].
+    pub async fn count_failed(
+        &self,
+        mut item_filters: Vec<String>,
+        mut message_filters: Vec<String>,
+    ) -> Result<i64, sqlx::Error> {
+        // default to all-inclusive filter if none are provided
+        if item_filters.is_empty() {
+            item_filters.push("%".to_owned())
+        }
+
+        if message_filters.is_empty() {
+            message_filters.push("%".to_owned())
+        }
+
+        sqlx::query(
+            r#"
+            SELECT
+                COUNT(*) AS count
+            FROM
+                failed
+            WHERE
+                item::TEXT LIKE ANY($1)
+                AND message LIKE ANY($2)
+            "#,
+        )
+        .bind(item_filters)
+        .bind(message_filters)
+        .try_map(|row| row.try_get("count"))
+        .fetch_one(&self.client)
+        .await
+    }
+
+    /// Move up to `limit` failed items with an id greater than `after` and matching the provided
+    /// filters (see [`Self::query_failed`]) back into the queue, in ascending id order.
+    ///
+    /// `transform` is applied to each item before it is requeued. If it returns an error, the item
+    /// is left in the failed table and the error is returned in [`RequeueBatch::errors`].
+    ///
+    /// Requeued items keep their original id and parents, such that their history is preserved.
+    pub async fn requeue_failed<E>(
+        &self,
+        after: i64,
+        limit: i64,
+        mut item_filters: Vec<String>,
+        mut message_filters: Vec<String>,
+        mut transform: impl FnMut(Op<T>) -> Result<Op<T>, E>,
+    ) -> Result<RequeueBatch<E>, sqlx::Error> {
+        // default to all-inclusive filter if none are provided
+        if item_filters.is_empty() {
+            item_filters.push("%".to_owned())
+        }
+
+        if message_filters.is_empty() {
+            message_filters.push("%".to_owned())
+        }
+
+        let mut tx = self.client.begin().await?;
+
+        let records = sqlx::query(
+            r#"
+            SELECT
+                id,
+                parents,
+                item::TEXT,
+                created_at
+            FROM
+                failed
+            WHERE
+                id > $1
+                AND item::TEXT LIKE ANY($2)
+                AND message LIKE ANY($3)
+            ORDER BY
+                id ASC
+            LIMIT
+                $4
+            FOR UPDATE
+                SKIP LOCKED
+            "#,
+        )
+        .bind(after)
+        .bind(item_filters)
+        .bind(message_filters)
+        .bind(limit)
+        .try_map(|row| RequeueRecord::from_row(&row))
+        .fetch_all(tx.as_mut())
+        .await?;
+
+        let mut batch = RequeueBatch {
+            requeued: vec![],
+            errors: vec![],
+            last_id: records.last().map(|record| record.id),
+        };
+
+        for record in records {
+            let op = de::<Op<T>>(&record.item).map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
+
+            let op = match transform(op) {
+                Ok(op) => op,
+                Err(error) => {
+                    batch.errors.push((record.id, error));
+                    continue;
+                }
+            };
+
+            sqlx::query("DELETE FROM failed WHERE id = $1")
+                .bind(record.id)
+                .execute(tx.as_mut())
+                .await?;
+
+            sqlx::query(
+                "
+                INSERT INTO
+                queue  (id, item,      parents, created_at)
+                VALUES ($1, $2::JSONB, $3,      $4        )
+                ",
+            )
+            .bind(record.id)
+            .bind(Json(op))
+            .bind(record.parents)
+            .bind(record.created_at)
+            .execute(tx.as_mut())
+            .await?;
+
+            debug!(id = record.id, "requeued failed item");
+
+            batch.requeued.push(record.id);
+        }
+
+        tx.commit().await?;
+
+        Ok(batch)
+    }
+
     pub async fn stats(&self) -> Result<Stats, sqlx::Error> {
         sqlx::query(
             r#"
@@ -251,6 +436,122 @@ impl<T: QueueMessage> PgQueue<T> {
         .await
     }
 
+    /// The number and age of the items in the optimize table, per tag.
+    pub async fn optimize_stats(&self) -> Result<Vec<OptimizeStats>, sqlx::Error> {
+        sqlx::query(
+            r#"
+            SELECT
+              tag,
+              count(*) AS count,
+              EXTRACT(EPOCH FROM now() - min(created_at))::FLOAT8 AS max_age_seconds,
+              avg(EXTRACT(EPOCH FROM now() - created_at))::FLOAT8 AS avg_age_seconds
+            FROM
+              optimize
+            GROUP BY
+              tag
+            ORDER BY
+              tag ASC
+            "#,
+        )
+        .try_map(|row| OptimizeStats::from_row(&row))
+        .fetch_all(&self.client)
+        .await
+    }
+
+    /// Query the optimize table, optionally only returning items with the provided tag. Items are
+    /// returned oldest first.
+    pub async fn query_optimize(
+        &self,
+        tag: Option<String>,
+        page: i64,
+        per_page: i64,
+    ) -> Result<Vec<OptimizeItem<T>>, sqlx::Error> {
+        sqlx::query(
+            r#"
+            SELECT
+                id,
+                parents,
+                tag,
+                item,
+                EXTRACT(EPOCH FROM now() - created_at)::FLOAT8 AS age_seconds
+            FROM
+                optimize
+            WHERE
+                $1::TEXT IS NULL
+                OR tag = $1
+            ORDER BY
+                id ASC
+            LIMIT
+                $2
+            OFFSET
+                $3
+            "#,
+        )
+        .bind(tag)
+        .bind(per_page)
+        .bind((page - 1) * per_page)
+        .try_map(|row| OptimizeItem::<T>::from_row(&row))
+        .fetch_all(&self.client)
+        .await
+    }
+
+    /// Move all items with the provided tag from the optimize table directly into the queue,
+    /// bypassing the optimization pass of the plugin that owns the tag.
+    ///
+    /// Flushed items keep their original id, parents and creation time, such that their history is
+    /// preserved, and are enqueued with the default priority. Items that are currently being
+    /// optimized are waited on, and flushed if they are still present once the pass is complete.
+    /// Note that an op that is interesting to multiple plugins has a separate row for each tag, and
+    /// only the row for `tag` is flushed. Returns the ids of the flushed items.
+    pub async fn flush_optimize(&self, tag: &str) -> Result<Vec<i64>, sqlx::Error> {
+        sqlx::query(
+            r#"
+            WITH flushed AS (
+              DELETE FROM
+                optimize
+              WHERE
+                id = ANY(
+                  SELECT
+                    id
+                  FROM
+                    optimize
+                  WHERE
+                    tag = $1
+                  FOR UPDATE
+                )
+              RETURNING
+                id,
+                parents,
+                item,
+                created_at
+            )
+            INSERT INTO queue (id, item, parents, created_at)
+            SELECT
+              id,
+              item,
+              parents,
+              created_at
+            FROM
+              flushed
+            RETURNING
+              id
+            "#,
+        )
+        .bind(tag)
+        .try_map(|row| Id::from_row(&row))
+        .fetch_all(&self.client)
+        .await
+        .map(|ids| {
+            ids.into_iter()
+                .map(|Id { id }| {
+                    debug!(id, "flushed optimize item");
+                    id
+                })
+                .sorted()
+                .collect()
+        })
+    }
+
     pub async fn truncate(&self, tables: Tables) -> Result<(), sqlx::Error> {
         if tables.queue {
             sqlx::query(r"TRUNCATE queue").execute(&self.client).await?;
@@ -311,6 +612,30 @@ pub struct Stats {
     pub optimize: Json<BTreeMap<String, u64>>,
 }
 
+/// The items in the optimize table for a single tag.
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct OptimizeStats {
+    pub tag: String,
+    pub count: i64,
+    /// The age of the oldest item with this tag, in seconds.
+    pub max_age_seconds: f64,
+    /// The average age of the items with this tag, in seconds.
+    pub avg_age_seconds: f64,
+}
+
+/// An item in the optimize table, as returned by [`PgQueue::query_optimize`].
+#[derive(Debug, FromRow, Serialize)]
+#[serde(bound(serialize = ""))]
+pub struct OptimizeItem<T: QueueMessage> {
+    pub id: i64,
+    pub parents: Vec<i64>,
+    pub tag: String,
+    pub item: Json<Op<T>>,
+    /// How long this item has been in the optimize table, in seconds. Items produced by an
+    /// optimization pass inherit the age of the items they were produced from.
+    pub age_seconds: f64,
+}
+
 pub struct Tables {
     pub queue: bool,
     pub optimize: bool,
@@ -340,6 +665,8 @@ impl<T: QueueMessage> voyager_vm::Queue<T> for PgQueue<T> {
             config.retryable_error_expo_backoff_multiplier;
         let retryable_error_expo_backoff_max = config.retryable_error_expo_backoff_max;
         let vacuum_on_boot = config.vacuum_on_boot;
+        let anti_starvation_interval = config.anti_starvation_interval;
+        let idempotency_window_seconds = config.idempotency_window_seconds;
 
         let pool = config.into_pg_pool().await?;
 
@@ -392,6 +719,24 @@ impl<T: QueueMessage> voyager_vm::Queue<T> for PgQueue<T> {
             CREATE INDEX IF NOT EXISTS index_queue_handle_at ON queue(handle_at DESC) INCLUDE (id);
 
             CREATE INDEX IF NOT EXISTS optimize_tag_id_idx ON optimize(tag, id);
+
+            ALTER TABLE queue ADD COLUMN IF NOT EXISTS priority SMALLINT NOT NULL DEFAULT 0;
+
+            CREATE INDEX IF NOT EXISTS index_queue_priority_handle_at ON queue(priority DESC, handle_at ASC) INCLUDE (id);
+
+            -- the admission key of the item, see InterestFilter::admission_key. admission_limited is
+            -- null if the item has not been classified yet.
+            ALTER TABLE queue ADD COLUMN IF NOT EXISTS admission_plugin TEXT;
+            ALTER TABLE queue ADD COLUMN IF NOT EXISTS admission_chain_id TEXT;
+            ALTER TABLE queue ADD COLUMN IF NOT EXISTS admission_limited BOOLEAN;
+
+            CREATE TABLE IF NOT EXISTS
+              idempotency_key (
+                key BYTEA PRIMARY KEY,
+                created_at timestamptz NOT NULL DEFAULT now()
+              );
+
+            CREATE INDEX IF NOT EXISTS index_idempotency_key_created_at ON idempotency_key (created_at);
             "#,
         )
         .try_for_each(|result| async move {
@@ -406,10 +751,20 @@ impl<T: QueueMessage> voyager_vm::Queue<T> for PgQueue<T> {
             optimize_batch_limit,
             retryable_error_expo_backoff_max,
             retryable_error_expo_backoff_multiplier,
+            anti_starvation_interval,
+            idempotency_window_seconds,
+            processed: Arc::new(AtomicU32::new(0)),
             metrics: Metrics::new(),
             __marker: PhantomData,
         };
 
+        if idempotency_window_seconds != 0 {
+            tokio::spawn(prune_idempotency_keys(
+                this.client.clone(),
+                idempotency_window_seconds,
+            ));
+        }
+
         if vacuum_on_boot {
             this.vacuum(Tables {
                 queue: true,
@@ -430,28 +785,60 @@ impl<T: QueueMessage> voyager_vm::Queue<T> for PgQueue<T> {
     ) -> Result<EnqueueResult, Self::Error> {
         trace!("enqueue");
 
-        let (optimize, ready): (Vec<_>, Vec<_>) =
-            op.normalize()
-                .into_iter()
-                .partition_map(|op| match filter.check_interest(&op) {
-                    FilterResult::Interest(interest) => Either::Left((op, interest)),
-                    FilterResult::NoInterest => Either::Right(op),
-                });
-
         let mut tx = self.client.begin().await?;
 
+        let (optimize, ready): (Vec<_>, Vec<_>) = dedupe(
+            &self.metrics,
+            &mut tx,
+            filter,
+            self.idempotency_window_seconds,
+            op.normalize(),
+        )
+        .await?
+        .into_iter()
+        .partition_map(|op| match filter.check_interest(&op) {
+            FilterResult::Interest(interest) => Either::Left((op, interest)),
+            FilterResult::NoInterest => Either::Right(op),
+        });
+
+        let priorities = ready
+            .iter()
+            .map(|op| filter.priority(op, None))
+            .collect::<Vec<_>>();
+
+        let (admission_plugins, admission_chain_ids, admission_limited): (Vec<_>, Vec<_>, Vec<_>) =
+            ready
+                .iter()
+                .map(|op| admission_columns(filter.admission_key(op)))
+                .multiunzip();
+
         let ready_ids = sqlx::query(
             "
-            INSERT INTO queue (item)
-            SELECT * FROM UNNEST($1::JSONB[])
+            INSERT INTO queue (item, priority, admission_plugin, admission_chain_id, admission_limited)
+            SELECT * FROM UNNEST($1::JSONB[], $2::SMALLINT[], $3::TEXT[], $4::TEXT[], $5::BOOLEAN[])
             RETURNING id
             ",
         )
         .bind(ready.into_iter().map(Json).collect::<Vec<_>>())
+        .bind(
+            priorities
+                .iter()
+                .map(|p| i16::from(p.0))
+                .collect::<Vec<_>>(),
+        )
+        .bind(admission_plugins)
+        .bind(admission_chain_ids)
+        .bind(admission_limited)
         .try_map(|x| Id::from_row(&x))
         .fetch_all(tx.as_mut())
         .await?;
 
+        for priority in priorities {
+            self.metrics
+                .enqueued_item_count
+                .add(1, &[KeyValue::new("priority", i64::from(priority.0))]);
+        }
+
         for ready in &ready_ids {
             debug!(id = ready.id, "enqueued ready item");
         }
@@ -511,39 +898,142 @@ impl<T: QueueMessage> voyager_vm::Queue<T> for PgQueue<T> {
     {
         trace!("process");
 
+        // only items that are actually taken from the queue count towards the interval, such that
+        // empty polls don't affect how often priorities are ignored
+        let fifo = self.anti_starvation_interval != 0
+            && self.processed.load(Ordering::Relaxed) % self.anti_starvation_interval
+                == self.anti_starvation_interval - 1;
+
+        let held = filter.held();
+
         let mut tx = self.client.begin().await?;
 
-        let row = sqlx::query(
-            r#"
-            DELETE FROM
-              queue
-            WHERE
-              id = (
+        // held items are skipped by the query itself, such that a backlog of held items is never
+        // read, and only the single candidate that is locked is read and decoded. candidates that
+        // are not admitted (i.e. because they haven't been classified yet, or a chain became
+        // unavailable since `held` was read) are left in place. they stay locked by this
+        // transaction, so SKIP LOCKED does not skip them and they are excluded explicitly.
+        let mut skipped = vec![];
+        let mut row = None;
+
+        while skipped.len() < MAX_ADMISSION_ATTEMPTS {
+            let candidate = sqlx::query(if fifo {
+                r#"
                 SELECT
-                  id
+                  id,
+                  parents,
+                  item::text,
+                  attempt,
+                  priority,
+                  handle_at,
+                  created_at,
+                  admission_limited IS NULL AS unclassified
                 FROM
                   queue
                 WHERE
                   handle_at < now()
+                  AND (admission_plugin IS NULL OR admission_plugin <> ALL($1::TEXT[]))
+                  AND (admission_chain_id IS NULL OR admission_chain_id <> ALL($2::TEXT[]))
+                  AND (admission_chain_id IS NULL OR NOT admission_limited OR admission_chain_id <> ALL($3::TEXT[]))
+                  AND id <> ALL($4::BIGINT[])
                 ORDER BY
                   handle_at ASC
                 FOR UPDATE
                   SKIP LOCKED
-                LIMIT 1)
-            RETURNING
-              id,
-              parents,
-              item::text,
-              attempt,
-              created_at
-            "#,
-        )
-        .try_map(|x| QueueRecord::from_row(&x))
-        .fetch_optional(tx.as_mut())
-        .await?;
+                LIMIT 1
+                "#
+            } else {
+                r#"
+                SELECT
+                  id,
+                  parents,
+                  item::text,
+                  attempt,
+                  priority,
+                  handle_at,
+                  created_at,
+                  admission_limited IS NULL AS unclassified
+                FROM
+                  queue
+                WHERE
+                  handle_at < now()
+                  AND (admission_plugin IS NULL OR admission_plugin <> ALL($1::TEXT[]))
+                  AND (admission_chain_id IS NULL OR admission_chain_id <> ALL($2::TEXT[]))
+                  AND (admission_chain_id IS NULL OR NOT admission_limited OR admission_chain_id <> ALL($3::TEXT[]))
+                  AND id <> ALL($4::BIGINT[])
+                ORDER BY
+                  priority DESC,
+                  handle_at ASC
+                FOR UPDATE
+                  SKIP LOCKED
+                LIMIT 1
+                "#
+            })
+            .bind(&held.plugins)
+            .bind(&held.chains)
+            .bind(&held.limited_chains)
+            .bind(&skipped)
+            .try_map(|x| {
+                Ok((
+                    QueueRecord::from_row(&x)?,
+                    x.try_get::<bool, _>("unclassified")?,
+                ))
+            })
+            .fetch_optional(tx.as_mut())
+            .await?;
+
+            let Some((record, unclassified)) = candidate else {
+                break;
+            };
+
+            let id = record.id;
+
+            // items that fail to decode are admitted, such that the error is surfaced when
+            // processing them
+            let admission = match de::<Op<T>>(&record.item) {
+                Ok(op) => {
+                    // items that were inserted without an admission key (i.e. requeued or flushed
+                    // items) are classified once they are read, such that the query skips them from
+                    // then on if they are held
+                    if let Some(admission_key) =
+                        unclassified.then(|| filter.admission_key(&op)).flatten()
+                    {
+                        let is_held = held.holds(&admission_key);
+
+                        classify(&mut tx, id, admission_key).await?;
+
+                        if is_held {
+                            trace!(%id, "item is held");
+                            skipped.push(id);
+                            continue;
+                        }
+                    }
+
+                    filter.admit(&op)
+                }
+                Err(_) => Some(Admission::default()),
+            };
+
+            let Some(admission) = admission else {
+                trace!(%id, "item not admitted");
+                skipped.push(id);
+                continue;
+            };
+
+            sqlx::query("DELETE FROM queue WHERE id = $1")
+                .bind(id)
+                .execute(tx.as_mut())
+                .await?;
+
+            self.processed.fetch_add(1, Ordering::Relaxed);
+
+            row = Some((record, admission));
+
+            break;
+        }
 
         let res = match row {
-            Some(record) => {
+            Some((record, _admission)) => {
                 process_item(
                     &self.metrics,
                     &mut tx,
@@ -552,6 +1042,7 @@ impl<T: QueueMessage> voyager_vm::Queue<T> for PgQueue<T> {
                     filter,
                     self.retryable_error_expo_backoff_max,
                     self.retryable_error_expo_backoff_multiplier,
+                    self.idempotency_window_seconds,
                 )
                 .await?
             }
@@ -615,22 +1106,35 @@ impl<T: QueueMessage> voyager_vm::Queue<T> for PgQueue<T> {
             return Ok(());
         }
 
-        let (ids, msgs) = msgs
+        let (ids, parents, created_ats, msgs): (Vec<_>, Vec<_>, Vec<_>, Vec<_>) = msgs
             .into_iter()
             .map(|r| {
                 Ok((
                     r.id,
+                    r.parents,
+                    r.created_at,
                     de(&r.item).map_err(|e| sqlx::Error::Decode(Box::new(e)))?,
                 ))
             })
-            .collect::<Result<(Vec<_>, Vec<_>), sqlx::Error>>()
-            .map_err(Either::Left)?;
+            .collect::<Result<Vec<_>, sqlx::Error>>()
+            .map_err(Either::Left)?
+            .into_iter()
+            .multiunzip();
 
         self.metrics.optimize_item_count.record(
             msgs.len() as u64,
             &[KeyValue::new("tag".to_owned(), tag.to_owned())],
         );
 
+        for created_at in &created_ats {
+            self.metrics.optimize_item_age.record(
+                (time::OffsetDateTime::now_utc() - *created_at)
+                    .as_seconds_f64()
+                    .max(0.0),
+                &[KeyValue::new("tag".to_owned(), tag.to_owned())],
+            );
+        }
+
         let now = std::time::Instant::now();
 
         let PassResult {
@@ -659,28 +1163,47 @@ impl<T: QueueMessage> voyager_vm::Queue<T> for PgQueue<T> {
             "optimized items"
         );
 
+        // the consumed optimize items are deleted, so the new items inherit their parents
+        // directly; this keeps the lineage of an item traceable back through optimizer passes.
         let get_parent_ids = |parent_idxs: &[usize]| {
-            ids.iter()
+            parents
+                .iter()
                 .enumerate()
-                .filter_map(|(idx, id)| parent_idxs.contains(&idx).then_some(*id))
+                .filter(|(idx, _)| parent_idxs.contains(idx))
+                .flat_map(|(_, parents)| parents.iter().copied())
+                .sorted()
+                .dedup()
                 .collect::<Vec<_>>()
         };
 
+        // items that are optimized further keep the age of the oldest item they were produced
+        // from, so that items held back by a pass (i.e. for batching) don't appear to be new
+        let get_created_at = |parent_idxs: &[usize]| {
+            created_ats
+                .iter()
+                .enumerate()
+                .filter(|(idx, _)| parent_idxs.contains(idx))
+                .map(|(_, created_at)| *created_at)
+                .min()
+                .unwrap_or_else(time::OffsetDateTime::now_utc)
+        };
+
         for (parent_idxs, new_msg, tag) in optimize_further {
             let parents = get_parent_ids(&parent_idxs);
             trace!(parent_idxs = ?&parent_idxs, parents = ?&parents);
 
             let new_row = sqlx::query(
                 "
-                INSERT INTO optimize (item, parents, tag)
+                INSERT INTO optimize (item, parents, tag, created_at)
                 VALUES
-                    ($1::JSONB, $2, $3)
+                    ($1::JSONB, $2, $3, $4)
                 RETURNING id
                 ",
             )
             .bind(Json(new_msg))
             .bind(&parents)
             .bind(tag)
+            .bind(get_created_at(&parent_idxs))
             .try_map(|row| Id::from_row(&row))
             .fetch_one(tx.as_mut())
             .await
@@ -727,21 +1250,36 @@ impl<T: QueueMessage> voyager_vm::Queue<T> for PgQueue<T> {
                     FilterResult::NoInterest => {}
                 }
 
-                ready_insert_into_queue.push((parents.clone(), op));
+                ready_insert_into_queue.push((
+                    parents.clone(),
+                    filter.priority(&op, Some(tag)),
+                    op,
+                ));
             }
         }
 
-        for (parents, op) in ready_insert_into_queue {
+        for (parents, priority, op) in ready_insert_into_queue {
+            self.metrics
+                .enqueued_item_count
+                .add(1, &[KeyValue::new("priority", i64::from(priority.0))]);
+
+            let (admission_plugin, admission_chain_id, admission_limited) =
+                admission_columns(filter.admission_key(&op));
+
             let ready_ids = sqlx::query(
                 "
-                INSERT INTO queue (item, parents)
+                INSERT INTO queue (item, parents, priority, admission_plugin, admission_chain_id, admission_limited)
                 VALUES
-                    ($2::JSONB, $1)
+                    ($2::JSONB, $1, $3, $4, $5, $6)
                 RETURNING id
                 ",
             )
             .bind(parents)
             .bind(Json(op))
+            .bind(i16::from(priority.0))
+            .bind(admission_plugin)
+            .bind(admission_chain_id)
+            .bind(admission_limited)
             .try_map(|x| Id::from_row(&x))
             .fetch_all(tx.as_mut())
             .await
@@ -756,6 +1294,91 @@ impl<T: QueueMessage> voyager_vm::Queue<T> for PgQueue<T> {
 
         Ok(())
     }
+
+    #[instrument(level = "trace", skip_all, fields(id = id.raw(), %max_depth))]
+    async fn history(
+        &self,
+        id: ItemId,
+        max_depth: u32,
+    ) -> Result<Vec<HistoryItem<T>>, Self::Error> {
+        let mut history = vec![];
+        let mut seen = HashSet::new();
+        let mut frontier = vec![id.raw()];
+
+        for depth in 0..=max_depth {
+            frontier.retain(|id| seen.insert(*id));
+
+            if frontier.is_empty() {
+                break;
+            }
+
+            let records = sqlx::query(
+                r#"
+                SELECT id, parents, 'queued'::TEXT AS status, item::TEXT, NULL::TEXT AS message FROM queue WHERE id = ANY($1)
+                UNION ALL
+                SELECT id, parents, 'optimize'::TEXT AS status, item::TEXT, NULL::TEXT AS message FROM optimize WHERE id = ANY($1)
+                UNION ALL
+                SELECT id, parents, 'done'::TEXT AS status, item::TEXT, NULL::TEXT AS message FROM done WHERE id = ANY($1)
+                UNION ALL
+                SELECT id, parents, 'failed'::TEXT AS status, item::TEXT, message FROM failed WHERE id = ANY($1)
+                ORDER BY id ASC
+                "#,
+            )
+            .bind(&frontier)
+            .try_map(|row| HistoryRecord::from_row(&row))
+            .fetch_all(&self.client)
+            .await?;
+
+            frontier = vec![];
+
+            for record in records.into_iter().unique_by(|record| record.id) {
+                frontier.extend(&record.parents);
+                history.push(record.into_history_item(depth)?);
+            }
+        }
+
+        Ok(history)
+    }
+}
+
+#[derive(Debug, FromRow)]
+struct HistoryRecord {
+    id: i64,
+    parents: Vec<i64>,
+    status: String,
+    item: String,
+    message: Option<String>,
+}
+
+impl HistoryRecord {
+    fn into_history_item<T: QueueMessage>(self, depth: u32) -> Result<HistoryItem<T>, sqlx::Error> {
+        let item_id = |id: i64| {
+            ItemId::new(id).map_err(|e| sqlx::Error::Decode(format!("invalid item id: {e}").into()))
+        };
+
+        Ok(HistoryItem {
+            id: item_id(self.id)?,
+            parents: self
+                .parents
+                .into_iter()
+                .map(item_id)
+                .collect::<Result<_, _>>()?,
+            status: match &*self.status {
+                "queued" => ItemStatus::Queued,
+                "optimize" => ItemStatus::Optimize,
+                "done" => ItemStatus::Done,
+                "failed" => ItemStatus::Failed,
+                status => {
+                    return Err(sqlx::Error::Decode(
+                        format!("unknown item status {status}").into(),
+                    ));
+                }
+            },
+            item: de(&self.item).map_err(|e| sqlx::Error::Decode(Box::new(e)))?,
+            message: self.message,
+            depth,
+        })
+    }
 }
 
 #[instrument(
@@ -773,6 +1396,7 @@ async fn process_item<'a, T, F, Fut, R, Filter>(
     filter: &'a Filter,
     retryable_error_expo_backoff_max: f64,
     retryable_error_expo_backoff_multiplier: f64,
+    idempotency_window_seconds: u64,
 ) -> Result<Option<R>, sqlx::Error>
 where
     T: QueueMessage,
@@ -786,32 +1410,47 @@ where
     // really don't feel like defining a new error type right now
     let op = de::<Op<T>>(&record.item).map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
 
+    let priority = Priority(u8::try_from(record.priority).unwrap_or_default());
+    let attributes = [KeyValue::new("priority", i64::from(priority.0))];
+
+    metrics.item_wait_duration.record(
+        (time::OffsetDateTime::now_utc() - record.handle_at)
+            .as_seconds_f64()
+            .max(0.0),
+        &attributes,
+    );
+
     let now = std::time::Instant::now();
     let (r, res) = f(op.clone(), ItemId::new(record.id).unwrap()).await;
-    metrics
-        .item_processing_duration
-        .record(Instant::now().duration_since(now).as_secs_f64(), &[]);
+    metrics.item_processing_duration.record(
+        Instant::now().duration_since(now).as_secs_f64(),
+        &attributes,
+    );
 
     match res {
         Err(QueueError::Fatal(error)) => {
             let error = full_error_string(error);
             error!(%error, "fatal error");
             insert_error(record, error, tx).await?;
-            metrics.fatal_errors_count.add(1, &[]);
+            metrics.fatal_errors_count.add(1, &attributes);
         }
         Err(QueueError::Unprocessable(error)) => {
             let error = full_error_string(error);
             info!(%error, "unprocessable message");
             insert_error(record, error, tx).await?;
-            metrics.unprocessable_count.add(1, &[]);
+            metrics.unprocessable_count.add(1, &attributes);
         }
         Err(QueueError::Retry(error)) => {
             warn!(error = %full_error_string(error), "retryable error");
+
+            let (admission_plugin, admission_chain_id, admission_limited) =
+                admission_columns(filter.admission_key(&op));
+
             sqlx::query(
                 "
                 INSERT INTO
-                queue  (id, item,      parents, attempt, handle_at, created_at)
-                VALUES ($1, $2::JSONB, $3,      $4,      $5,        $6        )
+                queue  (id, item,      parents, attempt, handle_at, created_at, priority, admission_plugin, admission_chain_id, admission_limited)
+                VALUES ($1, $2::JSONB, $3,      $4,      $5,        $6,         $7,       $8,               $9,                 $10              )
                 ",
             )
             .bind(record.id)
@@ -831,11 +1470,15 @@ where
                 ),
             )
             .bind(record.created_at)
+            .bind(record.priority)
+            .bind(admission_plugin)
+            .bind(admission_chain_id)
+            .bind(admission_limited)
             .execute(tx.as_mut())
             .await?;
 
             tokio::time::sleep(Duration::from_millis(500)).await;
-            metrics.retryable_errors_count.add(1, &[]);
+            metrics.retryable_errors_count.add(1, &attributes);
         }
         Ok(ops) => {
             'block: {
@@ -858,22 +1501,58 @@ where
                     break 'block;
                 }
 
-                let (optimize, ready): (Vec<_>, Vec<_>) = ops
-                    .into_iter()
-                    .flat_map(Op::normalize)
-                    .partition_map(|op| match filter.check_interest(&op) {
-                        FilterResult::Interest(tag) => Either::Left((op, tag)),
-                        FilterResult::NoInterest => Either::Right(op),
-                    });
+                let (optimize, ready): (Vec<_>, Vec<_>) = dedupe(
+                    metrics,
+                    tx,
+                    filter,
+                    idempotency_window_seconds,
+                    ops.into_iter().flat_map(Op::normalize).collect(),
+                )
+                .await?
+                .into_iter()
+                .partition_map(|op| match filter.check_interest(&op) {
+                    FilterResult::Interest(tag) => Either::Left((op, tag)),
+                    FilterResult::NoInterest => Either::Right(op),
+                });
+
+                // new items are at least as important as the item that produced them
+                let priorities = ready
+                    .iter()
+                    .map(|op| filter.priority(op, None).max(priority))
+                    .collect::<Vec<_>>();
+
+                for priority in &priorities {
+                    metrics
+                        .enqueued_item_count
+                        .add(1, &[KeyValue::new("priority", i64::from(priority.0))]);
+                }
+
+                let (admission_plugins, admission_chain_ids, admission_limited): (
+                    Vec<_>,
+                    Vec<_>,
+                    Vec<_>,
+                ) = ready
+                    .iter()
+                    .map(|op| admission_columns(filter.admission_key(op)))
+                    .multiunzip();
 
                 sqlx::query(
                     "
-                    INSERT INTO queue (item, parents)
-                    SELECT *, $1 as parents FROM UNNEST($2::JSONB[])
+                    INSERT INTO queue (item, priority, admission_plugin, admission_chain_id, admission_limited, parents)
+                    SELECT *, $1 as parents FROM UNNEST($2::JSONB[], $3::SMALLINT[], $4::TEXT[], $5::TEXT[], $6::BOOLEAN[])
                     ",
                 )
                 .bind(vec![record.id])
                 .bind(ready.into_iter().map(Json).collect::<Vec<_>>())
+                .bind(
+                    priorities
+                        .iter()
+                        .map(|p| i16::from(p.0))
+                        .collect::<Vec<_>>(),
+                )
+                .bind(admission_plugins)
+                .bind(admission_chain_ids)
+                .bind(admission_limited)
                 .execute(tx.as_mut())
                 .await?;
 
@@ -902,7 +1581,7 @@ where
                 .execute(tx.as_mut())
                 .await?;
 
-                metrics.processed_item_count.add(1, &[]);
+                metrics.processed_item_count.add(1, &attributes);
             }
         }
     }
@@ -910,6 +1589,148 @@ where
     Ok(Some(r))
 }
 
+/// The `admission_plugin`, `admission_chain_id` and `admission_limited` columns of a ready item. Items
+/// without an [`AdmissionKey`] are left unclassified.
+fn admission_columns(
+    admission_key: Option<AdmissionKey>,
+) -> (Option<String>, Option<String>, Option<bool>) {
+    match admission_key {
+        Some(AdmissionKey {
+            plugin,
+            chain_id,
+            limited,
+        }) => (plugin, chain_id, Some(limited)),
+        None => (None, None, None),
+    }
+}
+
+/// Store the admission key of an unclassified item, which must be locked by `tx`.
+async fn classify(
+    tx: &mut Transaction<'static, Postgres>,
+    id: i64,
+    admission_key: AdmissionKey,
+) -> Result<(), sqlx::Error> {
+    let (admission_plugin, admission_chain_id, admission_limited) =
+        admission_columns(Some(admission_key));
+
+    sqlx::query(
+        "
+        UPDATE
+          queue
+        SET
+          admission_plugin = $2,
+          admission_chain_id = $3,
+          admission_limited = $4
+        WHERE
+          id = $1
+        ",
+    )
+    .bind(id)
+    .bind(admission_plugin)
+    .bind(admission_chain_id)
+    .bind(admission_limited)
+    .execute(tx.as_mut())
+    .await?;
+
+    Ok(())
+}
+
+/// Drop all ops whose idempotency key has already been seen within the idempotency window, recording
+/// the keys of the remaining ops.
+///
+/// The keys are recorded in `tx`, so they are only persisted if the ops are actually inserted. Only
+/// the sha256 hash of each key is stored, such that keys of any length can be used.
+async fn dedupe<T: QueueMessage, Filter: InterestFilter<T>>(
+    metrics: &Metrics,
+    tx: &mut Transaction<'static, Postgres>,
+    filter: &Filter,
+    idempotency_window_seconds: u64,
+    ops: Vec<Op<T>>,
+) -> Result<Vec<Op<T>>, sqlx::Error> {
+    if idempotency_window_seconds == 0 {
+        return Ok(ops);
+    }
+
+    let window = idempotency_window_seconds as f64;
+
+    let mut deduped = Vec::with_capacity(ops.len());
+
+    for op in ops {
+        let Some(key) = filter.idempotency_key(&op) else {
+            deduped.push(op);
+            continue;
+        };
+
+        let key_hash = Sha256::digest(key.as_bytes());
+
+        // an expired key is treated the same as a new key
+        let inserted = sqlx::query(
+            "
+            INSERT INTO idempotency_key (key)
+            VALUES ($1)
+            ON CONFLICT (key) DO UPDATE
+            SET created_at = now()
+            WHERE idempotency_key.created_at < now() - make_interval(secs => $2)
+            RETURNING key
+            ",
+        )
+        .bind(key_hash.as_slice())
+        .bind(window)
+        .fetch_optional(tx.as_mut())
+        .await?
+        .is_some();
+
+        if inserted {
+            deduped.push(op);
+        } else {
+            debug!(%key, "dropping duplicate op");
+
+            metrics.suppressed_duplicates_count.add(1, &[]);
+        }
+    }
+
+    Ok(deduped)
+}
+
+/// Delete the expired idempotency keys once per idempotency window, until `pool` is closed.
+///
+/// Expired keys are already ignored by [`dedupe`], so this only keeps the table from growing
+/// unbounded. It runs outside of the enqueue and process transactions such that they don't scan the
+/// table.
+async fn prune_idempotency_keys(pool: PgPool, idempotency_window_seconds: u64) {
+    let mut interval = tokio::time::interval(Duration::from_secs(idempotency_window_seconds));
+
+    loop {
+        interval.tick().await;
+
+        if pool.is_closed() {
+            break;
+        }
+
+        let result = sqlx::query(
+            "DELETE FROM idempotency_key WHERE created_at < now() - make_interval(secs => $1)",
+        )
+        .bind(idempotency_window_seconds as f64)
+        .execute(&pool)
+        .await;
+
+        match result {
+            Ok(result) => {
+                trace!(
+                    rows_affected = result.rows_affected(),
+                    "pruned idempotency keys"
+                );
+            }
+            Err(error) => {
+                warn!(
+                    error = %full_error_string(Box::new(error)),
+                    "error pruning idempotency keys"
+                );
+            }
+        }
+    }
+}
+
 async fn insert_error(
     record: QueueRecord,
     error: String,
@@ -0,0 +1,51 @@
+//! Runs the shared queue test suite against postgres.
+//!
+//! These tests require a postgres database to run against, specified with
+//! `PG_QUEUE_TEST_DATABASE_URL`, and are ignored by default; run them with
+//! `cargo test -p pg-queue -- --ignored`. Every test runs in its own schema, which is left in place
+//! for inspection after the test.
+
+use pg_queue::{PgQueue, PgQueueConfig};
+use sqlx::{Executor, PgPool};
+use voyager_vm::{
+    Queue,
+    test_utils::{SimpleMessage, queue_suite::unique_name},
+};
+
+async fn new_queue(idempotency_window_seconds: u64) -> PgQueue<SimpleMessage> {
+    let database_url = std::env::var("PG_QUEUE_TEST_DATABASE_URL")
+        .expect("PG_QUEUE_TEST_DATABASE_URL must be set to run the postgres queue tests");
+
+    let schema = unique_name("pg_queue_test");
+
+    PgPool::connect(&database_url)
+        .await
+        .unwrap()
+        .execute(&*format!("CREATE SCHEMA {schema}"))
+        .await
+        .unwrap();
+
+    let separator = if database_url.contains('?') { '&' } else { '?' };
+
+    PgQueue::new(PgQueueConfig {
+        database_url: format!("{database_url}{separator}options=-csearch_path%3D{schema}"),
+        max_connections: 2,
+        min_connections: 0,
+        idle_timeout: None,
+        max_lifetime: None,
+        optimize_batch_limit: None,
+        retryable_error_expo_backoff_max: pg_queue::default_retryable_error_expo_backoff_max(),
+        retryable_error_expo_backoff_multiplier:
+            pg_queue::default_retryable_error_expo_backoff_multiplier(),
+        vacuum_on_boot: false,
+        anti_starvation_interval: pg_queue::default_anti_starvation_interval(),
+        idempotency_window_seconds,
+    })
+    .await
+    .unwrap()
+}
+
+voyager_vm::queue_tests!(
+    #[ignore = "requires a postgres database, see PG_QUEUE_TEST_DATABASE_URL"]
+    new_queue
+);
@@ -4,7 +4,7 @@ use std::{fmt::Debug, future::Future, sync::Arc, time::Duration};
 use arc_swap::ArcSwapOption;
 use jsonrpsee::core::{
     DeserializeOwned,
-    client::{BatchResponse, ClientT},
+    client::{BatchResponse, ClientT, Subscription, SubscriptionClientT},
     params::BatchRequestBuilder,
     traits::ToRpcParams,
 };
@@ -213,6 +213,55 @@ impl ClientT for Client {
     }
 }
 
+/// Subscriptions are made on the current connection, and end (yield `None`) once that connection is
+/// lost. They are *not* resubscribed on reconnect, since any notifications sent while the client was
+/// disconnected are missed; callers should handle this and resubscribe once the client is
+/// [connected](Client::wait_until_connected) again.
+impl SubscriptionClientT for Client {
+    async fn subscribe<'a, Notif, Params>(
+        &self,
+        subscribe_method: &'a str,
+        params: Params,
+        unsubscribe_method: &'a str,
+    ) -> Result<Subscription<Notif>, jsonrpsee::core::client::Error>
+    where
+        Params: ToRpcParams + Send,
+        Notif: DeserializeOwned,
+    {
+        self.inner
+            .client
+            .load_full()
+            .as_deref()
+            .ok_or_else(|| {
+                jsonrpsee::core::client::Error::Custom(format!(
+                    "not yet connected (subscription: {subscribe_method})",
+                ))
+            })?
+            .subscribe(subscribe_method, params, unsubscribe_method)
+            .await
+    }
+
+    async fn subscribe_to_method<Notif>(
+        &self,
+        method: &str,
+    ) -> Result<Subscription<Notif>, jsonrpsee::core::client::Error>
+    where
+        Notif: DeserializeOwned,
+    {
+        self.inner
+            .client
+            .load_full()
+            .as_deref()
+            .ok_or_else(|| {
+                jsonrpsee::core::client::Error::Custom(format!(
+                    "not yet connected (subscription: {method})",
+                ))
+            })?
+            .subscribe_to_method(method)
+            .await
+    }
+}
+
 impl ClientT for &Client {
     async fn notification<Params>(
         &self,
@@ -0,0 +1,843 @@
+//! Relayer fee profitability policy for the transaction plugins.
+//!
+//! The reward of relaying a packet is the fee of the zkgm token orders it contains, i.e. the spread
+//! between the base amount sent on the source chain and the quote amount received on this chain,
+//! which is paid to the relayer (the `fee_recipient` of the transaction plugin) on a protocol fill.
+//! If the relayer provided a `relayer_msg` for the packet, it is also willing to fill the orders as
+//! a market maker, paying the quote amount on this chain and receiving the base amount on the
+//! source chain.
+//!
+//! The simulated gas cost of a batch is split evenly between its messages, and every packet receipt
+//! in the batch is evaluated against its share. Unprofitable packet receipts are split out of the
+//! batch and held, delayed, or dropped, while the rest of the batch is submitted.
+
+use std::{collections::BTreeMap, str};
+
+use ibc_union_spec::{
+    ChannelId, Packet,
+    datagram::{Datagram, MsgPacketRecv},
+};
+use opentelemetry::{KeyValue, metrics::Counter};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{debug, info, warn};
+use ucs03_zkgm_packet::{
+    Root, TokenOrder, ZkgmPacket,
+    batch::{Batch, BatchInstructionV0, BatchV0},
+    token_order::{TokenOrderV2, TokenOrderV2Metadata},
+};
+use unionlabs_primitives::{Bytes, U256};
+use voyager_sdk::{
+    message::VoyagerMessage,
+    rpc::{RpcError, RpcResult},
+    vm::{Op, conc, defer_relative, noop, seq},
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProfitabilityConfig {
+    /// The value of the tokens that relayers are paid in.
+    #[serde(default)]
+    pub tokens: Vec<TokenValuation>,
+
+    /// The minimum ratio of the reward of a packet receipt to its share of the gas cost of the
+    /// batch for it to be submitted. A ratio of 1 requires the reward to cover the gas cost, and a
+    /// ratio of 0 submits every packet.
+    #[serde(with = "::serde_utils::string", default = "default_min_profit_ratio")]
+    pub min_profit_ratio: f64,
+
+    /// What to do with a packet receipt that is not profitable.
+    #[serde(default)]
+    pub unprofitable: UnprofitableAction,
+
+    /// Overrides for packets received on specific channels.
+    #[serde(default)]
+    pub channels: Vec<ChannelOverride>,
+}
+
+const fn default_min_profit_ratio() -> f64 {
+    1.0
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TokenValuation {
+    /// The quote token on this chain, or the base token on the source chain of a market maker fill;
+    /// the lowercase hex encoded address on evm chains, or the denom on cosmos chains.
+    pub token: String,
+
+    /// The value of one unit of this token, denominated in the smallest unit of the fee token of
+    /// this chain (i.e. wei).
+    #[serde(with = "::serde_utils::string")]
+    pub price: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case", tag = "type")]
+pub enum UnprofitableAction {
+    /// Fail the op, moving it to the failed queue where it can be inspected and requeued manually.
+    /// The held messages are split out into their own op first, such that the rest of the batch is
+    /// still submitted.
+    Hold,
+    /// Requeue the op after a delay, at which point it is evaluated again with the gas price at
+    /// that time.
+    Delay { seconds: u64 },
+    /// Drop the op.
+    Drop,
+}
+
+impl Default for UnprofitableAction {
+    fn default() -> Self {
+        Self::Delay { seconds: 60 }
+    }
+}
+
+impl UnprofitableAction {
+    /// Lower is more lenient.
+    fn severity(&self) -> u8 {
+        match self {
+            Self::Delay { .. } => 0,
+            Self::Hold => 1,
+            Self::Drop => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChannelOverride {
+    /// The channel on this chain that packets are received on.
+    pub channel_id: ChannelId,
+
+    #[serde(with = "::serde_utils::string_opt", default)]
+    pub min_profit_ratio: Option<f64>,
+
+    #[serde(default)]
+    pub unprofitable: Option<UnprofitableAction>,
+}
+
+/// The outcome of evaluating a packet receipt against a [`ProfitabilityConfig`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Evaluation {
+    /// The reward of the packets, denominated in the smallest unit of the fee token.
+    pub reward: f64,
+    /// The share of the gas cost of the batch, denominated in the smallest unit of the fee token.
+    pub cost: u128,
+    pub min_profit_ratio: f64,
+    /// The action to take if the packets are not profitable.
+    pub unprofitable: UnprofitableAction,
+}
+
+impl Evaluation {
+    pub fn is_profitable(&self) -> bool {
+        self.reward >= self.cost as f64 * self.min_profit_ratio
+    }
+}
+
+/// A batch split by [`ProfitabilityPolicy::evaluate`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Verdict<T> {
+    /// The messages to submit; all profitable packet receipts and all other messages, in their
+    /// original order.
+    pub submit: Vec<T>,
+    /// The unprofitable packet receipts, along with their evaluations.
+    pub unprofitable: Vec<(T, Evaluation)>,
+}
+
+#[derive(Debug)]
+pub struct ProfitabilityPolicy {
+    config: ProfitabilityConfig,
+    chain_id: String,
+    accepted_revenue: Counter<f64>,
+    rejected_revenue: Counter<f64>,
+}
+
+impl ProfitabilityPolicy {
+    pub fn new(chain_id: impl Into<String>, config: ProfitabilityConfig) -> Self {
+        let meter = opentelemetry::global::meter("voyager");
+
+        Self {
+            config,
+            chain_id: chain_id.into(),
+            accepted_revenue: meter.f64_counter("relayer.revenue.accepted").build(),
+            rejected_revenue: meter.f64_counter("relayer.revenue.rejected").build(),
+        }
+    }
+
+    /// Evaluate a batch of `msgs` that costs `cost` to submit, splitting the unprofitable packet
+    /// receipts out of the batch. `datagram` returns the datagram of a message, if it has one.
+    ///
+    /// The cost is split evenly between all messages in the batch, and every packet receipt is
+    /// evaluated against its share. No revenue is recorded here, since a batch may be evaluated
+    /// multiple times before it is submitted; see [`Self::record_submitted`] and
+    /// [`Self::handle_unprofitable`].
+    pub fn evaluate<T>(
+        &self,
+        msgs: Vec<T>,
+        datagram: impl Fn(&T) -> Option<&Datagram>,
+        cost: u128,
+    ) -> Verdict<T> {
+        let share = cost / u128::try_from(msgs.len().max(1)).expect("usize fits in u128; qed;");
+
+        let mut verdict = Verdict {
+            submit: vec![],
+            unprofitable: vec![],
+        };
+
+        for msg in msgs {
+            match datagram(&msg).and_then(|datagram| self.evaluate_datagram(datagram, share)) {
+                Some(evaluation) if !evaluation.is_profitable() => {
+                    verdict.unprofitable.push((msg, evaluation));
+                }
+                _ => verdict.submit.push(msg),
+            }
+        }
+
+        verdict
+    }
+
+    /// Evaluate a single datagram that costs `cost` to submit.
+    ///
+    /// Returns `None` if the datagram does not receive any packets, in which case it is always
+    /// submitted. If it receives packets on multiple channels, the most lenient override of those
+    /// channels applies.
+    fn evaluate_datagram(&self, datagram: &Datagram, cost: u128) -> Option<Evaluation> {
+        let Datagram::PacketRecv(msg) = datagram else {
+            return None;
+        };
+
+        let packets = self.packet_rewards(msg);
+
+        if packets.is_empty() {
+            return None;
+        }
+
+        let mut evaluation = Evaluation {
+            reward: packets.iter().map(|(_, reward)| reward).sum(),
+            cost,
+            min_profit_ratio: f64::INFINITY,
+            unprofitable: UnprofitableAction::Drop,
+        };
+
+        for (packet, _) in &packets {
+            let (min_profit_ratio, unprofitable) =
+                self.channel_policy(packet.destination_channel_id);
+
+            evaluation.min_profit_ratio = evaluation.min_profit_ratio.min(min_profit_ratio);
+
+            if unprofitable.severity() < evaluation.unprofitable.severity() {
+                evaluation.unprofitable = unprofitable;
+            }
+        }
+
+        debug!(?evaluation, "evaluated packet profitability");
+
+        Some(evaluation)
+    }
+
+    /// Record the reward of the packets received by `datagrams` as accepted revenue. This is to be
+    /// called once the datagrams have been successfully submitted.
+    pub fn record_submitted<'a>(&self, datagrams: impl IntoIterator<Item = &'a Datagram>) {
+        for datagram in datagrams {
+            self.record_revenue(&self.accepted_revenue, datagram);
+        }
+    }
+
+    /// Turn the unprofitable packet receipts of a batch into follow-up ops, resubmitting the rest
+    /// of the batch. `resubmit` builds the op that submits a batch of messages, and `ops` are any
+    /// other follow-up ops of the batch, such as the handling of messages that failed simulation.
+    ///
+    /// Every unprofitable message is handled as configured in its [`Evaluation`]. Held messages
+    /// are resubmitted on their own, such that only they end up in the failed queue once they are
+    /// evaluated again; if nothing else is left of the batch, the op is failed immediately. The
+    /// reward of dropped messages is recorded as rejected revenue; `datagram` returns the datagram
+    /// of a message, if it has one.
+    pub fn handle_unprofitable<T: Serialize>(
+        &self,
+        verdict: Verdict<T>,
+        datagram: impl Fn(&T) -> Option<&Datagram>,
+        ops: impl IntoIterator<Item = Op<VoyagerMessage>>,
+        resubmit: impl Fn(Vec<T>) -> Op<VoyagerMessage>,
+    ) -> RpcResult<Op<VoyagerMessage>> {
+        let Verdict {
+            submit,
+            unprofitable,
+        } = verdict;
+
+        let mut ops = ops.into_iter().collect::<Vec<_>>();
+
+        if !submit.is_empty() {
+            info!(
+                batch.size = submit.len(),
+                "resubmitting the profitable messages of the batch"
+            );
+
+            ops.push(resubmit(submit));
+        }
+
+        let mut held = vec![];
+        let mut delayed = BTreeMap::<u64, Vec<T>>::new();
+        let mut dropped = 0_usize;
+
+        for (msg, evaluation) in unprofitable {
+            match evaluation.unprofitable {
+                UnprofitableAction::Hold => held.push((msg, evaluation)),
+                UnprofitableAction::Delay { seconds } => {
+                    info!(
+                        seconds,
+                        reward = evaluation.reward,
+                        cost = %evaluation.cost,
+                        "packet is not profitable, delaying"
+                    );
+
+                    delayed.entry(seconds).or_default().push(msg);
+                }
+                UnprofitableAction::Drop => {
+                    warn!(
+                        reward = evaluation.reward,
+                        cost = %evaluation.cost,
+                        "packet is not profitable, dropping"
+                    );
+
+                    if let Some(datagram) = datagram(&msg) {
+                        self.record_revenue(&self.rejected_revenue, datagram);
+                    }
+
+                    dropped += 1;
+                }
+            }
+        }
+
+        for (seconds, msgs) in delayed {
+            ops.push(seq([defer_relative(seconds), resubmit(msgs)]));
+        }
+
+        if !held.is_empty() {
+            if ops.is_empty() && dropped == 0 {
+                return Err(
+                    RpcError::fatal_from_message("packets are not profitable, holding").with_data(
+                        json!(
+                            held.iter()
+                                .map(|(msg, evaluation)| json!({
+                                    "msg": msg,
+                                    "reward": evaluation.reward,
+                                    "cost": evaluation.cost.to_string(),
+                                    "min_profit_ratio": evaluation.min_profit_ratio,
+                                }))
+                                .collect::<Vec<_>>()
+                        ),
+                    ),
+                );
+            }
+
+            info!(
+                batch.size = held.len(),
+                "packets are not profitable, splitting them out to be held"
+            );
+
+            ops.push(resubmit(held.into_iter().map(|(msg, _)| msg).collect()));
+        }
+
+        Ok(if ops.is_empty() { noop() } else { conc(ops) })
+    }
+
+    fn record_revenue(&self, counter: &Counter<f64>, datagram: &Datagram) {
+        let Datagram::PacketRecv(msg) = datagram else {
+            return;
+        };
+
+        for (packet, reward) in self.packet_rewards(msg) {
+            counter.add(
+                reward,
+                &[
+                    KeyValue::new("chain_id", self.chain_id.clone()),
+                    KeyValue::new("channel_id", packet.destination_channel_id.to_string()),
+                ],
+            );
+        }
+    }
+
+    /// The reward of every packet received by `msg`.
+    fn packet_rewards<'a>(&self, msg: &'a MsgPacketRecv) -> Vec<(&'a Packet, f64)> {
+        msg.packets
+            .iter()
+            .enumerate()
+            .map(|(idx, packet)| {
+                let relayer_msg = msg.relayer_msgs.get(idx).map_or(&[][..], |msg| &**msg);
+
+                (packet, self.packet_reward(packet, relayer_msg))
+            })
+            .collect()
+    }
+
+    fn channel_policy(&self, channel_id: ChannelId) -> (f64, UnprofitableAction) {
+        let channel = self
+            .config
+            .channels
+            .iter()
+            .find(|channel| channel.channel_id == channel_id);
+
+        (
+            channel
+                .and_then(|channel| channel.min_profit_ratio)
+                .unwrap_or(self.config.min_profit_ratio),
+            channel
+                .and_then(|channel| channel.unprofitable)
+                .unwrap_or(self.config.unprofitable),
+        )
+    }
+
+    /// The reward of relaying `packet` with `relayer_msg`. Packets that are not zkgm packets are
+    /// worth nothing.
+    fn packet_reward(&self, packet: &Packet, relayer_msg: &[u8]) -> f64 {
+        match ZkgmPacket::decode(&packet.data) {
+            Ok(zkgm_packet) => self.instruction_reward(&zkgm_packet.instruction, relayer_msg),
+            Err(err) => {
+                warn!(
+                    destination_channel_id = %packet.destination_channel_id,
+                    "unable to decode packet as a zkgm packet: {err}"
+                );
+
+                0.0
+            }
+        }
+    }
+
+    fn instruction_reward(&self, instruction: &Root, relayer_msg: &[u8]) -> f64 {
+        match instruction {
+            Root::TokenOrder(token_order) => self.token_order_reward(token_order, relayer_msg),
+            Root::Batch(Batch::V0(BatchV0 { instructions })) => instructions
+                .iter()
+                .map(|instruction| match instruction {
+                    BatchInstructionV0::TokenOrder(token_order) => {
+                        self.token_order_reward(token_order, relayer_msg)
+                    }
+                    BatchInstructionV0::Call(_) => 0.0,
+                })
+                .sum(),
+            // the fees of a forwarded instruction are paid out on the final hop
+            Root::Forward(_) | Root::Call(_) => 0.0,
+        }
+    }
+
+    #[allow(deprecated)]
+    fn token_order_reward(&self, token_order: &TokenOrder, relayer_msg: &[u8]) -> f64 {
+        match token_order {
+            TokenOrder::V1(token_order) => self.order_value(
+                &token_order.base_token,
+                token_order.base_amount,
+                &token_order.quote_token,
+                token_order.quote_amount,
+                relayer_msg,
+            ),
+            // solver orders are filled by the solver, not the relayer
+            TokenOrder::V2(TokenOrderV2 {
+                metadata: TokenOrderV2Metadata::Solve(_),
+                ..
+            }) => 0.0,
+            TokenOrder::V2(TokenOrderV2 {
+                base_token,
+                base_amount,
+                quote_token,
+                quote_amount,
+                ..
+            }) => self.order_value(
+                base_token,
+                *base_amount,
+                quote_token,
+                *quote_amount,
+                relayer_msg,
+            ),
+        }
+    }
+
+    /// The value of filling a token order.
+    ///
+    /// If the relayer provided a `relayer_msg` and both tokens are priced, the order is valued as a
+    /// market maker fill, i.e. the value of the base amount received on the source chain minus the
+    /// value of the quote amount paid on this chain. Otherwise, it is valued as a protocol fill (see
+    /// [`Self::fee_value`]).
+    fn order_value(
+        &self,
+        base_token: &Bytes,
+        base_amount: U256,
+        quote_token: &Bytes,
+        quote_amount: U256,
+        relayer_msg: &[u8],
+    ) -> f64 {
+        if !relayer_msg.is_empty()
+            && let Some(base_price) = self.price(base_token)
+            && let Some(quote_price) = self.price(quote_token)
+        {
+            return (to_f64(base_amount) * base_price - to_f64(quote_amount) * quote_price)
+                .max(0.0);
+        }
+
+        self.fee_value(quote_token, base_amount, quote_amount)
+    }
+
+    /// The configured price of `token`, if any.
+    fn price(&self, token: &Bytes) -> Option<f64> {
+        let hex = token.to_string();
+        let denom = str::from_utf8(token).ok();
+
+        self.config
+            .tokens
+            .iter()
+            .find(|valuation| valuation.token == hex || Some(&*valuation.token) == denom)
+            .map(|valuation| valuation.price)
+    }
+
+    /// The value of the fee of a token order. Tokens without a configured price are worth nothing.
+    fn fee_value(&self, quote_token: &Bytes, base_amount: U256, quote_amount: U256) -> f64 {
+        if base_amount <= quote_amount {
+            return 0.0;
+        }
+
+        let Some(price) = self.price(quote_token) else {
+            debug!(%quote_token, "no price configured for token");
+            return 0.0;
+        };
+
+        to_f64(base_amount - quote_amount) * price
+    }
+}
+
+fn to_f64(amount: U256) -> f64 {
+    amount
+        .to_string()
+        .parse::<f64>()
+        .expect("a decimal integer is a valid float; qed;")
+}
+
+#[cfg(test)]
+mod tests {
+    use ibc_union_spec::{ClientId, MustBeZero, Timestamp, datagram::MsgUpdateClient};
+    use ucs03_zkgm_packet::token_order::SolverMetadata;
+    use unionlabs_primitives::H256;
+    use voyager_sdk::rpc::RpcErrorCode;
+
+    use super::*;
+
+    fn channel_id(id: u32) -> ChannelId {
+        ChannelId::new(id.try_into().unwrap())
+    }
+
+    fn token_order(quote_token: &str, base_amount: u64, quote_amount: u64) -> TokenOrderV2 {
+        TokenOrderV2 {
+            sender: Bytes::default(),
+            receiver: Bytes::default(),
+            base_token: Bytes::default(),
+            base_amount: base_amount.into(),
+            quote_token: quote_token.as_bytes().into(),
+            quote_amount: quote_amount.into(),
+            metadata: TokenOrderV2Metadata::Escrow {
+                metadata: Bytes::default(),
+            },
+        }
+    }
+
+    fn recv(channel: u32, instruction: Root) -> Datagram {
+        recv_with_relayer_msg(channel, instruction, Bytes::default())
+    }
+
+    fn recv_with_relayer_msg(channel: u32, instruction: Root, relayer_msg: Bytes) -> Datagram {
+        Datagram::PacketRecv(MsgPacketRecv {
+            packets: vec![Packet {
+                source_channel_id: channel_id(1),
+                destination_channel_id: channel_id(channel),
+                data: ZkgmPacket {
+                    salt: H256::default(),
+                    path: U256::ZERO,
+                    instruction,
+                }
+                .encode(),
+                timeout_height: MustBeZero,
+                timeout_timestamp: Timestamp::from_nanos(1),
+            }],
+            relayer_msgs: vec![relayer_msg],
+            proof: Bytes::default(),
+            proof_height: 1,
+        })
+    }
+
+    fn policy() -> ProfitabilityPolicy {
+        ProfitabilityPolicy::new(
+            "test",
+            serde_json::from_value(serde_json::json!({
+                "tokens": [
+                    { "token": "au", "price": "2" },
+                    { "token": "bu", "price": "3" }
+                ],
+                "min_profit_ratio": "1.5",
+                "channels": [
+                    { "channel_id": 2, "min_profit_ratio": "0" },
+                    { "channel_id": 3, "unprofitable": { "type": "drop" } },
+                    { "channel_id": 4, "unprofitable": { "type": "hold" } }
+                ]
+            }))
+            .unwrap(),
+        )
+    }
+
+    // a fee of 50au, worth 100
+    fn order() -> Root {
+        Root::TokenOrder(TokenOrder::V2(token_order("au", 150, 100)))
+    }
+
+    #[test]
+    fn evaluate_datagram() {
+        let policy = policy();
+
+        assert_eq!(
+            policy.evaluate_datagram(
+                &Datagram::PacketRecv(MsgPacketRecv {
+                    packets: vec![],
+                    relayer_msgs: vec![],
+                    proof: Bytes::default(),
+                    proof_height: 1,
+                }),
+                1000
+            ),
+            None
+        );
+
+        let evaluation = policy.evaluate_datagram(&recv(1, order()), 60).unwrap();
+        assert_eq!(evaluation.reward, 100.0);
+        assert!(evaluation.is_profitable());
+
+        let evaluation = policy.evaluate_datagram(&recv(1, order()), 70).unwrap();
+        assert!(!evaluation.is_profitable());
+        assert_eq!(
+            evaluation.unprofitable,
+            UnprofitableAction::Delay { seconds: 60 }
+        );
+
+        // unpriced tokens, solver orders, and orders without a fee are worth nothing
+        let evaluation = policy
+            .evaluate_datagram(
+                &recv(
+                    1,
+                    Root::Batch(Batch::V0(BatchV0 {
+                        instructions: vec![
+                            BatchInstructionV0::TokenOrder(TokenOrder::V2(token_order(
+                                "cu", 150, 100,
+                            ))),
+                            BatchInstructionV0::TokenOrder(TokenOrder::V2(TokenOrderV2 {
+                                metadata: TokenOrderV2Metadata::Solve(SolverMetadata {
+                                    solver_address: Bytes::default(),
+                                    metadata: Bytes::default(),
+                                }),
+                                ..token_order("au", 150, 100)
+                            })),
+                            BatchInstructionV0::TokenOrder(TokenOrder::V2(token_order(
+                                "au", 100, 150,
+                            ))),
+                        ],
+                    })),
+                ),
+                1,
+            )
+            .unwrap();
+        assert_eq!(evaluation.reward, 0.0);
+
+        let evaluation = policy.evaluate_datagram(&recv(3, order()), 1000).unwrap();
+        assert!(!evaluation.is_profitable());
+        assert_eq!(evaluation.unprofitable, UnprofitableAction::Drop);
+    }
+
+    #[test]
+    fn evaluate_market_maker_fill() {
+        let policy = policy();
+
+        // receiving 150bu (worth 450) on the source chain and paying 100au (worth 200) on this chain
+        let order = || {
+            Root::TokenOrder(TokenOrder::V2(TokenOrderV2 {
+                base_token: b"bu".into(),
+                ..token_order("au", 150, 100)
+            }))
+        };
+
+        let evaluation = policy
+            .evaluate_datagram(&recv_with_relayer_msg(1, order(), b"relayer".into()), 1)
+            .unwrap();
+        assert_eq!(evaluation.reward, 250.0);
+
+        // without a relayer_msg, the order can only be filled by the protocol
+        let evaluation = policy.evaluate_datagram(&recv(1, order()), 1).unwrap();
+        assert_eq!(evaluation.reward, 100.0);
+
+        // an unpriced base token is valued as a protocol fill
+        let evaluation = policy
+            .evaluate_datagram(
+                &recv_with_relayer_msg(
+                    1,
+                    Root::TokenOrder(TokenOrder::V2(TokenOrderV2 {
+                        base_token: b"cu".into(),
+                        ..token_order("au", 150, 100)
+                    })),
+                    b"relayer".into(),
+                ),
+                1,
+            )
+            .unwrap();
+        assert_eq!(evaluation.reward, 100.0);
+    }
+
+    #[test]
+    fn evaluate_splits_unprofitable_packets() {
+        let policy = policy();
+
+        let update = Datagram::UpdateClient(MsgUpdateClient {
+            client_id: ClientId::new(1.try_into().unwrap()),
+            client_message: Bytes::default(),
+        });
+
+        // the cost of 200 is split between the 4 messages, 50 each
+        let verdict = policy.evaluate(
+            vec![
+                update.clone(),
+                recv(1, order()),
+                recv(3, token_order_root(100)),
+                recv(2, token_order_root(100)),
+            ],
+            Some,
+            200,
+        );
+
+        assert_eq!(
+            verdict.submit,
+            [update, recv(1, order()), recv(2, token_order_root(100))]
+        );
+        assert_eq!(
+            verdict.unprofitable,
+            [(
+                recv(3, token_order_root(100)),
+                Evaluation {
+                    reward: 0.0,
+                    cost: 50,
+                    min_profit_ratio: 1.5,
+                    unprofitable: UnprofitableAction::Drop,
+                }
+            )]
+        );
+    }
+
+    fn token_order_root(amount: u64) -> Root {
+        Root::TokenOrder(TokenOrder::V2(token_order("au", amount, amount)))
+    }
+
+    fn evaluation(unprofitable: UnprofitableAction) -> Evaluation {
+        Evaluation {
+            reward: 0.0,
+            cost: 1,
+            min_profit_ratio: 1.0,
+            unprofitable,
+        }
+    }
+
+    fn resubmit(msgs: Vec<u32>) -> Op<VoyagerMessage> {
+        seq(msgs.into_iter().map(|msg| defer_relative(msg.into())))
+    }
+
+    #[test]
+    fn handle_unprofitable_splits_by_action() {
+        let op = policy()
+            .handle_unprofitable(
+                Verdict {
+                    submit: vec![1, 2],
+                    unprofitable: vec![
+                        (3, evaluation(UnprofitableAction::Hold)),
+                        (4, evaluation(UnprofitableAction::Delay { seconds: 10 })),
+                        (5, evaluation(UnprofitableAction::Drop)),
+                        (6, evaluation(UnprofitableAction::Delay { seconds: 10 })),
+                        (7, evaluation(UnprofitableAction::Delay { seconds: 20 })),
+                    ],
+                },
+                |_| None,
+                [],
+                resubmit,
+            )
+            .unwrap();
+
+        assert_eq!(
+            op,
+            conc([
+                resubmit(vec![1, 2]),
+                seq([defer_relative(10), resubmit(vec![4, 6])]),
+                seq([defer_relative(20), resubmit(vec![7])]),
+                resubmit(vec![3]),
+            ])
+        );
+    }
+
+    #[test]
+    fn handle_unprofitable_holds_and_drops() {
+        // only held messages are failed
+        let err = policy()
+            .handle_unprofitable(
+                Verdict {
+                    submit: vec![],
+                    unprofitable: vec![
+                        (1, evaluation(UnprofitableAction::Hold)),
+                        (2, evaluation(UnprofitableAction::Hold)),
+                    ],
+                },
+                |_| None,
+                [],
+                resubmit,
+            )
+            .unwrap_err();
+        assert!(matches!(err.code(), RpcErrorCode::Fatal));
+
+        // held messages are split out of other follow-up ops of the batch
+        assert_eq!(
+            policy()
+                .handle_unprofitable(
+                    Verdict {
+                        submit: vec![],
+                        unprofitable: vec![(1, evaluation(UnprofitableAction::Hold))],
+                    },
+                    |_| None,
+                    [noop()],
+                    resubmit,
+                )
+                .unwrap(),
+            conc([noop(), resubmit(vec![1])])
+        );
+
+        // held messages are split out of messages that are dropped
+        assert_eq!(
+            policy()
+                .handle_unprofitable(
+                    Verdict {
+                        submit: vec![],
+                        unprofitable: vec![
+                            (1, evaluation(UnprofitableAction::Hold)),
+                            (2, evaluation(UnprofitableAction::Drop)),
+                        ],
+                    },
+                    |_| None,
+                    [],
+                    resubmit,
+                )
+                .unwrap(),
+            conc([resubmit(vec![1])])
+        );
+
+        assert_eq!(
+            policy()
+                .handle_unprofitable(
+                    Verdict {
+                        submit: vec![],
+                        unprofitable: vec![(1, evaluation(UnprofitableAction::Drop))],
+                    },
+                    |_| None,
+                    [],
+                    resubmit,
+                )
+                .unwrap(),
+            noop()
+        );
+    }
+}
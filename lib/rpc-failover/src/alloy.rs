@@ -0,0 +1,230 @@
+//! An alloy transport that fails over between multiple endpoints.
+
+use std::{
+    str::FromStr,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use alloy::{
+    rpc::{
+        client::{BuiltInConnectionString, ClientBuilder, RpcClient},
+        json_rpc::{Id, Request, RequestPacket, ResponsePacket, ResponsePayload, RpcError},
+    },
+    transports::{BoxTransport, TransportError, TransportErrorKind, TransportFut},
+};
+use tower::Service;
+
+use crate::{Endpoints, FailoverConfig, connect_all};
+
+/// A transport over all endpoints of a [`FailoverConfig`]. Requests are sent to the healthiest
+/// endpoint, failing over to the next one if the endpoint is unavailable.
+///
+/// If a quorum is configured, `eth_blockNumber` and `eth_getBlockByNumber` with the `latest`,
+/// `safe` and `finalized` tags are sent to all endpoints, and the highest block that the quorum
+/// has reached is returned.
+#[derive(Debug, Clone)]
+pub struct FailoverTransport {
+    endpoints: Arc<Endpoints<BoxTransport>>,
+}
+
+impl FailoverTransport {
+    /// Connect to `primary_url` and all fallback urls of `config`, and spawn the health checks.
+    /// See [`connect_all`] for how unavailable endpoints are handled.
+    pub async fn connect(
+        primary_url: &str,
+        config: &FailoverConfig,
+    ) -> Result<Self, TransportError> {
+        let transports = connect_all(primary_url, config, |url| async move {
+            BuiltInConnectionString::from_str(&url)?
+                .connect_boxed()
+                .await
+        })
+        .await?;
+
+        let endpoints = Arc::new(Endpoints::new(transports, config));
+
+        let health_check = Request::new("eth_blockNumber", Id::Number(0), ())
+            .serialize()
+            .expect("serialization is infallible; qed;");
+
+        Endpoints::spawn_health_checks(&endpoints, config, move |transport: &BoxTransport| {
+            transport
+                .clone()
+                .call(RequestPacket::Single(health_check.clone()))
+        });
+
+        Ok(Self { endpoints })
+    }
+
+    /// Build an [`RpcClient`] over this transport.
+    pub fn into_client(self) -> RpcClient {
+        ClientBuilder::default().transport(self, false)
+    }
+}
+
+/// Connect to `primary_url` and all fallback urls of `config`, returning a client that can be used
+/// to build a provider with `ProviderBuilder::connect_client`.
+pub async fn connect_client(
+    primary_url: &str,
+    config: &FailoverConfig,
+) -> Result<RpcClient, TransportError> {
+    Ok(FailoverTransport::connect(primary_url, config)
+        .await?
+        .into_client())
+}
+
+impl Service<RequestPacket> for FailoverTransport {
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: RequestPacket) -> Self::Future {
+        let endpoints = self.endpoints.clone();
+
+        Box::pin(async move {
+            let call = |transport: &BoxTransport| transport.clone().call(req.clone());
+
+            if endpoints.quorum().is_some() && is_latest_block_request(&req) {
+                endpoints
+                    .call_quorum(
+                        |transport| {
+                            let fut = call(transport);
+
+                            async move {
+                                let res = fut.await?;
+
+                                // an error response counts as a failed endpoint here, since the
+                                // request is known to be valid
+                                match block_number(&res) {
+                                    Some(_) => Ok(res),
+                                    None => Err(TransportErrorKind::custom_str(
+                                        "invalid latest block response",
+                                    )),
+                                }
+                            }
+                        },
+                        block_number,
+                        is_endpoint_failure,
+                    )
+                    .await
+                    .map_err(|err| {
+                        TransportErrorKind::custom_str(&format!(
+                            "{err}: {}",
+                            err.errors
+                                .iter()
+                                .map(ToString::to_string)
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        ))
+                    })
+            } else {
+                endpoints.call(call, is_endpoint_failure).await
+            }
+        })
+    }
+}
+
+/// Whether `err` was caused by the endpoint, rather than by the request.
+fn is_endpoint_failure(err: &TransportError) -> bool {
+    matches!(
+        err,
+        RpcError::Transport(_) | RpcError::NullResp | RpcError::DeserError { .. }
+    )
+}
+
+fn is_latest_block_request(req: &RequestPacket) -> bool {
+    let RequestPacket::Single(req) = req else {
+        return false;
+    };
+
+    match req.method() {
+        "eth_blockNumber" => true,
+        "eth_getBlockByNumber" => req
+            .params()
+            .and_then(|params| serde_json::from_str::<Vec<serde_json::Value>>(params.get()).ok())
+            .is_some_and(|params| {
+                params
+                    .first()
+                    .and_then(|tag| tag.as_str())
+                    .is_some_and(|tag| matches!(tag, "latest" | "safe" | "finalized"))
+            }),
+        _ => false,
+    }
+}
+
+/// The block number contained in a response to a [latest block request](is_latest_block_request).
+fn block_number(res: &ResponsePacket) -> Option<u64> {
+    let ResponsePacket::Single(res) = res else {
+        return None;
+    };
+
+    let ResponsePayload::Success(payload) = &res.payload else {
+        return None;
+    };
+
+    let number = match serde_json::from_str::<serde_json::Value>(payload.get()).ok()? {
+        serde_json::Value::Object(block) => block.get("number")?.as_str()?.to_owned(),
+        serde_json::Value::String(number) => number,
+        _ => return None,
+    };
+
+    u64::from_str_radix(number.strip_prefix("0x")?, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::rpc::json_rpc::Response;
+    use serde_json::value::RawValue;
+
+    use super::*;
+
+    fn response(payload: &str) -> ResponsePacket {
+        ResponsePacket::Single(Response {
+            id: Id::Number(0),
+            payload: ResponsePayload::Success(RawValue::from_string(payload.to_owned()).unwrap()),
+        })
+    }
+
+    #[test]
+    fn latest_block_request() {
+        let req = |method: &'static str, params: serde_json::Value| {
+            RequestPacket::Single(
+                Request::new(method, Id::Number(0), params)
+                    .serialize()
+                    .unwrap(),
+            )
+        };
+
+        assert!(is_latest_block_request(&req(
+            "eth_blockNumber",
+            serde_json::json!([])
+        )));
+        assert!(is_latest_block_request(&req(
+            "eth_getBlockByNumber",
+            serde_json::json!(["finalized", false])
+        )));
+        assert!(!is_latest_block_request(&req(
+            "eth_getBlockByNumber",
+            serde_json::json!(["0x10", false])
+        )));
+        assert!(!is_latest_block_request(&req(
+            "eth_chainId",
+            serde_json::json!([])
+        )));
+    }
+
+    #[test]
+    fn parse_block_number() {
+        assert_eq!(block_number(&response(r#""0x10""#)), Some(16));
+        assert_eq!(
+            block_number(&response(r#"{"number":"0x11","hash":"0x00"}"#)),
+            Some(17)
+        );
+        assert_eq!(block_number(&response("null")), None);
+    }
+}
@@ -0,0 +1,617 @@
+//! Failover between multiple rpc endpoints of the same chain.
+//!
+//! [`Endpoints`] holds one client per endpoint and routes every request to the healthy endpoint
+//! with the lowest latency, failing over to the next one if the request fails. Endpoints that fail
+//! [`FailoverConfig::max_failures`] times in a row are excluded for a cooldown period, and are
+//! brought back by the periodic health checks once they respond again.
+//!
+//! Security sensitive reads (i.e. the latest finalized height) can additionally require a quorum
+//! of endpoints to agree, such that a single lagging or malicious endpoint can not cause the
+//! relayer to act on a height that the rest of the network has not reached.
+
+use std::{
+    error::Error,
+    future::Future,
+    num::NonZeroUsize,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, trace, warn};
+use unionlabs::ErrorReporter;
+
+#[cfg(feature = "alloy")]
+pub mod alloy;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "RawFailoverConfig")]
+pub struct FailoverConfig {
+    /// Additional endpoints of the same chain, used if the primary endpoint is unavailable or
+    /// slower than these.
+    pub fallback_urls: Vec<String>,
+
+    /// The amount of consecutive failed requests after which an endpoint is excluded.
+    pub max_failures: u32,
+
+    /// How long an excluded endpoint is only used as a last resort.
+    pub cooldown_seconds: u64,
+
+    /// How often all endpoints are checked, to measure their latency and to bring excluded
+    /// endpoints back. Set to 0 to disable health checks.
+    pub health_check_interval_seconds: u64,
+
+    /// The amount of endpoints that must have reached a height before it is returned from a
+    /// latest height query. If not set, the latest height of the fastest endpoint is used.
+    pub quorum: Option<NonZeroUsize>,
+}
+
+impl Default for FailoverConfig {
+    fn default() -> Self {
+        Self {
+            fallback_urls: vec![],
+            max_failures: default_max_failures(),
+            cooldown_seconds: default_cooldown_seconds(),
+            health_check_interval_seconds: default_health_check_interval_seconds(),
+            quorum: None,
+        }
+    }
+}
+
+impl FailoverConfig {
+    /// All endpoint urls, with `primary_url` first.
+    pub fn urls<'a>(&'a self, primary_url: &'a str) -> impl Iterator<Item = &'a str> {
+        [primary_url]
+            .into_iter()
+            .chain(self.fallback_urls.iter().map(String::as_str))
+    }
+
+    /// Ensure that the configured quorum can be reached by the configured endpoints.
+    pub fn validate(&self) -> Result<(), InvalidQuorumError> {
+        let endpoints = 1 + self.fallback_urls.len();
+
+        match self.quorum {
+            Some(quorum) if quorum.get() > endpoints => Err(InvalidQuorumError {
+                quorum: quorum.get(),
+                endpoints,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("quorum of {quorum} is larger than the {endpoints} configured endpoints")]
+pub struct InvalidQuorumError {
+    pub quorum: usize,
+    pub endpoints: usize,
+}
+
+/// The serialized form of [`FailoverConfig`], which is validated on deserialization.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawFailoverConfig {
+    #[serde(default)]
+    fallback_urls: Vec<String>,
+    #[serde(default = "default_max_failures")]
+    max_failures: u32,
+    #[serde(default = "default_cooldown_seconds")]
+    cooldown_seconds: u64,
+    #[serde(default = "default_health_check_interval_seconds")]
+    health_check_interval_seconds: u64,
+    #[serde(default)]
+    quorum: Option<NonZeroUsize>,
+}
+
+impl TryFrom<RawFailoverConfig> for FailoverConfig {
+    type Error = InvalidQuorumError;
+
+    fn try_from(raw: RawFailoverConfig) -> Result<Self, Self::Error> {
+        let config = Self {
+            fallback_urls: raw.fallback_urls,
+            max_failures: raw.max_failures,
+            cooldown_seconds: raw.cooldown_seconds,
+            health_check_interval_seconds: raw.health_check_interval_seconds,
+            quorum: raw.quorum,
+        };
+
+        config.validate()?;
+
+        Ok(config)
+    }
+}
+
+const fn default_max_failures() -> u32 {
+    3
+}
+
+const fn default_cooldown_seconds() -> u64 {
+    30
+}
+
+const fn default_health_check_interval_seconds() -> u64 {
+    10
+}
+
+/// The weight of the latest sample in the latency moving average.
+const LATENCY_SMOOTHING: f64 = 0.3;
+
+#[derive(Debug, thiserror::Error)]
+#[error("quorum of {quorum} endpoints not reached, only {responses} responded")]
+pub struct QuorumError<E> {
+    pub quorum: usize,
+    pub responses: usize,
+    pub errors: Vec<E>,
+}
+
+/// Connect to `primary_url` and all fallback urls of `config` concurrently, returning the
+/// `(url, client)` pairs of the endpoints that could be connected to, in the configured order.
+///
+/// Endpoints that can not be connected to are logged and excluded, such that a single unavailable
+/// endpoint does not prevent startup. They are not retried until the clients are rebuilt. This only
+/// fails if no endpoint (or fewer endpoints than the configured quorum) could be connected to, in
+/// which case the error of the first endpoint that failed is returned.
+pub async fn connect_all<C, E, Fut>(
+    primary_url: &str,
+    config: &FailoverConfig,
+    connect: impl Fn(String) -> Fut,
+) -> Result<Vec<(String, C)>, E>
+where
+    E: Error,
+    Fut: Future<Output = Result<C, E>>,
+{
+    let results = futures::future::join_all(config.urls(primary_url).map(|url| {
+        let fut = connect(url.to_owned());
+        async move { (url, fut.await) }
+    }))
+    .await;
+
+    let required = config.quorum.map_or(1, NonZeroUsize::get);
+
+    let mut clients = vec![];
+    let mut first_err = None;
+
+    for (url, result) in results {
+        match result {
+            Ok(client) => clients.push((url.to_owned(), client)),
+            Err(err) => {
+                warn!(%url, err = %ErrorReporter(&err), "unable to connect to endpoint, excluding it");
+                first_err.get_or_insert(err);
+            }
+        }
+    }
+
+    match first_err {
+        Some(err) if clients.len() < required => Err(err),
+        _ => Ok(clients),
+    }
+}
+
+#[derive(Debug)]
+pub struct Endpoints<C> {
+    endpoints: Vec<Endpoint<C>>,
+    max_failures: u32,
+    cooldown: Duration,
+    quorum: Option<NonZeroUsize>,
+}
+
+#[derive(Debug)]
+struct Endpoint<C> {
+    url: String,
+    client: C,
+    health: Mutex<Health>,
+}
+
+#[derive(Debug, Default)]
+struct Health {
+    /// Exponential moving average of the latency of successful requests.
+    latency: Option<Duration>,
+    consecutive_failures: u32,
+    excluded_until: Option<Instant>,
+}
+
+impl<C> Endpoints<C> {
+    /// Build the endpoints from `(url, client)` pairs, where the first endpoint is the primary.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `endpoints` is empty.
+    pub fn new(endpoints: impl IntoIterator<Item = (String, C)>, config: &FailoverConfig) -> Self {
+        let endpoints = endpoints
+            .into_iter()
+            .map(|(url, client)| Endpoint {
+                url,
+                client,
+                health: Mutex::new(Health::default()),
+            })
+            .collect::<Vec<_>>();
+
+        assert!(!endpoints.is_empty(), "at least one endpoint is required");
+
+        Self {
+            endpoints,
+            max_failures: config.max_failures.max(1),
+            cooldown: Duration::from_secs(config.cooldown_seconds),
+            quorum: config.quorum,
+        }
+    }
+
+    /// The client of the primary endpoint.
+    pub fn primary(&self) -> &C {
+        &self.endpoints[0].client
+    }
+
+    pub fn len(&self) -> usize {
+        self.endpoints.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.endpoints.is_empty()
+    }
+
+    pub fn quorum(&self) -> Option<NonZeroUsize> {
+        self.quorum
+    }
+
+    /// The indexes of all endpoints in the order they should be tried in: healthy endpoints by
+    /// latency (endpoints without a measured latency first, in the configured order), followed by
+    /// the excluded endpoints as a last resort.
+    fn ordered(&self) -> Vec<usize> {
+        let now = Instant::now();
+
+        let mut order = self
+            .endpoints
+            .iter()
+            .enumerate()
+            .map(|(idx, endpoint)| {
+                let health = endpoint.health.lock().expect("mutex is not poisoned; qed;");
+
+                let excluded = health
+                    .excluded_until
+                    .is_some_and(|excluded_until| excluded_until > now);
+
+                (excluded, health.latency.unwrap_or_default(), idx)
+            })
+            .collect::<Vec<_>>();
+
+        order.sort();
+
+        order.into_iter().map(|(_, _, idx)| idx).collect()
+    }
+
+    /// Call `f` on the endpoints in order until it succeeds, or until it returns an error that
+    /// `is_endpoint_failure` does not consider to be caused by the endpoint (i.e. an error
+    /// response to an invalid request), which is returned as is. If all endpoints fail, the error
+    /// of the last one is returned.
+    pub async fn call<'a, T, E, Fut>(
+        &'a self,
+        f: impl Fn(&'a C) -> Fut,
+        is_endpoint_failure: impl Fn(&E) -> bool,
+    ) -> Result<T, E>
+    where
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut last_err = None;
+
+        for idx in self.ordered() {
+            let endpoint = &self.endpoints[idx];
+
+            let start = Instant::now();
+
+            match f(&endpoint.client).await {
+                Ok(t) => {
+                    endpoint.success(start.elapsed());
+                    return Ok(t);
+                }
+                Err(err) if is_endpoint_failure(&err) => {
+                    if self.endpoints.len() > 1 {
+                        warn!(url = %endpoint.url, "request failed, failing over to the next endpoint");
+                    }
+
+                    endpoint.failure(self.max_failures, self.cooldown);
+                    last_err = Some(err);
+                }
+                Err(err) => {
+                    endpoint.success(start.elapsed());
+                    return Err(err);
+                }
+            }
+        }
+
+        Err(last_err.expect("there is at least one endpoint; qed;"))
+    }
+
+    /// Call `f` on all endpoints concurrently and return the response with the highest `key` that
+    /// at least [`FailoverConfig::quorum`] endpoints have reached, i.e. the highest height that a
+    /// quorum of endpoints agree on. If no quorum is configured, this is the same as
+    /// [`Self::call`].
+    pub async fn call_quorum<'a, T, E, K, Fut>(
+        &'a self,
+        f: impl Fn(&'a C) -> Fut,
+        key: impl Fn(&T) -> K,
+        is_endpoint_failure: impl Fn(&E) -> bool,
+    ) -> Result<T, QuorumError<E>>
+    where
+        K: Ord,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let Some(quorum) = self.quorum else {
+            return self
+                .call(f, is_endpoint_failure)
+                .await
+                .map_err(|err| QuorumError {
+                    quorum: 1,
+                    responses: 0,
+                    errors: vec![err],
+                });
+        };
+
+        let results = futures::future::join_all(self.endpoints.iter().map(|endpoint| {
+            let fut = f(&endpoint.client);
+            async move {
+                let start = Instant::now();
+                let result = fut.await;
+                (endpoint, start.elapsed(), result)
+            }
+        }))
+        .await;
+
+        let mut responses = vec![];
+        let mut errors = vec![];
+
+        for (endpoint, latency, result) in results {
+            match result {
+                Ok(t) => {
+                    endpoint.success(latency);
+                    responses.push(t);
+                }
+                Err(err) => {
+                    if is_endpoint_failure(&err) {
+                        endpoint.failure(self.max_failures, self.cooldown);
+                    }
+                    errors.push(err);
+                }
+            }
+        }
+
+        if responses.len() < quorum.get() {
+            return Err(QuorumError {
+                quorum: quorum.get(),
+                responses: responses.len(),
+                errors,
+            });
+        }
+
+        // sort descending, such that the response at index `quorum - 1` is the highest one that
+        // `quorum` endpoints have reached
+        responses.sort_by_key(|t| std::cmp::Reverse(key(t)));
+
+        debug!(
+            quorum = quorum.get(),
+            responses = responses.len(),
+            "quorum reached"
+        );
+
+        Ok(responses.swap_remove(quorum.get() - 1))
+    }
+
+    /// Run `check` against all endpoints, updating their health.
+    pub async fn health_check<'a, T, E, Fut>(&'a self, check: impl Fn(&'a C) -> Fut)
+    where
+        Fut: Future<Output = Result<T, E>>,
+    {
+        futures::future::join_all(self.endpoints.iter().map(|endpoint| {
+            let fut = check(&endpoint.client);
+            async move {
+                let start = Instant::now();
+                match fut.await {
+                    Ok(_) => {
+                        trace!(url = %endpoint.url, "endpoint is healthy");
+                        endpoint.success(start.elapsed());
+                    }
+                    Err(_) => {
+                        debug!(url = %endpoint.url, "health check failed");
+                        endpoint.failure(self.max_failures, self.cooldown);
+                    }
+                }
+            }
+        }))
+        .await;
+    }
+}
+
+impl<C: Send + Sync + 'static> Endpoints<C> {
+    /// Spawn a task that runs [`Self::health_check`] every
+    /// [`FailoverConfig::health_check_interval_seconds`]. The task exits once `endpoints` is
+    /// dropped. Health checks are not run if there is only a single endpoint.
+    pub fn spawn_health_checks<T, E, Fut>(
+        endpoints: &Arc<Self>,
+        config: &FailoverConfig,
+        check: impl Fn(&C) -> Fut + Send + Sync + 'static,
+    ) where
+        Fut: Future<Output = Result<T, E>> + Send,
+    {
+        if endpoints.len() < 2 || config.health_check_interval_seconds == 0 {
+            return;
+        }
+
+        let interval = Duration::from_secs(config.health_check_interval_seconds);
+        let endpoints = Arc::downgrade(endpoints);
+
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+
+                let Some(endpoints) = endpoints.upgrade() else {
+                    return;
+                };
+
+                endpoints.health_check(&check).await;
+            }
+        });
+    }
+}
+
+impl<C> Endpoint<C> {
+    fn success(&self, latency: Duration) {
+        let mut health = self.health.lock().expect("mutex is not poisoned; qed;");
+
+        health.latency = Some(match health.latency {
+            Some(average) => {
+                average.mul_f64(1.0 - LATENCY_SMOOTHING) + latency.mul_f64(LATENCY_SMOOTHING)
+            }
+            None => latency,
+        });
+        health.consecutive_failures = 0;
+        health.excluded_until = None;
+    }
+
+    fn failure(&self, max_failures: u32, cooldown: Duration) {
+        let mut health = self.health.lock().expect("mutex is not poisoned; qed;");
+
+        health.consecutive_failures += 1;
+
+        if health.consecutive_failures >= max_failures {
+            warn!(
+                url = %self.url,
+                consecutive_failures = health.consecutive_failures,
+                "excluding unhealthy endpoint"
+            );
+
+            health.excluded_until = Some(Instant::now() + cooldown);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endpoints(quorum: Option<usize>) -> Endpoints<u64> {
+        Endpoints::new(
+            [("a", 10), ("b", 12), ("c", 11)].map(|(url, client)| (url.to_owned(), client)),
+            &FailoverConfig {
+                max_failures: 1,
+                quorum: quorum.and_then(NonZeroUsize::new),
+                ..Default::default()
+            },
+        )
+    }
+
+    #[test]
+    fn quorum_larger_than_endpoints_is_rejected() {
+        assert!(serde_json::from_str::<FailoverConfig>(r#"{"quorum":1}"#).is_ok());
+        assert!(
+            serde_json::from_str::<FailoverConfig>(r#"{"fallback_urls":["b"],"quorum":2}"#).is_ok()
+        );
+        assert!(
+            serde_json::from_str::<FailoverConfig>(r#"{"fallback_urls":["b"],"quorum":3}"#)
+                .unwrap_err()
+                .to_string()
+                .contains("quorum of 3 is larger than the 2 configured endpoints")
+        );
+    }
+
+    #[tokio::test]
+    async fn unavailable_endpoints_are_excluded() {
+        #[derive(Debug, PartialEq, thiserror::Error)]
+        #[error("{0} is down")]
+        struct Down(String);
+
+        let config = |quorum| FailoverConfig {
+            fallback_urls: vec!["b".to_owned(), "c".to_owned()],
+            quorum: NonZeroUsize::new(quorum),
+            ..Default::default()
+        };
+
+        let connect = async |url: String| if url == "c" { Ok(url) } else { Err(Down(url)) };
+
+        assert_eq!(
+            connect_all("a", &config(0), connect).await,
+            Ok(vec![("c".to_owned(), "c".to_owned())])
+        );
+        assert_eq!(
+            connect_all("a", &config(2), connect).await,
+            Err(Down("a".to_owned()))
+        );
+        assert_eq!(
+            connect_all("a", &config(0), async |url| Err::<(), _>(Down(url))).await,
+            Err(Down("a".to_owned()))
+        );
+    }
+
+    #[tokio::test]
+    async fn failover() {
+        let endpoints = endpoints(None);
+
+        // the first endpoint fails and is excluded
+        assert_eq!(
+            endpoints
+                .call(
+                    async |c| if *c == 10 { Err("down") } else { Ok(*c) },
+                    |_| true
+                )
+                .await,
+            Ok(12)
+        );
+        // the excluded endpoint is only used as a last resort
+        assert_ne!(
+            endpoints.call(async |c| Ok::<_, ()>(*c), |_| true).await,
+            Ok(10)
+        );
+
+        // errors that are not caused by the endpoint are returned as is
+        assert_eq!(
+            endpoints
+                .call(async |_| Err::<u64, _>("bad request"), |_| false)
+                .await,
+            Err("bad request")
+        );
+
+        // the last error is returned if all endpoints fail
+        assert_eq!(
+            endpoints.call(async |c| Err::<u64, _>(*c), |_| true).await,
+            Err(10)
+        );
+    }
+
+    #[tokio::test]
+    async fn quorum() {
+        async fn height(c: &u64) -> Result<u64, ()> {
+            Ok(*c)
+        }
+
+        assert_eq!(
+            endpoints(Some(1))
+                .call_quorum(height, |h: &u64| *h, |_| true)
+                .await
+                .unwrap(),
+            12
+        );
+        assert_eq!(
+            endpoints(Some(2))
+                .call_quorum(height, |h: &u64| *h, |_| true)
+                .await
+                .unwrap(),
+            11
+        );
+        assert_eq!(
+            endpoints(Some(3))
+                .call_quorum(height, |h: &u64| *h, |_| true)
+                .await
+                .unwrap(),
+            10
+        );
+
+        let err = endpoints(Some(3))
+            .call_quorum(
+                async |c| if *c == 11 { Err(()) } else { Ok(*c) },
+                |h: &u64| *h,
+                |_| true,
+            )
+            .await
+            .unwrap_err();
+
+        assert_eq!((err.quorum, err.responses), (3, 2));
+    }
+}
@@ -0,0 +1,1848 @@
+use core::f64;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Write,
+    future::Future,
+    marker::PhantomData,
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use futures_util::TryStreamExt;
+use itertools::Itertools;
+use opentelemetry::KeyValue;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use sha2::{Digest, Sha256};
+use sqlx::{
+    Either, Executor, QueryBuilder, Row, Sqlite, SqlitePool, Transaction,
+    prelude::FromRow,
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous},
+    types::Json,
+};
+use tracing::{Instrument, debug, error, info, info_span, instrument, trace, trace_span, warn};
+use voyager_vm::{
+    BoxDynError, Captures, DEFAULT_ANTI_STARVATION_INTERVAL, EnqueueResult, HistoryItem, ItemId,
+    ItemStatus, Op, Priority, QueueError, QueueMessage,
+    filter::{Admission, AdmissionKey, FilterResult, Interest, InterestFilter},
+    pass::{Pass, PassResult},
+};
+
+use crate::metrics::Metrics;
+
+pub mod metrics;
+
+/// A fifo queue backed by an embedded SQLite database. This mirrors the semantics of `pg-queue`,
+/// without requiring a database server.
+///
+/// Since SQLite only allows for a single writer at a time, items are not held in an open
+/// transaction while they are being processed. Instead, they are marked as `locked` when they are
+/// picked up, and removed (or unlocked, in the case of a retry) once processing is complete. All
+/// locks are released when the queue is opened, so any items that were being processed when
+/// voyager exited will be picked up again on the next start.
+///
+/// The queue assumes the following database schema:
+///
+/// ```ignore
+/// id INTEGER PRIMARY KEY
+/// item TEXT
+/// parents TEXT (json array)
+/// created_at INTEGER (unix millis)
+/// handle_at INTEGER (unix millis)
+/// attempt INTEGER
+/// priority INTEGER
+/// locked 0..1
+/// ```
+#[derive(Debug, Clone)]
+pub struct SqliteQueue<T> {
+    client: SqlitePool,
+    optimize_batch_limit: Option<i64>,
+    retryable_error_expo_backoff_max: f64,
+    retryable_error_expo_backoff_multiplier: f64,
+    anti_starvation_interval: u32,
+    idempotency_window_seconds: u64,
+    /// Total amount of items taken from the queue, used to periodically ignore priorities.
+    processed: Arc<AtomicU32>,
+
+    metrics: Metrics,
+
+    __marker: PhantomData<fn() -> T>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SqliteQueueConfig {
+    /// Path to the database file. It will be created if it does not exist.
+    pub path: PathBuf,
+    #[serde(default = "default_max_connections")]
+    pub max_connections: u32,
+    /// How long to wait for the database lock to be released before returning an error.
+    #[serde(default = "default_busy_timeout")]
+    pub busy_timeout: Duration,
+    #[serde(default)]
+    pub optimize_batch_limit: Option<i64>,
+    #[serde(default = "default_retryable_error_expo_backoff_max")]
+    pub retryable_error_expo_backoff_max: f64,
+    #[serde(default = "default_retryable_error_expo_backoff_multiplier")]
+    pub retryable_error_expo_backoff_multiplier: f64,
+    #[serde(default)]
+    pub vacuum_on_boot: bool,
+    /// Take every nth item in FIFO order, see [`DEFAULT_ANTI_STARVATION_INTERVAL`]. Set to 0 to
+    /// always process by priority.
+    #[serde(default = "default_anti_starvation_interval")]
+    pub anti_starvation_interval: u32,
+    /// How long the idempotency key of an op is remembered for, in seconds. Any other ops with the
+    /// same key that are inserted within this window are dropped. Set to 0 to disable
+    /// deduplication.
+    ///
+    /// See [`InterestFilter::idempotency_key`].
+    #[serde(default)]
+    pub idempotency_window_seconds: u64,
+}
+
+/// How many ready items are checked per poll when looking for an item that is admitted by the
+/// [`InterestFilter`], see [`InterestFilter::admit`]. Held items are already skipped by the query,
+/// so this only bounds the items that are not admitted for other reasons.
+const MAX_ADMISSION_ATTEMPTS: usize = 64;
+
+pub const fn default_anti_starvation_interval() -> u32 {
+    DEFAULT_ANTI_STARVATION_INTERVAL
+}
+
+pub const fn default_max_connections() -> u32 {
+    10
+}
+
+pub const fn default_busy_timeout() -> Duration {
+    Duration::from_secs(30)
+}
+
+pub const fn default_retryable_error_expo_backoff_max() -> f64 {
+    60.0 * 5.0
+}
+
+pub const fn default_retryable_error_expo_backoff_multiplier() -> f64 {
+    2.0
+}
+
+impl SqliteQueueConfig {
+    pub async fn into_sqlite_pool(self) -> sqlx::Result<SqlitePool> {
+        SqlitePoolOptions::new()
+            .max_connections(self.max_connections)
+            .connect_with(
+                SqliteConnectOptions::new()
+                    .filename(&self.path)
+                    .create_if_missing(true)
+                    .journal_mode(SqliteJournalMode::Wal)
+                    .synchronous(SqliteSynchronous::Normal)
+                    .busy_timeout(self.busy_timeout),
+            )
+            .await
+    }
+}
+
+#[derive(FromRow)]
+struct Id {
+    id: i64,
+}
+
+#[derive(Debug, FromRow)]
+struct QueueRecord {
+    id: i64,
+    parents: Json<Vec<i64>>,
+    item: String,
+    created_at: i64,
+    handle_at: i64,
+    attempt: i64,
+    priority: i64,
+}
+
+#[derive(Debug, FromRow)]
+struct OptimizeRecord {
+    id: i64,
+    parents: Json<Vec<i64>>,
+    item: String,
+    created_at: i64,
+}
+
+#[derive(Debug, FromRow)]
+struct RequeueRecord {
+    id: i64,
+    parents: Json<Vec<i64>>,
+    item: String,
+    created_at: i64,
+}
+
+/// The result of a single [`SqliteQueue::requeue_failed`] call.
+#[derive(Debug)]
+pub struct RequeueBatch<E> {
+    /// The ids of the items that were moved back into the queue.
+    pub requeued: Vec<i64>,
+    /// The items that the transform failed on. These are left in the failed table.
+    pub errors: Vec<(i64, E)>,
+    /// The highest id visited in this batch, to be passed as `after` to fetch the next batch. This
+    /// is `None` if there were no more matching items.
+    pub last_id: Option<i64>,
+}
+
+#[derive(Debug, FromRow, Serialize)]
+#[serde(bound(serialize = ""))]
+pub struct FailedRecord<T: QueueMessage> {
+    pub id: i64,
+    pub parents: Json<Vec<i64>>,
+    pub item: Json<Op<T>>,
+    pub message: String,
+}
+
+impl<T: QueueMessage> SqliteQueue<T> {
+    /// Query the failed table. The filters are SQL `LIKE` patterns, which will be run on the
+    /// item as stored in the database (compact JSON, as produced by `serde_json`).
+    pub async fn query_failed(
+        &self,
+        page: i64,
+        per_page: i64,
+        mut item_filters: Vec<String>,
+        mut message_filters: Vec<String>,
+    ) -> Result<Vec<FailedRecord<T>>, sqlx::Error> {
+        // default to all-inclusive filter if none are provided
+        if item_filters.is_empty() {
+            item_filters.push("%".to_owned())
+        }
+
+        if message_filters.is_empty() {
+            message_filters.push("%".to_owned())
+        }
+
+        let mut query = QueryBuilder::<Sqlite>::new(
+            r#"
+            SELECT
+                id,
+                parents,
+                item,
+                message
+            FROM
+                failed
+            WHERE
+            "#,
+        );
+
+        push_like_any(&mut query, "item", item_filters);
+        query.push(" AND ");
+        push_like_any(&mut query, "message", message_filters);
+
+        query
+            .push(" ORDER BY id DESC LIMIT ")
+            .push_bind(per_page)
+            .push(" OFFSET ")
+            .push_bind((page - 1) * per_page);
+
+        query
+            .build()
+            .try_map(|row| FailedRecord::<T>::from_row(&row))
+            .fetch_all(&self.client)
+            .await
+    }
+
+    pub async fn query_failed_by_id(
+        &self,
+        id: i64,
+    ) -> Result<Option<FailedRecord<T>>, sqlx::Error> {
+        sqlx::query(
+            r#"
+            SELECT
+               id,
+               parents,
+               item,
+               message
+            FROM
+               failed
+            WHERE
+               id = ?1
+            "#,
+        )
+        .bind(id)
+        .try_map(|row| FailedRecord::<T>::from_row(&row))
+        .fetch_optional(&self.client)
+        .await
+    }
+
+    /// Count the failed items matching the provided filters. The filters are the same as in
+    /// [`Self::query_failed`].
+    pub async fn count_failed(
+        &self,
+        mut item_filters: Vec<String>,
+        mut message_filters: Vec<String>,
+    ) -> Result<i64, sqlx::Error> {
+        // default to all-inclusive filter if none are provided
+        if item_filters.is_empty() {
+            item_filters.push("%".to_owned())
+        }
+
+        if message_filters.is_empty() {
+            message_filters.push("%".to_owned())
+        }
+
+        let mut query = QueryBuilder::<Sqlite>::new("SELECT COUNT(*) AS count FROM failed WHERE ");
+
+        push_like_any(&mut query, "item", item_filters);
+        query.push(" AND ");
+        push_like_any(&mut query, "message", message_filters);
+
+        query
+            .build()
+            .try_map(|row| row.try_get("count"))
+            .fetch_one(&self.client)
+            .await
+    }
+
+    /// Move up to `limit` failed items with an id greater than `after` and matching the provided
+    /// filters (see [`Self::query_failed`]) back into the queue, in ascending id order.
+    ///
+    /// `transform` is applied to each item before it is requeued. If it returns an error, the item
+    /// is left in the failed table and the error is returned in [`RequeueBatch::errors`].
+    ///
+    /// Requeued items keep their original id and parents, such that their history is preserved.
+    pub async fn requeue_failed<E>(
+        &self,
+        after: i64,
+        limit: i64,
+        mut item_filters: Vec<String>,
+        mut message_filters: Vec<String>,
+        mut transform: impl FnMut(Op<T>) -> Result<Op<T>, E>,
+    ) -> Result<RequeueBatch<E>, sqlx::Error> {
+        // default to all-inclusive filter if none are provided
+        if item_filters.is_empty() {
+            item_filters.push("%".to_owned())
+        }
+
+        if message_filters.is_empty() {
+            message_filters.push("%".to_owned())
+        }
+
+        let mut tx = self.client.begin().await?;
+
+        let mut query = QueryBuilder::<Sqlite>::new(
+            r#"
+            SELECT
+                id,
+                parents,
+                item,
+                created_at
+            FROM
+                failed
+            WHERE
+                id > "#,
+        );
+
+        query.push_bind(after).push(" AND ");
+        push_like_any(&mut query, "item", item_filters);
+        query.push(" AND ");
+        push_like_any(&mut query, "message", message_filters);
+        query.push(" ORDER BY id ASC LIMIT ").push_bind(limit);
+
+        let records = query
+            .build()
+            .try_map(|row| RequeueRecord::from_row(&row))
+            .fetch_all(tx.as_mut())
+            .await?;
+
+        let mut batch = RequeueBatch {
+            requeued: vec![],
+            errors: vec![],
+            last_id: records.last().map(|record| record.id),
+        };
+
+        let now = now_millis();
+
+        for record in records {
+            let op = de::<Op<T>>(&record.item).map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
+
+            let op = match transform(op) {
+                Ok(op) => op,
+                Err(error) => {
+                    batch.errors.push((record.id, error));
+                    continue;
+                }
+            };
+
+            sqlx::query("DELETE FROM failed WHERE id = ?1")
+                .bind(record.id)
+                .execute(tx.as_mut())
+                .await?;
+
+            sqlx::query(
+                "
+                INSERT INTO
+                queue  (id, item, parents, created_at, handle_at)
+                VALUES (?1, ?2,   ?3,      ?4,         ?5       )
+                ",
+            )
+            .bind(record.id)
+            .bind(Json(op))
+            .bind(record.parents)
+            .bind(record.created_at)
+            .bind(now)
+            .execute(tx.as_mut())
+            .await?;
+
+            debug!(id = record.id, "requeued failed item");
+
+            batch.requeued.push(record.id);
+        }
+
+        tx.commit().await?;
+
+        Ok(batch)
+    }
+
+    pub async fn stats(&self) -> Result<Stats, sqlx::Error> {
+        sqlx::query(
+            r#"
+            SELECT
+              (
+                SELECT
+                  count(*)
+                FROM
+                  queue
+              ) total,
+              (
+                SELECT
+                  count(*)
+                FROM
+                  queue
+                WHERE
+                  handle_at < ?1
+              ) ready,
+              (
+                SELECT
+                  json_group_object(tag, count)
+                FROM
+                  (
+                    SELECT
+                      tag,
+                      count(*) AS count
+                    FROM
+                      optimize
+                    GROUP BY
+                      tag
+                  )
+              ) optimize
+            "#,
+        )
+        .bind(now_millis())
+        .try_map(|row| Stats::from_row(&row))
+        .fetch_one(&self.client)
+        .await
+    }
+
+    /// The number and age of the items in the optimize table, per tag.
+    pub async fn optimize_stats(&self) -> Result<Vec<OptimizeStats>, sqlx::Error> {
+        sqlx::query(
+            r#"
+            SELECT
+              tag,
+              count(*) AS count,
+              (?1 - min(created_at)) / 1000.0 AS max_age_seconds,
+              avg(?1 - created_at) / 1000.0 AS avg_age_seconds
+            FROM
+              optimize
+            GROUP BY
+              tag
+            ORDER BY
+              tag ASC
+            "#,
+        )
+        .bind(now_millis())
+        .try_map(|row| OptimizeStats::from_row(&row))
+        .fetch_all(&self.client)
+        .await
+    }
+
+    /// Query the optimize table, optionally only returning items with the provided tag. Items are
+    /// returned oldest first.
+    pub async fn query_optimize(
+        &self,
+        tag: Option<String>,
+        page: i64,
+        per_page: i64,
+    ) -> Result<Vec<OptimizeItem<T>>, sqlx::Error> {
+        sqlx::query(
+            r#"
+            SELECT
+                id,
+                parents,
+                tag,
+                item,
+                (?1 - created_at) / 1000.0 AS age_seconds
+            FROM
+                optimize
+            WHERE
+                ?2 IS NULL
+                OR tag = ?2
+            ORDER BY
+                id ASC
+            LIMIT
+                ?3
+            OFFSET
+                ?4
+            "#,
+        )
+        .bind(now_millis())
+        .bind(tag)
+        .bind(per_page)
+        .bind((page - 1) * per_page)
+        .try_map(|row| OptimizeItem::<T>::from_row(&row))
+        .fetch_all(&self.client)
+        .await
+    }
+
+    /// Move all items with the provided tag from the optimize table directly into the queue,
+    /// bypassing the optimization pass of the plugin that owns the tag.
+    ///
+    /// Flushed items keep their original id, parents and creation time, such that their history is
+    /// preserved, and are enqueued with the default priority. Items that are currently being
+    /// optimized are skipped. Note that an op that is interesting to multiple plugins has a
+    /// separate row for each tag, and only the row for `tag` is flushed. Returns the ids of the
+    /// flushed items.
+    pub async fn flush_optimize(&self, tag: &str) -> Result<Vec<i64>, sqlx::Error> {
+        let mut tx = self.client.begin().await?;
+
+        let records = sqlx::query(
+            r#"
+            DELETE FROM
+              optimize
+            WHERE
+              tag = ?1
+              AND locked = 0
+            RETURNING
+              id,
+              parents,
+              item,
+              created_at
+            "#,
+        )
+        .bind(tag)
+        .try_map(|row| RequeueRecord::from_row(&row))
+        .fetch_all(tx.as_mut())
+        .await?;
+
+        let now = now_millis();
+
+        let mut ids = vec![];
+
+        for record in records {
+            sqlx::query(
+                "
+                INSERT INTO
+                queue  (id, item, parents, created_at, handle_at)
+                VALUES (?1, ?2,   ?3,      ?4,         ?5       )
+                ",
+            )
+            .bind(record.id)
+            .bind(record.item)
+            .bind(record.parents)
+            .bind(record.created_at)
+            .bind(now)
+            .execute(tx.as_mut())
+            .await?;
+
+            debug!(id = record.id, "flushed optimize item");
+
+            ids.push(record.id);
+        }
+
+        tx.commit().await?;
+
+        ids.sort_unstable();
+
+        Ok(ids)
+    }
+
+    pub async fn truncate(&self, tables: Tables) -> Result<(), sqlx::Error> {
+        if tables.queue {
+            sqlx::query(r"DELETE FROM queue")
+                .execute(&self.client)
+                .await?;
+        }
+
+        if tables.optimize {
+            sqlx::query(r"DELETE FROM optimize")
+                .execute(&self.client)
+                .await?;
+        }
+
+        if tables.done {
+            sqlx::query(r"DELETE FROM done")
+                .execute(&self.client)
+                .await?;
+        }
+
+        if tables.failed {
+            sqlx::query(r"DELETE FROM failed")
+                .execute(&self.client)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// SQLite can only vacuum the entire database, so this will run a single `VACUUM` if any of
+    /// the tables are selected.
+    pub async fn vacuum(&self, tables: Tables) -> Result<(), sqlx::Error> {
+        if tables.queue || tables.optimize || tables.done || tables.failed {
+            sqlx::query(r"VACUUM").execute(&self.client).await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct Stats {
+    pub total: i64,
+    pub ready: i64,
+    pub optimize: Json<BTreeMap<String, u64>>,
+}
+
+/// The items in the optimize table for a single tag.
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct OptimizeStats {
+    pub tag: String,
+    pub count: i64,
+    /// The age of the oldest item with this tag, in seconds.
+    pub max_age_seconds: f64,
+    /// The average age of the items with this tag, in seconds.
+    pub avg_age_seconds: f64,
+}
+
+/// An item in the optimize table, as returned by [`SqliteQueue::query_optimize`].
+#[derive(Debug, FromRow, Serialize)]
+#[serde(bound(serialize = ""))]
+pub struct OptimizeItem<T: QueueMessage> {
+    pub id: i64,
+    pub parents: Json<Vec<i64>>,
+    pub tag: String,
+    pub item: Json<Op<T>>,
+    /// How long this item has been in the optimize table, in seconds. Items produced by an
+    /// optimization pass inherit the age of the items they were produced from.
+    pub age_seconds: f64,
+}
+
+pub struct Tables {
+    pub queue: bool,
+    pub optimize: bool,
+    pub done: bool,
+    pub failed: bool,
+}
+
+impl<T: QueueMessage> voyager_vm::Queue<T> for SqliteQueue<T> {
+    type Config = SqliteQueueConfig;
+    type Error = sqlx::Error;
+
+    async fn new(config: Self::Config) -> Result<Self, Self::Error> {
+        let optimize_batch_limit = config.optimize_batch_limit;
+        let retryable_error_expo_backoff_multiplier =
+            config.retryable_error_expo_backoff_multiplier;
+        let retryable_error_expo_backoff_max = config.retryable_error_expo_backoff_max;
+        let vacuum_on_boot = config.vacuum_on_boot;
+        let anti_starvation_interval = config.anti_starvation_interval;
+        let idempotency_window_seconds = config.idempotency_window_seconds;
+
+        let pool = config.into_sqlite_pool().await?;
+
+        pool.execute_many(
+            r#"
+            -- ids are shared between the queue and optimize tables, as in pg-queue
+            CREATE TABLE IF NOT EXISTS
+              id_seq (
+                id INTEGER NOT NULL
+              );
+
+            INSERT INTO id_seq (id) SELECT 0 WHERE NOT EXISTS (SELECT 1 FROM id_seq);
+
+            CREATE TABLE IF NOT EXISTS
+              queue (
+                id INTEGER PRIMARY KEY,
+                item TEXT NOT NULL,
+                parents TEXT NOT NULL DEFAULT '[]',
+                created_at INTEGER NOT NULL,
+                handle_at INTEGER NOT NULL,
+                attempt INTEGER NOT NULL DEFAULT 0,
+                priority INTEGER NOT NULL DEFAULT 0,
+                locked INTEGER NOT NULL DEFAULT 0
+              );
+
+            CREATE TABLE IF NOT EXISTS
+              optimize (
+                id INTEGER PRIMARY KEY,
+                item TEXT NOT NULL,
+                tag TEXT NOT NULL,
+                parents TEXT NOT NULL DEFAULT '[]',
+                created_at INTEGER NOT NULL,
+                locked INTEGER NOT NULL DEFAULT 0
+              );
+
+            CREATE TABLE IF NOT EXISTS
+              done (
+                id INTEGER NOT NULL,
+                item TEXT NOT NULL,
+                parents TEXT NOT NULL DEFAULT '[]',
+                created_at INTEGER NOT NULL,
+                PRIMARY KEY (id, created_at)
+              );
+
+            CREATE TABLE IF NOT EXISTS
+              failed (
+                id INTEGER PRIMARY KEY,
+                item TEXT NOT NULL,
+                parents TEXT NOT NULL DEFAULT '[]',
+                message TEXT,
+                created_at INTEGER NOT NULL
+              );
+
+            CREATE INDEX IF NOT EXISTS index_queue_locked_handle_at ON queue (locked, handle_at ASC);
+
+            CREATE INDEX IF NOT EXISTS index_queue_locked_priority_handle_at ON queue (locked, priority DESC, handle_at ASC);
+
+            CREATE INDEX IF NOT EXISTS optimize_tag_id_idx ON optimize (tag, id);
+
+            CREATE TABLE IF NOT EXISTS
+              idempotency_key (
+                key BLOB PRIMARY KEY,
+                created_at INTEGER NOT NULL
+              );
+
+            CREATE INDEX IF NOT EXISTS index_idempotency_key_created_at ON idempotency_key (created_at);
+
+            -- any items that were being processed when the queue was last closed need to be picked up again
+            UPDATE queue SET locked = 0 WHERE locked = 1;
+
+            UPDATE optimize SET locked = 0 WHERE locked = 1;
+            "#,
+        )
+        .try_for_each(|result| async move {
+            trace!("rows affected: {}", result.rows_affected());
+            Ok(())
+        })
+        .instrument(info_span!("init"))
+        .await?;
+
+        // the admission key of the item, see InterestFilter::admission_key. admission_limited is
+        // null if the item has not been classified yet. sqlite doesn't support adding columns only
+        // if they don't exist yet, so the columns are added to existing databases here.
+        let has_admission_key = sqlx::query(
+            "SELECT COUNT(*) FROM pragma_table_info('queue') WHERE name = 'admission_limited'",
+        )
+        .try_map(|x| x.try_get::<i64, _>(0))
+        .fetch_one(&pool)
+        .await?
+            != 0;
+
+        if !has_admission_key {
+            pool.execute_many(
+                r#"
+                ALTER TABLE queue ADD COLUMN admission_plugin TEXT;
+                ALTER TABLE queue ADD COLUMN admission_chain_id TEXT;
+                ALTER TABLE queue ADD COLUMN admission_limited INTEGER;
+                "#,
+            )
+            .try_for_each(|_| async move { Ok(()) })
+            .instrument(info_span!("migrate"))
+            .await?;
+        }
+
+        let this = Self {
+            client: pool,
+            optimize_batch_limit,
+            retryable_error_expo_backoff_max,
+            retryable_error_expo_backoff_multiplier,
+            anti_starvation_interval,
+            idempotency_window_seconds,
+            processed: Arc::new(AtomicU32::new(0)),
+            metrics: Metrics::new(),
+            __marker: PhantomData,
+        };
+
+        if idempotency_window_seconds != 0 {
+            tokio::spawn(prune_idempotency_keys(
+                this.client.clone(),
+                idempotency_window_seconds,
+            ));
+        }
+
+        if vacuum_on_boot {
+            this.vacuum(Tables {
+                queue: true,
+                optimize: true,
+                done: true,
+                failed: true,
+            })
+            .await?;
+        }
+
+        Ok(this)
+    }
+
+    async fn enqueue<'a, Filter: InterestFilter<T>>(
+        &'a self,
+        op: Op<T>,
+        filter: &'a Filter,
+    ) -> Result<EnqueueResult, Self::Error> {
+        trace!("enqueue");
+
+        let mut tx = self.client.begin().await?;
+
+        let (optimize, ready): (Vec<_>, Vec<_>) = dedupe(
+            &self.metrics,
+            &mut tx,
+            filter,
+            self.idempotency_window_seconds,
+            op.normalize(),
+        )
+        .await?
+        .into_iter()
+        .partition_map(|op| match filter.check_interest(&op) {
+            FilterResult::Interest(interest) => Either::Left((op, interest)),
+            FilterResult::NoInterest => Either::Right(op),
+        });
+
+        let ready_ids = insert_queue(
+            &self.metrics,
+            &mut tx,
+            filter,
+            &[],
+            ready.into_iter().map(|op| {
+                let priority = filter.priority(&op, None);
+                (op, priority)
+            }),
+        )
+        .await?;
+
+        for ready in &ready_ids {
+            debug!(id = ready, "enqueued ready item");
+        }
+
+        let optimize_further_ids = insert_optimize(
+            &mut tx,
+            &[],
+            now_millis(),
+            optimize.into_iter().flat_map(|(op, interest)| {
+                interest.tags.into_iter().map(move |tag| (op.clone(), tag))
+            }),
+        )
+        .await?;
+
+        for ready in &optimize_further_ids {
+            debug!(id = ready, "enqueued optimize item");
+        }
+
+        tx.commit().await?;
+
+        Ok(EnqueueResult {
+            queue: ready_ids
+                .into_iter()
+                .map(|id| ItemId::new(id).expect("invalid id returned from database"))
+                .collect(),
+            optimize: optimize_further_ids
+                .into_iter()
+                .map(|id| ItemId::new(id).expect("invalid id returned from database"))
+                .collect(),
+        })
+    }
+
+    #[instrument(level = "trace", skip_all)]
+    async fn process<'a, F, Fut, R, Filter>(
+        &'a self,
+        filter: &'a Filter,
+        f: F,
+    ) -> Result<Option<R>, Self::Error>
+    where
+        F: (FnOnce(Op<T>, ItemId) -> Fut) + Send + Captures<'a>,
+        Fut: Future<Output = (R, Result<Vec<Op<T>>, QueueError>)> + Send + Captures<'a>,
+        R: Send + Sync + 'static,
+        Filter: InterestFilter<T>,
+    {
+        trace!("process");
+
+        // only items that are actually taken from the queue count towards the interval, such that
+        // empty polls don't affect how often priorities are ignored
+        let fifo = self.anti_starvation_interval != 0
+            && self.processed.load(Ordering::Relaxed) % self.anti_starvation_interval
+                == self.anti_starvation_interval - 1;
+
+        let held = filter.held();
+
+        // held items are skipped by the query itself, such that a backlog of held items is never
+        // read, and only the single candidate that is locked is read and decoded. candidates that
+        // are not admitted (i.e. because they haven't been classified yet, or a chain became
+        // unavailable since `held` was read) are unlocked again and left in place, and excluded
+        // from the following queries of this poll.
+        let mut skipped = vec![];
+        let mut row = None;
+
+        while skipped.len() < MAX_ADMISSION_ATTEMPTS {
+            let candidate = sqlx::query(if fifo {
+                r#"
+                UPDATE
+                  queue
+                SET
+                  locked = 1
+                WHERE
+                  id = (
+                    SELECT
+                      id
+                    FROM
+                      queue
+                    WHERE
+                      locked = 0
+                      AND handle_at < ?1
+                      AND (admission_plugin IS NULL OR admission_plugin NOT IN (SELECT value FROM json_each(?2)))
+                      AND (admission_chain_id IS NULL OR admission_chain_id NOT IN (SELECT value FROM json_each(?3)))
+                      AND (admission_chain_id IS NULL OR NOT admission_limited OR admission_chain_id NOT IN (SELECT value FROM json_each(?4)))
+                      AND id NOT IN (SELECT value FROM json_each(?5))
+                    ORDER BY
+                      handle_at ASC
+                    LIMIT 1)
+                RETURNING
+                  id,
+                  parents,
+                  item,
+                  attempt,
+                  priority,
+                  handle_at,
+                  created_at,
+                  admission_limited IS NULL AS unclassified
+                "#
+            } else {
+                r#"
+                UPDATE
+                  queue
+                SET
+                  locked = 1
+                WHERE
+                  id = (
+                    SELECT
+                      id
+                    FROM
+                      queue
+                    WHERE
+                      locked = 0
+                      AND handle_at < ?1
+                      AND (admission_plugin IS NULL OR admission_plugin NOT IN (SELECT value FROM json_each(?2)))
+                      AND (admission_chain_id IS NULL OR admission_chain_id NOT IN (SELECT value FROM json_each(?3)))
+                      AND (admission_chain_id IS NULL OR NOT admission_limited OR admission_chain_id NOT IN (SELECT value FROM json_each(?4)))
+                      AND id NOT IN (SELECT value FROM json_each(?5))
+                    ORDER BY
+                      priority DESC,
+                      handle_at ASC
+                    LIMIT 1)
+                RETURNING
+                  id,
+                  parents,
+                  item,
+                  attempt,
+                  priority,
+                  handle_at,
+                  created_at,
+                  admission_limited IS NULL AS unclassified
+                "#
+            })
+            .bind(now_millis())
+            .bind(Json(&held.plugins))
+            .bind(Json(&held.chains))
+            .bind(Json(&held.limited_chains))
+            .bind(Json(&skipped))
+            .try_map(|x| {
+                Ok((
+                    QueueRecord::from_row(&x)?,
+                    x.try_get::<bool, _>("unclassified")?,
+                ))
+            })
+            .fetch_optional(&self.client)
+            .await?;
+
+            let Some((record, unclassified)) = candidate else {
+                break;
+            };
+
+            let id = record.id;
+
+            // items that fail to decode are admitted, such that the error is surfaced when
+            // processing them
+            let admission = match de::<Op<T>>(&record.item) {
+                Ok(op) => {
+                    // items that were inserted without an admission key (i.e. requeued or flushed
+                    // items) are classified once they are read, such that the query skips them from
+                    // then on if they are held
+                    let admission_key = unclassified.then(|| filter.admission_key(&op)).flatten();
+
+                    let is_held = admission_key
+                        .as_ref()
+                        .is_some_and(|admission_key| held.holds(admission_key));
+
+                    if admission_key.is_some() {
+                        let (admission_plugin, admission_chain_id, admission_limited) =
+                            admission_columns(admission_key);
+
+                        sqlx::query(
+                            "
+                            UPDATE
+                              queue
+                            SET
+                              admission_plugin = ?2,
+                              admission_chain_id = ?3,
+                              admission_limited = ?4
+                            WHERE
+                              id = ?1
+                            ",
+                        )
+                        .bind(id)
+                        .bind(admission_plugin)
+                        .bind(admission_chain_id)
+                        .bind(admission_limited)
+                        .execute(&self.client)
+                        .await?;
+                    }
+
+                    if is_held {
+                        trace!(%id, "item is held");
+                        None
+                    } else {
+                        filter.admit(&op)
+                    }
+                }
+                Err(_) => Some(Admission::default()),
+            };
+
+            let Some(admission) = admission else {
+                trace!(%id, "item not admitted");
+
+                sqlx::query("UPDATE queue SET locked = 0 WHERE id = ?1")
+                    .bind(id)
+                    .execute(&self.client)
+                    .await?;
+
+                skipped.push(id);
+
+                continue;
+            };
+
+            self.processed.fetch_add(1, Ordering::Relaxed);
+
+            row = Some((record, admission));
+
+            break;
+        }
+
+        let Some((record, _admission)) = row else {
+            return Ok(None);
+        };
+
+        let id = record.id;
+
+        let res = process_item(
+            &self.metrics,
+            &self.client,
+            record,
+            f,
+            filter,
+            self.retryable_error_expo_backoff_max,
+            self.retryable_error_expo_backoff_multiplier,
+            self.idempotency_window_seconds,
+        )
+        .await;
+
+        if res.is_err() {
+            // release the item so that it can be picked up again, as if the transaction was rolled back
+            sqlx::query("UPDATE queue SET locked = 0 WHERE id = ?1")
+                .bind(id)
+                .execute(&self.client)
+                .await?;
+        }
+
+        res
+    }
+
+    #[instrument(level = "trace", skip_all, fields(%tag))]
+    async fn optimize<'a, O, Filter>(
+        &'a self,
+        tag: &'a str,
+        filter: &'a Filter,
+        optimizer: &'a O,
+    ) -> Result<(), Either<Self::Error, O::Error>>
+    where
+        O: Pass<T>,
+        Filter: InterestFilter<T>,
+    {
+        trace!(%tag, "optimize");
+
+        let mut msgs = sqlx::query(
+            r#"
+            UPDATE
+              optimize
+            SET
+              locked = 1
+            WHERE
+              id IN (
+                SELECT
+                  id
+                FROM
+                  optimize
+                WHERE
+                  tag = ?1
+                  AND locked = 0
+                ORDER BY
+                  id ASC
+                LIMIT ?2)
+            RETURNING
+              id,
+              parents,
+              item,
+              created_at
+            "#,
+        )
+        .bind(tag)
+        // a negative limit is no limit
+        .bind(self.optimize_batch_limit.unwrap_or(-1))
+        .try_map(|x| OptimizeRecord::from_row(&x))
+        .fetch_all(&self.client)
+        .await
+        .map_err(Either::Left)?;
+
+        if msgs.is_empty() {
+            trace!("optimizer queue is empty");
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            return Ok(());
+        }
+
+        // the order of RETURNING is unspecified in sqlite
+        msgs.sort_unstable_by_key(|r| r.id);
+
+        let ids = msgs.iter().map(|r| r.id).collect::<Vec<_>>();
+
+        let res = self.run_optimize(tag, filter, optimizer, msgs).await;
+
+        if res.is_err() {
+            // release the items so that they can be picked up again, as if the transaction was rolled back
+            sqlx::query(
+                "UPDATE optimize SET locked = 0 WHERE id IN (SELECT value FROM json_each(?1))",
+            )
+            .bind(Json(&ids))
+            .execute(&self.client)
+            .await
+            .map_err(Either::Left)?;
+        }
+
+        res
+    }
+
+    #[instrument(level = "trace", skip_all, fields(id = id.raw(), %max_depth))]
+    async fn history(
+        &self,
+        id: ItemId,
+        max_depth: u32,
+    ) -> Result<Vec<HistoryItem<T>>, Self::Error> {
+        let mut history = vec![];
+        let mut seen = HashSet::new();
+        let mut frontier = vec![id.raw()];
+
+        for depth in 0..=max_depth {
+            frontier.retain(|id| seen.insert(*id));
+
+            if frontier.is_empty() {
+                break;
+            }
+
+            let records = sqlx::query(
+                r#"
+                WITH ids AS (SELECT value AS id FROM json_each(?1))
+                SELECT id, parents, 'queued' AS status, item, NULL AS message FROM queue WHERE id IN (SELECT id FROM ids)
+                UNION ALL
+                SELECT id, parents, 'optimize' AS status, item, NULL AS message FROM optimize WHERE id IN (SELECT id FROM ids)
+                UNION ALL
+                SELECT id, parents, 'done' AS status, item, NULL AS message FROM done WHERE id IN (SELECT id FROM ids)
+                UNION ALL
+                SELECT id, parents, 'failed' AS status, item, message FROM failed WHERE id IN (SELECT id FROM ids)
+                ORDER BY id ASC
+                "#,
+            )
+            .bind(Json(&frontier))
+            .try_map(|row| HistoryRecord::from_row(&row))
+            .fetch_all(&self.client)
+            .await?;
+
+            frontier = vec![];
+
+            for record in records.into_iter().unique_by(|record| record.id) {
+                frontier.extend(&record.parents.0);
+                history.push(record.into_history_item(depth)?);
+            }
+        }
+
+        Ok(history)
+    }
+}
+
+#[derive(Debug, FromRow)]
+struct HistoryRecord {
+    id: i64,
+    parents: Json<Vec<i64>>,
+    status: String,
+    item: String,
+    message: Option<String>,
+}
+
+impl HistoryRecord {
+    fn into_history_item<T: QueueMessage>(self, depth: u32) -> Result<HistoryItem<T>, sqlx::Error> {
+        let item_id = |id: i64| {
+            ItemId::new(id).map_err(|e| sqlx::Error::Decode(format!("invalid item id: {e}").into()))
+        };
+
+        Ok(HistoryItem {
+            id: item_id(self.id)?,
+            parents: self
+                .parents
+                .0
+                .into_iter()
+                .map(item_id)
+                .collect::<Result<_, _>>()?,
+            status: match &*self.status {
+                "queued" => ItemStatus::Queued,
+                "optimize" => ItemStatus::Optimize,
+                "done" => ItemStatus::Done,
+                "failed" => ItemStatus::Failed,
+                status => {
+                    return Err(sqlx::Error::Decode(
+                        format!("unknown item status {status}").into(),
+                    ));
+                }
+            },
+            item: de(&self.item).map_err(|e| sqlx::Error::Decode(Box::new(e)))?,
+            message: self.message,
+            depth,
+        })
+    }
+}
+
+impl<T: QueueMessage> SqliteQueue<T> {
+    async fn run_optimize<O, Filter>(
+        &self,
+        tag: &str,
+        filter: &Filter,
+        optimizer: &O,
+        msgs: Vec<OptimizeRecord>,
+    ) -> Result<(), Either<sqlx::Error, O::Error>>
+    where
+        O: Pass<T>,
+        Filter: InterestFilter<T>,
+    {
+        let (ids, parents, created_ats, msgs): (Vec<_>, Vec<_>, Vec<_>, Vec<_>) = msgs
+            .into_iter()
+            .map(|r| {
+                Ok((
+                    r.id,
+                    r.parents.0,
+                    r.created_at,
+                    de(&r.item).map_err(|e| sqlx::Error::Decode(Box::new(e)))?,
+                ))
+            })
+            .collect::<Result<Vec<_>, sqlx::Error>>()
+            .map_err(Either::Left)?
+            .into_iter()
+            .multiunzip();
+
+        self.metrics.optimize_item_count.record(
+            msgs.len() as u64,
+            &[KeyValue::new("tag".to_owned(), tag.to_owned())],
+        );
+
+        let now_ms = now_millis();
+
+        for created_at in &created_ats {
+            self.metrics.optimize_item_age.record(
+                ((now_ms - created_at) as f64 / 1000.0).max(0.0),
+                &[KeyValue::new("tag".to_owned(), tag.to_owned())],
+            );
+        }
+
+        let now = std::time::Instant::now();
+
+        let PassResult {
+            optimize_further,
+            ready,
+        } = optimizer
+            .run_pass(msgs)
+            .instrument(trace_span!(
+                "optimizing items",
+                ids = ids
+                    .iter()
+                    .map(|id| id.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ))
+            .await
+            .map_err(Either::Right)?;
+        self.metrics.optimize_processing_duration.record(
+            Instant::now().duration_since(now).as_secs_f64(),
+            &[KeyValue::new("tag".to_owned(), tag.to_owned())],
+        );
+
+        trace!(
+            ready = ready.len(),
+            optimize_further = optimize_further.len(),
+            "optimized items"
+        );
+
+        // the consumed optimize items are deleted, so the new items inherit their parents
+        // directly; this keeps the lineage of an item traceable back through optimizer passes.
+        let get_parent_ids = |parent_idxs: &[usize]| {
+            parents
+                .iter()
+                .enumerate()
+                .filter(|(idx, _)| parent_idxs.contains(idx))
+                .flat_map(|(_, parents)| parents.iter().copied())
+                .sorted()
+                .dedup()
+                .collect::<Vec<_>>()
+        };
+
+        // items that are optimized further keep the age of the oldest item they were produced
+        // from, so that items held back by a pass (i.e. for batching) don't appear to be new
+        let get_created_at = |parent_idxs: &[usize]| {
+            created_ats
+                .iter()
+                .enumerate()
+                .filter(|(idx, _)| parent_idxs.contains(idx))
+                .map(|(_, created_at)| *created_at)
+                .min()
+                .unwrap_or(now_ms)
+        };
+
+        let mut tx = self.client.begin().await.map_err(Either::Left)?;
+
+        sqlx::query("DELETE FROM optimize WHERE id IN (SELECT value FROM json_each(?1))")
+            .bind(Json(&ids))
+            .execute(tx.as_mut())
+            .await
+            .map_err(Either::Left)?;
+
+        for (parent_idxs, new_msg, tag) in optimize_further {
+            let parents = get_parent_ids(&parent_idxs);
+            trace!(parent_idxs = ?&parent_idxs, parents = ?&parents);
+
+            for id in insert_optimize(
+                &mut tx,
+                &parents,
+                get_created_at(&parent_idxs),
+                [(new_msg, tag.as_str())],
+            )
+            .await
+            .map_err(Either::Left)?
+            {
+                debug!(id, "inserted new optimizer message");
+            }
+        }
+
+        for (parent_idxs, op) in ready {
+            let normalized_ops = op.normalize();
+
+            let parents = get_parent_ids(&parent_idxs);
+            trace!(parent_idxs = ?&parent_idxs, parents = ?&parents);
+
+            'block: for op in normalized_ops {
+                match filter.check_interest(&op) {
+                    FilterResult::Interest(Interest { tags, remove }) => {
+                        for id in insert_optimize(
+                            &mut tx,
+                            &parents,
+                            now_ms,
+                            tags.into_iter().map(|tag| (op.clone(), tag)),
+                        )
+                        .await
+                        .map_err(Either::Left)?
+                        {
+                            debug!(id, "inserted new optimizer message");
+                        }
+
+                        if remove {
+                            break 'block;
+                        }
+                    }
+                    FilterResult::NoInterest => {}
+                }
+
+                let priority = filter.priority(&op, Some(tag));
+
+                for id in insert_queue(&self.metrics, &mut tx, filter, &parents, [(op, priority)])
+                    .await
+                    .map_err(Either::Left)?
+                {
+                    debug!(id, "enqueued ready item");
+                }
+            }
+        }
+
+        tx.commit().await.map_err(Either::Left)?;
+
+        Ok(())
+    }
+}
+
+#[instrument(
+    skip_all,
+    fields(
+        item_id = record.id,
+        attempt = record.attempt
+    )
+)]
+async fn process_item<'a, T, F, Fut, R, Filter>(
+    metrics: &Metrics,
+    client: &SqlitePool,
+    record: QueueRecord,
+    f: F,
+    filter: &'a Filter,
+    retryable_error_expo_backoff_max: f64,
+    retryable_error_expo_backoff_multiplier: f64,
+    idempotency_window_seconds: u64,
+) -> Result<Option<R>, sqlx::Error>
+where
+    T: QueueMessage,
+    F: (FnOnce(Op<T>, ItemId) -> Fut) + Send + Captures<'a>,
+    Fut: Future<Output = (R, Result<Vec<Op<T>>, QueueError>)> + Send + Captures<'a>,
+    R: Send + Sync + 'static,
+    Filter: InterestFilter<T>,
+{
+    trace!(%record.item);
+
+    // really don't feel like defining a new error type right now
+    let op = de::<Op<T>>(&record.item).map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
+
+    let priority = Priority(u8::try_from(record.priority).unwrap_or_default());
+    let attributes = [KeyValue::new("priority", i64::from(priority.0))];
+
+    metrics.item_wait_duration.record(
+        (now_millis().saturating_sub(record.handle_at).max(0) as f64) / 1000.0,
+        &attributes,
+    );
+
+    let now = std::time::Instant::now();
+    let (r, res) = f(op.clone(), ItemId::new(record.id).unwrap()).await;
+    metrics.item_processing_duration.record(
+        Instant::now().duration_since(now).as_secs_f64(),
+        &attributes,
+    );
+
+    let mut tx = client.begin().await?;
+
+    match res {
+        Err(QueueError::Fatal(error)) => {
+            let error = full_error_string(error);
+            error!(%error, "fatal error");
+            insert_error(record, error, &mut tx).await?;
+            metrics.fatal_errors_count.add(1, &attributes);
+        }
+        Err(QueueError::Unprocessable(error)) => {
+            let error = full_error_string(error);
+            info!(%error, "unprocessable message");
+            insert_error(record, error, &mut tx).await?;
+            metrics.unprocessable_count.add(1, &attributes);
+        }
+        Err(QueueError::Retry(error)) => {
+            warn!(error = %full_error_string(error), "retryable error");
+
+            let backoff = Duration::try_from_secs_f64(
+                (record.attempt as f64)
+                    .powf(retryable_error_expo_backoff_multiplier)
+                    .clamp(f64::MIN, retryable_error_expo_backoff_max),
+            )
+            .unwrap_or(Duration::MAX);
+
+            sqlx::query(
+                "
+                UPDATE
+                  queue
+                SET
+                  locked = 0,
+                  attempt = ?2,
+                  handle_at = ?3
+                WHERE
+                  id = ?1
+                ",
+            )
+            .bind(record.id)
+            .bind(record.attempt.saturating_add(1))
+            .bind(
+                now_millis().saturating_add(i64::try_from(backoff.as_millis()).unwrap_or(i64::MAX)),
+            )
+            .execute(tx.as_mut())
+            .await?;
+
+            // no sleep here, the backoff is encoded in handle_at and sleeping while the transaction
+            // is open would hold the write lock of the database
+            metrics.retryable_errors_count.add(1, &attributes);
+        }
+        Ok(ops) => {
+            sqlx::query("DELETE FROM queue WHERE id = ?1")
+                .bind(record.id)
+                .execute(tx.as_mut())
+                .await?;
+
+            // insert the op we just processed into done
+            sqlx::query(
+                "
+                INSERT INTO
+                done   (id, parents, item, created_at)
+                VALUES (?1, ?2,      ?3,   ?4        )
+                ",
+            )
+            .bind(record.id)
+            .bind(&record.parents)
+            .bind(&record.item)
+            .bind(record.created_at)
+            .execute(tx.as_mut())
+            .await?;
+
+            let (optimize, ready): (Vec<_>, Vec<_>) = dedupe(
+                metrics,
+                &mut tx,
+                filter,
+                idempotency_window_seconds,
+                ops.into_iter().flat_map(Op::normalize).collect(),
+            )
+            .await?
+            .into_iter()
+            .partition_map(|op| match filter.check_interest(&op) {
+                FilterResult::Interest(tag) => Either::Left((op, tag)),
+                FilterResult::NoInterest => Either::Right(op),
+            });
+
+            // new items are at least as important as the item that produced them
+            insert_queue(
+                metrics,
+                &mut tx,
+                filter,
+                &[record.id],
+                ready.into_iter().map(|op| {
+                    let priority = filter.priority(&op, None).max(priority);
+                    (op, priority)
+                }),
+            )
+            .await?;
+
+            insert_optimize(
+                &mut tx,
+                &[record.id],
+                now_millis(),
+                optimize.iter().flat_map(|(op, interest)| {
+                    interest.tags.iter().map(move |tag| (op.clone(), *tag))
+                }),
+            )
+            .await?;
+
+            metrics.processed_item_count.add(1, &attributes);
+        }
+    }
+
+    tx.commit().await?;
+
+    Ok(Some(r))
+}
+
+async fn insert_error(
+    record: QueueRecord,
+    error: String,
+    tx: &mut Transaction<'static, Sqlite>,
+) -> Result<(), sqlx::Error> {
+    // move the op into failed, along with the error message
+
+    sqlx::query("DELETE FROM queue WHERE id = ?1")
+        .bind(record.id)
+        .execute(tx.as_mut())
+        .await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO
+        failed (id, parents, item, created_at, message)
+        VALUES (?1, ?2,      ?3,   ?4,         ?5     )
+        "#,
+    )
+    .bind(record.id)
+    .bind(record.parents)
+    .bind(record.item)
+    .bind(record.created_at)
+    .bind(error)
+    .execute(tx.as_mut())
+    .await?;
+
+    Ok(())
+}
+
+/// Allocate `n` new ids from the shared id sequence.
+async fn next_ids(
+    tx: &mut Transaction<'static, Sqlite>,
+    n: usize,
+) -> Result<std::ops::RangeInclusive<i64>, sqlx::Error> {
+    let n = i64::try_from(n).expect("too many items");
+
+    let last = sqlx::query("UPDATE id_seq SET id = id + ?1 RETURNING id")
+        .bind(n)
+        .try_map(|x| Id::from_row(&x))
+        .fetch_one(tx.as_mut())
+        .await?
+        .id;
+
+    Ok((last - n + 1)..=last)
+}
+
+/// The `admission_plugin`, `admission_chain_id` and `admission_limited` columns of a ready item. Items
+/// without an [`AdmissionKey`] are left unclassified.
+fn admission_columns(
+    admission_key: Option<AdmissionKey>,
+) -> (Option<String>, Option<String>, Option<bool>) {
+    match admission_key {
+        Some(AdmissionKey {
+            plugin,
+            chain_id,
+            limited,
+        }) => (plugin, chain_id, Some(limited)),
+        None => (None, None, None),
+    }
+}
+
+async fn insert_queue<T: QueueMessage>(
+    metrics: &Metrics,
+    tx: &mut Transaction<'static, Sqlite>,
+    filter: &impl InterestFilter<T>,
+    parents: &[i64],
+    ops: impl IntoIterator<Item = (Op<T>, Priority)>,
+) -> Result<Vec<i64>, sqlx::Error> {
+    let ops = ops.into_iter().collect::<Vec<_>>();
+
+    if ops.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let now = now_millis();
+
+    let mut ids = vec![];
+
+    for (id, (op, priority)) in next_ids(tx, ops.len()).await?.zip(ops) {
+        let (admission_plugin, admission_chain_id, admission_limited) =
+            admission_columns(filter.admission_key(&op));
+
+        sqlx::query(
+            "
+            INSERT INTO
+            queue  (id, item, parents, created_at, handle_at, priority, admission_plugin, admission_chain_id, admission_limited)
+            VALUES (?1, ?2,   ?3,      ?4,         ?4,        ?5,       ?6,               ?7,                 ?8               )
+            ",
+        )
+        .bind(id)
+        .bind(Json(op))
+        .bind(Json(parents))
+        .bind(now)
+        .bind(priority.0)
+        .bind(admission_plugin)
+        .bind(admission_chain_id)
+        .bind(admission_limited)
+        .execute(tx.as_mut())
+        .await?;
+
+        metrics
+            .enqueued_item_count
+            .add(1, &[KeyValue::new("priority", i64::from(priority.0))]);
+
+        ids.push(id);
+    }
+
+    Ok(ids)
+}
+
+/// Drop all ops whose idempotency key has already been seen within the idempotency window, recording
+/// the keys of the remaining ops.
+///
+/// The keys are recorded in `tx`, so they are only persisted if the ops are actually inserted. Only
+/// the sha256 hash of each key is stored, such that keys of any length can be used.
+async fn dedupe<T: QueueMessage, Filter: InterestFilter<T>>(
+    metrics: &Metrics,
+    tx: &mut Transaction<'static, Sqlite>,
+    filter: &Filter,
+    idempotency_window_seconds: u64,
+    ops: Vec<Op<T>>,
+) -> Result<Vec<Op<T>>, sqlx::Error> {
+    if idempotency_window_seconds == 0 {
+        return Ok(ops);
+    }
+
+    let now = now_millis();
+    let expired_before = expired_before(now, idempotency_window_seconds);
+
+    let mut deduped = Vec::with_capacity(ops.len());
+
+    for op in ops {
+        let Some(key) = filter.idempotency_key(&op) else {
+            deduped.push(op);
+            continue;
+        };
+
+        let key_hash = Sha256::digest(key.as_bytes());
+
+        // an expired key is treated the same as a new key
+        let inserted = sqlx::query(
+            "
+            INSERT INTO idempotency_key (key, created_at)
+            VALUES (?1, ?2)
+            ON CONFLICT (key) DO UPDATE
+            SET created_at = ?2
+            WHERE idempotency_key.created_at < ?3
+            RETURNING key
+            ",
+        )
+        .bind(key_hash.as_slice())
+        .bind(now)
+        .bind(expired_before)
+        .fetch_optional(tx.as_mut())
+        .await?
+        .is_some();
+
+        if inserted {
+            deduped.push(op);
+        } else {
+            debug!(%key, "dropping duplicate op");
+
+            metrics.suppressed_duplicates_count.add(1, &[]);
+        }
+    }
+
+    Ok(deduped)
+}
+
+/// Delete the expired idempotency keys once per idempotency window, until `pool` is closed.
+///
+/// Expired keys are already ignored by [`dedupe`], so this only keeps the table from growing
+/// unbounded. It runs outside of the enqueue and process transactions such that they don't scan the
+/// table while holding the write lock.
+async fn prune_idempotency_keys(pool: SqlitePool, idempotency_window_seconds: u64) {
+    let mut interval = tokio::time::interval(Duration::from_secs(idempotency_window_seconds));
+
+    loop {
+        interval.tick().await;
+
+        if pool.is_closed() {
+            break;
+        }
+
+        let result = sqlx::query("DELETE FROM idempotency_key WHERE created_at < ?1")
+            .bind(expired_before(now_millis(), idempotency_window_seconds))
+            .execute(&pool)
+            .await;
+
+        match result {
+            Ok(result) => {
+                trace!(
+                    rows_affected = result.rows_affected(),
+                    "pruned idempotency keys"
+                );
+            }
+            Err(error) => {
+                warn!(
+                    error = %full_error_string(Box::new(error)),
+                    "error pruning idempotency keys"
+                );
+            }
+        }
+    }
+}
+
+/// The timestamp (in milliseconds) before which recorded idempotency keys are expired.
+fn expired_before(now: i64, idempotency_window_seconds: u64) -> i64 {
+    now.saturating_sub(
+        i64::try_from(idempotency_window_seconds)
+            .unwrap_or(i64::MAX)
+            .saturating_mul(1000),
+    )
+}
+
+async fn insert_optimize<T: QueueMessage>(
+    tx: &mut Transaction<'static, Sqlite>,
+    parents: &[i64],
+    created_at: i64,
+    ops: impl IntoIterator<Item = (Op<T>, &str)>,
+) -> Result<Vec<i64>, sqlx::Error> {
+    let ops = ops.into_iter().collect::<Vec<_>>();
+
+    if ops.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let mut ids = vec![];
+
+    for (id, (op, tag)) in next_ids(tx, ops.len()).await?.zip(ops) {
+        sqlx::query(
+            "
+            INSERT INTO
+            optimize (id, item, tag, parents, created_at)
+            VALUES   (?1, ?2,   ?3,  ?4,      ?5        )
+            ",
+        )
+        .bind(id)
+        .bind(Json(op))
+        .bind(tag)
+        .bind(Json(parents))
+        .bind(created_at)
+        .execute(tx.as_mut())
+        .await?;
+
+        ids.push(id);
+    }
+
+    Ok(ids)
+}
+
+/// Push `(column LIKE ? OR column LIKE ? ...)` onto the query.
+fn push_like_any(query: &mut QueryBuilder<'_, Sqlite>, column: &str, filters: Vec<String>) {
+    query.push("(");
+
+    let mut separated = query.separated(" OR ");
+    for filter in filters {
+        separated.push(format!("{column} LIKE "));
+        separated.push_bind_unseparated(filter);
+    }
+
+    query.push(")");
+}
+
+/// The current unix timestamp in milliseconds, which is how all timestamps are stored.
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("the current timestamp must be greater than the unix epoch")
+        .as_millis()
+        .try_into()
+        .expect("timestamp overflows i64")
+}
+
+// copied from unionlabs::ErrorReporter
+fn full_error_string(error: BoxDynError) -> String {
+    let mut s = String::new();
+
+    write!(s, "{}", error).unwrap();
+
+    for e in core::iter::successors(error.source(), |e| (*e).source()) {
+        write!(s, ": {e}").unwrap();
+    }
+
+    s
+}
+
+fn de<T: DeserializeOwned>(s: &str) -> Result<T, serde_json::Error> {
+    let mut deserializer = serde_json::Deserializer::from_str(s);
+    deserializer.disable_recursion_limit();
+    let json = T::deserialize(&mut deserializer)?;
+    Ok(json)
+}
@@ -0,0 +1,26 @@
+//! Runs the shared queue test suite against sqlite.
+
+use sqlite_queue::{SqliteQueue, SqliteQueueConfig};
+use voyager_vm::{
+    Queue,
+    test_utils::{SimpleMessage, queue_suite::unique_name},
+};
+
+async fn new_queue(idempotency_window_seconds: u64) -> SqliteQueue<SimpleMessage> {
+    SqliteQueue::new(SqliteQueueConfig {
+        path: std::env::temp_dir().join(format!("{}.db", unique_name("sqlite_queue_test"))),
+        max_connections: sqlite_queue::default_max_connections(),
+        busy_timeout: sqlite_queue::default_busy_timeout(),
+        optimize_batch_limit: None,
+        retryable_error_expo_backoff_max: sqlite_queue::default_retryable_error_expo_backoff_max(),
+        retryable_error_expo_backoff_multiplier:
+            sqlite_queue::default_retryable_error_expo_backoff_multiplier(),
+        vacuum_on_boot: false,
+        anti_starvation_interval: sqlite_queue::default_anti_starvation_interval(),
+        idempotency_window_seconds,
+    })
+    .await
+    .unwrap()
+}
+
+voyager_vm::queue_tests!(new_queue);
@@ -0,0 +1,245 @@
+//! A buffer for the events pushed by a websocket subscription, keyed by block height.
+//!
+//! A subscription only delivers the events of the blocks produced while it is connected, so the
+//! buffer tracks which heights it *covers*. A height is covered if the subscription was connected
+//! before the block was produced, and has since seen the head of a later block. Nodes deliver
+//! notifications in order, so this guarantees that all events of the block have been received.
+//! Heights that are not covered, such as the blocks produced while the subscription was
+//! reconnecting, must be fetched by polling instead.
+//!
+//! While connected, reorgs are handled by [removing](SubscriptionBuffer::remove) the events of the
+//! reorged blocks. Reorgs that happen while the subscription is disconnected are never pushed, so
+//! on reconnect the heights that were not yet finalized are no longer covered.
+
+use std::{collections::BTreeMap, ops::Range, sync::Mutex};
+
+use tracing::debug;
+
+#[derive(Debug)]
+pub struct SubscriptionBuffer<T> {
+    inner: Mutex<Inner<T>>,
+    retention: u64,
+}
+
+#[derive(Debug)]
+struct Inner<T> {
+    /// The covered heights, oldest first. Only the last range grows, while connected.
+    covered: Vec<Range<u64>>,
+    connected: bool,
+    events: BTreeMap<u64, Vec<T>>,
+}
+
+impl<T: Clone> SubscriptionBuffer<T> {
+    /// Create an empty buffer that keeps the events of the last `retention` blocks.
+    pub fn new(retention: u64) -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                covered: vec![],
+                connected: false,
+                events: BTreeMap::new(),
+            }),
+            retention,
+        }
+    }
+
+    /// The subscription has been (re)established, and `latest_height` was the latest height of the
+    /// chain at that time. All blocks after `latest_height` will be pushed by the subscription.
+    ///
+    /// Blocks after `finalized_height` may have been reorged while the subscription was not
+    /// connected, so they are no longer covered. If the finalized height is not known, nothing
+    /// that was covered before is.
+    pub fn connected(&self, latest_height: u64, finalized_height: Option<u64>) {
+        let mut inner = self.inner.lock().expect("mutex is not poisoned; qed;");
+
+        debug!(latest_height, ?finalized_height, "subscription connected");
+
+        let covered_until = finalized_height.map_or(0, |height| height + 1);
+
+        inner.covered = std::mem::take(&mut inner.covered)
+            .into_iter()
+            .map(|range| range.start..range.end.min(covered_until))
+            .filter(|range| !range.is_empty())
+            .collect();
+        inner.events.retain(|height, _| *height < covered_until);
+
+        inner.connected = true;
+        inner.covered.push(latest_height + 1..latest_height + 1);
+    }
+
+    /// The subscription was lost. Blocks produced until it is [connected](Self::connected) again
+    /// are not covered.
+    pub fn disconnected(&self) {
+        let mut inner = self.inner.lock().expect("mutex is not poisoned; qed;");
+
+        if inner.connected {
+            debug!(covered = ?inner.covered.last(), "subscription disconnected");
+        }
+
+        inner.connected = false;
+    }
+
+    /// The subscription has seen the head of the block at `height`, covering all heights before it.
+    pub fn head(&self, height: u64) {
+        let mut inner = self.inner.lock().expect("mutex is not poisoned; qed;");
+
+        if !inner.connected {
+            return;
+        }
+
+        let current = inner
+            .covered
+            .last_mut()
+            .expect("connected implies a range; qed;");
+        current.end = current.end.max(height);
+
+        let min_height = height.saturating_sub(self.retention);
+
+        inner.events = inner.events.split_off(&min_height);
+
+        let len = inner.covered.len();
+        inner.covered = std::mem::take(&mut inner.covered)
+            .into_iter()
+            .enumerate()
+            .filter(|(idx, range)| idx + 1 == len || range.end > min_height)
+            .map(|(_, range)| range.start.max(min_height)..range.end)
+            .collect();
+    }
+
+    /// Buffer an event emitted at `height`. Events received while disconnected are ignored.
+    pub fn insert(&self, height: u64, event: T) {
+        let mut inner = self.inner.lock().expect("mutex is not poisoned; qed;");
+
+        if inner.connected {
+            inner.events.entry(height).or_default().push(event);
+        }
+    }
+
+    /// Remove all events at `height` matching `f`, i.e. because the block they were emitted in was
+    /// reorged out.
+    pub fn remove(&self, height: u64, f: impl Fn(&T) -> bool) {
+        let mut inner = self.inner.lock().expect("mutex is not poisoned; qed;");
+
+        if let Some(events) = inner.events.get_mut(&height) {
+            events.retain(|event| !f(event));
+        }
+    }
+
+    /// Whether the subscription covers `height`.
+    pub fn covers(&self, height: u64) -> bool {
+        self.inner
+            .lock()
+            .expect("mutex is not poisoned; qed;")
+            .covers(height)
+    }
+
+    /// The events emitted at `height`, or `None` if the subscription does not cover `height` and it
+    /// must be fetched by polling instead.
+    pub fn get(&self, height: u64) -> Option<Vec<T>> {
+        let inner = self.inner.lock().expect("mutex is not poisoned; qed;");
+
+        inner
+            .covers(height)
+            .then(|| inner.events.get(&height).cloned().unwrap_or_default())
+    }
+}
+
+impl<T> Inner<T> {
+    fn covers(&self, height: u64) -> bool {
+        self.covered.iter().any(|range| range.contains(&height))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn covers_heights_seen_while_connected() {
+        let buffer = SubscriptionBuffer::new(100);
+
+        buffer.insert(5, "missed");
+
+        buffer.connected(10, Some(10));
+        buffer.insert(11, "a");
+        buffer.insert(11, "b");
+        buffer.head(11);
+
+        // the head of 11 has been seen, but its events may still be in flight
+        assert_eq!(buffer.get(10), None);
+        assert_eq!(buffer.get(11), None);
+
+        buffer.head(13);
+
+        assert_eq!(buffer.get(11), Some(vec!["a", "b"]));
+        assert_eq!(buffer.get(12), Some(vec![]));
+        assert_eq!(buffer.get(13), None);
+        assert_eq!(buffer.get(5), None);
+    }
+
+    #[test]
+    fn gap_on_reconnect() {
+        let buffer = SubscriptionBuffer::new(100);
+
+        buffer.connected(10, Some(10));
+        buffer.head(13);
+        buffer.disconnected();
+
+        buffer.insert(14, "missed");
+        buffer.head(16);
+
+        buffer.connected(17, Some(17));
+        buffer.insert(18, "a");
+        buffer.head(20);
+
+        assert!(buffer.covers(12));
+        assert!((13..=17).all(|height| !buffer.covers(height)));
+        assert_eq!(buffer.get(18), Some(vec!["a"]));
+    }
+
+    #[test]
+    fn reconnect_uncovers_unfinalized_heights() {
+        let buffer = SubscriptionBuffer::new(100);
+
+        buffer.connected(10, Some(5));
+        buffer.insert(11, "finalized");
+        buffer.insert(14, "reorged");
+        buffer.head(16);
+        buffer.disconnected();
+
+        buffer.connected(20, Some(12));
+        buffer.head(22);
+
+        assert_eq!(buffer.get(11), Some(vec!["finalized"]));
+        assert_eq!(buffer.get(12), Some(vec![]));
+        assert!((13..=20).all(|height| !buffer.covers(height)));
+        assert_eq!(buffer.get(21), Some(vec![]));
+
+        buffer.disconnected();
+        buffer.connected(30, None);
+        buffer.head(32);
+
+        assert!((0..=30).all(|height| !buffer.covers(height)));
+        assert_eq!(buffer.get(31), Some(vec![]));
+    }
+
+    #[test]
+    fn remove_and_prune() {
+        let buffer = SubscriptionBuffer::new(5);
+
+        buffer.connected(0, Some(0));
+        buffer.insert(1, 1);
+        buffer.insert(2, 2);
+        buffer.insert(2, 3);
+        buffer.remove(2, |event| *event == 2);
+        buffer.head(3);
+
+        assert_eq!(buffer.get(2), Some(vec![3]));
+
+        buffer.head(10);
+
+        assert_eq!(buffer.get(1), None);
+        assert_eq!(buffer.get(2), None);
+        assert_eq!(buffer.get(5), Some(vec![]));
+        assert_eq!(buffer.get(9), Some(vec![]));
+    }
+}
@@ -0,0 +1,314 @@
+//! A durable record of the transactions submitted by the transaction plugins.
+//!
+//! Every included transaction is written as a [`TxRecord`], containing the signer that submitted
+//! it, the gas and fees it used, and the outcome of every datagram in it along with the hashes of
+//! the packets that the datagram relayed. Records are written either to a table in a postgres
+//! database (usually the database of the queue) or appended as json lines to a local file, and can
+//! be searched by packet hash.
+
+use std::path::PathBuf;
+
+use futures::TryStreamExt;
+use ibc_union_spec::datagram::Datagram;
+use serde::{Deserialize, Serialize};
+use sqlx::{Executor, PgPool, postgres::PgPoolOptions, types::Json};
+use tokio::{
+    fs::{File, OpenOptions},
+    io::AsyncWriteExt,
+    sync::Mutex,
+};
+use tracing::{debug, trace};
+use unionlabs_primitives::H256;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type", deny_unknown_fields)]
+pub enum AuditLogConfig {
+    /// Write records to the `tx_audit_log` table of a postgres database. The table is created if
+    /// it does not exist.
+    Postgres { database_url: String },
+    /// Append records as json lines to a local file.
+    File { path: PathBuf },
+}
+
+/// A transaction that was included on chain.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TxRecord {
+    pub chain_id: String,
+    /// The address of the signer that submitted the transaction.
+    pub signer: String,
+    pub tx_hash: H256,
+    pub height: u64,
+    /// Unix timestamp (in seconds) of when the record was written.
+    pub timestamp: u64,
+    pub gas_used: u64,
+    /// The fee paid for the transaction, in the smallest unit of the fee token.
+    #[serde(with = "::serde_utils::string")]
+    pub fee: u128,
+    /// The denom of the fee token. This is `None` for the native token of evm chains.
+    #[serde(default)]
+    pub fee_denom: Option<String>,
+    pub messages: Vec<MessageRecord>,
+}
+
+impl TxRecord {
+    /// The hashes of all packets relayed in this transaction, including the ones whose datagram
+    /// reverted.
+    pub fn packet_hashes(&self) -> impl Iterator<Item = &H256> {
+        self.messages.iter().flat_map(|msg| &msg.packet_hashes)
+    }
+}
+
+/// A single datagram of a [`TxRecord`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MessageRecord {
+    /// The name of the datagram, i.e. `packet_recv`.
+    pub datagram: String,
+    pub packet_hashes: Vec<H256>,
+    pub outcome: MessageOutcome,
+}
+
+impl MessageRecord {
+    pub fn new(datagram: &Datagram, outcome: MessageOutcome) -> Self {
+        Self {
+            datagram: datagram.name().to_owned(),
+            packet_hashes: datagram
+                .packets()
+                .iter()
+                .map(|packet| packet.hash())
+                .collect(),
+            outcome,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type", deny_unknown_fields)]
+pub enum MessageOutcome {
+    Success,
+    Reverted { reason: String },
+    /// The transaction got stuck and was replaced by a cancellation, so the datagram was never
+    /// executed. The record is that of the cancellation.
+    Cancelled { replacements: u32 },
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuditLogError {
+    #[error("database error")]
+    Database(#[from] sqlx::Error),
+    #[error("io error")]
+    Io(#[from] std::io::Error),
+    #[error("invalid record at line {line}")]
+    InvalidRecord {
+        line: usize,
+        #[source]
+        error: serde_json::Error,
+    },
+}
+
+#[derive(Debug)]
+pub enum AuditLog {
+    Postgres(PgPool),
+    File { path: PathBuf, file: Mutex<File> },
+}
+
+impl AuditLog {
+    pub async fn new(config: AuditLogConfig) -> Result<Self, AuditLogError> {
+        match config {
+            AuditLogConfig::Postgres { database_url } => {
+                let db = PgPoolOptions::new().connect(&database_url).await?;
+
+                db.execute_many(
+                    r#"
+                    CREATE TABLE IF NOT EXISTS
+                      tx_audit_log (
+                        id BIGSERIAL PRIMARY KEY,
+                        chain_id TEXT NOT NULL,
+                        signer TEXT NOT NULL,
+                        tx_hash TEXT NOT NULL,
+                        packet_hashes TEXT[] NOT NULL,
+                        record JSONB NOT NULL
+                      );
+
+                    CREATE INDEX IF NOT EXISTS tx_audit_log_packet_hashes ON tx_audit_log USING GIN (packet_hashes);
+
+                    CREATE INDEX IF NOT EXISTS tx_audit_log_chain_id_signer ON tx_audit_log(chain_id, signer);
+                    "#,
+                )
+                .try_for_each(|result| async move {
+                    trace!("rows affected: {}", result.rows_affected());
+                    Ok(())
+                })
+                .await?;
+
+                Ok(Self::Postgres(db))
+            }
+            AuditLogConfig::File { path } => {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .await?;
+
+                Ok(Self::File {
+                    path,
+                    file: Mutex::new(file),
+                })
+            }
+        }
+    }
+
+    pub async fn record(&self, record: &TxRecord) -> Result<(), AuditLogError> {
+        debug!(tx_hash = %record.tx_hash, "writing tx audit log record");
+
+        match self {
+            Self::Postgres(db) => {
+                sqlx::query(
+                    r#"
+                    INSERT INTO
+                      tx_audit_log (chain_id, signer, tx_hash, packet_hashes, record)
+                    VALUES
+                      ($1, $2, $3, $4, $5)
+                    "#,
+                )
+                .bind(&record.chain_id)
+                .bind(&record.signer)
+                .bind(record.tx_hash.to_string())
+                .bind(
+                    record
+                        .packet_hashes()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>(),
+                )
+                .bind(Json(record))
+                .execute(db)
+                .await?;
+            }
+            Self::File { file, .. } => {
+                let mut line =
+                    serde_json::to_vec(record).expect("serialization is infallible; qed;");
+                line.push(b'\n');
+
+                let mut file = file.lock().await;
+                file.write_all(&line).await?;
+                file.flush().await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Find all transactions that relayed the packet with the hash `packet_hash`, oldest first.
+    pub async fn find_by_packet_hash(
+        &self,
+        packet_hash: &H256,
+    ) -> Result<Vec<TxRecord>, AuditLogError> {
+        match self {
+            Self::Postgres(db) => Ok(sqlx::query_scalar::<_, Json<TxRecord>>(
+                r#"
+                SELECT
+                  record
+                FROM
+                  tx_audit_log
+                WHERE
+                  packet_hashes @> ARRAY[$1::TEXT]
+                ORDER BY
+                  id ASC
+                "#,
+            )
+            .bind(packet_hash.to_string())
+            .fetch_all(db)
+            .await?
+            .into_iter()
+            .map(|Json(record)| record)
+            .collect()),
+            Self::File { path, .. } => {
+                let contents = tokio::fs::read_to_string(path).await?;
+
+                let mut records = vec![];
+
+                for (idx, line) in contents.lines().enumerate() {
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+
+                    let record = serde_json::from_str::<TxRecord>(line).map_err(|error| {
+                        AuditLogError::InvalidRecord {
+                            line: idx + 1,
+                            error,
+                        }
+                    })?;
+
+                    if record.packet_hashes().any(|hash| hash == packet_hash) {
+                        records.push(record);
+                    }
+                }
+
+                Ok(records)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(tx_hash: u8, packet_hashes: &[u8]) -> TxRecord {
+        TxRecord {
+            chain_id: "1".to_owned(),
+            signer: "0x0000000000000000000000000000000000000001".to_owned(),
+            tx_hash: H256::new([tx_hash; 32]),
+            height: 1,
+            timestamp: 1,
+            gas_used: 100_000,
+            fee: 1_000_000,
+            fee_denom: None,
+            messages: vec![
+                MessageRecord {
+                    datagram: "update_client".to_owned(),
+                    packet_hashes: vec![],
+                    outcome: MessageOutcome::Success,
+                },
+                MessageRecord {
+                    datagram: "packet_recv".to_owned(),
+                    packet_hashes: packet_hashes.iter().map(|b| H256::new([*b; 32])).collect(),
+                    outcome: MessageOutcome::Reverted {
+                        reason: "already received".to_owned(),
+                    },
+                },
+            ],
+        }
+    }
+
+    #[tokio::test]
+    async fn file() {
+        let path = std::env::temp_dir().join(format!("tx-audit-log-{}.jsonl", std::process::id()));
+
+        let log = AuditLog::new(AuditLogConfig::File { path: path.clone() })
+            .await
+            .unwrap();
+
+        log.record(&record(1, &[10, 11])).await.unwrap();
+        log.record(&record(2, &[11])).await.unwrap();
+        log.record(&record(3, &[])).await.unwrap();
+
+        assert_eq!(
+            log.find_by_packet_hash(&H256::new([11; 32])).await.unwrap(),
+            [record(1, &[10, 11]), record(2, &[11])]
+        );
+        assert_eq!(
+            log.find_by_packet_hash(&H256::new([10; 32])).await.unwrap(),
+            [record(1, &[10, 11])]
+        );
+        assert!(
+            log.find_by_packet_hash(&H256::new([12; 32]))
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
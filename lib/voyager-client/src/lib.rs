@@ -131,6 +131,20 @@ impl<C: VoyagerRpcClient> VoyagerClient<C> {
             .await?)
     }
 
+    #[instrument(skip_all, fields(%client_type, %ibc_interface))]
+    pub async fn encode_misbehaviour<V: IbcSpec>(
+        &self,
+        client_type: ClientType,
+        ibc_interface: IbcInterface,
+        header_a: Value,
+        header_b: Value,
+    ) -> RpcResult<Bytes> {
+        Ok(self
+            .0
+            .encode_misbehaviour(client_type, ibc_interface, V::ID, header_a, header_b)
+            .await?)
+    }
+
     #[instrument(skip_all, fields(%client_type, %ibc_interface))]
     pub async fn decode_client_state<V: IbcSpec, T: DeserializeOwned>(
         &self,
@@ -165,6 +179,22 @@ impl<C: VoyagerRpcClient> VoyagerClient<C> {
         ))
     }
 
+    #[instrument(skip_all, fields(%client_type, %ibc_interface))]
+    pub async fn decode_header<V: IbcSpec, T: DeserializeOwned>(
+        &self,
+        client_type: ClientType,
+        ibc_interface: IbcInterface,
+        header: Bytes,
+    ) -> RpcResult<T> {
+        let header = self
+            .0
+            .decode_header(client_type, ibc_interface, V::ID, header)
+            .await?;
+
+        serde_json::from_value(header)
+            .map_err(RpcError::fatal("error decoding header from json value"))
+    }
+
     #[instrument(skip_all, fields(%client_type, %ibc_interface))]
     pub async fn encode_client_state<V: IbcSpec>(
         &self,
@@ -0,0 +1,548 @@
+//! Per-chain concurrency limits and circuit breaking.
+//!
+//! The engine only has a single global worker count, so a chain with a misbehaving RPC can end up
+//! occupying every worker with items that will just be retried. [`ChainLimits`] tracks the number
+//! of in-flight calls per chain, and opens a circuit breaker for a chain after it has failed with
+//! [`QueueError::Retry`] too many times in a row. While a chain is at its in-flight limit or its
+//! breaker is open, any ops whose next call is for it are left in place in the queue and skipped
+//! when taking items from the queue (see [`EngineFilter`](crate::filter::EngineFilter)), until the
+//! chain is available again.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use opentelemetry::KeyValue;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::{debug, trace, warn};
+use voyager_primitives::ChainId;
+use voyager_rpc::types::ChainStatus;
+use voyager_vm::{QueueError, now};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+// schemars keys definitions by name, and this would otherwise collide with the cache `Config`
+#[schemars(rename = "ChainLimitsConfig")]
+pub struct Config {
+    /// The default maximum number of calls that can be in flight for a single chain at once. If
+    /// not set, the number of in-flight calls is only bounded by the number of workers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_in_flight: Option<usize>,
+    /// Per-chain overrides for `max_in_flight`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub max_in_flight_per_chain: HashMap<ChainId, usize>,
+    /// The number of consecutive retryable failures for a chain after which its circuit breaker
+    /// opens. Set to 0 to disable the circuit breaker.
+    #[serde(default = "default_breaker_failure_threshold")]
+    pub breaker_failure_threshold: u32,
+    /// How long the circuit breaker stays open the first time it trips. This is doubled for every
+    /// consecutive trip, up to `breaker_max_backoff_seconds`.
+    #[serde(default = "default_breaker_base_backoff_seconds")]
+    pub breaker_base_backoff_seconds: u64,
+    /// The maximum time the circuit breaker stays open, capping the doubled backoff.
+    #[serde(default = "default_breaker_max_backoff_seconds")]
+    pub breaker_max_backoff_seconds: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_in_flight: None,
+            max_in_flight_per_chain: HashMap::new(),
+            breaker_failure_threshold: default_breaker_failure_threshold(),
+            breaker_base_backoff_seconds: default_breaker_base_backoff_seconds(),
+            breaker_max_backoff_seconds: default_breaker_max_backoff_seconds(),
+        }
+    }
+}
+
+#[must_use]
+pub const fn default_breaker_failure_threshold() -> u32 {
+    5
+}
+
+#[must_use]
+pub const fn default_breaker_base_backoff_seconds() -> u64 {
+    1
+}
+
+#[must_use]
+pub const fn default_breaker_max_backoff_seconds() -> u64 {
+    60
+}
+
+#[derive(Debug)]
+pub struct ChainLimits {
+    config: Config,
+    chains: Mutex<HashMap<ChainId, Arc<ChainState>>>,
+
+    in_flight_metric: opentelemetry::metrics::UpDownCounter<i64>,
+    held_counter_metric: opentelemetry::metrics::Counter<u64>,
+    breaker_trip_counter_metric: opentelemetry::metrics::Counter<u64>,
+    breaker_open_metric: opentelemetry::metrics::Gauge<u64>,
+}
+
+#[derive(Debug)]
+struct ChainState {
+    max_in_flight: Option<usize>,
+    semaphore: Option<Arc<Semaphore>>,
+    in_flight: AtomicUsize,
+    breaker: Mutex<Breaker>,
+}
+
+#[derive(Debug, Default)]
+struct Breaker {
+    consecutive_failures: u32,
+    trips: u32,
+    /// Unix timestamp (in seconds) until which the breaker is open.
+    open_until: Option<u64>,
+}
+
+impl Breaker {
+    fn is_open(&self, now: u64) -> bool {
+        self.open_until.is_some_and(|open_until| open_until > now)
+    }
+}
+
+/// Returned by [`ChainLimits::try_acquire`] if a call for a chain may be handled. Holds the chain's
+/// in-flight slot until it is dropped.
+#[derive(Debug)]
+pub struct ChainPermit {
+    chain_id: ChainId,
+    state: Arc<ChainState>,
+    _permit: Option<OwnedSemaphorePermit>,
+    in_flight_metric: opentelemetry::metrics::UpDownCounter<i64>,
+}
+
+impl Drop for ChainPermit {
+    fn drop(&mut self) {
+        self.state.in_flight.fetch_sub(1, Ordering::SeqCst);
+        self.in_flight_metric
+            .add(-1, &[KeyValue::new("chain_id", self.chain_id.to_string())]);
+    }
+}
+
+impl ChainLimits {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            chains: Mutex::new(HashMap::new()),
+            in_flight_metric: opentelemetry::global::meter("voyager")
+                .i64_up_down_counter("chain.in_flight")
+                .build(),
+            held_counter_metric: opentelemetry::global::meter("voyager")
+                .u64_counter("chain.held")
+                .build(),
+            breaker_trip_counter_metric: opentelemetry::global::meter("voyager")
+                .u64_counter("chain.breaker.trips")
+                .build(),
+            breaker_open_metric: opentelemetry::global::meter("voyager")
+                .u64_gauge("chain.breaker.open")
+                .build(),
+        }
+    }
+
+    fn state(&self, chain_id: &ChainId) -> Arc<ChainState> {
+        self.chains
+            .lock()
+            .expect("mutex is not poisoned; qed;")
+            .entry(chain_id.clone())
+            .or_insert_with(|| {
+                let max_in_flight = self
+                    .config
+                    .max_in_flight_per_chain
+                    .get(chain_id)
+                    .copied()
+                    .or(self.config.max_in_flight);
+
+                Arc::new(ChainState {
+                    max_in_flight,
+                    semaphore: max_in_flight.map(|n| Arc::new(Semaphore::new(n))),
+                    in_flight: AtomicUsize::new(0),
+                    breaker: Mutex::new(Breaker::default()),
+                })
+            })
+            .clone()
+    }
+
+    /// Try to acquire an in-flight slot for `chain_id`.
+    ///
+    /// Returns `None` if the chain's circuit breaker is open or the chain is at its in-flight
+    /// limit.
+    pub fn try_acquire(&self, chain_id: &ChainId) -> Option<ChainPermit> {
+        let state = self.state(chain_id);
+
+        let chain_id_attribute = KeyValue::new("chain_id", chain_id.to_string());
+
+        if let Some(open_until) = state
+            .breaker
+            .lock()
+            .expect("mutex is not poisoned; qed;")
+            .open_until
+            .filter(|open_until| *open_until > now())
+        {
+            trace!(%chain_id, %open_until, "circuit breaker is open");
+
+            self.held_counter_metric.add(
+                1,
+                &[chain_id_attribute, KeyValue::new("reason", "breaker_open")],
+            );
+
+            return None;
+        }
+
+        let permit = match &state.semaphore {
+            Some(semaphore) => match semaphore.clone().try_acquire_owned() {
+                Ok(permit) => Some(permit),
+                Err(_) => {
+                    trace!(%chain_id, "chain is at its in-flight limit");
+
+                    self.held_counter_metric.add(
+                        1,
+                        &[
+                            chain_id_attribute,
+                            KeyValue::new("reason", "in_flight_limit"),
+                        ],
+                    );
+
+                    return None;
+                }
+            },
+            None => None,
+        };
+
+        state.in_flight.fetch_add(1, Ordering::SeqCst);
+        self.in_flight_metric.add(1, &[chain_id_attribute]);
+
+        Some(ChainPermit {
+            chain_id: chain_id.clone(),
+            state,
+            _permit: permit,
+            in_flight_metric: self.in_flight_metric.clone(),
+        })
+    }
+
+    /// Record the outcome of a call for `chain_id` in the chain's circuit breaker.
+    ///
+    /// Only [`QueueError::Retry`] counts as a failure; fatal and unprocessable errors are a property
+    /// of the message, not of the chain's availability.
+    pub fn record<T>(&self, chain_id: &ChainId, res: &Result<T, QueueError>) {
+        let attributes = [KeyValue::new("chain_id", chain_id.to_string())];
+
+        let state = self.state(chain_id);
+
+        let mut breaker = state.breaker.lock().expect("mutex is not poisoned; qed;");
+
+        match res {
+            Ok(_) => {
+                if breaker.open_until.take().is_some() || breaker.trips > 0 {
+                    debug!(%chain_id, "circuit breaker closed");
+                }
+
+                breaker.consecutive_failures = 0;
+                breaker.trips = 0;
+
+                self.breaker_open_metric.record(0, &attributes);
+            }
+            Err(QueueError::Retry(_)) => {
+                breaker.consecutive_failures = breaker.consecutive_failures.saturating_add(1);
+
+                let now = now();
+
+                // other calls that were already in flight when the breaker opened shouldn't extend
+                // the backoff
+                if self.config.breaker_failure_threshold != 0
+                    && breaker.consecutive_failures >= self.config.breaker_failure_threshold
+                    && !breaker.is_open(now)
+                {
+                    breaker.trips = breaker.trips.saturating_add(1);
+
+                    let backoff = self.backoff(breaker.trips);
+
+                    breaker.open_until = Some(now + backoff);
+
+                    warn!(
+                        %chain_id,
+                        consecutive_failures = breaker.consecutive_failures,
+                        trips = breaker.trips,
+                        "circuit breaker opened for {backoff} seconds"
+                    );
+
+                    self.breaker_trip_counter_metric.add(1, &attributes);
+                    self.breaker_open_metric.record(1, &attributes);
+                }
+            }
+            Err(_) => {}
+        }
+    }
+
+    /// Close the circuit breaker of `chain_id` and forget its failures, i.e. because the plugin
+    /// interacting with it was removed. In-flight calls are still counted towards the chain's limit.
+    pub fn reset_breaker(&self, chain_id: &ChainId) {
+        let Some(state) = self
+            .chains
+            .lock()
+            .expect("mutex is not poisoned; qed;")
+            .get(chain_id)
+            .cloned()
+        else {
+            return;
+        };
+
+        *state.breaker.lock().expect("mutex is not poisoned; qed;") = Breaker::default();
+
+        self.breaker_open_metric
+            .record(0, &[KeyValue::new("chain_id", chain_id.to_string())]);
+    }
+
+    /// The chains for which [`Self::try_acquire`] currently fails, i.e. chains whose circuit breaker
+    /// is open or that are at their in-flight limit.
+    pub fn unavailable(&self) -> Vec<ChainId> {
+        let now = now();
+
+        self.chains
+            .lock()
+            .expect("mutex is not poisoned; qed;")
+            .iter()
+            .filter(|(_, state)| {
+                state
+                    .breaker
+                    .lock()
+                    .expect("mutex is not poisoned; qed;")
+                    .is_open(now)
+                    || state
+                        .semaphore
+                        .as_ref()
+                        .is_some_and(|semaphore| semaphore.available_permits() == 0)
+            })
+            .map(|(chain_id, _)| chain_id.clone())
+            .collect()
+    }
+
+    fn backoff(&self, trips: u32) -> u64 {
+        1_u64
+            .checked_shl(trips.saturating_sub(1))
+            .map_or(u64::MAX, |factor| {
+                self.config
+                    .breaker_base_backoff_seconds
+                    .saturating_mul(factor)
+            })
+            .min(self.config.breaker_max_backoff_seconds)
+    }
+
+    /// The current status of every chain that has had a call handled.
+    pub fn status(&self) -> Vec<ChainStatus> {
+        let now = now();
+
+        let mut status = self
+            .chains
+            .lock()
+            .expect("mutex is not poisoned; qed;")
+            .iter()
+            .map(|(chain_id, state)| {
+                let breaker = state.breaker.lock().expect("mutex is not poisoned; qed;");
+
+                ChainStatus {
+                    chain_id: chain_id.clone(),
+                    in_flight: state.in_flight.load(Ordering::SeqCst),
+                    max_in_flight: state.max_in_flight,
+                    consecutive_failures: breaker.consecutive_failures,
+                    breaker_trips: breaker.trips,
+                    breaker_open_until: breaker.open_until.filter(|_| breaker.is_open(now)),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        status.sort_by(|a, b| a.chain_id.as_str().cmp(b.chain_id.as_str()));
+
+        status
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn retry() -> Result<(), QueueError> {
+        Err(QueueError::Retry("rpc is down".into()))
+    }
+
+    #[test]
+    fn in_flight_limit() {
+        let a = ChainId::new("a");
+        let b = ChainId::new("b");
+        let c = ChainId::new("c");
+
+        let limits = ChainLimits::new(Config {
+            max_in_flight: Some(2),
+            max_in_flight_per_chain: [(b.clone(), 1)].into_iter().collect(),
+            ..Default::default()
+        });
+
+        let a1 = limits.try_acquire(&a).unwrap();
+        let _a2 = limits.try_acquire(&a).unwrap();
+        assert!(limits.try_acquire(&a).is_none());
+
+        // the per-chain override takes precedence over the default
+        let _b1 = limits.try_acquire(&b).unwrap();
+        assert!(limits.try_acquire(&b).is_none());
+
+        let status = limits.status();
+        assert_eq!(status[0].chain_id, a);
+        assert_eq!(status[0].in_flight, 2);
+        assert_eq!(status[0].max_in_flight, Some(2));
+        assert_eq!(status[1].chain_id, b);
+        assert_eq!(status[1].in_flight, 1);
+        assert_eq!(status[1].max_in_flight, Some(1));
+
+        // dropping a permit frees the slot
+        drop(a1);
+        assert_eq!(limits.status()[0].in_flight, 1);
+        let _a3 = limits.try_acquire(&a).unwrap();
+
+        let unlimited = ChainLimits::new(Config::default());
+        let _permits = (0..100)
+            .map(|_| unlimited.try_acquire(&c).unwrap())
+            .collect::<Vec<_>>();
+    }
+
+    #[test]
+    fn breaker_opens_after_consecutive_retries() {
+        let a = ChainId::new("a");
+
+        let limits = ChainLimits::new(Config {
+            breaker_failure_threshold: 2,
+            breaker_base_backoff_seconds: 60,
+            ..Default::default()
+        });
+
+        limits.record(&a, &retry());
+        assert!(limits.try_acquire(&a).is_some());
+
+        // fatal and unprocessable errors don't count towards the threshold, but don't reset it either
+        limits.record::<()>(&a, &Err(QueueError::Fatal("bad message".into())));
+        assert!(limits.try_acquire(&a).is_some());
+
+        limits.record(&a, &retry());
+        assert!(limits.try_acquire(&a).is_none());
+
+        let status = &limits.status()[0];
+        assert_eq!(status.consecutive_failures, 2);
+        assert_eq!(status.breaker_trips, 1);
+        assert!(status.breaker_open_until.is_some_and(|t| t > now()));
+
+        // retries of calls that were already in flight don't extend the backoff
+        limits.record(&a, &retry());
+        assert_eq!(limits.status()[0].breaker_trips, 1);
+
+        limits.record(&a, &Ok(()));
+        assert!(limits.try_acquire(&a).is_some());
+
+        let status = &limits.status()[0];
+        assert_eq!(status.consecutive_failures, 0);
+        assert_eq!(status.breaker_trips, 0);
+        assert_eq!(status.breaker_open_until, None);
+    }
+
+    #[test]
+    fn reset_breaker() {
+        let a = ChainId::new("a");
+
+        let limits = ChainLimits::new(Config {
+            max_in_flight: Some(2),
+            breaker_failure_threshold: 1,
+            breaker_base_backoff_seconds: 60,
+            ..Default::default()
+        });
+
+        let _a1 = limits.try_acquire(&a).unwrap();
+
+        limits.record(&a, &retry());
+        assert!(limits.try_acquire(&a).is_none());
+
+        limits.reset_breaker(&a);
+
+        let status = &limits.status()[0];
+        assert_eq!(status.consecutive_failures, 0);
+        assert_eq!(status.breaker_trips, 0);
+        assert_eq!(status.breaker_open_until, None);
+        // the in-flight call still holds its slot
+        assert_eq!(status.in_flight, 1);
+
+        let _a2 = limits.try_acquire(&a).unwrap();
+        assert!(limits.try_acquire(&a).is_none());
+
+        // unknown chains are ignored
+        limits.reset_breaker(&ChainId::new("b"));
+    }
+
+    #[test]
+    fn unavailable() {
+        let a = ChainId::new("a");
+        let b = ChainId::new("b");
+        let c = ChainId::new("c");
+
+        let limits = ChainLimits::new(Config {
+            max_in_flight: Some(1),
+            breaker_failure_threshold: 1,
+            breaker_base_backoff_seconds: 60,
+            ..Default::default()
+        });
+
+        assert!(limits.unavailable().is_empty());
+
+        let a1 = limits.try_acquire(&a).unwrap();
+
+        let b1 = limits.try_acquire(&b).unwrap();
+        limits.record(&b, &retry());
+        drop(b1);
+
+        drop(limits.try_acquire(&c).unwrap());
+
+        let mut unavailable = limits.unavailable();
+        unavailable.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        assert_eq!(unavailable, [a.clone(), b.clone()]);
+
+        drop(a1);
+        limits.reset_breaker(&b);
+
+        assert!(limits.unavailable().is_empty());
+    }
+
+    #[test]
+    fn breaker_disabled() {
+        let a = ChainId::new("a");
+
+        let limits = ChainLimits::new(Config {
+            breaker_failure_threshold: 0,
+            ..Default::default()
+        });
+
+        for _ in 0..100 {
+            limits.record(&a, &retry());
+        }
+
+        assert!(limits.try_acquire(&a).is_some());
+        assert_eq!(limits.status()[0].breaker_trips, 0);
+    }
+
+    #[test]
+    fn breaker_backoff() {
+        let limits = ChainLimits::new(Config {
+            breaker_base_backoff_seconds: 3,
+            breaker_max_backoff_seconds: 20,
+            ..Default::default()
+        });
+
+        assert_eq!(
+            [1, 2, 3, 4, 5, 64, u32::MAX].map(|trips| limits.backoff(trips)),
+            [3, 6, 12, 20, 20, 20, 20]
+        );
+    }
+}
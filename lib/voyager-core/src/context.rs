@@ -1,8 +1,15 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{collections::HashMap, path::PathBuf, sync::RwLock};
 
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use voyager_message::{
+    PluginMessage,
+    call::{
+        Call, FetchUpdateHeaders, Index, IndexRange, SubmitTx, WaitForClientUpdate, WaitForHeight,
+        WaitForHeightRelative, WaitForTimestamp, WaitForTrustedHeight, WaitForTrustedTimestamp,
+    },
+};
 use voyager_plugin_protocol::WorkerClient;
 use voyager_primitives::{ChainId, ClientType, ConsensusType, IbcInterface, IbcSpecId};
 use voyager_rpc::{
@@ -12,9 +19,12 @@ use voyager_rpc::{
         ProofModuleInfo, StateModuleInfo,
     },
 };
-use voyager_vm::QueueError;
+use voyager_vm::{Priority, QueueError};
 
-use crate::{equivalent_chain_ids::EquivalentChainIds, ibc_spec_handlers::IbcSpecHandlers};
+use crate::{
+    chain_limits::ChainLimits, equivalent_chain_ids::EquivalentChainIds,
+    ibc_spec_handlers::IbcSpecHandlers, pause::Pauses,
+};
 
 pub struct Context {
     pub(crate) state_modules: HashMap<(ChainId, IbcSpecId), WorkerClient>,
@@ -31,12 +41,17 @@ pub struct Context {
 
     pub(crate) client_consensus_types: HashMap<ClientType, ConsensusType>,
 
-    pub(crate) plugins: HashMap<String, WorkerClient>,
+    /// Plugins can be added and removed at runtime when the config is reloaded.
+    pub(crate) plugins: RwLock<HashMap<String, WorkerClient>>,
 
     pub(crate) equivalent_chain_ids: EquivalentChainIds,
 
     // ibc version id => handler
     pub(crate) ibc_spec_handlers: IbcSpecHandlers,
+
+    pub(crate) chain_limits: ChainLimits,
+
+    pub(crate) pauses: Pauses,
 }
 
 #[derive(Debug, Clone, PartialEq, Hash, Serialize, Deserialize, JsonSchema)]
@@ -46,6 +61,11 @@ pub struct PluginConfig {
     pub config: Value,
     #[serde(default = "default_enabled")]
     pub enabled: bool,
+    /// The priority of the ops produced by this plugin's optimization passes, and of calls to this
+    /// plugin. Higher priorities are processed first.
+    #[serde(default)]
+    #[schemars(with = "u8")]
+    pub priority: Priority,
 }
 
 #[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize, JsonSchema)]
@@ -137,13 +157,62 @@ impl Context {
             consensus,
             client,
             client_bootstrap,
+            chains: self.chain_limits.status(),
         }
     }
 
-    pub fn plugin<'a>(&'a self, name: &str) -> Result<&'a WorkerClient, PluginNotFound> {
-        self.plugins.get(name).ok_or_else(|| PluginNotFound {
-            name: name.to_owned(),
-        })
+    pub fn chain_limits(&self) -> &ChainLimits {
+        &self.chain_limits
+    }
+
+    pub fn pauses(&self) -> &Pauses {
+        &self.pauses
+    }
+
+    /// The chain that handling `call` will primarily interact with, if any.
+    ///
+    /// For plugin calls, this is inferred from the plugin name, see [`Self::plugin_chain_id`].
+    pub fn call_chain_id(&self, call: &Call) -> Option<ChainId> {
+        match call {
+            Call::Index(Index { chain_id, .. })
+            | Call::IndexRange(IndexRange { chain_id, .. })
+            | Call::FetchUpdateHeaders(FetchUpdateHeaders { chain_id, .. })
+            | Call::SubmitTx(SubmitTx { chain_id, .. })
+            | Call::WaitForHeight(WaitForHeight { chain_id, .. })
+            | Call::WaitForTimestamp(WaitForTimestamp { chain_id, .. })
+            | Call::WaitForHeightRelative(WaitForHeightRelative { chain_id, .. })
+            | Call::WaitForTrustedHeight(WaitForTrustedHeight { chain_id, .. })
+            | Call::WaitForTrustedTimestamp(WaitForTrustedTimestamp { chain_id, .. })
+            | Call::WaitForClientUpdate(WaitForClientUpdate { chain_id, .. }) => {
+                Some(chain_id.clone())
+            }
+            Call::Plugin(PluginMessage { plugin, .. }) => self.plugin_chain_id(plugin),
+        }
+    }
+
+    /// The chain that the plugin `plugin` interacts with, inferred from the plugin name following
+    /// the `{PLUGIN_NAME}/{chain_id}` convention. The chain id is only used if it is a chain that
+    /// voyager has a module configured for.
+    pub fn plugin_chain_id(&self, plugin: &str) -> Option<ChainId> {
+        let chain_id = ChainId::new(plugin.split('/').nth(1)?.to_owned());
+
+        (self.finality_modules.contains_key(&chain_id)
+            || self
+                .state_modules
+                .keys()
+                .any(|(state_chain_id, _)| state_chain_id == &chain_id))
+        .then_some(chain_id)
+    }
+
+    pub fn plugin(&self, name: &str) -> Result<WorkerClient, PluginNotFound> {
+        self.plugins
+            .read()
+            .expect("poisoned")
+            .get(name)
+            .cloned()
+            .ok_or_else(|| PluginNotFound {
+                name: name.to_owned(),
+            })
     }
 
     pub fn equivalent_chain_ids(&self) -> &EquivalentChainIds {
@@ -1,4 +1,8 @@
-use std::fmt::Debug;
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    sync::{Arc, OnceLock, RwLock},
+};
 
 use anyhow::anyhow;
 use jaq_core::{
@@ -7,28 +11,45 @@ use jaq_core::{
 };
 use jaq_json::Val;
 use opentelemetry::{KeyValue, global, metrics::Histogram};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
 use tracing::{error, instrument, trace};
+use unionlabs::primitives::H256;
+use voyager_message::{PluginMessage, call::Call, callback::Callback, data::Data};
+use voyager_primitives::ChainId;
 use voyager_rpc::types::PluginInfo;
 use voyager_vm::{
-    Op,
-    filter::{FilterResult, Interest, InterestFilter},
+    Op, Priority, Step,
+    filter::{Admission, AdmissionKey, FilterResult, Held, Interest, InterestFilter},
 };
 
-use crate::VoyagerMessage;
+use crate::{VoyagerMessage, context::Context};
 
+/// The interest filters of all running plugins.
+///
+/// This is cheap to clone, and all clones share the same filters; replacing the filters with
+/// [`InterestFilters::replace`] (i.e. when reloading the config) is visible to all clones.
 #[derive(Clone)]
 pub struct InterestFilters {
-    pub filters: Vec<(Filter<Native<Val>>, &'static str)>,
+    filters: Arc<RwLock<Vec<(Filter<Native<Val>>, &'static str)>>>,
+    /// The configured priority of each plugin. Plugins that aren't present use the default priority.
+    priorities: Arc<RwLock<HashMap<String, Priority>>>,
     filter_run_time_histogram: Histogram<f64>,
 }
 
 impl InterestFilters {
-    pub fn new(filters: Vec<PluginInfo>) -> anyhow::Result<Self> {
+    pub fn new(
+        filters: Vec<PluginInfo>,
+        priorities: HashMap<String, Priority>,
+    ) -> anyhow::Result<Self> {
         Ok(Self {
-            filters: filters
-                .into_iter()
-                .map(make_filter)
-                .collect::<anyhow::Result<_>>()?,
+            filters: Arc::new(RwLock::new(
+                filters
+                    .into_iter()
+                    .map(make_filter)
+                    .collect::<anyhow::Result<_>>()?,
+            )),
+            priorities: Arc::new(RwLock::new(priorities)),
             filter_run_time_histogram: global::meter("voyager")
                 .f64_histogram("plugin.filter.run_time")
                 .with_unit("s")
@@ -48,6 +69,50 @@ impl InterestFilters {
                 .build(),
         })
     }
+
+    /// Replace all filters and priorities. Filters are run in the order they are provided in.
+    pub fn replace(
+        &self,
+        filters: Vec<PluginInfo>,
+        priorities: HashMap<String, Priority>,
+    ) -> anyhow::Result<()> {
+        let filters = filters
+            .into_iter()
+            .map(make_filter)
+            .collect::<anyhow::Result<_>>()?;
+
+        *self.filters.write().expect("poisoned") = filters;
+        *self.priorities.write().expect("poisoned") = priorities;
+
+        Ok(())
+    }
+
+    /// The names of all plugins with a registered interest filter.
+    pub fn plugin_names(&self) -> Vec<&'static str> {
+        self.filters
+            .read()
+            .expect("poisoned")
+            .iter()
+            .map(|(_, plugin_name)| *plugin_name)
+            .collect()
+    }
+
+    /// Whether the interest filter of `plugin_name` expresses interest in `op`.
+    pub fn is_interested(&self, plugin_name: &str, op: &Op<VoyagerMessage>) -> bool {
+        let msg_json = Val::from(serde_json::to_value(op.clone()).unwrap());
+
+        self.filters
+            .read()
+            .expect("poisoned")
+            .iter()
+            .filter(|(_, name)| *name == plugin_name)
+            .any(|(filter, name)| {
+                matches!(
+                    run_filter(filter, name, msg_json.clone()),
+                    Ok(JaqFilterResult::Copy(_) | JaqFilterResult::Take(_))
+                )
+            })
+    }
 }
 
 pub fn make_filter(
@@ -56,29 +121,7 @@ pub fn make_filter(
         interest_filter,
     }: PluginInfo,
 ) -> anyhow::Result<(Filter<Native<Val>>, &'static str)> {
-    fn map_jq_errs(es: Vec<(File<&str, &str>, impl Debug)>) -> anyhow::Error {
-        anyhow!(
-            es.iter()
-                .map(|(file, error)| format!("{}: {:?}", file.path, error))
-                .collect::<Vec<_>>()
-                .join(",")
-        )
-    }
-
-    let program = File {
-        code: &*interest_filter,
-        path: &*name,
-    };
-
-    let loader = Loader::new(jaq_std::defs().chain(jaq_json::defs()));
-    let arena = Arena::default();
-
-    let modules = loader.load(&arena, program).map_err(map_jq_errs)?;
-
-    let filter = jaq_core::Compiler::default()
-        .with_funs(jaq_std::funs().chain(jaq_json::funs()))
-        .compile(modules)
-        .map_err(map_jq_errs)?;
+    let filter = compile(&interest_filter, &name)?;
 
     // let mut ctx = ParseCtx::new(["PLUGIN_NAME".to_owned()].into());
     // ctx.insert_natives(jaq_core::core());
@@ -124,6 +167,54 @@ pub fn make_filter(
     Ok((filter, String::leak(name)))
 }
 
+/// Compile a jaq filter to be used with [`run_transform`].
+pub fn make_transform(transform: &str) -> anyhow::Result<Filter<Native<Val>>> {
+    compile(transform, "transform")
+}
+
+/// Run a transform compiled with [`make_transform`] on the provided value. The transform must
+/// return exactly one value.
+pub fn run_transform(filter: &Filter<Native<Val>>, value: Value) -> anyhow::Result<Value> {
+    let inputs = RcIter::new(core::iter::empty());
+    let mut out = filter.run((Ctx::new([], &inputs), Val::from(value)));
+
+    let result = out
+        .next()
+        .ok_or_else(|| anyhow!("transform didn't return any values"))?
+        .map_err(|err| anyhow!("transform failed: {err}"))?;
+
+    if out.next().is_some() {
+        return Err(anyhow!(
+            "transform returned multiple values, only a single value is valid"
+        ));
+    }
+
+    Ok(serde_json::from_str(&result.to_string())?)
+}
+
+fn compile(code: &str, path: &str) -> anyhow::Result<Filter<Native<Val>>> {
+    fn map_jq_errs(es: Vec<(File<&str, &str>, impl Debug)>) -> anyhow::Error {
+        anyhow!(
+            es.iter()
+                .map(|(file, error)| format!("{}: {:?}", file.path, error))
+                .collect::<Vec<_>>()
+                .join(",")
+        )
+    }
+
+    let program = File { code, path };
+
+    let loader = Loader::new(jaq_std::defs().chain(jaq_json::defs()));
+    let arena = Arena::default();
+
+    let modules = loader.load(&arena, program).map_err(map_jq_errs)?;
+
+    jaq_core::Compiler::default()
+        .with_funs(jaq_std::funs().chain(jaq_json::funs()))
+        .compile(modules)
+        .map_err(map_jq_errs)
+}
+
 impl InterestFilter<VoyagerMessage> for InterestFilters {
     #[instrument(skip_all)]
     fn check_interest<'a>(&'a self, op: &Op<VoyagerMessage>) -> FilterResult<'a> {
@@ -132,10 +223,10 @@ impl InterestFilter<VoyagerMessage> for InterestFilters {
         let mut tags = vec![];
         let mut take = false;
 
-        for (filter, plugin_name) in &self.filters {
+        for (filter, plugin_name) in self.filters.read().expect("poisoned").iter() {
             let now = std::time::SystemTime::now();
 
-            let result = match run_filter(filter, plugin_name, msg_json.clone()) {
+            let result = match run_filter(filter, *plugin_name, msg_json.clone()) {
                 Ok(JaqFilterResult::Copy(tag)) => {
                     tags.push(tag);
                     "copy"
@@ -173,6 +264,202 @@ impl InterestFilter<VoyagerMessage> for InterestFilters {
             })
         }
     }
+
+    /// Ops produced by a plugin's optimization pass use the priority of that plugin, as do calls to
+    /// a plugin.
+    fn priority(&self, op: &Op<VoyagerMessage>, tag: Option<&str>) -> Priority {
+        let plugin = tag.or(match op {
+            Op::Call(Call::Plugin(PluginMessage { plugin, .. })) => Some(plugin.as_str()),
+            _ => None,
+        });
+
+        plugin
+            .and_then(|plugin| {
+                self.priorities
+                    .read()
+                    .expect("poisoned")
+                    .get(plugin)
+                    .copied()
+            })
+            .unwrap_or_default()
+    }
+
+    fn idempotency_key(&self, op: &Op<VoyagerMessage>) -> Option<String> {
+        match op {
+            Op::Data(Data::IbcEvent(event)) => event.idempotency_key(),
+            _ => None,
+        }
+    }
+}
+
+/// [`InterestFilters`], except that all ops are keyed by a caller-supplied idempotency key (i.e.
+/// the `idempotency_key` parameter of the `/enqueue` endpoint) instead.
+///
+/// Enqueueing a single op can insert multiple ops into the queue (see [`Op::normalize`]), so each
+/// op is keyed by the hash of both the caller-supplied key and the op itself. Enqueueing the same op
+/// with the same key again within the queue's idempotency window is then a noop.
+#[derive(Clone)]
+pub struct IdempotencyKeyFilter {
+    interest_filters: InterestFilters,
+    idempotency_key: String,
+}
+
+impl IdempotencyKeyFilter {
+    pub fn new(interest_filters: InterestFilters, idempotency_key: String) -> Self {
+        Self {
+            interest_filters,
+            idempotency_key,
+        }
+    }
+}
+
+impl InterestFilter<VoyagerMessage> for IdempotencyKeyFilter {
+    fn check_interest<'a>(&'a self, op: &Op<VoyagerMessage>) -> FilterResult<'a> {
+        self.interest_filters.check_interest(op)
+    }
+
+    fn priority(&self, op: &Op<VoyagerMessage>, tag: Option<&str>) -> Priority {
+        self.interest_filters.priority(op, tag)
+    }
+
+    fn idempotency_key(&self, op: &Op<VoyagerMessage>) -> Option<String> {
+        let op = serde_json::to_vec(op).expect("serialization is infallible; qed;");
+
+        let hash = Sha256::new()
+            .chain_update((self.idempotency_key.len() as u64).to_be_bytes())
+            .chain_update(&self.idempotency_key)
+            .chain_update(op)
+            .finalize();
+
+        Some(format!("enqueue/{}", H256::new(hash.into())))
+    }
+}
+
+/// The [`InterestFilter`] that the engine workers process the queue with.
+///
+/// This is [`InterestFilters`], except that ops whose next call or callback is for a paused plugin
+/// or chain (see [`Pauses`](crate::pause::Pauses)), or whose next call is for a chain that is at
+/// its in-flight limit or has its circuit breaker open (see
+/// [`ChainLimits`](crate::chain_limits::ChainLimits)), are not admitted, leaving them in place in
+/// the queue until they can be handled.
+#[derive(Clone)]
+pub struct EngineFilter {
+    interest_filters: InterestFilters,
+    context: Arc<OnceLock<Context>>,
+}
+
+impl EngineFilter {
+    pub fn new(interest_filters: InterestFilters, context: Arc<OnceLock<Context>>) -> Self {
+        Self {
+            interest_filters,
+            context,
+        }
+    }
+}
+
+impl InterestFilter<VoyagerMessage> for EngineFilter {
+    fn check_interest<'a>(&'a self, op: &Op<VoyagerMessage>) -> FilterResult<'a> {
+        self.interest_filters.check_interest(op)
+    }
+
+    fn priority(&self, op: &Op<VoyagerMessage>, tag: Option<&str>) -> Priority {
+        self.interest_filters.priority(op, tag)
+    }
+
+    fn idempotency_key(&self, op: &Op<VoyagerMessage>) -> Option<String> {
+        self.interest_filters.idempotency_key(op)
+    }
+
+    fn admit(&self, op: &Op<VoyagerMessage>) -> Option<Admission> {
+        // nothing can be handled before the context is initialized anyways
+        let Some(context) = self.context.get() else {
+            return Some(Admission::default());
+        };
+
+        let Some((plugin, chain_id, is_call)) = admission_target(context, op) else {
+            return Some(Admission::default());
+        };
+
+        if context.pauses().is_paused(plugin, chain_id.as_ref()) {
+            trace!(?plugin, ?chain_id, "paused, holding op in the queue");
+
+            return None;
+        }
+
+        // only calls are subject to the chain limits, matching the outcomes recorded by the handler
+        match chain_id {
+            Some(chain_id) if is_call => context
+                .chain_limits()
+                .try_acquire(&chain_id)
+                .map(Admission::new),
+            _ => Some(Admission::default()),
+        }
+    }
+
+    fn admission_key(&self, op: &Op<VoyagerMessage>) -> Option<AdmissionKey> {
+        // ops can't be classified before the context is initialized, they will be classified once
+        // they are read from the queue
+        let context = self.context.get()?;
+
+        Some(
+            admission_target(context, op)
+                .map(|(plugin, chain_id, is_call)| AdmissionKey {
+                    plugin: plugin.map(ToOwned::to_owned),
+                    chain_id: chain_id.map(|chain_id| chain_id.to_string()),
+                    limited: is_call,
+                })
+                .unwrap_or_default(),
+        )
+    }
+
+    fn held(&self) -> Held {
+        let Some(context) = self.context.get() else {
+            return Held::default();
+        };
+
+        let paused = context.pauses().paused();
+
+        Held {
+            plugins: paused.plugins,
+            chains: paused
+                .chains
+                .into_iter()
+                .map(|chain_id| chain_id.to_string())
+                .collect(),
+            limited_chains: context
+                .chain_limits()
+                .unavailable()
+                .into_iter()
+                .map(|chain_id| chain_id.to_string())
+                .collect(),
+        }
+    }
+}
+
+/// The plugin handling the next step of `op` (if it is a plugin message), the chain it interacts
+/// with and whether it is a call (and as such subject to the chain limits).
+///
+/// Returns `None` if the next step is never held.
+fn admission_target<'a>(
+    context: &Context,
+    op: &'a Op<VoyagerMessage>,
+) -> Option<(Option<&'a str>, Option<ChainId>, bool)> {
+    match op.next_step() {
+        Some(Step::Call(call)) => Some((
+            match call {
+                Call::Plugin(PluginMessage { plugin, .. }) => Some(plugin.as_str()),
+                _ => None,
+            },
+            context.call_chain_id(call),
+            true,
+        )),
+        Some(Step::Callback(Callback::Plugin(PluginMessage { plugin, .. }))) => Some((
+            Some(plugin.as_str()),
+            context.plugin_chain_id(plugin),
+            false,
+        )),
+        Some(Step::Callback(_)) | None => None,
+    }
 }
 
 #[instrument(
@@ -1,6 +1,7 @@
 #![feature(trait_alias, slice_partition_dedup)]
 
 use std::{
+    any::Any,
     collections::{HashMap, VecDeque},
     net::{IpAddr, Ipv4Addr, SocketAddr},
     panic::AssertUnwindSafe,
@@ -19,6 +20,7 @@ use jsonrpsee::core::middleware::{RpcServiceBuilder, RpcServiceT};
 use opentelemetry::{KeyValue, metrics::Counter};
 use serde::Serialize;
 use serde_json::Value;
+use tokio::sync::{Mutex, mpsc};
 use tokio_util::sync::CancellationToken;
 use tracing::{
     Instrument, debug, debug_span, error, info, info_span, instrument, trace, trace_span, warn,
@@ -34,11 +36,13 @@ use voyager_message::{
     data::{Data, IbcDatagram, OrderedHeaders},
 };
 use voyager_plugin_protocol::{
-    INVALID_CONFIG_EXIT_CODE, WithId, WorkerClient, coordinator_server, worker_child_process,
+    INVALID_CONFIG_EXIT_CODE, WithId, WorkerClient, coordinator_server, record::ClientMode,
+    worker_child_process,
 };
 use voyager_primitives::{ClientInfo, IbcSpec, QueryHeight};
 use voyager_rpc::{
-    ClientModuleClient, PluginClient, RpcError, VoyagerRpcServer, json_rpc_error_to_queue_error,
+    ClientModuleClient, PluginClient, RpcError, VoyagerAdminRpcServer, VoyagerQueueRpcServer,
+    VoyagerRpcServer, json_rpc_error_to_queue_error,
     types::{
         ClientBootstrapModuleInfo, ClientModuleInfo, FinalityModuleInfo, PluginInfo,
         ProofModuleInfo, StateModuleInfo,
@@ -53,23 +57,33 @@ use voyager_vm::{
 };
 
 use crate::{
+    chain_limits::ChainLimits,
     context::{Context, ModuleConfig, ModulesConfig, PluginConfig},
     equivalent_chain_ids::EquivalentChainIds,
-    filter::InterestFilters,
+    filter::{EngineFilter, IdempotencyKeyFilter, InterestFilters},
     ibc_spec_handlers::IbcSpecHandlers,
-    server::Server,
+    pause::Pauses,
+    reload::{
+        ConfigLoader, ModuleWorker, PluginWorker, Reloader, Workers, module_args, plugin_args,
+    },
+    server::{AdminServer, QueueServer, Server},
 };
 
 pub mod cache;
+pub mod chain_limits;
 pub mod context;
 pub mod equivalent_chain_ids;
 pub mod filter;
 pub mod ibc_spec_handlers;
+pub mod pause;
+pub mod reload;
+pub mod replay;
 pub mod server;
 
 pub struct Engine<Q: Queue<VoyagerMessage>> {
     context: Arc<OnceLock<Context>>,
     interest_filters: InterestFilters,
+    engine_filter: EngineFilter,
     cache: cache::Cache,
     queue: Q,
     cancellation_token: CancellationToken,
@@ -77,11 +91,17 @@ pub struct Engine<Q: Queue<VoyagerMessage>> {
     num_workers: usize,
     rest_laddr: SocketAddr,
     rpc_laddr: SocketAddr,
+    admin_laddr: SocketAddr,
     optimizer_delay_milliseconds: u64,
     // TODO: Make this generic
     rpc_middleware: LoggerMiddlewareLayer,
+    reloader: Reloader,
+    /// The optimizers to run, for all plugins started at build time and by config reloads.
+    optimizers: Mutex<mpsc::UnboundedReceiver<(String, CancellationToken)>>,
 }
 
+type EngineTask<'a> = BoxFuture<'a, Result<Result<(), BoxDynError>, Box<dyn Any + Send>>>;
+
 impl Engine<InMemoryQueue<VoyagerMessage>> {
     #[allow(clippy::new_without_default)]
     pub fn builder() -> EngineBuilder {
@@ -92,12 +112,16 @@ impl Engine<InMemoryQueue<VoyagerMessage>> {
             equivalent_chain_ids: Default::default(),
             ipc_client_request_timeout: Default::default(),
             cache_config: Default::default(),
+            chain_limits_config: Default::default(),
+            client_mode: ClientMode::Live,
+            config_loader: None,
             trace_ratio: default_trace_ratio(),
             num_workers: 1,
             rest_laddr: default_rest_laddr(),
             rpc_laddr: default_rpc_laddr(),
+            admin_laddr: default_admin_laddr(),
             optimizer_delay_milliseconds: default_optimizer_delay_milliseconds(),
-            queue_config: (),
+            queue_config: Default::default(),
         }
     }
 }
@@ -121,11 +145,15 @@ impl<Q: Queue<VoyagerMessage>> Engine<Q> {
         Server::new(self.cache.clone(), self.context.clone())
     }
 
+    pub fn reloader(&self) -> &Reloader {
+        &self.reloader
+    }
+
     #[allow(clippy::too_many_lines)]
     pub fn run(&self) -> impl Future<Output = ()> + use<'_, Q> {
         let queue_rx = api::run(self.rest_laddr);
 
-        let mut tasks = FuturesUnordered::<BoxFuture<Result<Result<(), BoxDynError>, _>>>::new();
+        let mut tasks = FuturesUnordered::<EngineTask>::new();
 
         {
             tasks.push(Box::pin(
@@ -143,7 +171,10 @@ impl<Q: Queue<VoyagerMessage>> Engine<Q> {
 
                     let addr = server.local_addr()?;
 
-                    let handle = server.start(self.server().into_rpc());
+                    let mut module = self.server().into_rpc();
+                    module.merge(QueueServer::new(self.queue.clone()).into_rpc())?;
+
+                    let handle = server.start(module);
 
                     info!("rpc listening on {addr}");
 
@@ -157,19 +188,62 @@ impl<Q: Queue<VoyagerMessage>> Engine<Q> {
                 .catch_unwind(),
             ));
 
+            // operator actions are served separately from the public rpc, without cors, such that
+            // they can be bound to a private interface
+            tasks.push(Box::pin(
+                AssertUnwindSafe(async {
+                    let server = jsonrpsee::server::Server::builder()
+                        .set_rpc_middleware(
+                            RpcServiceBuilder::new().layer(self.rpc_middleware.clone()),
+                        )
+                        .build(&self.admin_laddr)
+                        .await?;
+
+                    let addr = server.local_addr()?;
+
+                    let handle = server.start(AdminServer::new(self.reloader.clone()).into_rpc());
+
+                    info!("admin rpc listening on {addr}");
+
+                    handle
+                        .stopped()
+                        .instrument(trace_span!("voyager_admin_rpc_server"))
+                        .await;
+
+                    Err("admin rpc server exited".into())
+                })
+                .catch_unwind(),
+            ));
+
             tasks.push(Box::pin(
                 AssertUnwindSafe(async {
                     debug!("checking for new messages");
 
                     pin_utils::pin_mut!(queue_rx);
 
-                    while let Some(op) = queue_rx.next().await {
+                    while let Some((op, idempotency_key)) = queue_rx.next().await {
                         info!(
+                            ?idempotency_key,
                             "received new message: {}",
                             serde_json::to_value(&op).unwrap()
                         );
 
-                        self.queue.enqueue(op, &self.interest_filters).await?;
+                        match idempotency_key {
+                            Some(idempotency_key) => {
+                                self.queue
+                                    .enqueue(
+                                        op,
+                                        &IdempotencyKeyFilter::new(
+                                            self.interest_filters.clone(),
+                                            idempotency_key,
+                                        ),
+                                    )
+                                    .await?;
+                            }
+                            None => {
+                                self.queue.enqueue(op, &self.interest_filters).await?;
+                            }
+                        }
                     }
 
                     Ok(())
@@ -187,7 +261,7 @@ impl<Q: Queue<VoyagerMessage>> Engine<Q> {
                         voyager_vm::engine::Engine::new(
                             self.server(),
                             &self.queue,
-                            &self.interest_filters,
+                            &self.engine_filter,
                         )
                         .run()
                         .for_each(async |res| match res {
@@ -210,81 +284,72 @@ impl<Q: Queue<VoyagerMessage>> Engine<Q> {
                     .catch_unwind(),
                 ));
             }
-
-            for (_filter, plugin_name) in &self.interest_filters.filters {
-                debug!(%plugin_name, "spawning optimizer");
-
-                tasks.push(Box::pin(
-                    AssertUnwindSafe(
-                        async {
-                            let pass = PluginOptPass::new(
-                                self.context
-                                    .get()
-                                    .unwrap()
-                                    .plugin(plugin_name)
-                                    .expect("plugin exists")
-                                    .client(),
-                            );
-
-                            loop {
-                                trace!("optimizing");
-
-                                let res = self
-                                    .queue
-                                    .optimize(plugin_name, &self.interest_filters, &pass)
-                                    .await
-                                    .map_err(|e| {
-                                        e.map_either::<_, _, BoxDynError, BoxDynError>(
-                                            |x| Box::new(x),
-                                            |x| Box::new(x),
-                                        )
-                                        .into_inner()
-                                    });
-
-                                if let Err(error) = res {
-                                    error!(
-                                        error = %ErrorReporter(&*error),
-                                        "optimization pass returned with error"
-                                    );
-                                }
-
-                                tokio::time::sleep(std::time::Duration::from_millis(
-                                    self.optimizer_delay_milliseconds,
-                                ))
-                                .await;
-                            }
-                        }
-                        .instrument(trace_span!("optimize", %plugin_name)), // .instrument(trace_span!("optimize_verbose", filter = %filter.to_string())),
-                    )
-                    .catch_unwind(),
-                ));
-            }
         }
 
         self.cancellation_token
             .run_until_cancelled(async move {
-                while let Some(res) = tasks.next().await {
-                    match res {
-                        Ok(Ok(())) => {
-                            info!("task exited gracefully");
-                        }
-                        Ok(Err(error)) => {
-                            error!(
-                                error = %ErrorReporter(&*error),
-                                "task returned with an error"
-                            );
-                            break;
-                        }
-                        Err(_err) => {
-                            // can't do anything with dyn Any
-                            error!("task panicked");
-                            break;
+                let mut optimizers = self.optimizers.lock().await;
+
+                loop {
+                    tokio::select! {
+                        Some((plugin_name, cancellation_token)) = optimizers.recv() => {
+                            tasks.push(self.optimizer(plugin_name, cancellation_token));
                         }
+                        res = tasks.next() => match res {
+                            None => break,
+                            Some(Ok(Ok(()))) => {
+                                info!("task exited gracefully");
+                            }
+                            Some(Ok(Err(error))) => {
+                                error!(
+                                    error = %ErrorReporter(&*error),
+                                    "task returned with an error"
+                                );
+                                break;
+                            }
+                            Some(Err(_err)) => {
+                                // can't do anything with dyn Any
+                                error!("task panicked");
+                                break;
+                            }
+                        },
                     }
                 }
             })
             .map(|_| ())
     }
+
+    /// Run the optimizer for `plugin_name` until `cancellation_token` is cancelled, i.e. because the
+    /// plugin was removed or restarted on a config reload.
+    fn optimizer(
+        &self,
+        plugin_name: String,
+        cancellation_token: CancellationToken,
+    ) -> EngineTask<'_> {
+        debug!(%plugin_name, "spawning optimizer");
+
+        let span = trace_span!("optimize", %plugin_name);
+
+        Box::pin(
+            AssertUnwindSafe(
+                async move {
+                    cancellation_token
+                        .run_until_cancelled(run_optimizer(
+                            self.context.get().unwrap(),
+                            &self.queue,
+                            &self.interest_filters,
+                            &plugin_name,
+                            self.optimizer_delay_milliseconds,
+                        ))
+                        .await;
+
+                    Ok(())
+                }
+                .instrument(span),
+            )
+            .catch_unwind(),
+        )
+    }
 }
 
 pub struct EngineBuilder<Q: Queue<VoyagerMessage> = InMemoryQueue<VoyagerMessage>> {
@@ -294,11 +359,15 @@ pub struct EngineBuilder<Q: Queue<VoyagerMessage> = InMemoryQueue<VoyagerMessage
     equivalent_chain_ids: EquivalentChainIds,
     ipc_client_request_timeout: Duration,
     cache_config: cache::Config,
+    chain_limits_config: chain_limits::Config,
+    client_mode: ClientMode,
+    config_loader: Option<ConfigLoader>,
     trace_ratio: Option<f64>,
     ibc_spec_handlers: IbcSpecHandlers,
     num_workers: usize,
     rest_laddr: SocketAddr,
     rpc_laddr: SocketAddr,
+    admin_laddr: SocketAddr,
     optimizer_delay_milliseconds: u64,
 }
 
@@ -338,6 +407,33 @@ impl<Q: Queue<VoyagerMessage>> EngineBuilder<Q> {
         }
     }
 
+    pub fn with_chain_limits_config(self, chain_limits_config: chain_limits::Config) -> Self {
+        Self {
+            chain_limits_config,
+            ..self
+        }
+    }
+
+    /// Set how requests to plugins and modules are handled.
+    ///
+    /// In [`ClientMode::Replay`], neither plugin nor module processes are started, and all plugin
+    /// and module responses are served from the recording.
+    pub fn with_client_mode(self, client_mode: ClientMode) -> Self {
+        Self {
+            client_mode,
+            ..self
+        }
+    }
+
+    /// Set how the plugin and module configuration is re-read when the config is reloaded. If not
+    /// set, reloading the config will fail.
+    pub fn with_config_loader(self, config_loader: ConfigLoader) -> Self {
+        Self {
+            config_loader: Some(config_loader),
+            ..self
+        }
+    }
+
     pub fn with_trace_ratio(self, trace_ratio: Option<f64>) -> Self {
         Self {
             trace_ratio,
@@ -360,6 +456,14 @@ impl<Q: Queue<VoyagerMessage>> EngineBuilder<Q> {
         Self { rpc_laddr, ..self }
     }
 
+    /// Set the address to serve [`VoyagerAdminRpc`](voyager_rpc::VoyagerAdminRpc) on.
+    pub fn with_admin_laddr(self, admin_laddr: SocketAddr) -> Self {
+        Self {
+            admin_laddr,
+            ..self
+        }
+    }
+
     pub fn with_optimizer_delay_milliseconds(self, optimizer_delay_milliseconds: u64) -> Self {
         Self {
             optimizer_delay_milliseconds,
@@ -383,11 +487,15 @@ impl<Q: Queue<VoyagerMessage>> EngineBuilder<Q> {
             equivalent_chain_ids: self.equivalent_chain_ids,
             ipc_client_request_timeout: self.ipc_client_request_timeout,
             cache_config: self.cache_config,
+            chain_limits_config: self.chain_limits_config,
+            client_mode: self.client_mode,
+            config_loader: self.config_loader,
             trace_ratio: self.trace_ratio,
             ibc_spec_handlers: self.ibc_spec_handlers,
             num_workers: self.num_workers,
             rest_laddr: self.rest_laddr,
             rpc_laddr: self.rpc_laddr,
+            admin_laddr: self.admin_laddr,
             optimizer_delay_milliseconds: self.optimizer_delay_milliseconds,
         }
     }
@@ -410,6 +518,8 @@ impl<Q: Queue<VoyagerMessage>> EngineBuilder<Q> {
             plugins: Default::default(),
             equivalent_chain_ids: self.equivalent_chain_ids,
             ibc_spec_handlers: self.ibc_spec_handlers,
+            chain_limits: ChainLimits::new(self.chain_limits_config),
+            pauses: Pauses::new(),
         };
 
         let logger_middleware_layer = LoggerMiddlewareLayer::new();
@@ -417,6 +527,8 @@ impl<Q: Queue<VoyagerMessage>> EngineBuilder<Q> {
         let context = Arc::new(OnceLock::new());
 
         let mut interest_filters = HashMap::new();
+        let mut plugin_priorities = HashMap::new();
+        let mut workers = Workers::default();
 
         let cache = cache::Cache::new(self.cache_config);
 
@@ -441,14 +553,14 @@ impl<Q: Queue<VoyagerMessage>> EngineBuilder<Q> {
 
                 debug!("starting rpc server for plugin {}", plugin_info.name);
 
-                tokio::spawn(
+                let coordinator_server = tokio::spawn(
                     coordinator_server(&plugin_info.name, server, logger_middleware_layer.clone())
                         .await?,
                 );
 
                 debug!("started rpc server for plugin {}", plugin_info.name);
 
-                Ok((idx, plugin_config, plugin_info))
+                Ok((idx, plugin_config, plugin_info, coordinator_server))
             })
             .try_for_each_concurrent(
                 None,
@@ -459,22 +571,30 @@ impl<Q: Queue<VoyagerMessage>> EngineBuilder<Q> {
                         name,
                         interest_filter,
                     },
+                    coordinator_server,
                 )| {
                     debug!("registering plugin {}", name);
 
-                    tokio::spawn(worker_child_process(
-                        name.clone(),
-                        plugin_config.path,
-                        cancellation_token.clone(),
-                        [plugin_config.config.to_string()]
-                            .into_iter()
-                            .chain(self.trace_ratio.map(|r| r.to_string())),
-                    ));
+                    let plugin_cancellation_token = cancellation_token.child_token();
 
-                    let rpc_client = WorkerClient::new(&name, self.ipc_client_request_timeout);
+                    // all plugin responses are served from the recording when replaying, so there's
+                    // no need to start them
+                    if !matches!(self.client_mode, ClientMode::Replay(_)) {
+                        tokio::spawn(worker_child_process(
+                            name.clone(),
+                            plugin_config.path.clone(),
+                            plugin_cancellation_token.clone(),
+                            plugin_args(&plugin_config, self.trace_ratio),
+                        ));
+                    }
+
+                    let rpc_client = WorkerClient::new(&name, self.ipc_client_request_timeout)
+                        .with_mode(self.client_mode.clone());
 
                     let prev = context_inner
                         .plugins
+                        .get_mut()
+                        .expect("poisoned")
                         .insert(name.clone(), rpc_client.clone());
 
                     if prev.is_some() {
@@ -485,7 +605,18 @@ impl<Q: Queue<VoyagerMessage>> EngineBuilder<Q> {
 
                     info!("registered plugin {name}");
 
-                    interest_filters.insert((idx, name), interest_filter);
+                    plugin_priorities.insert(name.clone(), plugin_config.priority);
+                    interest_filters.insert((idx, name.clone()), interest_filter.clone());
+
+                    workers.plugins.insert(
+                        name,
+                        PluginWorker {
+                            config: plugin_config,
+                            interest_filter,
+                            cancellation_token: plugin_cancellation_token,
+                            coordinator_server,
+                        },
+                    );
 
                     future::ready(Ok(()))
                 },
@@ -525,6 +656,8 @@ impl<Q: Queue<VoyagerMessage>> EngineBuilder<Q> {
                 Ok(())
             },
             self.trace_ratio,
+            &self.client_mode,
+            &mut workers,
         )
         .await?;
 
@@ -561,6 +694,8 @@ impl<Q: Queue<VoyagerMessage>> EngineBuilder<Q> {
                 Ok(())
             },
             self.trace_ratio,
+            &self.client_mode,
+            &mut workers,
         )
         .await?;
 
@@ -603,6 +738,8 @@ impl<Q: Queue<VoyagerMessage>> EngineBuilder<Q> {
                 Ok(())
             },
             self.trace_ratio,
+            &self.client_mode,
+            &mut workers,
         )
         .await?;
 
@@ -663,6 +800,8 @@ impl<Q: Queue<VoyagerMessage>> EngineBuilder<Q> {
                 Ok(())
             },
             self.trace_ratio,
+            &self.client_mode,
+            &mut workers,
         )
         .await?;
 
@@ -715,6 +854,8 @@ impl<Q: Queue<VoyagerMessage>> EngineBuilder<Q> {
                 Ok(())
             },
             self.trace_ratio,
+            &self.client_mode,
+            &mut workers,
         )
         .await?;
 
@@ -722,6 +863,8 @@ impl<Q: Queue<VoyagerMessage>> EngineBuilder<Q> {
 
         let futures = context_inner
             .plugins
+            .get_mut()
+            .expect("poisoned")
             .iter()
             .map(|(name, client)| async move {
                 match client
@@ -765,9 +908,33 @@ impl<Q: Queue<VoyagerMessage>> EngineBuilder<Q> {
                     interest_filter,
                 })
                 .collect(),
+            plugin_priorities,
         )?;
 
+        let (optimizers_tx, optimizers_rx) = mpsc::unbounded_channel();
+
+        for (plugin_name, worker) in &workers.plugins {
+            optimizers_tx
+                .send((plugin_name.clone(), worker.cancellation_token.clone()))
+                .expect("receiver is not dropped; qed;");
+        }
+
+        let reloader = Reloader {
+            config_loader: self.config_loader,
+            workers: Arc::new(Mutex::new(workers)),
+            context: context.clone(),
+            cache: cache.clone(),
+            interest_filters: interest_filters.clone(),
+            cancellation_token: cancellation_token.clone(),
+            rpc_middleware: logger_middleware_layer.clone(),
+            ipc_client_request_timeout: self.ipc_client_request_timeout,
+            client_mode: self.client_mode,
+            trace_ratio: self.trace_ratio,
+            optimizers: optimizers_tx,
+        };
+
         Ok(Engine {
+            engine_filter: EngineFilter::new(interest_filters.clone(), context.clone()),
             interest_filters,
             cancellation_token,
             context,
@@ -776,8 +943,11 @@ impl<Q: Queue<VoyagerMessage>> EngineBuilder<Q> {
             num_workers: self.num_workers,
             rest_laddr: self.rest_laddr,
             rpc_laddr: self.rpc_laddr,
+            admin_laddr: self.admin_laddr,
             optimizer_delay_milliseconds: self.optimizer_delay_milliseconds,
             rpc_middleware: logger_middleware_layer,
+            reloader,
+            optimizers: Mutex::new(optimizers_rx),
         })
     }
 }
@@ -786,9 +956,8 @@ pub struct Handler {
     server: Server,
 }
 
-impl voyager_vm::Handler<VoyagerMessage> for Handler {
-    #[instrument(skip_all)]
-    async fn call(&self, call: Call) -> Result<Op<VoyagerMessage>, QueueError> {
+impl Handler {
+    async fn handle_call(&self, call: Call) -> Result<Op<VoyagerMessage>, QueueError> {
         match call {
             Call::Index(Index {
                 start_height,
@@ -1107,6 +1276,25 @@ impl voyager_vm::Handler<VoyagerMessage> for Handler {
             }
         }
     }
+}
+
+impl voyager_vm::Handler<VoyagerMessage> for Handler {
+    #[instrument(skip_all)]
+    async fn call(&self, call: Call) -> Result<Op<VoyagerMessage>, QueueError> {
+        let context = self.server.context()?;
+
+        let chain_id = context.call_chain_id(&call);
+
+        let res = self.handle_call(call).await;
+
+        // the chain's in-flight slot is held by the admission of the op that this call is a part
+        // of, see EngineFilter
+        if let Some(chain_id) = chain_id {
+            context.chain_limits().record(&chain_id, &res);
+        }
+
+        res
+    }
 
     #[instrument(skip_all)]
     async fn callback(
@@ -1259,6 +1447,8 @@ async fn modules_startup<Info: Serialize + Clone + Unpin + Send + 'static>(
     id_f: fn(&Info) -> String,
     mut push_f: impl FnMut(&Info, WorkerClient) -> anyhow::Result<()>,
     trace_ratio: Option<f64>,
+    client_mode: &ClientMode,
+    workers: &mut Workers,
 ) -> anyhow::Result<()> {
     stream::iter(configs)
         .filter(|module_config| {
@@ -1311,19 +1501,29 @@ async fn modules_startup<Info: Serialize + Clone + Unpin + Send + 'static>(
 
             debug!("registering module {}", id);
 
-            tokio::spawn(worker_child_process(
-                id.clone(),
-                module_config.path,
-                cancellation_token.clone(),
-                [
-                    module_config.config.to_string(),
-                    serde_json::to_string(&module_config.info).unwrap(),
-                ]
-                .into_iter()
-                .chain(trace_ratio.map(|r| r.to_string())),
-            ));
+            let args = module_args(&module_config, trace_ratio);
+            let module_cancellation_token = cancellation_token.child_token();
+
+            // modules are never called when replaying, so there's no need to start them
+            let worker = if matches!(client_mode, ClientMode::Replay(_)) {
+                ModuleWorker {
+                    path: module_config.path.clone(),
+                    args,
+                    cancellation_token: module_cancellation_token,
+                }
+            } else {
+                ModuleWorker::spawn(
+                    id.clone(),
+                    module_config.path.clone(),
+                    args,
+                    module_cancellation_token,
+                )
+            };
 
-            let rpc_client = WorkerClient::new(&id, ipc_client_request_timeout);
+            workers.modules.insert(id.clone(), worker);
+
+            let rpc_client =
+                WorkerClient::new(&id, ipc_client_request_timeout).with_mode(client_mode.clone());
 
             push_f(&module_config.info, rpc_client)?;
 
@@ -1338,7 +1538,7 @@ pub mod api {
 
     use axum::{
         Json,
-        extract::State,
+        extract::{Query, State},
         http::StatusCode,
         routing::{get, post},
     };
@@ -1346,11 +1546,22 @@ pub mod api {
         SinkExt,
         channel::mpsc::{UnboundedReceiver, UnboundedSender, unbounded},
     };
+    use serde::Deserialize;
     use voyager_message::VoyagerMessage;
     use voyager_vm::Op;
 
-    pub fn run(laddr: SocketAddr) -> UnboundedReceiver<Op<VoyagerMessage>> {
-        let (queue_tx, queue_rx) = unbounded::<Op<VoyagerMessage>>();
+    /// An op to enqueue, along with the idempotency key it was enqueued with, if any.
+    pub type Enqueue = (Op<VoyagerMessage>, Option<String>);
+
+    #[derive(Deserialize)]
+    struct EnqueueParams {
+        /// Only enqueue the op if no op with the same key has been enqueued within the queue's
+        /// idempotency window, see [`IdempotencyKeyFilter`](crate::filter::IdempotencyKeyFilter).
+        idempotency_key: Option<String>,
+    }
+
+    pub fn run(laddr: SocketAddr) -> UnboundedReceiver<Enqueue> {
+        let (queue_tx, queue_rx) = unbounded::<Enqueue>();
 
         tokio::spawn(async move {
             let app = axum::Router::new()
@@ -1368,15 +1579,67 @@ pub mod api {
 
     // #[axum::debug_handler]
     async fn enqueue(
-        State(mut sender): State<UnboundedSender<Op<VoyagerMessage>>>,
+        State(mut sender): State<UnboundedSender<Enqueue>>,
+        Query(EnqueueParams { idempotency_key }): Query<EnqueueParams>,
         Json(op): Json<Op<VoyagerMessage>>,
     ) -> StatusCode {
-        sender.send(op).await.expect("receiver should not close");
+        sender
+            .send((op, idempotency_key))
+            .await
+            .expect("receiver should not close");
 
         StatusCode::OK
     }
 }
 
+/// Run the optimization pass of `plugin_name` over its tagged items, forever.
+async fn run_optimizer<Q: Queue<VoyagerMessage>>(
+    context: &Context,
+    queue: &Q,
+    interest_filters: &InterestFilters,
+    plugin_name: &str,
+    optimizer_delay_milliseconds: u64,
+) {
+    let client = context.plugin(plugin_name).expect("plugin exists");
+
+    let pass = PluginOptPass::new(client.client());
+
+    loop {
+        if context.pauses().is_plugin_paused(plugin_name) {
+            trace!("plugin is paused, not optimizing");
+
+            tokio::time::sleep(std::time::Duration::from_millis(
+                optimizer_delay_milliseconds,
+            ))
+            .await;
+
+            continue;
+        }
+
+        trace!("optimizing");
+
+        let res = queue
+            .optimize(plugin_name, interest_filters, &pass)
+            .await
+            .map_err(|e| {
+                e.map_either::<_, _, BoxDynError, BoxDynError>(|x| Box::new(x), |x| Box::new(x))
+                    .into_inner()
+            });
+
+        if let Err(error) = res {
+            error!(
+                error = %ErrorReporter(&*error),
+                "optimization pass returned with error"
+            );
+        }
+
+        tokio::time::sleep(std::time::Duration::from_millis(
+            optimizer_delay_milliseconds,
+        ))
+        .await;
+    }
+}
+
 pub struct PluginOptPass<T> {
     client: T,
 }
@@ -1416,6 +1679,11 @@ pub const fn default_rpc_laddr() -> SocketAddr {
     SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 7178)
 }
 
+#[must_use]
+pub const fn default_admin_laddr() -> SocketAddr {
+    SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 7179)
+}
+
 #[must_use]
 #[inline]
 pub const fn default_optimizer_delay_milliseconds() -> u64 {
@@ -0,0 +1,105 @@
+//! Pausing plugins and chains at runtime.
+//!
+//! While a plugin or chain is paused, any ops whose next call or callback would be handled by it
+//! are left in place in the queue and skipped when taking items from the queue (see
+//! [`EngineFilter`](crate::filter::EngineFilter)), and the optimization passes of paused plugins
+//! are skipped. Nothing is failed, dropped or re-enqueued: once resumed, the held ops are picked up
+//! again in their original order.
+//!
+//! The paused set is only kept in memory, and is cleared when voyager restarts.
+
+use std::{collections::BTreeSet, sync::RwLock};
+
+use voyager_primitives::ChainId;
+use voyager_rpc::types::PausedResponse;
+
+#[derive(Debug)]
+pub struct Pauses {
+    inner: RwLock<Paused>,
+}
+
+#[derive(Debug, Default)]
+struct Paused {
+    plugins: BTreeSet<String>,
+    chains: BTreeSet<ChainId>,
+}
+
+impl Default for Pauses {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Pauses {
+    pub fn new() -> Self {
+        Self {
+            inner: RwLock::new(Paused::default()),
+        }
+    }
+
+    /// Pause a plugin. Returns `false` if the plugin was already paused.
+    pub fn pause_plugin(&self, plugin: String) -> bool {
+        self.inner
+            .write()
+            .expect("lock is not poisoned; qed;")
+            .plugins
+            .insert(plugin)
+    }
+
+    /// Resume a plugin. Returns `false` if the plugin was not paused.
+    pub fn resume_plugin(&self, plugin: &str) -> bool {
+        self.inner
+            .write()
+            .expect("lock is not poisoned; qed;")
+            .plugins
+            .remove(plugin)
+    }
+
+    /// Pause a chain. Returns `false` if the chain was already paused.
+    pub fn pause_chain(&self, chain_id: ChainId) -> bool {
+        self.inner
+            .write()
+            .expect("lock is not poisoned; qed;")
+            .chains
+            .insert(chain_id)
+    }
+
+    /// Resume a chain. Returns `false` if the chain was not paused.
+    pub fn resume_chain(&self, chain_id: &ChainId) -> bool {
+        self.inner
+            .write()
+            .expect("lock is not poisoned; qed;")
+            .chains
+            .remove(chain_id)
+    }
+
+    pub fn is_plugin_paused(&self, plugin: &str) -> bool {
+        self.inner
+            .read()
+            .expect("lock is not poisoned; qed;")
+            .plugins
+            .contains(plugin)
+    }
+
+    /// The currently paused plugins and chains.
+    pub fn paused(&self) -> PausedResponse {
+        let paused = self.inner.read().expect("lock is not poisoned; qed;");
+
+        PausedResponse {
+            plugins: paused.plugins.iter().cloned().collect(),
+            chains: paused.chains.iter().cloned().collect(),
+        }
+    }
+
+    /// Whether an op whose next call or callback is handled by `plugin` (if it is a plugin message)
+    /// and interacts with `chain_id` should be held in the queue.
+    ///
+    /// `chain_id` is the chain the op interacts with, as returned by
+    /// [`Context::call_chain_id`](crate::context::Context::call_chain_id).
+    pub fn is_paused(&self, plugin: Option<&str>, chain_id: Option<&ChainId>) -> bool {
+        let paused = self.inner.read().expect("lock is not poisoned; qed;");
+
+        plugin.is_some_and(|plugin| paused.plugins.contains(plugin))
+            || chain_id.is_some_and(|chain_id| paused.chains.contains(chain_id))
+    }
+}
@@ -0,0 +1,544 @@
+//! Reloading the plugin and module configuration of a running engine.
+//!
+//! The config is re-read with the engine's [`ConfigLoader`] and diffed against the workers that are
+//! currently running. Only workers whose configuration changed are restarted; everything else,
+//! including the queue, is left untouched. Plugins can be freely added and removed, and the
+//! interest filters are refreshed from the plugin info of all (new or changed) plugins.
+//!
+//! Modules can only be reconfigured, not added or removed, since the module routing tables in the
+//! [`Context`] are fixed once the engine is built.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    path::PathBuf,
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail};
+use serde::Serialize;
+use tokio::{
+    sync::{Mutex, mpsc},
+    task::JoinHandle,
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, info_span};
+use voyager_plugin_protocol::{
+    WorkerClient, coordinator_server, record::ClientMode, worker_child_process,
+};
+use voyager_rpc::types::{PluginInfo, ReloadConfigResponse};
+
+use crate::{
+    LoggerMiddlewareLayer, cache,
+    context::{Context, ModuleConfig, ModulesConfig, PluginConfig},
+    filter::InterestFilters,
+    get_plugin_info,
+    server::Server,
+};
+
+/// Loads the plugin and module configuration to reload an engine with.
+pub type ConfigLoader = Arc<dyn Fn() -> anyhow::Result<ReloadableConfig> + Send + Sync>;
+
+/// The parts of the configuration that can be changed without restarting voyager.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReloadableConfig {
+    pub plugins: Vec<PluginConfig>,
+    pub modules: ModulesConfig,
+}
+
+/// All worker processes spawned by the engine.
+#[derive(Default)]
+pub(crate) struct Workers {
+    pub(crate) plugins: HashMap<String, PluginWorker>,
+    /// Keyed by module id.
+    pub(crate) modules: HashMap<String, ModuleWorker>,
+}
+
+pub(crate) struct PluginWorker {
+    pub(crate) config: PluginConfig,
+    pub(crate) interest_filter: String,
+    /// Cancels both the plugin process and its optimizer.
+    pub(crate) cancellation_token: CancellationToken,
+    pub(crate) coordinator_server: JoinHandle<()>,
+}
+
+pub(crate) struct ModuleWorker {
+    pub(crate) path: PathBuf,
+    pub(crate) args: Vec<String>,
+    pub(crate) cancellation_token: CancellationToken,
+}
+
+impl ModuleWorker {
+    pub(crate) fn spawn(
+        id: String,
+        path: PathBuf,
+        args: Vec<String>,
+        cancellation_token: CancellationToken,
+    ) -> Self {
+        tokio::spawn(worker_child_process(
+            id,
+            path.clone(),
+            cancellation_token.clone(),
+            args.clone(),
+        ));
+
+        Self {
+            path,
+            args,
+            cancellation_token,
+        }
+    }
+}
+
+/// The args a plugin worker process is started with.
+pub(crate) fn plugin_args(plugin_config: &PluginConfig, trace_ratio: Option<f64>) -> Vec<String> {
+    [plugin_config.config.to_string()]
+        .into_iter()
+        .chain(trace_ratio.map(|r| r.to_string()))
+        .collect()
+}
+
+/// The args a module worker process is started with.
+pub(crate) fn module_args<Info: Serialize>(
+    module_config: &ModuleConfig<Info>,
+    trace_ratio: Option<f64>,
+) -> Vec<String> {
+    [
+        module_config.config.to_string(),
+        serde_json::to_string(&module_config.info).unwrap(),
+    ]
+    .into_iter()
+    .chain(trace_ratio.map(|r| r.to_string()))
+    .collect()
+}
+
+/// Whether the worker process for `a` needs to be restarted to run with `b`.
+fn plugin_process_changed(a: &PluginConfig, b: &PluginConfig) -> bool {
+    a.path != b.path || a.config != b.config
+}
+
+/// Diff the running plugins and modules against the new config, returning the changes that need to
+/// be made to apply it. Plugins whose process is unchanged are not listed, but still take the new
+/// config (i.e. a changed priority).
+///
+/// `modules` are the id, path, and args of all enabled modules, see [`Reloader::module_processes`].
+pub(crate) fn plan(
+    running_plugins: &HashMap<String, PluginConfig>,
+    running_modules: &HashMap<String, (PathBuf, Vec<String>)>,
+    plugins: &[(PluginConfig, PluginInfo)],
+    modules: &BTreeMap<String, (PathBuf, Vec<String>)>,
+) -> anyhow::Result<ReloadConfigResponse> {
+    if modules.keys().collect::<BTreeSet<_>>() != running_modules.keys().collect::<BTreeSet<_>>() {
+        bail!(
+            "the set of enabled modules changed, adding or removing modules requires \
+            restarting voyager"
+        );
+    }
+
+    let mut names = HashSet::new();
+    for (_, plugin_info) in plugins {
+        if !names.insert(&plugin_info.name) {
+            bail!(
+                "multiple plugins configured with name `{}`",
+                plugin_info.name
+            );
+        }
+    }
+
+    let mut response = ReloadConfigResponse {
+        restarted_modules: modules
+            .iter()
+            .filter(|(id, process)| running_modules.get(*id) != Some(*process))
+            .map(|(id, _)| id.clone())
+            .collect(),
+        removed_plugins: running_plugins
+            .keys()
+            .filter(|name| !names.contains(name))
+            .cloned()
+            .collect(),
+        ..Default::default()
+    };
+
+    response.removed_plugins.sort();
+
+    for (plugin_config, plugin_info) in plugins {
+        match running_plugins.get(&plugin_info.name) {
+            Some(running) if !plugin_process_changed(running, plugin_config) => {}
+            Some(_) => response.restarted_plugins.push(plugin_info.name.clone()),
+            None => response.added_plugins.push(plugin_info.name.clone()),
+        }
+    }
+
+    Ok(response)
+}
+
+/// Reloads the configuration of a running [`Engine`](crate::Engine).
+#[derive(Clone)]
+pub struct Reloader {
+    pub(crate) config_loader: Option<ConfigLoader>,
+    pub(crate) workers: Arc<Mutex<Workers>>,
+    pub(crate) context: Arc<OnceLock<Context>>,
+    pub(crate) cache: cache::Cache,
+    pub(crate) interest_filters: InterestFilters,
+    pub(crate) cancellation_token: CancellationToken,
+    pub(crate) rpc_middleware: LoggerMiddlewareLayer,
+    pub(crate) ipc_client_request_timeout: Duration,
+    pub(crate) client_mode: ClientMode,
+    pub(crate) trace_ratio: Option<f64>,
+    /// The optimizers of started plugins are run by the engine, along with all of its other tasks.
+    pub(crate) optimizers: mpsc::UnboundedSender<(String, CancellationToken)>,
+}
+
+impl Reloader {
+    /// Reload the configuration with the configured [`ConfigLoader`].
+    ///
+    /// The new configuration is fully validated before any running workers are touched, so an
+    /// invalid config leaves the engine as it was.
+    pub async fn reload(&self) -> anyhow::Result<ReloadConfigResponse> {
+        let config_loader = self
+            .config_loader
+            .as_ref()
+            .ok_or_else(|| anyhow!("no config loader is configured, unable to reload"))?;
+
+        let ReloadableConfig { plugins, modules } = config_loader()?;
+
+        // hold the lock for the entire reload to ensure only one reload runs at a time
+        let mut workers = self.workers.lock().await;
+
+        let context = self.context.get().expect("engine has been built; qed;");
+
+        let modules = self.module_processes(modules);
+
+        let mut plugin_infos = vec![];
+
+        for plugin_config in plugins.into_iter().filter(|c| c.enabled) {
+            // the plugin info only depends on the path and config, so it doesn't need to be
+            // queried again for unchanged plugins
+            let plugin_info = match workers
+                .plugins
+                .iter()
+                .find(|(_, worker)| !plugin_process_changed(&worker.config, &plugin_config))
+            {
+                Some((name, worker)) => PluginInfo {
+                    name: name.clone(),
+                    interest_filter: worker.interest_filter.clone(),
+                },
+                None => {
+                    // this spawns the plugin process and waits for it to exit
+                    let span = info_span!("get_plugin_info", path = %plugin_config.path.display());
+                    let plugin_config = plugin_config.clone();
+
+                    tokio::task::spawn_blocking(move || {
+                        span.in_scope(|| get_plugin_info(&plugin_config))
+                    })
+                    .await??
+                }
+            };
+
+            plugin_infos.push((plugin_config, plugin_info));
+        }
+
+        let response = plan(
+            &workers
+                .plugins
+                .iter()
+                .map(|(name, worker)| (name.clone(), worker.config.clone()))
+                .collect(),
+            &workers
+                .modules
+                .iter()
+                .map(|(id, worker)| (id.clone(), (worker.path.clone(), worker.args.clone())))
+                .collect(),
+            &plugin_infos,
+            &modules,
+        )?;
+
+        // this also validates all of the filters, so it must happen before any workers are
+        // modified
+        self.interest_filters.replace(
+            plugin_infos.iter().map(|(_, info)| info.clone()).collect(),
+            plugin_infos
+                .iter()
+                .map(|(config, info)| (info.name.clone(), config.priority))
+                .collect(),
+        )?;
+
+        for id in &response.restarted_modules {
+            info!(%id, "module config changed, restarting module");
+
+            let worker = workers
+                .modules
+                .get_mut(id)
+                .expect("module set is unchanged; qed;");
+
+            let (path, args) = modules[id].clone();
+
+            worker.cancellation_token.cancel();
+
+            *worker = ModuleWorker::spawn(
+                id.clone(),
+                path,
+                args,
+                self.cancellation_token.child_token(),
+            );
+        }
+
+        for name in &response.removed_plugins {
+            info!(%name, "plugin removed, stopping plugin");
+
+            let worker = workers.plugins.remove(name).expect("plugin exists; qed;");
+
+            worker.cancellation_token.cancel();
+            worker.coordinator_server.abort();
+
+            context.plugins.write().expect("poisoned").remove(name);
+
+            // a plugin added later with the same name starts out unpaused
+            context.pauses.resume_plugin(name);
+
+            // the failures of the removed plugin shouldn't hold calls for its chain, unless
+            // another plugin still interacts with it
+            if let Some(chain_id) = context.plugin_chain_id(name).filter(|chain_id| {
+                !plugin_infos
+                    .iter()
+                    .any(|(_, info)| context.plugin_chain_id(&info.name).as_ref() == Some(chain_id))
+            }) {
+                context.chain_limits.reset_breaker(&chain_id);
+            }
+        }
+
+        for (
+            plugin_config,
+            PluginInfo {
+                name,
+                interest_filter,
+            },
+        ) in plugin_infos
+        {
+            if response.added_plugins.contains(&name) {
+                info!(%name, "plugin added, starting plugin");
+
+                let coordinator_server = tokio::spawn(
+                    coordinator_server(
+                        &name,
+                        Server::new(self.cache.clone(), self.context.clone()),
+                        self.rpc_middleware.clone(),
+                    )
+                    .await?,
+                );
+
+                context.plugins.write().expect("poisoned").insert(
+                    name.clone(),
+                    WorkerClient::new(&name, self.ipc_client_request_timeout)
+                        .with_mode(self.client_mode.clone()),
+                );
+
+                let cancellation_token = self.cancellation_token.child_token();
+
+                self.spawn_plugin(&name, &plugin_config, &cancellation_token);
+
+                workers.plugins.insert(
+                    name,
+                    PluginWorker {
+                        config: plugin_config,
+                        interest_filter,
+                        cancellation_token,
+                        coordinator_server,
+                    },
+                );
+            } else {
+                let worker = workers
+                    .plugins
+                    .get_mut(&name)
+                    .expect("plugin is running; qed;");
+
+                if response.restarted_plugins.contains(&name) {
+                    info!(%name, "plugin config changed, restarting plugin");
+
+                    worker.cancellation_token.cancel();
+                    worker.cancellation_token = self.cancellation_token.child_token();
+
+                    self.spawn_plugin(&name, &plugin_config, &worker.cancellation_token);
+                }
+
+                worker.config = plugin_config;
+                worker.interest_filter = interest_filter;
+            }
+        }
+
+        Ok(response)
+    }
+
+    /// The id, path, and args of all enabled modules.
+    fn module_processes(&self, modules: ModulesConfig) -> BTreeMap<String, (PathBuf, Vec<String>)> {
+        fn processes<Info: Serialize>(
+            configs: Vec<ModuleConfig<Info>>,
+            id_f: fn(&Info) -> String,
+            trace_ratio: Option<f64>,
+        ) -> impl Iterator<Item = (String, (PathBuf, Vec<String>))> {
+            configs
+                .into_iter()
+                .filter(|module_config| module_config.enabled)
+                .map(move |module_config| {
+                    (
+                        id_f(&module_config.info),
+                        (
+                            module_config.path.clone(),
+                            module_args(&module_config, trace_ratio),
+                        ),
+                    )
+                })
+        }
+
+        let ModulesConfig {
+            state,
+            proof,
+            consensus,
+            client,
+            client_bootstrap,
+        } = modules;
+
+        processes(state, |info| info.id(), self.trace_ratio)
+            .chain(processes(proof, |info| info.id(), self.trace_ratio))
+            .chain(processes(consensus, |info| info.id(), self.trace_ratio))
+            .chain(processes(client, |info| info.id(), self.trace_ratio))
+            .chain(processes(
+                client_bootstrap,
+                |info| info.id(),
+                self.trace_ratio,
+            ))
+            .collect()
+    }
+
+    /// Spawn the worker process for a plugin and have the engine run its optimizer, both of which run
+    /// until `cancellation_token` is cancelled.
+    fn spawn_plugin(
+        &self,
+        name: &str,
+        plugin_config: &PluginConfig,
+        cancellation_token: &CancellationToken,
+    ) {
+        tokio::spawn(worker_child_process(
+            name.to_owned(),
+            plugin_config.path.clone(),
+            cancellation_token.clone(),
+            plugin_args(plugin_config, self.trace_ratio),
+        ));
+
+        if self
+            .optimizers
+            .send((name.to_owned(), cancellation_token.clone()))
+            .is_err()
+        {
+            debug!(plugin_name = %name, "engine is not running, not spawning optimizer");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn plugin(name: &str, config: serde_json::Value) -> (PluginConfig, PluginInfo) {
+        (
+            PluginConfig {
+                path: PathBuf::from(format!("/bin/{name}")),
+                config,
+                enabled: true,
+                priority: Default::default(),
+            },
+            PluginInfo {
+                name: name.to_owned(),
+                interest_filter: "false".to_owned(),
+            },
+        )
+    }
+
+    fn module(path: &str) -> (PathBuf, Vec<String>) {
+        (PathBuf::from(path), vec!["{}".to_owned()])
+    }
+
+    #[test]
+    fn plan_diffs_plugins_and_modules() {
+        let running_plugins = [
+            plugin("unchanged", json!({})),
+            plugin("changed", json!({ "a": 1 })),
+            plugin("removed", json!({})),
+        ]
+        .into_iter()
+        .map(|(config, info)| (info.name, config))
+        .collect();
+
+        let running_modules = [
+            ("m1".to_owned(), module("/bin/m1")),
+            ("m2".to_owned(), module("/bin/m2")),
+        ]
+        .into_iter()
+        .collect();
+
+        let mut unchanged = plugin("unchanged", json!({}));
+        // a changed priority doesn't require a restart
+        unchanged.0.priority = voyager_vm::Priority(1);
+
+        let response = plan(
+            &running_plugins,
+            &running_modules,
+            &[
+                unchanged,
+                plugin("changed", json!({ "a": 2 })),
+                plugin("added", json!({})),
+            ],
+            &[
+                ("m1".to_owned(), module("/bin/m1")),
+                ("m2".to_owned(), module("/bin/m2-new")),
+            ]
+            .into_iter()
+            .collect(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            response,
+            ReloadConfigResponse {
+                added_plugins: vec!["added".to_owned()],
+                removed_plugins: vec!["removed".to_owned()],
+                restarted_plugins: vec!["changed".to_owned()],
+                restarted_modules: vec!["m2".to_owned()],
+            }
+        );
+    }
+
+    #[test]
+    fn plan_rejects_module_set_change() {
+        let running_modules = [("m1".to_owned(), module("/bin/m1"))].into_iter().collect();
+
+        plan(
+            &HashMap::new(),
+            &running_modules,
+            &[],
+            &[
+                ("m1".to_owned(), module("/bin/m1")),
+                ("m2".to_owned(), module("/bin/m2")),
+            ]
+            .into_iter()
+            .collect(),
+        )
+        .unwrap_err();
+    }
+
+    #[test]
+    fn plan_rejects_duplicate_plugin_names() {
+        plan(
+            &HashMap::new(),
+            &HashMap::new(),
+            &[
+                plugin("a", json!({ "a": 1 })),
+                plugin("a", json!({ "a": 2 })),
+            ],
+            &BTreeMap::new(),
+        )
+        .unwrap_err();
+    }
+}
@@ -0,0 +1,123 @@
+//! Capturing and replaying the handling of a single op.
+//!
+//! An op is captured by handling it once against live plugins and modules with an engine built
+//! in [`ClientMode::Record`], and saving the op along with every response that was returned as a
+//! [`Fixture`]. The fixture can then be replayed offline with an engine built in
+//! [`ClientMode::Replay`], where every plugin and module response is served from the fixture.
+//!
+//! Handling is always a dry run: any op that would submit a transaction (a [`SubmitTx`] call, or
+//! a call to a plugin that handles [`SubmitTx`]) is halted instead of handled, and returned in
+//! [`Replay::halted`].
+//!
+//! [`SubmitTx`]: voyager_message::call::SubmitTx
+//! [`Replay::halted`]: voyager_vm::engine::Replay::halted
+//! [`ClientMode::Record`]: voyager_plugin_protocol::record::ClientMode::Record
+//! [`ClientMode::Replay`]: voyager_plugin_protocol::record::ClientMode::Replay
+
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use voyager_message::{
+    PluginMessage, VoyagerMessage,
+    call::{Call, SubmitTx},
+};
+use voyager_plugin_protocol::record::RecordedCall;
+use voyager_primitives::ChainId;
+use voyager_vm::{Op, Queue, Visit, call, engine::Replay};
+
+use crate::{Engine, PluginOptPass};
+
+/// A captured op, along with all of the responses returned while handling it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct Fixture {
+    pub op: Op<VoyagerMessage>,
+    pub calls: Vec<RecordedCall>,
+    /// The result of handling the op when it was captured.
+    pub replay: Replay<VoyagerMessage>,
+}
+
+impl<Q: Queue<VoyagerMessage>> Engine<Q> {
+    /// Handle `op` to completion with this engine's plugins and modules, without going through the
+    /// queue and without submitting any transactions. See [`voyager_vm::engine::replay`] for more
+    /// information.
+    pub async fn replay(&self, op: Op<VoyagerMessage>, max_steps: usize) -> Replay<VoyagerMessage> {
+        let context = self.context.get().expect("engine has been built; qed;");
+
+        let clients = self
+            .interest_filters
+            .plugin_names()
+            .into_iter()
+            .map(|plugin_name| {
+                (
+                    plugin_name,
+                    context.plugin(plugin_name).expect("plugin exists"),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let passes = clients
+            .iter()
+            .map(|(plugin_name, client)| ((*plugin_name).to_owned(), PluginOptPass::new(client)))
+            .collect::<HashMap<_, _>>();
+
+        // plugins named `{PLUGIN_NAME}/{chain_id}` that handle transaction submissions for that
+        // chain, calls to these plugins are never made
+        let transaction_plugins = clients
+            .iter()
+            .map(|(plugin_name, _)| *plugin_name)
+            .filter(|plugin_name| {
+                plugin_name.split('/').nth(1).is_some_and(|chain_id| {
+                    self.interest_filters.is_interested(
+                        plugin_name,
+                        &call(SubmitTx {
+                            chain_id: ChainId::new(chain_id.to_owned()),
+                            datagrams: vec![],
+                        }),
+                    )
+                })
+            })
+            .collect::<HashSet<_>>();
+
+        voyager_vm::engine::replay(
+            &self.server(),
+            &self.interest_filters,
+            &passes,
+            op,
+            max_steps,
+            |op| submits_transactions(op, &transaction_plugins),
+        )
+        .await
+    }
+}
+
+/// Whether handling `op` would (eventually) submit a transaction.
+fn submits_transactions(op: &Op<VoyagerMessage>, transaction_plugins: &HashSet<&str>) -> bool {
+    struct SubmitTxVisitor<'a> {
+        transaction_plugins: &'a HashSet<&'a str>,
+        found: bool,
+    }
+
+    impl Visit<VoyagerMessage> for SubmitTxVisitor<'_> {
+        fn visit_call(&mut self, call: &mut Call) {
+            match call {
+                Call::SubmitTx(_) => self.found = true,
+                Call::Plugin(PluginMessage { plugin, .. })
+                    if self.transaction_plugins.contains(plugin.as_str()) =>
+                {
+                    self.found = true
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut visitor = SubmitTxVisitor {
+        transaction_plugins,
+        found: false,
+    };
+
+    visitor.visit_op(&mut op.clone());
+
+    visitor.found
+}
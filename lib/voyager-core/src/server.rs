@@ -5,8 +5,9 @@ use std::sync::{Arc, OnceLock};
 use futures::TryFutureExt;
 use jsonrpsee::{Extensions, core::async_trait};
 use serde_json::Value;
-use tracing::{debug, info_span, instrument, trace};
+use tracing::{debug, info, info_span, instrument, trace};
 use unionlabs::{ibc::core::client::height::Height, primitives::Bytes};
+use voyager_message::VoyagerMessage;
 use voyager_plugin_protocol::WithId;
 use voyager_primitives::{
     ChainId, ClientInfo, ClientStateMeta, ClientType, ConsensusStateMeta, IbcInterface, IbcSpec,
@@ -14,18 +15,20 @@ use voyager_primitives::{
 };
 use voyager_rpc::{
     ClientBootstrapModuleClient, ClientModuleClient, FinalityModuleClient, PluginClient,
-    RawProofModuleClient, RawStateModuleClient, RpcError, RpcResult, VoyagerRpcServer,
+    RawProofModuleClient, RawStateModuleClient, RpcError, RpcResult, VoyagerAdminRpcServer,
+    VoyagerQueueRpcServer, VoyagerRpcServer,
     types::{
-        IbcProofResponse, IbcStateResponse, InfoResponse, SelfClientStateResponse,
-        SelfConsensusStateResponse,
+        IbcProofResponse, IbcStateResponse, InfoResponse, PausedResponse, ReloadConfigResponse,
+        SelfClientStateResponse, SelfConsensusStateResponse,
     },
 };
 use voyager_types::{IbcProof, RawClientId};
-use voyager_vm::ItemId;
+use voyager_vm::{HistoryItem, ItemId, Queue};
 
 use crate::{
     cache::{ClientInfoRequest, StateRequest},
     context::Context,
+    reload::Reloader,
 };
 
 #[derive(Clone)]
@@ -792,6 +795,36 @@ impl Server {
             .await
     }
 
+    // TODO: Use valuable here
+    #[instrument(skip_all, fields(%client_type, %ibc_interface, %ibc_spec_id, %header_a, %header_b))]
+    pub async fn encode_misbehaviour(
+        &self,
+        client_type: &ClientType,
+        ibc_interface: &IbcInterface,
+        ibc_spec_id: &IbcSpecId,
+        header_a: Value,
+        header_b: Value,
+    ) -> RpcResult<Bytes> {
+        self.span()
+            .in_scope(|| async {
+                trace!("encoding misbehaviour");
+
+                let client_module = self
+                    .context()?
+                    .client_module(client_type, ibc_interface, ibc_spec_id)?
+                    .with_id(self.item_id);
+
+                let misbehaviour = client_module
+                    .encode_misbehaviour(header_a, header_b)
+                    .await?;
+
+                trace!(%misbehaviour, "encoded misbehaviour");
+
+                Ok(misbehaviour)
+            })
+            .await
+    }
+
     // TODO: Use valuable here
     #[instrument(skip_all, fields(%client_type, %ibc_interface, %ibc_spec_id))]
     pub async fn decode_client_state_meta(
@@ -1129,6 +1162,26 @@ impl VoyagerRpcServer for Server {
             .await
     }
 
+    async fn encode_misbehaviour(
+        &self,
+        e: &Extensions,
+        client_type: ClientType,
+        ibc_interface: IbcInterface,
+        ibc_spec_id: IbcSpecId,
+        header_a: Value,
+        header_b: Value,
+    ) -> RpcResult<Bytes> {
+        self.with_id(e.try_get().ok().cloned())
+            .encode_misbehaviour(
+                &client_type,
+                &ibc_interface,
+                &ibc_spec_id,
+                header_a,
+                header_b,
+            )
+            .await
+    }
+
     // TODO: Use valuable here
     async fn decode_client_state_meta(
         &self,
@@ -1213,7 +1266,8 @@ impl VoyagerRpcServer for Server {
         trace!(?params);
 
         Ok(PluginClient::<Value, Value>::custom(
-            self.with_id(e.try_get().ok().cloned())
+            &self
+                .with_id(e.try_get().ok().cloned())
                 .context()?
                 .plugin(&plugin)?,
             method,
@@ -1223,6 +1277,118 @@ impl VoyagerRpcServer for Server {
     }
 }
 
+/// Serves [`VoyagerQueueRpc`] for a [`Queue`].
+#[derive(Clone)]
+pub struct QueueServer<Q> {
+    queue: Q,
+}
+
+impl<Q: Queue<VoyagerMessage>> QueueServer<Q> {
+    pub fn new(queue: Q) -> Self {
+        Self { queue }
+    }
+}
+
+#[async_trait]
+impl<Q: Queue<VoyagerMessage>> VoyagerQueueRpcServer for QueueServer<Q> {
+    #[instrument(skip_all, fields(id = id.raw(), %max_depth))]
+    async fn queue_history(
+        &self,
+        id: ItemId,
+        max_depth: u32,
+    ) -> RpcResult<Vec<HistoryItem<VoyagerMessage>>> {
+        self.queue
+            .history(id, max_depth)
+            .await
+            .map_err(RpcError::retryable("error querying item history"))
+    }
+}
+
+/// Serves [`VoyagerAdminRpc`](voyager_rpc::VoyagerAdminRpc) for an engine.
+#[derive(Clone)]
+pub struct AdminServer {
+    reloader: Reloader,
+}
+
+impl AdminServer {
+    pub fn new(reloader: Reloader) -> Self {
+        Self { reloader }
+    }
+
+    fn context(&self) -> RpcResult<&Context> {
+        self.reloader
+            .context
+            .get()
+            .ok_or_else(|| RpcError::retryable_from_message("server has not started"))
+    }
+}
+
+#[async_trait]
+impl VoyagerAdminRpcServer for AdminServer {
+    #[instrument(skip_all)]
+    async fn reload_config(&self) -> RpcResult<ReloadConfigResponse> {
+        let response =
+            self.reloader.reload().await.map_err(|e| {
+                RpcError::fatal_from_message(format!("error reloading config: {e:#}"))
+            })?;
+
+        info!(?response, "reloaded config");
+
+        Ok(response)
+    }
+
+    #[instrument(skip_all, fields(%plugin))]
+    async fn pause_plugin(&self, plugin: String) -> RpcResult<PausedResponse> {
+        let context = self.context()?;
+
+        context.plugin(&plugin)?;
+
+        if context.pauses().pause_plugin(plugin) {
+            info!("paused plugin");
+        }
+
+        Ok(context.pauses().paused())
+    }
+
+    #[instrument(skip_all, fields(%plugin))]
+    async fn resume_plugin(&self, plugin: String) -> RpcResult<PausedResponse> {
+        let context = self.context()?;
+
+        if context.pauses().resume_plugin(&plugin) {
+            info!("resumed plugin");
+        }
+
+        Ok(context.pauses().paused())
+    }
+
+    #[instrument(skip_all, fields(%chain_id))]
+    async fn pause_chain(&self, chain_id: ChainId) -> RpcResult<PausedResponse> {
+        let context = self.context()?;
+
+        if context.pauses().pause_chain(chain_id) {
+            info!("paused chain");
+        }
+
+        Ok(context.pauses().paused())
+    }
+
+    #[instrument(skip_all, fields(%chain_id))]
+    async fn resume_chain(&self, chain_id: ChainId) -> RpcResult<PausedResponse> {
+        let context = self.context()?;
+
+        if context.pauses().resume_chain(&chain_id) {
+            info!("resumed chain");
+        }
+
+        Ok(context.pauses().paused())
+    }
+
+    #[instrument(skip_all)]
+    async fn paused(&self) -> RpcResult<PausedResponse> {
+        Ok(self.context()?.pauses().paused())
+    }
+}
+
 trait ExtensionsExt {
     /// Retrieve a value from this [`Extensions`], returning an [`RpcResult`] for more
     /// convenient handling in rpc server implementations.
@@ -2,6 +2,7 @@ use enumorph::Enumorph;
 use macros::model;
 use serde::de::DeserializeOwned;
 use serde_json::Value;
+use sha2::{Digest, Sha256};
 use subset_of::SubsetOf;
 use unionlabs::{ibc::core::client::height::Height, primitives::H256};
 use voyager_primitives::{ChainId, ClientInfo, IbcSpec, IbcSpecId};
@@ -91,6 +92,35 @@ impl ChainEvent {
             None
         }
     }
+
+    /// A key uniquely identifying this event, used to deduplicate events that are indexed more than
+    /// once (i.e. by multiple event source plugins with overlapping block ranges).
+    ///
+    /// This is the sha256 hash of the chain id, transaction hash, ibc spec id and event, such that
+    /// the key is of a fixed size regardless of the size of the event. Events without a
+    /// transaction hash can't be uniquely identified, and have no key.
+    pub fn idempotency_key(&self) -> Option<String> {
+        self.tx_hash.map(|tx_hash| {
+            let event = serde_json::to_vec(&self.event).expect("serialization is infallible; qed;");
+
+            let hash = [
+                self.chain_id.as_str().as_bytes(),
+                tx_hash.get().as_slice(),
+                self.ibc_spec_id.as_str().as_bytes(),
+                &event,
+            ]
+            .into_iter()
+            .fold(Sha256::new(), |hasher, bz| {
+                // length prefixed, such that the boundaries between the fields are unambiguous
+                hasher
+                    .chain_update((bz.len() as u64).to_be_bytes())
+                    .chain_update(bz)
+            })
+            .finalize();
+
+            format!("ibc_event/{}", H256::new(hash.into()))
+        })
+    }
 }
 
 #[model]
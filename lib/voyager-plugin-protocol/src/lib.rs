@@ -50,7 +50,7 @@ use reth_ipc::{
     server::{RpcService, RpcServiceBuilder},
 };
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
-use serde_json::value::RawValue;
+use serde_json::{Value, value::RawValue};
 use tokio::time::sleep;
 use tokio_util::sync::CancellationToken;
 use tower::Layer;
@@ -61,6 +61,10 @@ use voyager_client::VoyagerClient;
 use voyager_rpc::VoyagerRpcServer;
 use voyager_vm::ItemId;
 
+use crate::record::{ClientMode, RawParams, RecordedCall, split_params};
+
+pub mod record;
+
 pub const INVALID_CONFIG_EXIT_CODE: u8 = 13;
 pub const STARTUP_ERROR_EXIT_CODE: u8 = 14;
 
@@ -174,11 +178,13 @@ pub async fn worker_server<T>(
 /// The RPC client to communicate with a worker from the coordinator.
 ///
 /// This is a thin wrapper around a [`reconnecting_jsonrpc_ws_client::Client`]. If the worker crashes or restarts, it will automatically attempt to reconnect.
+///
+/// Requests can optionally be recorded or served from a recording, see [`ClientMode`].
 #[derive(Clone)]
 pub struct WorkerClient {
     client: reconnecting_jsonrpc_ws_client::Client,
-    #[allow(dead_code)]
     name: String,
+    mode: ClientMode,
 }
 
 impl WorkerClient {
@@ -187,8 +193,87 @@ impl WorkerClient {
     }
 }
 
-delegate_client_impl!(WorkerClient: |this| this.client);
-delegate_client_impl!(&WorkerClient: |this| this.client);
+impl ClientT for WorkerClient {
+    async fn notification<Params>(
+        &self,
+        method: &str,
+        params: Params,
+    ) -> Result<(), jsonrpsee::core::client::Error>
+    where
+        Params: ToRpcParams + Send,
+    {
+        self.client.notification(method, params).await
+    }
+
+    async fn request<R, Params>(
+        &self,
+        method: &str,
+        params: Params,
+    ) -> Result<R, jsonrpsee::core::client::Error>
+    where
+        R: DeserializeOwned,
+        Params: ToRpcParams + Send,
+    {
+        let (recorder, replayer) = match &self.mode {
+            ClientMode::Live => return self.client.request(method, params).await,
+            ClientMode::Record(recorder) => (Some(recorder), None),
+            ClientMode::Replay(replayer) => (None, Some(replayer)),
+        };
+
+        let raw_params = params.to_rpc_params()?;
+
+        let (item_id, request_params) = split_params(raw_params.as_deref())
+            .map_err(jsonrpsee::core::client::Error::ParseError)?;
+
+        let response = match replayer {
+            Some(replayer) => {
+                trace!(%method, name = %self.name, "replaying request");
+
+                replayer
+                    .next(&self.name, method, request_params.as_ref())
+                    .map_err(jsonrpsee::core::client::Error::Call)
+            }
+            None => {
+                self.client
+                    .request::<Value, _>(method, RawParams(raw_params))
+                    .await
+            }
+        };
+
+        if let Some(recorder) = recorder {
+            let recorded_response = match &response {
+                Ok(value) => Some(Ok(value.clone())),
+                Err(jsonrpsee::core::client::Error::Call(error)) => Some(Err(error.clone().into())),
+                // transport errors are not a response from the worker, so they aren't recorded
+                Err(_) => None,
+            };
+
+            if let Some(recorded_response) = recorded_response {
+                recorder.record(RecordedCall {
+                    client: self.name.clone(),
+                    item_id,
+                    method: method.to_owned(),
+                    params: request_params,
+                    response: recorded_response,
+                });
+            }
+        }
+
+        serde_json::from_value(response?).map_err(jsonrpsee::core::client::Error::ParseError)
+    }
+
+    fn batch_request<'a, R>(
+        &self,
+        batch: BatchRequestBuilder<'a>,
+    ) -> impl Future<Output = Result<BatchResponse<'a, R>, jsonrpsee::core::client::Error>> + Send
+    where
+        R: DeserializeOwned + Debug + 'a,
+    {
+        self.client.batch_request(batch)
+    }
+}
+
+delegate_client_impl!(&WorkerClient: |this| **this);
 
 impl WorkerClient {
     pub fn new(name: &str, request_timeout: Duration) -> Self {
@@ -216,9 +301,16 @@ impl WorkerClient {
         Self {
             client,
             name: name.to_owned(),
+            mode: ClientMode::Live,
         }
     }
 
+    /// Set how requests made through this client are handled.
+    #[must_use]
+    pub fn with_mode(self, mode: ClientMode) -> Self {
+        Self { mode, ..self }
+    }
+
     pub fn client(&self) -> &reconnecting_jsonrpc_ws_client::Client {
         &self.client
     }
@@ -0,0 +1,197 @@
+//! Recording and replaying of the requests made to workers.
+//!
+//! A [`WorkerClient`](crate::WorkerClient) can be put into [`ClientMode::Record`] to capture every
+//! request made through it along with the response, or into [`ClientMode::Replay`] to serve
+//! previously recorded responses instead of connecting to the worker at all. This allows for
+//! reproducing the handling of an op offline, without access to the chains the modules talk to.
+
+use std::sync::{Arc, Mutex};
+
+use jsonrpsee::{core::traits::ToRpcParams, types::ErrorObject};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, value::RawValue};
+use voyager_rpc::FATAL_JSONRPC_ERROR_CODE;
+use voyager_vm::ItemId;
+
+use crate::ParamsWithItemId;
+
+/// How a [`WorkerClient`](crate::WorkerClient) handles requests.
+#[derive(Debug, Clone, Default)]
+pub enum ClientMode {
+    /// Send all requests to the worker.
+    #[default]
+    Live,
+    /// Send all requests to the worker, recording the responses in the contained [`Recorder`].
+    Record(Recorder),
+    /// Serve all responses from the contained [`Replayer`]. The worker is never contacted.
+    Replay(Replayer),
+}
+
+/// A single request made to a worker, and the response it returned.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct RecordedCall {
+    /// The name of the worker the request was made to.
+    pub client: String,
+    /// The item id threaded through the request, if any.
+    pub item_id: Option<ItemId>,
+    pub method: String,
+    pub params: Option<Value>,
+    pub response: Result<Value, RecordedError>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct RecordedError {
+    pub code: i32,
+    pub message: String,
+    pub data: Option<Value>,
+}
+
+impl From<ErrorObject<'_>> for RecordedError {
+    fn from(error: ErrorObject<'_>) -> Self {
+        Self {
+            code: error.code(),
+            message: error.message().to_owned(),
+            data: error
+                .data()
+                .map(|data| serde_json::from_str(data.get()).expect("data is valid json; qed;")),
+        }
+    }
+}
+
+impl From<RecordedError> for ErrorObject<'static> {
+    fn from(error: RecordedError) -> Self {
+        ErrorObject::owned(error.code, error.message, error.data)
+    }
+}
+
+/// A shared log of [`RecordedCall`]s.
+#[derive(Debug, Clone, Default)]
+pub struct Recorder {
+    calls: Arc<Mutex<Vec<RecordedCall>>>,
+}
+
+impl Recorder {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, call: RecordedCall) {
+        self.calls.lock().expect("poisoned").push(call);
+    }
+
+    /// Remove and return all of the calls recorded so far.
+    pub fn take(&self) -> Vec<RecordedCall> {
+        std::mem::take(&mut *self.calls.lock().expect("poisoned"))
+    }
+}
+
+/// Serves [`RecordedCall`]s in place of a worker.
+///
+/// Requests are matched on the client name, method and params (ignoring the threaded item id).
+/// Each recorded call is only served once, and calls with identical requests are served in the
+/// order they were recorded in, so that retries see the same sequence of responses as the
+/// original run.
+#[derive(Debug, Clone, Default)]
+pub struct Replayer {
+    calls: Arc<Mutex<Vec<Option<RecordedCall>>>>,
+    misses: Arc<Mutex<Vec<String>>>,
+}
+
+impl Replayer {
+    #[must_use]
+    pub fn new(calls: Vec<RecordedCall>) -> Self {
+        Self {
+            calls: Arc::new(Mutex::new(calls.into_iter().map(Some).collect())),
+            misses: Default::default(),
+        }
+    }
+
+    /// Take the next recorded response for this request.
+    ///
+    /// If there is no matching recorded call, a fatal error is returned and the request is
+    /// recorded as a miss (see [`Replayer::misses`]).
+    pub fn next(
+        &self,
+        client: &str,
+        method: &str,
+        params: Option<&Value>,
+    ) -> Result<Value, ErrorObject<'static>> {
+        let call = self
+            .calls
+            .lock()
+            .expect("poisoned")
+            .iter_mut()
+            .find(|call| {
+                call.as_ref().is_some_and(|call| {
+                    call.client == client && call.method == method && call.params.as_ref() == params
+                })
+            })
+            .and_then(Option::take);
+
+        match call {
+            Some(call) => call.response.map_err(Into::into),
+            None => {
+                let message = format!("no recorded response for {method} on {client}");
+
+                self.misses.lock().expect("poisoned").push(message.clone());
+
+                Err(ErrorObject::owned(
+                    FATAL_JSONRPC_ERROR_CODE,
+                    message,
+                    Some(params),
+                ))
+            }
+        }
+    }
+
+    /// All requests that were made that had no matching recorded call.
+    pub fn misses(&self) -> Vec<String> {
+        self.misses.lock().expect("poisoned").clone()
+    }
+
+    /// All recorded calls that have not been served yet.
+    pub fn remaining(&self) -> Vec<RecordedCall> {
+        self.calls
+            .lock()
+            .expect("poisoned")
+            .iter()
+            .flatten()
+            .cloned()
+            .collect()
+    }
+}
+
+/// Split the raw params of a request into the item id threaded through it (if any) and the actual
+/// params of the request.
+pub(crate) fn split_params(
+    params: Option<&RawValue>,
+) -> Result<(Option<ItemId>, Option<Value>), serde_json::Error> {
+    let Some(params) = params else {
+        return Ok((None, None));
+    };
+
+    match serde_json::from_str::<ParamsWithItemId>(params.get()) {
+        Ok(ParamsWithItemId {
+            item_id, params, ..
+        }) => Ok((
+            Some(item_id),
+            params
+                .as_deref()
+                .map(|params| serde_json::from_str(params.get()))
+                .transpose()?,
+        )),
+        Err(_) => Ok((None, Some(serde_json::from_str(params.get())?))),
+    }
+}
+
+/// Already serialized request params.
+pub(crate) struct RawParams(pub(crate) Option<Box<RawValue>>);
+
+impl ToRpcParams for RawParams {
+    fn to_rpc_params(self) -> Result<Option<Box<RawValue>>, serde_json::Error> {
+        Ok(self.0)
+    }
+}
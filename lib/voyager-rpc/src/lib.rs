@@ -23,11 +23,11 @@ use voyager_primitives::{
     IbcSpecId, QueryHeight, Timestamp,
 };
 use voyager_types::{ProofType, RawClientId};
-use voyager_vm::{Op, QueueError, pass::PassResult};
+use voyager_vm::{HistoryItem, ItemId, Op, QueueError, pass::PassResult};
 
 use crate::types::{
-    IbcProofResponse, IbcStateResponse, InfoResponse, SelfClientStateResponse,
-    SelfConsensusStateResponse,
+    IbcProofResponse, IbcStateResponse, InfoResponse, PausedResponse, ReloadConfigResponse,
+    SelfClientStateResponse, SelfConsensusStateResponse,
 };
 
 pub mod types;
@@ -450,6 +450,16 @@ pub trait VoyagerRpc {
         header: Bytes,
     ) -> RpcResult<Value>;
 
+    #[method(name = "encodeMisbehaviour", with_extensions)]
+    async fn encode_misbehaviour(
+        &self,
+        client_type: ClientType,
+        ibc_interface: IbcInterface,
+        ibc_spec_id: IbcSpecId,
+        header_a: Value,
+        header_b: Value,
+    ) -> RpcResult<Bytes>;
+
     #[method(name = "decodeClientStateMeta", with_extensions)]
     async fn decode_client_state_meta(
         &self,
@@ -509,6 +519,77 @@ pub trait VoyagerRpc {
     ) -> RpcResult<Value>;
 }
 
+/// Introspection into the queue of a running voyager instance.
+///
+/// This is served on the same endpoint as [`VoyagerRpc`], but is a separate trait since it is
+/// backed by the queue rather than the plugin context.
+#[rpc(
+    client,
+    server,
+    client_bounds(Self: Send + Sync),
+    server_bounds(Self:),
+    namespace = "voyager",
+)]
+pub trait VoyagerQueueRpc {
+    /// Fetch the lineage of the item with the provided id, following its parents up to
+    /// `max_depth` levels. See [`Queue::history`](voyager_vm::Queue::history).
+    #[method(name = "queueHistory")]
+    async fn queue_history(
+        &self,
+        id: ItemId,
+        max_depth: u32,
+    ) -> RpcResult<Vec<HistoryItem<VoyagerMessage>>>;
+}
+
+/// Operator actions on a running voyager instance.
+///
+/// This is only served on the public JSON-RPC endpoint of voyager, and not to plugins and modules.
+#[rpc(
+    client,
+    server,
+    client_bounds(Self: Send + Sync),
+    server_bounds(Self:),
+    namespace = "voyager",
+)]
+pub trait VoyagerAdminRpc {
+    /// Reload the plugin and module configuration from the config file voyager was started with.
+    ///
+    /// Only plugins and modules whose configuration changed are restarted, and plugins are added or
+    /// removed as necessary. The queue and all other plugins and modules are left untouched.
+    #[method(name = "reloadConfig")]
+    async fn reload_config(&self) -> RpcResult<ReloadConfigResponse>;
+
+    /// Pause a plugin. Ops with a pending call or callback for the plugin are held in place in the
+    /// queue and its optimization passes are not run until it is resumed.
+    ///
+    /// Returns the paused set after pausing the plugin.
+    #[method(name = "pausePlugin")]
+    async fn pause_plugin(&self, plugin: String) -> RpcResult<PausedResponse>;
+
+    /// Resume a paused plugin.
+    ///
+    /// Returns the paused set after resuming the plugin.
+    #[method(name = "resumePlugin")]
+    async fn resume_plugin(&self, plugin: String) -> RpcResult<PausedResponse>;
+
+    /// Pause a chain. All ops with a pending call interacting with the chain are held in place in
+    /// the queue until it is resumed.
+    ///
+    /// Returns the paused set after pausing the chain.
+    #[method(name = "pauseChain")]
+    async fn pause_chain(&self, chain_id: ChainId) -> RpcResult<PausedResponse>;
+
+    /// Resume a paused chain.
+    ///
+    /// Returns the paused set after resuming the chain.
+    #[method(name = "resumeChain")]
+    async fn resume_chain(&self, chain_id: ChainId) -> RpcResult<PausedResponse>;
+
+    /// The plugins and chains that are currently paused.
+    #[method(name = "paused")]
+    async fn paused(&self) -> RpcResult<PausedResponse>;
+}
+
 #[rpc(client, server, namespace = "plugin")]
 pub trait Plugin<C: Member, Cb: Member> {
     #[method(name = "runPass", with_extensions)]
@@ -641,6 +722,18 @@ pub trait ClientModule {
     #[method(name = "decodeHeader", with_extensions)]
     async fn decode_header(&self, header: Bytes) -> RpcResult<Value>;
 
+    /// Build and encode the misbehaviour evidence from two conflicting headers for the same
+    /// height, both provided as JSON.
+    ///
+    /// The default implementation returns a fatal error, for light clients that do not support
+    /// misbehaviour.
+    #[method(name = "encodeMisbehaviour", with_extensions)]
+    async fn encode_misbehaviour(&self, _header_a: Value, _header_b: Value) -> RpcResult<Bytes> {
+        Err(RpcError::fatal_from_message(
+            "misbehaviour is not supported by this client type",
+        ))
+    }
+
     /// Encode the proof, provided as JSON.
     #[method(name = "encodeProof", with_extensions)]
     async fn encode_proof(&self, proof: Value) -> RpcResult<Bytes>;
@@ -15,6 +15,45 @@ pub struct InfoResponse {
     pub consensus: Vec<FinalityModuleInfo>,
     pub client: Vec<ClientModuleInfo>,
     pub client_bootstrap: Vec<ClientBootstrapModuleInfo>,
+    /// The concurrency and circuit breaker status of every chain that voyager has handled a call
+    /// for.
+    #[serde(default)]
+    pub chains: Vec<ChainStatus>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ChainStatus {
+    pub chain_id: ChainId,
+    /// The number of calls for this chain that are currently being handled.
+    pub in_flight: usize,
+    /// The maximum number of calls for this chain that can be handled at once, if limited.
+    pub max_in_flight: Option<usize>,
+    /// The number of consecutive retryable failures for this chain.
+    pub consecutive_failures: u32,
+    /// The number of consecutive times the circuit breaker for this chain has tripped.
+    pub breaker_trips: u32,
+    /// If the circuit breaker for this chain is currently open, the unix timestamp (in seconds)
+    /// until which calls for this chain will be held in the queue.
+    pub breaker_open_until: Option<u64>,
+}
+
+/// The changes made by a config reload.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ReloadConfigResponse {
+    pub added_plugins: Vec<String>,
+    pub removed_plugins: Vec<String>,
+    pub restarted_plugins: Vec<String>,
+    pub restarted_modules: Vec<String>,
+}
+
+/// The plugins and chains that are currently paused.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct PausedResponse {
+    pub plugins: Vec<String>,
+    pub chains: Vec<ChainId>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
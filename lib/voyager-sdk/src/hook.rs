@@ -3,7 +3,7 @@ use std::collections::BTreeSet;
 use tracing::info;
 use voyager_message::{
     VoyagerMessage,
-    call::{Call, FetchUpdateHeaders, SubmitTx},
+    call::{Call, FetchUpdateHeaders, IndexRange, SubmitTx},
 };
 use voyager_primitives::{ChainId, ClientType};
 use voyager_vm::Visit;
@@ -128,6 +128,42 @@ impl<F: for<'b> Fn(&'b SubmitTx) -> Call> Visit<VoyagerMessage> for SubmitTxHook
     }
 }
 
+/// A hook for an event source plugin that handles [`IndexRange`] messages.
+///
+/// Unlike matching on the top level [`Op`], this also handles messages nested in other messages,
+/// such as the queue of a [`Promise`] that aggregates the events of the indexed range.
+///
+/// [`Op`]: voyager_vm::Op
+/// [`Promise`]: voyager_vm::Promise
+pub struct IndexRangeHook<'a, F: for<'b> Fn(&'b IndexRange) -> Call> {
+    chain_id: &'a ChainId,
+    mk_msg: F,
+}
+
+impl<'a, F: for<'b> Fn(&'b IndexRange) -> Call> IndexRangeHook<'a, F> {
+    pub fn new(chain_id: &'a ChainId, mk_msg: F) -> Self {
+        Self { chain_id, mk_msg }
+    }
+}
+
+impl<F: for<'b> Fn(&'b IndexRange) -> Call> Visit<VoyagerMessage> for IndexRangeHook<'_, F> {
+    fn visit_call(&mut self, c: &mut Call) {
+        match c {
+            Call::IndexRange(index_range) if index_range.chain_id == self.chain_id => {
+                info!(
+                    from_height = %index_range.range.from_height(),
+                    to_height = %index_range.range.to_height(),
+                    "hooking for index range on `{}`",
+                    index_range.chain_id
+                );
+
+                *c = (self.mk_msg)(index_range)
+            }
+            _ => {}
+        }
+    }
+}
+
 /// For simple filters that either take the item they're interested in or express no interest (i.e. they never just copy an item). This wraps the provided filter (which is expected to return a bool) in an expression maps that maps false to null.
 pub fn simple_take_filter(inner_filter: String) -> String {
     format!(r#"if {inner_filter} then true else null end"#)
@@ -1,9 +1,22 @@
-use std::{future::Future, marker::PhantomData, time::Duration};
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    future::Future,
+    marker::PhantomData,
+    time::Duration,
+};
 
 use futures::{FutureExt, Stream, TryStreamExt, stream::try_unfold};
+use serde::{Deserialize, Serialize};
 use tokio::time::sleep;
+use tracing::{debug, trace};
+use unionlabs::ErrorReporter;
 
-use crate::{BoxDynError, HandlerFactory, Queue, QueueMessage, filter::InterestFilter, process};
+use crate::{
+    BoxDynError, HandlerFactory, ItemId, Op, Queue, QueueError, QueueMessage,
+    filter::{FilterResult, Interest, InterestFilter},
+    pass::Pass,
+    process,
+};
 
 pub struct Engine<'a, T, Q, H, F> {
     handler: H,
@@ -59,3 +72,251 @@ where
         })
     }
 }
+
+/// The result of [`replay`]ing an [`Op`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(
+    bound(serialize = "", deserialize = ""),
+    deny_unknown_fields,
+    rename_all = "snake_case"
+)]
+pub struct Replay<T: QueueMessage> {
+    /// Every item that was processed or optimized, in the order it was handled.
+    pub steps: Vec<ReplayStep<T>>,
+    /// Any data that bubbled up to the top level.
+    pub data: Vec<T::Data>,
+    /// Whether the replay ran to completion. This is false if `max_steps` was hit before all items
+    /// were handled.
+    pub complete: bool,
+    /// Ops that were not handled because they matched the `halt` predicate, in the order they were
+    /// produced.
+    #[serde(default)]
+    pub halted: Vec<Op<T>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(
+    bound(serialize = "", deserialize = ""),
+    deny_unknown_fields,
+    rename_all = "snake_case"
+)]
+pub struct ReplayStep<T: QueueMessage> {
+    pub id: ItemId,
+    pub parents: Vec<ItemId>,
+    pub op: Op<T>,
+    /// The tag of the optimization pass this item was consumed by, if it was not processed
+    /// directly.
+    pub optimized_by: Option<String>,
+    /// The error that handling this item failed with, if any. Items that fail with a retryable
+    /// error are requeued, and will show up again as a new step with the same id.
+    pub error: Option<String>,
+}
+
+/// Deterministically process `op` to completion, without a [`Queue`].
+///
+/// This is intended for reproducing the behaviour of a single op offline (i.e. in tests, against
+/// recorded responses), and differs from running an [`Engine`] in a few ways:
+///
+/// - All items are handled one at a time, in FIFO order, on the current task.
+/// - [`Op::Defer`] and [`Op::DeferRelative`] are treated as already elapsed.
+/// - Optimization passes are only run once there are no ready items left, in order of their tag.
+///
+/// Item ids are assigned sequentially, starting at 1. Handling stops after `max_steps` items have
+/// been handled, to bound replays of ops that never complete (such as the
+/// `WaitFor*`-style polling loops).
+///
+/// Any op for which `halt` returns true is neither handled nor optimized, and is instead returned in
+/// [`Replay::halted`]. This allows for handling an op without any side effects, i.e. without
+/// submitting transactions.
+pub async fn replay<T, H, F, P>(
+    handler_factory: &H,
+    filter: &F,
+    passes: &HashMap<String, P>,
+    op: Op<T>,
+    max_steps: usize,
+    halt: impl Fn(&Op<T>) -> bool,
+) -> Replay<T>
+where
+    T: QueueMessage,
+    H: HandlerFactory<T>,
+    F: InterestFilter<T>,
+    P: Pass<T>,
+{
+    let mut state = ReplayState {
+        filter,
+        halt: &halt,
+        next_id: 1,
+        ready: VecDeque::new(),
+        optimize: BTreeMap::new(),
+        halted: vec![],
+    };
+
+    let mut replay = Replay {
+        steps: vec![],
+        data: vec![],
+        complete: false,
+        halted: vec![],
+    };
+
+    state.enqueue(vec![], op);
+
+    while replay.steps.len() < max_steps {
+        if let Some((id, parents, op)) = state.ready.pop_front() {
+            let Some(op) = skip_defers(op) else {
+                continue;
+            };
+
+            if let Op::Data(data) = op {
+                replay.data.push(data);
+                continue;
+            }
+
+            trace!(id = id.raw(), "replaying item");
+
+            let res = process(op.clone(), &handler_factory.make_handler(id), 0).await;
+
+            let error = match res {
+                Ok(Some(new_op)) => {
+                    state.enqueue(vec![id], new_op);
+                    None
+                }
+                Ok(None) => None,
+                Err(err) => {
+                    debug!(id = id.raw(), error = %ErrorReporter(&err), "error replaying item");
+
+                    if let QueueError::Retry(_) = err {
+                        state.ready.push_back((id, parents.clone(), op.clone()));
+                    }
+
+                    Some(ErrorReporter(err).to_string())
+                }
+            };
+
+            replay.steps.push(ReplayStep {
+                id,
+                parents,
+                op,
+                optimized_by: None,
+                error,
+            });
+        } else if let Some((tag, items)) = state.optimize.pop_first() {
+            let (ids, ops): (Vec<_>, Vec<_>) =
+                items.iter().map(|(id, _, op)| (*id, op.clone())).unzip();
+
+            let error = match passes.get(&tag) {
+                Some(pass) => match pass.run_pass(ops).await {
+                    Ok(res) => {
+                        for (parent_idxs, op) in res.ready {
+                            state.enqueue(parent_idxs.iter().map(|&i| ids[i]).collect(), op);
+                        }
+
+                        for (parent_idxs, op, tag) in res.optimize_further {
+                            let id = state.next_id();
+                            state.optimize.entry(tag).or_default().push((
+                                id,
+                                parent_idxs.iter().map(|&i| ids[i]).collect(),
+                                op,
+                            ));
+                        }
+
+                        None
+                    }
+                    Err(err) => Some(ErrorReporter(err).to_string()),
+                },
+                None => Some(format!("no optimization pass for tag `{tag}`")),
+            };
+
+            replay
+                .steps
+                .extend(items.into_iter().map(|(id, parents, op)| ReplayStep {
+                    id,
+                    parents,
+                    op,
+                    optimized_by: Some(tag.clone()),
+                    error: error.clone(),
+                }));
+        } else {
+            replay.complete = true;
+            break;
+        }
+    }
+
+    replay.halted = state.halted;
+
+    replay
+}
+
+type ReplayItem<T> = (ItemId, Vec<ItemId>, Op<T>);
+
+struct ReplayState<'a, T: QueueMessage, F> {
+    filter: &'a F,
+    halt: &'a dyn Fn(&Op<T>) -> bool,
+    next_id: i64,
+    ready: VecDeque<ReplayItem<T>>,
+    optimize: BTreeMap<String, Vec<ReplayItem<T>>>,
+    halted: Vec<Op<T>>,
+}
+
+impl<T: QueueMessage, F: InterestFilter<T>> ReplayState<'_, T, F> {
+    fn next_id(&mut self) -> ItemId {
+        let id = ItemId::new(self.next_id).expect("replay item ids are always positive; qed;");
+        self.next_id += 1;
+        id
+    }
+
+    fn enqueue(&mut self, parents: Vec<ItemId>, op: Op<T>) {
+        'block: for op in op.normalize() {
+            if (self.halt)(&op) {
+                self.halted.push(op);
+                continue;
+            }
+
+            match self.filter.check_interest(&op) {
+                FilterResult::Interest(Interest { tags, remove }) => {
+                    for tag in tags {
+                        let id = self.next_id();
+                        self.optimize.entry(tag.to_owned()).or_default().push((
+                            id,
+                            parents.clone(),
+                            op.clone(),
+                        ));
+                    }
+
+                    if remove {
+                        continue 'block;
+                    }
+                }
+                FilterResult::NoInterest => {}
+            }
+
+            let id = self.next_id();
+            self.ready.push_back((id, parents.clone(), op));
+        }
+    }
+}
+
+/// Drop any defers at the head of `op`, returning `None` if there is nothing left to handle.
+fn skip_defers<T: QueueMessage>(op: Op<T>) -> Option<Op<T>> {
+    match op {
+        Op::Defer { .. } | Op::DeferRelative { .. } => None,
+        Op::Seq(mut queue) => {
+            while let Some(head) = queue.pop_front() {
+                if let Some(head) = skip_defers(head) {
+                    queue.push_front(head);
+                    break;
+                }
+            }
+
+            (!queue.is_empty()).then_some(Op::Seq(queue))
+        }
+        Op::Conc(queue) => {
+            let queue = queue
+                .into_iter()
+                .filter_map(skip_defers)
+                .collect::<VecDeque<_>>();
+
+            (!queue.is_empty()).then_some(Op::Conc(queue))
+        }
+        op => Some(op),
+    }
+}
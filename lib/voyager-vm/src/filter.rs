@@ -1,8 +1,111 @@
-use crate::{Op, QueueMessage};
+use std::any::Any;
+
+use crate::{Op, Priority, QueueMessage};
 
 /// A filter to run on [`Op`]s before they're pushed into the queue.
 pub trait InterestFilter<T: QueueMessage>: Send + Sync + Sized + 'static {
     fn check_interest<'a>(&'a self, op: &Op<T>) -> FilterResult<'a>;
+
+    /// The priority to insert a ready [`Op`] into the queue with. `tag` is the tag of the optimizer
+    /// that produced the op, if it was produced by an optimization pass.
+    ///
+    /// Ops produced by processing another op are inserted with the higher of this and the priority
+    /// of the op that produced them.
+    fn priority(&self, op: &Op<T>, tag: Option<&str>) -> Priority {
+        let _ = (op, tag);
+
+        Priority::default()
+    }
+
+    /// The idempotency key of an [`Op`], if it has one.
+    ///
+    /// Queues that support deduplication will only insert one op per key within their configured
+    /// idempotency window; any other ops with the same key are dropped. This is checked for every
+    /// op after normalization, both for ops passed to [`Queue::enqueue`] and for the ops produced
+    /// by processing another op.
+    ///
+    /// [`Queue::enqueue`]: crate::Queue::enqueue
+    fn idempotency_key(&self, op: &Op<T>) -> Option<String> {
+        let _ = op;
+
+        None
+    }
+
+    /// Check whether a ready [`Op`] can be handled right now.
+    ///
+    /// [`Queue::process`] skips ops that are not admitted and leaves them in place in the queue, in
+    /// their original order; they are picked up again once they are admitted. The returned
+    /// [`Admission`] is held until the op has been handled.
+    ///
+    /// [`Queue::process`]: crate::Queue::process
+    fn admit(&self, op: &Op<T>) -> Option<Admission> {
+        let _ = op;
+
+        Some(Admission::default())
+    }
+
+    /// The [`AdmissionKey`] of a ready [`Op`].
+    ///
+    /// Queues store this alongside the op when inserting it, such that ops that are currently held
+    /// (see [`Self::held`]) can be skipped without reading them. Returns `None` if the op can't be
+    /// classified yet, in which case the queue only checks it with [`Self::admit`] (and may try to
+    /// classify it again later).
+    fn admission_key(&self, op: &Op<T>) -> Option<AdmissionKey> {
+        let _ = op;
+
+        Some(AdmissionKey::default())
+    }
+
+    /// The plugins and chains that are currently holding ops. [`Self::admit`] must not admit any
+    /// op whose [`AdmissionKey`] is held.
+    fn held(&self) -> Held {
+        Held::default()
+    }
+}
+
+/// The plugin and chain that the next step of an [`Op`] is handled by, see
+/// [`InterestFilter::admission_key`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct AdmissionKey {
+    pub plugin: Option<String>,
+    pub chain_id: Option<String>,
+    /// Whether the next step is subject to the limits of `chain_id`, see [`Held::limited_chains`].
+    pub limited: bool,
+}
+
+/// The plugins and chains that are currently holding ops, see [`InterestFilter::held`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Held {
+    /// Ops whose next step is handled by any of these plugins are held.
+    pub plugins: Vec<String>,
+    /// Ops whose next step interacts with any of these chains are held.
+    pub chains: Vec<String>,
+    /// Ops whose next step interacts with any of these chains are held if they are
+    /// [`AdmissionKey::limited`].
+    pub limited_chains: Vec<String>,
+}
+
+impl Held {
+    pub fn holds(&self, key: &AdmissionKey) -> bool {
+        key.plugin
+            .as_ref()
+            .is_some_and(|plugin| self.plugins.contains(plugin))
+            || key.chain_id.as_ref().is_some_and(|chain_id| {
+                self.chains.contains(chain_id)
+                    || (key.limited && self.limited_chains.contains(chain_id))
+            })
+    }
+}
+
+/// A guard returned by [`InterestFilter::admit`], held for as long as the admitted op is being
+/// handled.
+#[derive(Default)]
+pub struct Admission(#[allow(dead_code)] Option<Box<dyn Any + Send + Sync>>);
+
+impl Admission {
+    pub fn new(guard: impl Any + Send + Sync) -> Self {
+        Self(Some(Box::new(guard)))
+    }
 }
 
 /// The result of running an [`InterestFilter`] on an [`Op`].
@@ -1,5 +1,6 @@
 use std::{
-    collections::BTreeMap,
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
     future::Future,
     sync::{
         Arc, Mutex,
@@ -8,41 +9,208 @@ use std::{
 };
 
 use either::Either;
+use opentelemetry::metrics::Counter;
+use serde::{Deserialize, Serialize};
 use tracing::{Instrument, debug, error, info, info_span, trace};
 use unionlabs::ErrorReporter;
 
 use crate::{
-    Captures, EnqueueResult, ItemId, Op, Queue, QueueError, QueueMessage,
-    filter::{FilterResult, Interest, InterestFilter},
+    Captures, DEFAULT_ANTI_STARVATION_INTERVAL, EnqueueResult, HistoryItem, ItemId, ItemStatus, Op,
+    Priority, Queue, QueueError, QueueMessage,
+    filter::{AdmissionKey, FilterResult, Interest, InterestFilter},
+    now,
     pass::Pass,
 };
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct InMemoryQueueConfig {
+    /// How long the idempotency key of an op is remembered for, in seconds. Any other ops with the
+    /// same key that are inserted within this window are dropped. Set to 0 to disable
+    /// deduplication.
+    ///
+    /// See [`InterestFilter::idempotency_key`].
+    #[serde(default)]
+    pub idempotency_window_seconds: u64,
+    /// Take every nth item in FIFO order, see [`DEFAULT_ANTI_STARVATION_INTERVAL`]. Set to 0 to
+    /// always process by priority.
+    #[serde(default = "default_anti_starvation_interval")]
+    pub anti_starvation_interval: u32,
+}
+
+impl Default for InMemoryQueueConfig {
+    fn default() -> Self {
+        Self {
+            idempotency_window_seconds: 0,
+            anti_starvation_interval: DEFAULT_ANTI_STARVATION_INTERVAL,
+        }
+    }
+}
+
+pub const fn default_anti_starvation_interval() -> u32 {
+    DEFAULT_ANTI_STARVATION_INTERVAL
+}
+
 #[derive(Debug, Clone)]
 pub struct InMemoryQueue<T: QueueMessage> {
     idx: Arc<AtomicU32>,
-    ready: Arc<Mutex<BTreeMap<u32, Item<T>>>>,
+    /// Total amount of items taken from `ready`, used to periodically ignore priorities.
+    processed: Arc<AtomicU32>,
+    ready: Arc<Mutex<Ready<T>>>,
     done: Arc<Mutex<BTreeMap<u32, Item<T>>>>,
+    failed: Arc<Mutex<BTreeMap<u32, (Item<T>, String)>>>,
     #[allow(clippy::type_complexity)]
     optimizer_queue: Arc<Mutex<BTreeMap<String, BTreeMap<u32, Item<T>>>>>,
+    idempotency_window_seconds: u64,
+    anti_starvation_interval: u32,
+    idempotency_keys: Arc<Mutex<IdempotencyKeys>>,
+    suppressed_duplicates_metric: Counter<u64>,
 }
 
 #[derive(Debug, Clone)]
 pub(crate) struct Item<T: QueueMessage> {
-    #[allow(dead_code)] // used in debug
     parents: Vec<u32>,
+    priority: Priority,
     op: Op<T>,
 }
 
+/// The ready items, along with their [`AdmissionKey`]s and an index by priority, such that the next
+/// item to process can be found without sorting all of them.
+#[derive(Debug)]
+struct Ready<T: QueueMessage> {
+    items: BTreeMap<u32, (Item<T>, Option<AdmissionKey>)>,
+    by_priority: BTreeSet<(Reverse<Priority>, u32)>,
+}
+
+impl<T: QueueMessage> Default for Ready<T> {
+    fn default() -> Self {
+        Self {
+            items: BTreeMap::new(),
+            by_priority: BTreeSet::new(),
+        }
+    }
+}
+
+impl<T: QueueMessage> Ready<T> {
+    fn insert(&mut self, id: u32, item: Item<T>, admission_key: Option<AdmissionKey>) {
+        self.by_priority.insert((Reverse(item.priority), id));
+        self.items.insert(id, (item, admission_key));
+    }
+
+    fn remove(&mut self, id: u32) -> Option<(Item<T>, Option<AdmissionKey>)> {
+        let (item, admission_key) = self.items.remove(&id)?;
+        self.by_priority.remove(&(Reverse(item.priority), id));
+        Some((item, admission_key))
+    }
+
+    fn get(&self, id: u32) -> Option<&Item<T>> {
+        self.items.get(&id).map(|(item, _)| item)
+    }
+
+    /// All items, oldest first if `fifo` is set and otherwise highest priority first, then oldest
+    /// first within the same priority.
+    fn iter(
+        &self,
+        fifo: bool,
+    ) -> impl Iterator<Item = (u32, &Item<T>, Option<&AdmissionKey>)> + '_ {
+        if fifo {
+            Either::Left(self.items.keys().copied())
+        } else {
+            Either::Right(self.by_priority.iter().map(|(_, id)| *id))
+        }
+        .map(|id| {
+            let (item, admission_key) = &self.items[&id];
+            (id, item, admission_key.as_ref())
+        })
+    }
+}
+
+/// The idempotency keys seen within the current window, and when they were first seen, along with
+/// the same keys in the order they were seen, such that expired keys can be pruned without scanning
+/// all of them.
+#[derive(Debug, Default)]
+struct IdempotencyKeys {
+    seen_at: HashMap<String, u64>,
+    by_seen_at: VecDeque<(u64, String)>,
+}
+
+impl IdempotencyKeys {
+    /// Remove all keys that were seen `window_seconds` or more before `now`.
+    fn prune(&mut self, now: u64, window_seconds: u64) {
+        while let Some((seen_at, _)) = self.by_seen_at.front() {
+            if *seen_at + window_seconds > now {
+                break;
+            }
+
+            let (_, key) = self.by_seen_at.pop_front().expect("front exists; qed;");
+            self.seen_at.remove(&key);
+        }
+    }
+
+    /// Record `key` as seen at `now`, returning `false` if it was already recorded.
+    fn insert(&mut self, key: &str, now: u64) -> bool {
+        if self.seen_at.contains_key(key) {
+            false
+        } else {
+            self.seen_at.insert(key.to_owned(), now);
+            self.by_seen_at.push_back((now, key.to_owned()));
+
+            true
+        }
+    }
+}
+
+impl<T: QueueMessage> InMemoryQueue<T> {
+    /// Record the idempotency key of `op`, returning `true` if it was already seen within the
+    /// idempotency window (in which case `op` should be dropped).
+    fn is_duplicate<Filter: InterestFilter<T>>(&self, filter: &Filter, op: &Op<T>) -> bool {
+        if self.idempotency_window_seconds == 0 {
+            return false;
+        }
+
+        let Some(key) = filter.idempotency_key(op) else {
+            return false;
+        };
+
+        let now = now();
+
+        let mut idempotency_keys = self.idempotency_keys.lock().expect("mutex is poisoned");
+
+        idempotency_keys.prune(now, self.idempotency_window_seconds);
+
+        if idempotency_keys.insert(&key, now) {
+            false
+        } else {
+            debug!(%key, "dropping duplicate op");
+
+            self.suppressed_duplicates_metric.add(1, &[]);
+
+            true
+        }
+    }
+}
+
 impl<T: QueueMessage> Queue<T> for InMemoryQueue<T> {
     type Error = std::convert::Infallible;
-    type Config = ();
+    type Config = InMemoryQueueConfig;
 
-    fn new(_cfg: Self::Config) -> impl Future<Output = Result<Self, Self::Error>> {
+    fn new(cfg: Self::Config) -> impl Future<Output = Result<Self, Self::Error>> {
         futures::future::ok(Self {
             idx: Arc::new(AtomicU32::default()),
+            processed: Arc::new(AtomicU32::default()),
             done: Arc::new(Mutex::new(BTreeMap::default())),
-            ready: Arc::new(Mutex::new(BTreeMap::default())),
+            failed: Arc::new(Mutex::new(BTreeMap::default())),
+            ready: Arc::new(Mutex::new(Ready::default())),
             optimizer_queue: Arc::new(Mutex::new(BTreeMap::default())),
+            idempotency_window_seconds: cfg.idempotency_window_seconds,
+            anti_starvation_interval: cfg.anti_starvation_interval,
+            idempotency_keys: Arc::new(Mutex::new(IdempotencyKeys::default())),
+            suppressed_duplicates_metric: opentelemetry::global::meter("in_memory_queue")
+                .u64_counter("in_memory_queue_suppressed_duplicates_count")
+                .with_description(
+                    "Total count of ops dropped because their idempotency key was already seen.",
+                )
+                .build(),
         })
     }
 
@@ -60,6 +228,10 @@ impl<T: QueueMessage> Queue<T> for InMemoryQueue<T> {
         let mut ready = self.ready.lock().expect("mutex is poisoned");
 
         for op in op.normalize() {
+            if self.is_duplicate(filter, &op) {
+                continue;
+            }
+
             match filter.check_interest(&op) {
                 FilterResult::Interest(Interest { tags, remove }) => {
                     for tag in tags {
@@ -67,6 +239,7 @@ impl<T: QueueMessage> Queue<T> for InMemoryQueue<T> {
                             self.idx.fetch_add(1, Ordering::SeqCst),
                             Item {
                                 parents: vec![],
+                                priority: Priority::default(),
                                 op: op.clone(),
                             },
                         );
@@ -77,8 +250,10 @@ impl<T: QueueMessage> Queue<T> for InMemoryQueue<T> {
                             self.idx.fetch_add(1, Ordering::SeqCst),
                             Item {
                                 parents: vec![],
-                                op,
+                                priority: filter.priority(&op, None),
+                                op: op.clone(),
                             },
+                            filter.admission_key(&op),
                         );
                     }
                 }
@@ -87,8 +262,10 @@ impl<T: QueueMessage> Queue<T> for InMemoryQueue<T> {
                         self.idx.fetch_add(1, Ordering::SeqCst),
                         Item {
                             parents: vec![],
-                            op,
+                            priority: filter.priority(&op, None),
+                            op: op.clone(),
                         },
+                        filter.admission_key(&op),
                     );
                 }
             }
@@ -113,9 +290,29 @@ impl<T: QueueMessage> Queue<T> for InMemoryQueue<T> {
         R: Send + Sync + 'static,
         Filter: InterestFilter<T>,
     {
+        let held = filter.held();
+
         let op = {
             let mut queue = self.ready.lock().expect("mutex is poisoned");
-            let op = queue.pop_first();
+
+            // only items that are actually taken from the queue count towards the interval
+            let fifo = self.anti_starvation_interval != 0
+                && self.processed.load(Ordering::SeqCst) % self.anti_starvation_interval
+                    == self.anti_starvation_interval - 1;
+
+            // items that are not admitted are left in place; held items are skipped without
+            // checking them
+            let admitted = queue
+                .iter(fifo)
+                .filter(|&(_, _, admission_key)| !admission_key.is_some_and(|key| held.holds(key)))
+                .find_map(|(id, item, _)| filter.admit(&item.op).map(|admission| (id, admission)));
+
+            let op = admitted.map(|(id, admission)| {
+                self.processed.fetch_add(1, Ordering::SeqCst);
+
+                let (item, admission_key) = queue.remove(id).expect("item exists; qed;");
+                (id, item, admission_key, admission)
+            });
 
             drop(queue);
 
@@ -123,14 +320,9 @@ impl<T: QueueMessage> Queue<T> for InMemoryQueue<T> {
         };
 
         match op {
-            Some((item_id, item)) => {
+            Some((item_id, item, admission_key, _admission)) => {
                 let span = info_span!("processing item", %item_id);
 
-                self.done
-                    .lock()
-                    .expect("mutex is poisoned")
-                    .insert(item_id, item.clone());
-
                 let (r, res) = f(
                     item.op.clone(),
                     ItemId::new(i64::from(item_id)).expect("infallible"),
@@ -144,13 +336,18 @@ impl<T: QueueMessage> Queue<T> for InMemoryQueue<T> {
                 match res {
                     Ok(ops) => {
                         for op in ops.into_iter().flat_map(Op::normalize) {
+                            if self.is_duplicate(filter, &op) {
+                                continue;
+                            }
+
                             match filter.check_interest(&op) {
                                 FilterResult::Interest(Interest { tags, remove }) => {
                                     for tag in tags {
                                         optimizer_queue.entry(tag.to_owned()).or_default().insert(
                                             self.idx.fetch_add(1, Ordering::SeqCst),
                                             Item {
-                                                parents: vec![],
+                                                parents: vec![item_id],
+                                                priority: Priority::default(),
                                                 op: op.clone(),
                                             },
                                         );
@@ -160,9 +357,13 @@ impl<T: QueueMessage> Queue<T> for InMemoryQueue<T> {
                                         ready.insert(
                                             self.idx.fetch_add(1, Ordering::SeqCst),
                                             Item {
-                                                parents: vec![],
-                                                op,
+                                                parents: vec![item_id],
+                                                priority: filter
+                                                    .priority(&op, None)
+                                                    .max(item.priority),
+                                                op: op.clone(),
                                             },
+                                            filter.admission_key(&op),
                                         );
                                     }
                                 }
@@ -170,28 +371,43 @@ impl<T: QueueMessage> Queue<T> for InMemoryQueue<T> {
                                     ready.insert(
                                         self.idx.fetch_add(1, Ordering::SeqCst),
                                         Item {
-                                            parents: vec![],
-                                            op,
+                                            parents: vec![item_id],
+                                            priority: filter.priority(&op, None).max(item.priority),
+                                            op: op.clone(),
                                         },
+                                        filter.admission_key(&op),
                                     );
                                 }
                             }
                         }
 
+                        self.done
+                            .lock()
+                            .expect("mutex is poisoned")
+                            .insert(item_id, item);
+
                         Ok(Some(r))
                     }
                     Err(why) => match why {
                         QueueError::Fatal(error) => {
                             error!(error = %ErrorReporter(&*error), "fatal error");
+                            self.failed
+                                .lock()
+                                .expect("mutex is poisoned")
+                                .insert(item_id, (item, ErrorReporter(&*error).to_string()));
                             Ok(None)
                         }
                         QueueError::Unprocessable(error) => {
                             info!(error = %ErrorReporter(&*error), "unprocessable message");
+                            self.failed
+                                .lock()
+                                .expect("mutex is poisoned")
+                                .insert(item_id, (item, ErrorReporter(&*error).to_string()));
                             Ok(None)
                         }
                         QueueError::Retry(error) => {
                             info!(error = %ErrorReporter(&*error), "retryable error");
-                            ready.insert(item_id, item);
+                            ready.insert(item_id, item, admission_key);
                             Ok(None)
                         }
                     },
@@ -259,6 +475,7 @@ impl<T: QueueMessage> Queue<T> for InMemoryQueue<T> {
                                             .map(|&i| &ids[i])
                                             .copied()
                                             .collect(),
+                                        priority: Priority::default(),
                                         op: op.clone(),
                                     },
                                 );
@@ -275,8 +492,10 @@ impl<T: QueueMessage> Queue<T> for InMemoryQueue<T> {
                         self.idx.fetch_add(1, Ordering::SeqCst),
                         Item {
                             parents: parents_idxs.iter().map(|&i| &ids[i]).copied().collect(),
-                            op,
+                            priority: filter.priority(&op, Some(tag)),
+                            op: op.clone(),
                         },
+                        filter.admission_key(&op),
                     );
                 }
             }
@@ -286,6 +505,7 @@ impl<T: QueueMessage> Queue<T> for InMemoryQueue<T> {
                     self.idx.fetch_add(1, Ordering::SeqCst),
                     Item {
                         parents: parents_idxs.iter().map(|&i| &ids[i]).copied().collect(),
+                        priority: Priority::default(),
                         op,
                     },
                 );
@@ -294,4 +514,69 @@ impl<T: QueueMessage> Queue<T> for InMemoryQueue<T> {
             Ok(())
         }
     }
+
+    fn history(
+        &self,
+        id: ItemId,
+        max_depth: u32,
+    ) -> impl Future<Output = Result<Vec<HistoryItem<T>>, Self::Error>> + Send + '_ {
+        let find = |id: u32| -> Option<(ItemStatus, Item<T>, Option<String>)> {
+            if let Some(item) = self.ready.lock().expect("mutex is poisoned").get(id) {
+                return Some((ItemStatus::Queued, item.clone(), None));
+            }
+
+            if let Some(item) = self
+                .optimizer_queue
+                .lock()
+                .expect("mutex is poisoned")
+                .values()
+                .find_map(|items| items.get(&id))
+            {
+                return Some((ItemStatus::Optimize, item.clone(), None));
+            }
+
+            if let Some(item) = self.done.lock().expect("mutex is poisoned").get(&id) {
+                return Some((ItemStatus::Done, item.clone(), None));
+            }
+
+            self.failed
+                .lock()
+                .expect("mutex is poisoned")
+                .get(&id)
+                .map(|(item, message)| (ItemStatus::Failed, item.clone(), Some(message.clone())))
+        };
+
+        let item_id = |id: u32| ItemId::new(i64::from(id)).expect("infallible");
+
+        let mut history = vec![];
+        let mut seen = BTreeSet::new();
+        let mut frontier = u32::try_from(id.raw()).ok().into_iter().collect::<Vec<_>>();
+
+        for depth in 0..=max_depth {
+            let mut next = vec![];
+
+            for id in frontier {
+                if !seen.insert(id) {
+                    continue;
+                }
+
+                if let Some((status, item, message)) = find(id) {
+                    next.extend(item.parents.iter().copied());
+
+                    history.push(HistoryItem {
+                        id: item_id(id),
+                        parents: item.parents.iter().copied().map(item_id).collect(),
+                        status,
+                        item: item.op,
+                        message,
+                        depth,
+                    });
+                }
+            }
+
+            frontier = next;
+        }
+
+        futures::future::ok(history)
+    }
 }
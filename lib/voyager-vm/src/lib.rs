@@ -7,7 +7,7 @@ use std::{
     self,
     collections::VecDeque,
     error::Error,
-    fmt::Debug,
+    fmt::{self, Debug, Display},
     future::Future,
     pin::Pin,
     time::{Duration, SystemTime, UNIX_EPOCH},
@@ -27,6 +27,9 @@ pub mod filter;
 pub mod in_memory;
 pub mod pass;
 
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
+
 #[cfg(test)]
 mod tests;
 
@@ -52,6 +55,10 @@ pub trait Queue<T: QueueMessage>: Debug + Clone + Send + Sync + Sized + 'static
 
     /// Process the item at the front of the queue, if there is one. New items will be pre-processed by `filter` before being reenqueued.
     ///
+    /// Items with a higher [`Priority`] are processed first. To prevent lower priorities from being
+    /// starved, implementations should periodically process the oldest ready item regardless of
+    /// its priority (see [`DEFAULT_ANTI_STARVATION_INTERVAL`]).
+    ///
     /// All items will be enqueued to be optimized, unless marked as ready by `filter`.
     fn process<'a, F, Fut, R, Filter>(
         &'a self,
@@ -73,6 +80,18 @@ pub trait Queue<T: QueueMessage>: Debug + Clone + Send + Sync + Sized + 'static
     where
         O: Pass<T>,
         Filter: InterestFilter<T>;
+
+    /// Walk the lineage of the item with the provided id, following its parents up to `max_depth`
+    /// levels.
+    ///
+    /// The returned list contains the item itself at depth 0 (if it exists), followed by all of its
+    /// ancestors in breadth-first order. Each item is only returned once, at the lowest depth it was
+    /// found at.
+    fn history(
+        &self,
+        id: ItemId,
+        max_depth: u32,
+    ) -> impl Future<Output = Result<Vec<HistoryItem<T>>, Self::Error>> + Send + '_;
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -82,6 +101,57 @@ pub struct EnqueueResult {
     pub optimize: Vec<ItemId>,
 }
 
+/// An item in the lineage of another item, as returned by [`Queue::history`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(
+    bound(serialize = "", deserialize = ""),
+    deny_unknown_fields,
+    rename_all = "snake_case"
+)]
+pub struct HistoryItem<T: QueueMessage> {
+    pub id: ItemId,
+    pub parents: Vec<ItemId>,
+    pub status: ItemStatus,
+    pub item: Op<T>,
+    /// The failure message, if this item failed.
+    pub message: Option<String>,
+    /// The distance from the item the history was requested for.
+    pub depth: u32,
+}
+
+/// Where an item currently lives in the queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemStatus {
+    /// The item is waiting to be processed.
+    Queued,
+    /// The item is waiting for an optimization pass.
+    Optimize,
+    /// The item was processed successfully.
+    Done,
+    /// The item failed with a fatal or unprocessable error.
+    Failed,
+}
+
+/// The priority of an item in the queue. Items with a higher priority are processed first, with
+/// each distinct priority acting as its own lane.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct Priority(pub u8);
+
+impl Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+/// By default, every nth item processed by a queue is the oldest ready item regardless of its
+/// priority, such that lower priority items are never starved by a steady stream of higher priority
+/// items.
+pub const DEFAULT_ANTI_STARVATION_INTERVAL: u32 = 10;
+
 /// The ID of an item in the queue.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 #[serde(transparent)]
@@ -378,7 +448,32 @@ pub fn process<'a, T: QueueMessage, H: Handler<T>>(
     Box::pin(fut)
 }
 
+/// The call or callback that is handled next when an [`Op`] is processed, see [`Op::next_step`].
+#[derive(Debug)]
+pub enum Step<'a, T: QueueMessage> {
+    Call(&'a T::Call),
+    Callback(&'a T::Callback),
+}
+
 impl<T: QueueMessage> Op<T> {
+    /// The call or callback that will be handled the next time this op is passed to [`process`],
+    /// if any.
+    #[must_use]
+    pub fn next_step(&self) -> Option<Step<'_, T>> {
+        match self {
+            Op::Call(call) => Some(Step::Call(call)),
+            Op::Seq(queue) | Op::Conc(queue) => queue.front().and_then(Op::next_step),
+            Op::Promise(Promise {
+                queue, receiver, ..
+            }) => match queue.front() {
+                Some(op) => op.next_step(),
+                None => Some(Step::Callback(receiver)),
+            },
+            Op::Void(op) => op.next_step(),
+            Op::Data(_) | Op::Defer { .. } | Op::DeferRelative { .. } | Op::Noop => None,
+        }
+    }
+
     pub fn normalize(self) -> Vec<Op<T>> {
         pub fn go<T: QueueMessage>(op: Op<T>) -> Vec<Op<T>> {
             match op {
@@ -0,0 +1,379 @@
+use std::{collections::VecDeque, convert::Infallible};
+
+use enumorph::Enumorph;
+use macros::model;
+use subset_of::SubsetOf;
+
+use crate::{
+    Op, QueueMessage, call, data,
+    filter::{FilterResult, Interest, InterestFilter},
+    pass::{Pass, PassResult},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SimpleMessage {}
+
+impl QueueMessage for SimpleMessage {
+    type Data = SimpleData;
+    type Call = SimpleCall;
+    type Callback = SimpleCallback;
+}
+
+/// Removes all [`FetchA`] calls from the queue, tagging them with `"a"`.
+pub struct FetchAFilter;
+
+impl InterestFilter<SimpleMessage> for FetchAFilter {
+    fn check_interest<'a>(&'a self, op: &Op<SimpleMessage>) -> FilterResult<'a> {
+        match op {
+            Op::Call(SimpleCall::A(FetchA {})) => FilterResult::Interest(Interest {
+                tags: vec!["a"],
+                remove: true,
+            }),
+            _ => FilterResult::NoInterest,
+        }
+    }
+}
+
+/// Keys all data ops by their debug representation.
+pub struct DataKeyFilter;
+
+impl InterestFilter<SimpleMessage> for DataKeyFilter {
+    fn check_interest<'a>(&'a self, _: &Op<SimpleMessage>) -> FilterResult<'a> {
+        FilterResult::NoInterest
+    }
+
+    fn idempotency_key(&self, op: &Op<SimpleMessage>) -> Option<String> {
+        match op {
+            Op::Data(data) => Some(format!("{data:?}")),
+            _ => None,
+        }
+    }
+}
+
+/// Replaces every op in the pass with a [`FetchC`] call.
+pub struct FetchAToFetchCPass;
+
+impl Pass<SimpleMessage> for FetchAToFetchCPass {
+    type Error = Infallible;
+
+    async fn run_pass(
+        &self,
+        ops: Vec<Op<SimpleMessage>>,
+    ) -> Result<PassResult<SimpleMessage>, Self::Error> {
+        Ok(PassResult {
+            optimize_further: vec![],
+            ready: (0..ops.len()).map(|i| (vec![i], call(FetchC {}))).collect(),
+        })
+    }
+}
+
+#[model]
+#[derive(Enumorph, SubsetOf)]
+pub enum SimpleData {
+    A(DataA),
+    B(DataB),
+    C(DataC),
+    D(DataD),
+    E(DataE),
+}
+#[model]
+pub struct DataA {}
+#[model]
+pub struct DataB {}
+#[model]
+pub struct DataC {}
+#[model]
+pub struct DataD {}
+#[model]
+pub struct DataE {}
+
+#[model]
+#[derive(Enumorph, SubsetOf)]
+pub enum SimpleCall {
+    A(FetchA),
+    B(FetchB),
+    C(FetchC),
+    D(FetchD),
+    E(FetchE),
+    PrintAbc(PrintAbc),
+}
+#[model]
+pub struct FetchA {}
+#[model]
+pub struct FetchB {}
+#[model]
+pub struct FetchC {}
+#[model]
+pub struct FetchD {}
+#[model]
+pub struct FetchE {}
+
+#[model]
+pub struct PrintAbc {
+    pub a: DataA,
+    pub b: DataB,
+    pub c: DataC,
+}
+
+#[model]
+#[derive(Enumorph)]
+pub enum SimpleCallback {
+    BuildPrintAbc(BuildPrintAbc),
+}
+
+#[model]
+pub struct BuildPrintAbc {}
+
+/// Tests that every [`Queue`](crate::Queue) implementation is expected to pass, see
+/// [`queue_tests!`](crate::queue_tests).
+pub mod queue_suite {
+    use std::{
+        sync::atomic::{AtomicBool, AtomicU32, Ordering},
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+
+    use super::*;
+    use crate::{
+        Queue, Step,
+        filter::{Admission, AdmissionKey, Held},
+        promise, seq,
+    };
+
+    /// Process every ready item in `queue` without handling it, returning the processed ops sorted
+    /// by their debug representation (queues only guarantee an order between items of different
+    /// priorities or ready times).
+    pub async fn drain<Q: Queue<SimpleMessage>, F: InterestFilter<SimpleMessage>>(
+        queue: &Q,
+        filter: &F,
+    ) -> Vec<Op<SimpleMessage>> {
+        let mut processed = vec![];
+
+        while let Some(op) = queue
+            .process(filter, async |op, _| (op, Ok(vec![])))
+            .await
+            .unwrap()
+        {
+            processed.push(op);
+        }
+
+        sorted(processed)
+    }
+
+    /// A name that is unique across tests and test runs, for queues that need a fresh database (or
+    /// schema) per test.
+    pub fn unique_name(prefix: &str) -> String {
+        static COUNTER: AtomicU32 = AtomicU32::new(0);
+
+        format!(
+            "{prefix}_{}_{}_{}",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_nanos(),
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::SeqCst)
+        )
+    }
+
+    pub fn sorted(mut ops: Vec<Op<SimpleMessage>>) -> Vec<Op<SimpleMessage>> {
+        ops.sort_by_cached_key(|op| format!("{op:?}"));
+        ops
+    }
+
+    /// Holds ops whose next step is a [`FetchB`] call while `hold` is set, as if they were handled
+    /// by a paused plugin `b`.
+    struct HoldFetchBFilter {
+        hold: AtomicBool,
+        admit_calls: AtomicU32,
+    }
+
+    impl HoldFetchBFilter {
+        fn is_fetch_b(op: &Op<SimpleMessage>) -> bool {
+            matches!(op.next_step(), Some(Step::Call(SimpleCall::B(_))))
+        }
+    }
+
+    impl InterestFilter<SimpleMessage> for HoldFetchBFilter {
+        fn check_interest<'a>(&'a self, _: &Op<SimpleMessage>) -> FilterResult<'a> {
+            FilterResult::NoInterest
+        }
+
+        fn admit(&self, op: &Op<SimpleMessage>) -> Option<Admission> {
+            self.admit_calls.fetch_add(1, Ordering::SeqCst);
+
+            (!(self.hold.load(Ordering::SeqCst) && Self::is_fetch_b(op))).then(Admission::default)
+        }
+
+        fn admission_key(&self, op: &Op<SimpleMessage>) -> Option<AdmissionKey> {
+            Some(AdmissionKey {
+                plugin: Self::is_fetch_b(op).then(|| "b".to_owned()),
+                ..Default::default()
+            })
+        }
+
+        fn held(&self) -> Held {
+            Held {
+                plugins: if self.hold.load(Ordering::SeqCst) {
+                    vec!["b".to_owned()]
+                } else {
+                    vec![]
+                },
+                ..Default::default()
+            }
+        }
+    }
+
+    pub async fn skips_held_items<Q: Queue<SimpleMessage>>(queue: &Q) {
+        let filter = HoldFetchBFilter {
+            hold: AtomicBool::new(true),
+            admit_calls: AtomicU32::new(0),
+        };
+
+        for op in [
+            call(FetchB {}),
+            seq([call(FetchB {}), call(FetchA {})]),
+            call(FetchA {}),
+            promise([call(FetchA {})], [], BuildPrintAbc {}),
+        ] {
+            queue.enqueue(op, &filter).await.unwrap();
+        }
+
+        assert_eq!(
+            drain(queue, &filter).await,
+            sorted(vec![
+                call(FetchA {}),
+                promise([call(FetchA {})], [], BuildPrintAbc {})
+            ])
+        );
+
+        // held items are skipped without being checked
+        assert_eq!(filter.admit_calls.load(Ordering::SeqCst), 2);
+
+        filter.hold.store(false, Ordering::SeqCst);
+
+        // held items are left in place, and picked up again once they are no longer held
+        assert_eq!(
+            drain(queue, &filter).await,
+            sorted(vec![
+                call(FetchB {}),
+                seq([call(FetchB {}), call(FetchA {})])
+            ])
+        );
+    }
+
+    /// Expects an idempotency window of 60 seconds.
+    pub async fn drops_duplicate_idempotency_keys<Q: Queue<SimpleMessage>>(queue: &Q) {
+        for op in [
+            data(DataA {}),
+            call(FetchA {}),
+            data(DataA {}),
+            data(DataB {}),
+            call(FetchA {}),
+        ] {
+            queue.enqueue(op, &DataKeyFilter).await.unwrap();
+        }
+
+        // ops without a key are never deduplicated
+        assert_eq!(
+            drain(queue, &DataKeyFilter).await,
+            sorted(vec![
+                data(DataA {}),
+                call(FetchA {}),
+                data(DataB {}),
+                call(FetchA {})
+            ])
+        );
+
+        // keys are remembered after the op has been processed
+        queue.enqueue(data(DataA {}), &DataKeyFilter).await.unwrap();
+
+        assert_eq!(drain(queue, &DataKeyFilter).await, vec![]);
+    }
+
+    /// Expects an idempotency window of 60 seconds.
+    pub async fn long_idempotency_keys<Q: Queue<SimpleMessage>>(queue: &Q) {
+        /// Keys all data ops by a key far larger than what most databases can index.
+        struct LongKeyFilter;
+
+        impl InterestFilter<SimpleMessage> for LongKeyFilter {
+            fn check_interest<'a>(&'a self, _: &Op<SimpleMessage>) -> FilterResult<'a> {
+                FilterResult::NoInterest
+            }
+
+            fn idempotency_key(&self, op: &Op<SimpleMessage>) -> Option<String> {
+                match op {
+                    Op::Data(data) => Some(format!("{}{data:?}", "x".repeat(1 << 20))),
+                    _ => None,
+                }
+            }
+        }
+
+        for op in [data(DataA {}), data(DataB {}), data(DataA {})] {
+            queue.enqueue(op, &LongKeyFilter).await.unwrap();
+        }
+
+        assert_eq!(
+            drain(queue, &LongKeyFilter).await,
+            sorted(vec![data(DataA {}), data(DataB {})])
+        );
+    }
+
+    /// Expects an idempotency window of 1 second.
+    pub async fn idempotency_keys_expire<Q: Queue<SimpleMessage>>(queue: &Q) {
+        queue.enqueue(data(DataA {}), &DataKeyFilter).await.unwrap();
+        queue.enqueue(data(DataA {}), &DataKeyFilter).await.unwrap();
+
+        tokio::time::sleep(Duration::from_secs(2)).await;
+
+        queue.enqueue(data(DataA {}), &DataKeyFilter).await.unwrap();
+
+        assert_eq!(
+            drain(queue, &DataKeyFilter).await,
+            vec![data(DataA {}), data(DataA {})]
+        );
+    }
+
+    /// Expects deduplication to be disabled (an idempotency window of 0 seconds).
+    pub async fn idempotency_window_disabled<Q: Queue<SimpleMessage>>(queue: &Q) {
+        queue.enqueue(data(DataA {}), &DataKeyFilter).await.unwrap();
+        queue.enqueue(data(DataA {}), &DataKeyFilter).await.unwrap();
+
+        assert_eq!(
+            drain(queue, &DataKeyFilter).await,
+            vec![data(DataA {}), data(DataA {})]
+        );
+    }
+}
+
+/// Generate the tests in [`queue_suite`] for a [`Queue`](crate::Queue) implementation.
+///
+/// `$new_queue` is called with the idempotency window (in seconds) the test expects, and returns a
+/// new, empty queue. Any attributes are applied to every generated test, i.e. `#[ignore]` for
+/// queues that require an external database.
+#[macro_export]
+macro_rules! queue_tests {
+    ($(#[$meta:meta])* $new_queue:expr) => {
+        $crate::queue_tests! {
+            $(#[$meta])* $new_queue;
+            drops_duplicate_idempotency_keys(60),
+            long_idempotency_keys(60),
+            idempotency_keys_expire(1),
+            idempotency_window_disabled(0),
+            skips_held_items(0),
+        }
+    };
+    (
+        $(#[$meta:meta])* $new_queue:expr;
+        $($test:ident($idempotency_window_seconds:literal)),* $(,)?
+    ) => {
+        $(
+            #[::tokio::test]
+            $(#[$meta])*
+            async fn $test() {
+                let queue = ($new_queue)($idempotency_window_seconds).await;
+
+                $crate::test_utils::queue_suite::$test(&queue).await;
+            }
+        )*
+    };
+}
@@ -1,9 +1,75 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
 use crate::{
-    QueueMessage, call, conc, data, defer, noop, now, promise, seq,
-    tests::utils::{BuildPrintAbc, DataA, DataB, DataC, FetchA, FetchB, PrintAbc, SimpleMessage},
+    Handler, HandlerFactory, ItemId, Op, Queue, QueueError, QueueMessage, Step, call, conc, data,
+    defer,
+    engine::replay,
+    filter::{Admission, FilterResult, InterestFilter},
+    in_memory::{InMemoryQueue, InMemoryQueueConfig},
+    noop, now, promise, seq,
+    test_utils::{
+        BuildPrintAbc, DataA, DataB, DataC, DataD, DataE, FetchA, FetchAFilter, FetchAToFetchCPass,
+        FetchB, FetchC, FetchD, FetchE, PrintAbc, SimpleCall, SimpleCallback, SimpleData,
+        SimpleMessage,
+    },
 };
 
-pub mod utils;
+impl Handler<SimpleMessage> for () {
+    async fn call(&self, call: SimpleCall) -> Result<Op<SimpleMessage>, QueueError> {
+        Ok(match call {
+            SimpleCall::A(FetchA {}) => data(DataA {}),
+            SimpleCall::B(FetchB {}) => data(DataB {}),
+            SimpleCall::C(FetchC {}) => data(DataC {}),
+            SimpleCall::D(FetchD {}) => data(DataD {}),
+            SimpleCall::E(FetchE {}) => data(DataE {}),
+            SimpleCall::PrintAbc(PrintAbc { a, b, c }) => {
+                println!("a = {a:?}, b = {b:?}, c = {c:?}");
+                noop()
+            }
+        })
+    }
+
+    async fn callback(
+        &self,
+        callback: SimpleCallback,
+        data: VecDeque<SimpleData>,
+    ) -> Result<Op<SimpleMessage>, QueueError> {
+        Ok(match callback {
+            SimpleCallback::BuildPrintAbc(BuildPrintAbc {}) => {
+                let mut data = data.into_iter().collect();
+
+                let op = call(PrintAbc {
+                    a: find_in_vec(&mut data, |d| d.clone().try_into().ok()).unwrap(),
+                    b: find_in_vec(&mut data, |d| d.clone().try_into().ok()).unwrap(),
+                    c: find_in_vec(&mut data, |d| d.clone().try_into().ok()).unwrap(),
+                });
+
+                assert!(data.is_empty());
+
+                op
+            }
+        })
+    }
+}
+
+impl HandlerFactory<SimpleMessage> for () {
+    type Handler = ();
+
+    fn make_handler(&self, _: ItemId) -> Self::Handler {}
+}
+
+fn find_in_vec<T, U>(v: &mut Vec<T>, mut predicate: impl FnMut(&T) -> Option<U>) -> Option<U> {
+    v.iter()
+        .enumerate()
+        .find_map(|(i, t)| predicate(t).map(|u| (i, u)))
+        .map(|(i, u)| {
+            v.remove(i);
+            u
+        })
+}
 
 #[derive(Debug, Clone, PartialEq)]
 enum UnitMessage {}
@@ -258,3 +324,153 @@ fn seq_conc_conc() {
 
     assert_eq!(op.normalize(), expected_output);
 }
+
+#[tokio::test]
+async fn replay_is_deterministic() {
+    let op = conc::<SimpleMessage>([call(FetchA {}), seq([defer(u64::MAX), call(FetchB {})])]);
+
+    let passes = HashMap::from([("a".to_owned(), FetchAToFetchCPass)]);
+
+    let replay_1 = replay(&(), &FetchAFilter, &passes, op.clone(), 100, |_| false).await;
+    let replay_2 = replay(&(), &FetchAFilter, &passes, op, 100, |_| false).await;
+
+    assert_eq!(replay_1, replay_2);
+
+    assert!(replay_1.complete);
+    // the defer is skipped, and the FetchA call is replaced with a FetchC call by the pass
+    assert_eq!(replay_1.data, vec![DataB {}.into(), DataC {}.into()]);
+    assert_eq!(
+        replay_1
+            .steps
+            .iter()
+            .map(|step| (step.id.raw(), step.optimized_by.as_deref()))
+            .collect::<Vec<_>>(),
+        vec![(2, None), (1, Some("a")), (4, None)]
+    );
+    assert_eq!(replay_1.steps[2].parents, vec![ItemId::new(1).unwrap()]);
+}
+
+#[tokio::test]
+async fn replay_stops_at_max_steps() {
+    let op = seq::<SimpleMessage>([call(FetchB {}), call(FetchB {}), call(FetchB {})]);
+
+    let replay = replay(
+        &(),
+        &(),
+        &HashMap::<String, FetchAToFetchCPass>::new(),
+        op,
+        2,
+        |_| false,
+    )
+    .await;
+
+    assert!(!replay.complete);
+    assert_eq!(replay.steps.len(), 2);
+}
+
+#[tokio::test]
+async fn replay_halts_matching_ops() {
+    let op = conc::<SimpleMessage>([call(FetchA {}), seq([call(FetchB {}), call(FetchA {})])]);
+
+    let replay = replay(
+        &(),
+        &(),
+        &HashMap::<String, FetchAToFetchCPass>::new(),
+        op,
+        100,
+        |op| matches!(op, Op::Call(SimpleCall::A(_))),
+    )
+    .await;
+
+    assert!(replay.complete);
+    // the top level FetchA is halted, and so is the one produced after FetchB in the seq
+    assert_eq!(replay.data, vec![DataB {}.into()]);
+    assert_eq!(
+        replay.halted,
+        vec![call(FetchA {}), call::<SimpleMessage>(FetchA {})]
+    );
+}
+
+async fn new_in_memory_queue(idempotency_window_seconds: u64) -> InMemoryQueue<SimpleMessage> {
+    InMemoryQueue::new(InMemoryQueueConfig {
+        idempotency_window_seconds,
+        ..Default::default()
+    })
+    .await
+    .unwrap()
+}
+
+crate::queue_tests!(new_in_memory_queue);
+
+/// Doesn't admit ops whose next step is a [`FetchB`] call while `hold` is set.
+struct HoldFetchBFilter {
+    hold: AtomicBool,
+}
+
+impl InterestFilter<SimpleMessage> for HoldFetchBFilter {
+    fn check_interest<'a>(&'a self, _: &Op<SimpleMessage>) -> FilterResult<'a> {
+        FilterResult::NoInterest
+    }
+
+    fn admit(&self, op: &Op<SimpleMessage>) -> Option<Admission> {
+        (!(self.hold.load(Ordering::SeqCst)
+            && matches!(op.next_step(), Some(Step::Call(SimpleCall::B(_))))))
+        .then(Admission::default)
+    }
+}
+
+#[tokio::test]
+async fn in_memory_queue_leaves_unadmitted_items_in_place() {
+    let queue = InMemoryQueue::<SimpleMessage>::new(InMemoryQueueConfig::default())
+        .await
+        .unwrap();
+
+    let filter = HoldFetchBFilter {
+        hold: AtomicBool::new(true),
+    };
+
+    for op in [
+        call(FetchB {}),
+        seq([call(FetchB {}), call(FetchA {})]),
+        call(FetchA {}),
+        promise([call(FetchA {})], [], BuildPrintAbc {}),
+    ] {
+        queue.enqueue(op, &filter).await.unwrap();
+    }
+
+    let mut processed = vec![];
+
+    while let Some(op) = queue
+        .process(&filter, async |op, _| (op, Ok(vec![])))
+        .await
+        .unwrap()
+    {
+        processed.push(op);
+    }
+
+    assert_eq!(
+        processed,
+        vec![
+            call(FetchA {}),
+            promise([call(FetchA {})], [], BuildPrintAbc {})
+        ]
+    );
+
+    filter.hold.store(false, Ordering::SeqCst);
+
+    processed.clear();
+
+    while let Some(op) = queue
+        .process(&filter, async |op, _| (op, Ok(vec![])))
+        .await
+        .unwrap()
+    {
+        processed.push(op);
+    }
+
+    // held items are processed in their original order once they are admitted
+    assert_eq!(
+        processed,
+        vec![call(FetchB {}), seq([call(FetchB {}), call(FetchA {})])]
+    );
+}
@@ -8,6 +8,27 @@ let
         "time_to_live" = mkOption { type = types.int; };
       };
     };
+    "#/definitions/ChainLimitsConfig" = types.submodule {
+      options = {
+        "breaker_base_backoff_seconds" = mkOption {
+          type = types.int;
+          default = 1;
+        };
+        "breaker_failure_threshold" = mkOption {
+          type = types.int;
+          default = 5;
+        };
+        "breaker_max_backoff_seconds" = mkOption {
+          type = types.int;
+          default = 60;
+        };
+        "max_in_flight" = mkOption { type = types.nullOr types.int; };
+        "max_in_flight_per_chain" = mkOption {
+          type = types.submodule { options = { }; };
+          default = { };
+        };
+      };
+    };
     "#/definitions/ClientBootstrapModuleInfo" = types.submodule {
       options = {
         "chain_id" = mkOption { type = types.str; };
@@ -153,7 +174,19 @@ let
     };
     "#/definitions/VoyagerConfig" = types.submodule {
       options = {
+        "admin_laddr" = mkOption {
+          type = types.str;
+          default = "127.0.0.1:7179";
+        };
         "cache" = mkOption { type = definitions."#/definitions/Config"; };
+        "chain_limits" = mkOption {
+          type = definitions."#/definitions/ChainLimitsConfig";
+          default = {
+            "breaker_base_backoff_seconds" = 1;
+            "breaker_failure_threshold" = 5;
+            "breaker_max_backoff_seconds" = 60;
+          };
+        };
         "ipc_client_request_timeout" = mkOption {
           type = definitions."#/definitions/Duration";
           default = {
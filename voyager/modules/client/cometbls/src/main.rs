@@ -1,5 +1,5 @@
 use alloy_sol_types::SolValue;
-use cometbls_light_client_types::{ClientState, ConsensusState, Header};
+use cometbls_light_client_types::{ClientState, ConsensusState, Header, Misbehaviour};
 use jsonrpsee::{Extensions, core::async_trait};
 use macros::model;
 use serde::{Deserialize, Serialize};
@@ -271,6 +271,29 @@ impl ClientModuleServer for Module {
         .map(into_value)
     }
 
+    #[instrument]
+    async fn encode_misbehaviour(
+        &self,
+        _: &Extensions,
+        header_a: Value,
+        header_b: Value,
+    ) -> RpcResult<Bytes> {
+        let header_a = serde_json::from_value::<Header>(header_a)
+            .map_err(RpcError::fatal("unable to deserialize header a"))?;
+        let header_b = serde_json::from_value::<Header>(header_b)
+            .map_err(RpcError::fatal("unable to deserialize header b"))?;
+
+        match self.ibc_interface {
+            SupportedIbcInterface::IbcCosmwasm => Ok(Misbehaviour { header_a, header_b }
+                .encode_as::<Bincode>()
+                .into()),
+            ibc_interface => Err(RpcError::fatal_from_message(format!(
+                "misbehaviour is not supported on {}",
+                ibc_interface.as_str()
+            ))),
+        }
+    }
+
     #[instrument(skip_all)]
     async fn encode_proof(&self, _: &Extensions, proof: Value) -> RpcResult<Bytes> {
         debug!(%proof, "encoding proof");
@@ -1,5 +1,5 @@
 use jsonrpsee::{Extensions, core::async_trait};
-use parlia_light_client_types::{ClientState, ConsensusState, Header, StateProof};
+use parlia_light_client_types::{ClientState, ConsensusState, Header, Misbehaviour, StateProof};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use tracing::instrument;
@@ -137,6 +137,40 @@ impl ClientModuleServer for Module {
             .map_err(RpcError::fatal("unable to decode header"))
     }
 
+    #[instrument]
+    async fn encode_misbehaviour(
+        &self,
+        _: &Extensions,
+        header_a: Value,
+        header_b: Value,
+    ) -> RpcResult<Bytes> {
+        let header_a = serde_json::from_value::<Header>(header_a)
+            .map_err(RpcError::fatal("unable to deserialize header a"))?;
+        let header_b = serde_json::from_value::<Header>(header_b)
+            .map_err(RpcError::fatal("unable to deserialize header b"))?;
+
+        if header_a.trusted_valset_epoch_number != header_b.trusted_valset_epoch_number {
+            return Err(RpcError::fatal_from_message(
+                "conflicting headers must be trusted by the same valset",
+            ));
+        }
+
+        let [source_1, target_1, attestation_1] = attestation_chain(header_a.chain)?;
+        let [source_2, target_2, attestation_2] = attestation_chain(header_b.chain)?;
+
+        Ok(Misbehaviour {
+            trusted_valset_epoch_number: header_a.trusted_valset_epoch_number,
+            source_1,
+            target_1,
+            attestation_1,
+            source_2,
+            target_2,
+            attestation_2,
+        }
+        .encode_as::<Bincode>()
+        .into())
+    }
+
     #[instrument]
     async fn encode_proof(&self, _: &Extensions, proof: Value) -> RpcResult<Bytes> {
         serde_json::from_value::<StateProof>(proof)
@@ -151,3 +185,17 @@ impl ClientModuleServer for Module {
             .map_err(RpcError::fatal("unable to decode proof"))
     }
 }
+
+/// The `[source, target, attestation]` headers at the end of the chain of a [`Header`].
+fn attestation_chain<T>(mut chain: Vec<T>) -> RpcResult<[T; 3]> {
+    let len = chain.len();
+
+    chain
+        .split_off(len.saturating_sub(3))
+        .try_into()
+        .map_err(|_| {
+            RpcError::fatal_from_message(
+                "header chain must contain at least the source, target and attestation",
+            )
+        })
+}
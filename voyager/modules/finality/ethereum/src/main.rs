@@ -12,6 +12,7 @@ use beacon_api::{
 };
 use beacon_api_types::chain_spec::PresetBaseKind;
 use jsonrpsee::{Extensions, core::async_trait};
+use rpc_failover::FailoverConfig;
 use serde::{Deserialize, Serialize};
 use tracing::{debug, instrument};
 use unionlabs::ibc::core::client::height::Height;
@@ -48,6 +49,14 @@ pub struct Config {
     /// The RPC endpoint for the beacon chain.
     pub beacon_rpc_url: String,
 
+    /// Fallback endpoints, health checks and quorum reads for `rpc_url`.
+    #[serde(default)]
+    pub rpc_failover: FailoverConfig,
+
+    /// Fallback endpoints, health checks and quorum reads for `beacon_rpc_url`.
+    #[serde(default)]
+    pub beacon_rpc_failover: FailoverConfig,
+
     #[serde(default)]
     pub max_cache_size: u32,
 }
@@ -153,8 +162,10 @@ impl FinalityModule for Module {
         let provider = DynProvider::new(
             ProviderBuilder::new()
                 .layer(CacheLayer::new(config.max_cache_size))
-                .connect(&config.rpc_url)
-                .await?,
+                .connect_client(
+                    rpc_failover::alloy::connect_client(&config.rpc_url, &config.rpc_failover)
+                        .await?,
+                ),
         );
 
         let chain_id = ChainId::new(provider.get_chain_id().await?.to_string());
@@ -162,7 +173,8 @@ impl FinalityModule for Module {
         info.ensure_chain_id(chain_id.to_string())?;
         info.ensure_consensus_type(ConsensusType::ETHEREUM)?;
 
-        let beacon_api_client = BeaconApiClient::new(config.beacon_rpc_url);
+        let beacon_api_client =
+            BeaconApiClient::new_with_failover(config.beacon_rpc_url, &config.beacon_rpc_failover);
 
         let spec = beacon_api_client.spec().await?;
 
@@ -1,6 +1,7 @@
 use std::{fmt::Debug, num::ParseIntError};
 
 use jsonrpsee::{Extensions, core::async_trait};
+use rpc_failover::FailoverConfig;
 use serde::{Deserialize, Serialize};
 use tracing::{error, instrument, trace};
 use unionlabs::ibc::core::client::height::Height;
@@ -28,13 +29,18 @@ pub struct Module {
 #[serde(deny_unknown_fields)]
 pub struct Config {
     pub rpc_url: String,
+
+    /// Fallback endpoints, health checks and quorum reads for `rpc_url`.
+    #[serde(default)]
+    pub rpc_failover: FailoverConfig,
 }
 
 impl FinalityModule for Module {
     type Config = Config;
 
     async fn new(config: Self::Config, info: FinalityModuleInfo) -> anyhow::Result<Self> {
-        let tm_client = cometbft_rpc::Client::new(config.rpc_url).await?;
+        let tm_client =
+            cometbft_rpc::Client::new_with_failover(config.rpc_url, &config.rpc_failover).await?;
 
         let chain_id = tm_client.status().await?.node_info.network.to_string();
 
@@ -465,6 +465,7 @@ impl Module {
                                 codespace,
                                 error_code,
                                 log,
+                                ..
                             })) => {
                                 error!(%codespace, %error_code, %log, "tx failed");
                                 Ok(noop())
@@ -5,8 +5,12 @@ use std::{
     cmp::Ordering,
     collections::{BTreeMap, BTreeSet, VecDeque, btree_map::Entry},
     num::{NonZeroU8, NonZeroU32, ParseIntError},
+    sync::Arc,
 };
 
+use cometbft_rpc::{
+    rpc_types::SubscriptionEventData, subscription::SubscriptionMessage, types::abci::event::Event,
+};
 use cosmos_sdk_event::CosmosSdkEvent;
 use ibc_union_spec::{
     IbcUnion, MustBeZero, Packet,
@@ -19,8 +23,12 @@ use ibc_union_spec::{
     path::ChannelPath,
     query::PacketByHash,
 };
+use index_checkpoint::{CheckpointConfig, IndexProgress};
 use jsonrpsee::{Extensions, core::async_trait};
+use rpc_failover::FailoverConfig;
 use serde::{Deserialize, Serialize};
+use subscription_buffer::SubscriptionBuffer;
+use tokio::sync::mpsc;
 use tracing::{debug, error, info, info_span, instrument, trace, warn};
 use unionlabs::{
     ErrorReporter,
@@ -31,17 +39,18 @@ use unionlabs::{
 use voyager_sdk::{
     ExtensionsExt, VoyagerClient,
     anyhow::{self, bail},
-    hook::simple_take_filter,
+    hook::{IndexRangeHook, simple_take_filter},
     into_value,
     message::{
         PluginMessage, VoyagerMessage,
-        call::{Call, WaitForHeight},
+        call::{Call, Index, WaitForHeight},
         data::{ChainEvent, Data, EventProvableHeight},
     },
     plugin::Plugin,
     primitives::{ChainId, ClientType, QueryHeight},
     rpc::{PluginServer, RpcError, RpcResult, types::PluginInfo},
-    vm::{Op, call, conc, data, noop, pass::PassResult, seq},
+    serde_json::Value,
+    vm::{Op, Visit, call, conc, data, noop, pass::PassResult, seq},
 };
 
 use crate::{
@@ -74,6 +83,11 @@ pub struct Module {
     pub index_trivial_events: bool,
 
     pub ibc_host_contract_address: Option<Bech32<H256>>,
+
+    /// The events pushed by the websocket subscription, if `ws_url` is configured.
+    pub subscription: Option<Arc<SubscriptionBuffer<(Option<H256>, Event)>>>,
+
+    pub index_progress: Option<Arc<IndexProgress>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -83,6 +97,10 @@ pub struct Config {
 
     pub rpc_url: String,
 
+    /// Fallback endpoints, health checks and quorum reads for `rpc_url`.
+    #[serde(default)]
+    pub rpc_failover: FailoverConfig,
+
     #[serde(default = "default_chunk_block_fetch_size")]
     pub chunk_block_fetch_size: u64,
 
@@ -95,12 +113,30 @@ pub struct Config {
 
     #[serde(default)]
     pub ibc_host_contract_address: Option<Bech32<H256>>,
+
+    /// The CometBFT websocket endpoint (i.e. `ws://localhost:26657/websocket`) to subscribe to new
+    /// events on. If set, blocks are only polled if they were produced while the subscription was
+    /// not connected.
+    #[serde(default)]
+    pub ws_url: Option<String>,
+
+    /// The amount of blocks to keep the subscribed events for.
+    #[serde(default = "default_subscription_buffer_blocks")]
+    pub subscription_buffer_blocks: u64,
+
+    /// Persist the last fully indexed height, and alert when indexing lags behind the chain.
+    #[serde(default)]
+    pub checkpoint: Option<CheckpointConfig>,
 }
 
 fn default_chunk_block_fetch_size() -> u64 {
     10
 }
 
+fn default_subscription_buffer_blocks() -> u64 {
+    10_000
+}
+
 fn default_refetch_delay() -> u64 {
     120
 }
@@ -119,7 +155,8 @@ impl Plugin for Module {
     type Cmd = Cmd;
 
     async fn new(config: Self::Config) -> anyhow::Result<Self> {
-        let tm_client = cometbft_rpc::Client::new(config.rpc_url).await?;
+        let tm_client =
+            cometbft_rpc::Client::new_with_failover(config.rpc_url, &config.rpc_failover).await?;
 
         let chain_id = tm_client.status().await?.node_info.network;
 
@@ -144,6 +181,54 @@ impl Plugin for Module {
                 source: Some(err),
             })?;
 
+        let subscription = match config.ws_url {
+            Some(ws_url) => {
+                let messages = cometbft_rpc::subscription::subscribe(
+                    &ws_url,
+                    vec![
+                        "tm.event='NewBlockEvents'".to_owned(),
+                        "tm.event='Tx'".to_owned(),
+                    ],
+                )?;
+
+                let buffer = Arc::new(SubscriptionBuffer::new(config.subscription_buffer_blocks));
+
+                tokio::spawn(subscribe(messages, tm_client.clone(), buffer.clone()));
+
+                Some(buffer)
+            }
+            None => None,
+        };
+
+        let index_progress = match config.checkpoint {
+            Some(checkpoint_config) => {
+                let index_progress =
+                    Arc::new(IndexProgress::new(chain_id.clone(), &checkpoint_config).await?);
+
+                if let (Some(rest_url), Some(checkpoint)) = (
+                    checkpoint_config.resume_rest_url,
+                    index_progress.checkpoint(),
+                ) {
+                    info!(%checkpoint, "resuming indexing from checkpoint");
+
+                    let op = call::<VoyagerMessage>(Index {
+                        chain_id: ChainId::new(chain_id.clone()),
+                        start_height: Height::new_with_revision(chain_revision, checkpoint + 1),
+                    });
+
+                    let idempotency_key =
+                        index_checkpoint::resume_idempotency_key(&chain_id, checkpoint);
+
+                    tokio::spawn(async move {
+                        index_checkpoint::enqueue(&rest_url, &op, &idempotency_key).await
+                    });
+                }
+
+                Some(index_progress)
+            }
+            None => None,
+        };
+
         Ok(Self {
             cometbft_client: tm_client,
             chain_id: ChainId::new(chain_id),
@@ -152,6 +237,8 @@ impl Plugin for Module {
             refetch_delay: config.refetch_delay,
             index_trivial_events: config.index_trivial_events,
             ibc_host_contract_address: config.ibc_host_contract_address,
+            subscription,
+            index_progress,
         })
     }
 
@@ -183,6 +270,55 @@ impl Plugin for Module {
     }
 }
 
+/// Fill `buffer` with the events pushed by the subscription. `NewBlockEvents` are pushed before the
+/// `Tx` events of the same block, so the head of a block is only seen once all events of the
+/// previous block have been received.
+async fn subscribe(
+    mut messages: mpsc::UnboundedReceiver<SubscriptionMessage>,
+    cometbft_client: cometbft_rpc::Client,
+    buffer: Arc<SubscriptionBuffer<(Option<H256>, Event)>>,
+) {
+    while let Some(message) = messages.recv().await {
+        match message {
+            SubscriptionMessage::Connected => {
+                buffer.disconnected();
+
+                match cometbft_client.status().await {
+                    // cometbft has instant finality, no committed block can be reorged
+                    Ok(status) => buffer.connected(
+                        status.sync_info.latest_block_height,
+                        Some(status.sync_info.latest_block_height),
+                    ),
+                    Err(err) => {
+                        error!(
+                            err = %ErrorReporter(err),
+                            "error fetching latest height, subscribed events will not be used until the next reconnect"
+                        );
+                    }
+                }
+            }
+            SubscriptionMessage::Event(event) => {
+                let tx_hash = event.tx_hash().map(|tx_hash| tx_hash.into_encoding());
+
+                match event.data {
+                    SubscriptionEventData::NewBlockEvents(block) => {
+                        for event in block.events {
+                            buffer.insert(block.height, (None, event));
+                        }
+
+                        buffer.head(block.height);
+                    }
+                    SubscriptionEventData::Tx(tx) => {
+                        for event in tx.tx_result.result.events {
+                            buffer.insert(tx.tx_result.height, (tx_hash, event));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
 fn plugin_name(chain_id: &ChainId) -> String {
     pub const PLUGIN_NAME: &str = env!("CARGO_PKG_NAME");
 
@@ -230,16 +366,21 @@ impl PluginServer<ModuleCall, Never> for Module {
                             }),
                         ))
                     }
-                    Op::Call(Call::IndexRange(fetch)) if fetch.chain_id == self.chain_id => {
-                        call(PluginMessage::new(
-                            self.plugin_name(),
-                            ModuleCall::from(FetchBlocks {
-                                height: fetch.range.from_height(),
-                                until: Some(fetch.range.to_height()),
-                            }),
-                        ))
+                    mut op => {
+                        // also handles ranges nested in other messages, i.e. a backfill promise
+                        IndexRangeHook::new(&self.chain_id, |fetch| {
+                            Call::Plugin(PluginMessage::new(
+                                self.plugin_name(),
+                                ModuleCall::from(FetchBlocks {
+                                    height: fetch.range.from_height(),
+                                    until: Some(fetch.range.to_height()),
+                                }),
+                            ))
+                        })
+                        .visit_op(&mut op);
+
+                        op
                     }
-                    op => op,
                 })
                 .enumerate()
                 .map(|(i, op)| (vec![i], op))
@@ -266,7 +407,17 @@ impl PluginServer<ModuleCall, Never> for Module {
             ModuleCall::FetchBlock(FetchBlock {
                 already_seen_events,
                 height,
-            }) => self.fetch_block(height, already_seen_events).await,
+            }) => {
+                let refetch = already_seen_events.is_some();
+
+                let op = self.fetch_block(height, already_seen_events).await?;
+
+                if let Some(index_progress) = self.index_progress.as_ref().filter(|_| !refetch) {
+                    index_progress.complete(height.height()).await;
+                }
+
+                Ok(op)
+            }
             ModuleCall::MakeChainEvent(MakeChainEvent {
                 height,
                 tx_hash,
@@ -277,6 +428,20 @@ impl PluginServer<ModuleCall, Never> for Module {
             }
         }
     }
+
+    #[instrument(skip_all, fields(chain_id = %self.chain_id))]
+    async fn custom(&self, _: &Extensions, method: String, _: Vec<Value>) -> RpcResult<Value> {
+        match method.as_str() {
+            "indexingStatus" => Ok(into_value(
+                self.index_progress
+                    .as_ref()
+                    .map(|index_progress| index_progress.status()),
+            )),
+            _ => Err(RpcError::fatal_from_message(format!(
+                "unknown method {method}"
+            ))),
+        }
+    }
 }
 
 impl Module {
@@ -310,6 +475,10 @@ impl Module {
 
         info!(%latest_height, %height, ?until, "fetching blocks");
 
+        if let Some(index_progress) = &self.index_progress {
+            index_progress.latest_height(latest_height.height());
+        }
+
         if !height.revision_matches(&latest_height) {
             return Err(RpcError::fatal_from_message(format!(
                 "revision number mismatch: fetching blocks from height \
@@ -354,6 +523,14 @@ impl Module {
                     "batch fetching blocks in range {height}..{next_height}"
                 );
 
+                // only the unbounded unfold is checkpointed, ranged fetches are backfills
+                let gap = match self.index_progress.as_ref().filter(|_| until.is_none()) {
+                    Some(index_progress) => {
+                        index_progress.schedule(height.height()..next_height).await
+                    }
+                    None => None,
+                };
+
                 Ok(conc(
                     (height.height()..next_height)
                         .map(|h| {
@@ -365,6 +542,18 @@ impl Module {
                                 }),
                             ))
                         })
+                        .chain(gap.map(|gap| {
+                            call(PluginMessage::new(
+                                self.plugin_name(),
+                                ModuleCall::from(FetchBlocks {
+                                    height: Height::new_with_revision(height.revision(), gap.start),
+                                    until: Some(Height::new_with_revision(
+                                        height.revision(),
+                                        gap.end - 1,
+                                    )),
+                                }),
+                            ))
+                        }))
                         .chain([continuation(Height::new_with_revision(
                             height.revision(),
                             next_height,
@@ -420,8 +609,7 @@ impl Module {
 
         let mut seen_batches = BTreeSet::new();
 
-        let mut handle_event = |event: cometbft_rpc::types::abci::event::Event,
-                                tx_hash: Option<_>| {
+        let mut handle_event = |event: Event, tx_hash: Option<_>| {
             trace!(%event.ty, "observed event");
 
             let event = match CosmosSdkEvent::<IbcEvent>::new(event) {
@@ -512,6 +700,25 @@ impl Module {
             }
         };
 
+        // events pushed by the subscription are complete, so the block does not need to be refetched
+        if let Some(events) = already_seen_events
+            .is_none()
+            .then(|| {
+                self.subscription
+                    .as_ref()
+                    .and_then(|subscription| subscription.get(height.height()))
+            })
+            .flatten()
+        {
+            info!("found {} subscribed events in block {height}", events.len());
+
+            for (tx_hash, event) in events {
+                handle_event(event, tx_hash)
+            }
+
+            return Ok(conc(make_chain_event_ops.into_iter().flatten()));
+        }
+
         loop {
             info!(%height, %page, "fetching page {page}");
 
@@ -2,13 +2,15 @@
 // #![warn(clippy::unwrap_used)] // allow for now
 
 use core::slice;
-use std::{cmp::Ordering, collections::VecDeque};
+use std::{cmp::Ordering, collections::VecDeque, sync::Arc, time::Duration};
 
 use alloy::{
+    eips::{BlockId, BlockNumberOrTag},
     providers::{DynProvider, Provider, ProviderBuilder, layers::CacheLayer},
-    rpc::types::Filter,
+    rpc::types::{Filter, Header, Log},
     sol_types::SolEventInterface,
 };
+use futures::StreamExt;
 use ibc_solidity::Ibc;
 use ibc_union_spec::{
     ChannelId, ChannelState, IbcUnion, Packet,
@@ -21,9 +23,17 @@ use ibc_union_spec::{
     path::{BatchPacketsPath, BatchReceiptsPath, ChannelPath, ConnectionPath},
     query::PacketByHash,
 };
-use jsonrpsee::{Extensions, core::async_trait};
+use index_checkpoint::{CheckpointConfig, IndexProgress};
+use jsonrpsee::{
+    Extensions,
+    core::{async_trait, client::SubscriptionClientT},
+    rpc_params,
+    ws_client::WsClientBuilder,
+};
+use rpc_failover::FailoverConfig;
 use serde::{Deserialize, Serialize};
-use tracing::{debug, info, info_span, instrument, trace, warn};
+use subscription_buffer::SubscriptionBuffer;
+use tracing::{debug, error, info, info_span, instrument, trace, warn};
 use unionlabs::{
     ErrorReporter,
     ibc::core::client::height::Height,
@@ -33,22 +43,27 @@ use unionlabs::{
 use voyager_sdk::{
     DefaultCmd, ExtensionsExt, VoyagerClient,
     anyhow::{self, bail},
-    hook::simple_take_filter,
+    hook::{IndexRangeHook, simple_take_filter},
+    into_value,
     message::{
         PluginMessage, VoyagerMessage,
-        call::{Call, WaitForHeight},
+        call::{Call, Index, WaitForHeight},
         data::{ChainEvent, Data, EventProvableHeight},
     },
     plugin::Plugin,
     primitives::{ChainId, ClientInfo, QueryHeight},
     rpc::{PluginServer, RpcError, RpcErrorExt, RpcResult, types::PluginInfo},
-    serde_json::json,
-    vm::{Op, call, conc, data, noop, pass::PassResult, seq},
+    serde_json::{Value, json},
+    vm::{Op, Visit, call, conc, data, noop, pass::PassResult, seq},
 };
 
-use crate::call::{FetchBlocks, FetchGetLogs, IbcEvents, MakeFullEvent, ModuleCall};
+use crate::{
+    call::{FetchBlocks, FetchGetLogs, IbcEvents, MakeFullEvent, ModuleCall},
+    reorg::IndexedBlocks,
+};
 
 pub mod call;
+pub mod reorg;
 
 #[tokio::main]
 async fn main() {
@@ -65,6 +80,16 @@ pub struct Module {
     pub index_trivial_events: bool,
 
     pub provider: DynProvider,
+
+    /// The logs pushed by the `eth_subscribe` subscription, if `ws_url` is configured.
+    pub subscription: Option<Arc<SubscriptionBuffer<Log>>>,
+
+    /// If set, blocks are indexed once they are this many blocks behind the latest block, instead
+    /// of once they are finalized.
+    pub confirmations: Option<u64>,
+    pub indexed_blocks: Arc<IndexedBlocks>,
+
+    pub index_progress: Option<Arc<IndexProgress>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -82,18 +107,54 @@ pub struct Config {
     /// The RPC endpoint for the execution chain.
     pub rpc_url: String,
 
+    /// Fallback endpoints, health checks and quorum reads for `rpc_url`.
+    #[serde(default)]
+    pub rpc_failover: FailoverConfig,
+
     /// Whether or not to fully index events that do not produce a counterparty action (packet_recv, packet_acknowledgement, packet_timeout, update_client).
     #[serde(default)]
     pub index_trivial_events: bool,
 
     #[serde(default)]
     pub max_cache_size: u32,
+
+    /// Websocket endpoint to subscribe to new logs with `eth_subscribe`. If set, `eth_getLogs` is
+    /// only used for blocks produced while the subscription was not connected.
+    #[serde(default)]
+    pub ws_url: Option<String>,
+
+    /// The amount of blocks to keep the subscribed logs for. This must be greater than the
+    /// distance between the latest and the finalized block.
+    #[serde(default = "default_subscription_buffer_blocks")]
+    pub subscription_buffer_blocks: u64,
+
+    /// Index blocks once they have this many confirmations, instead of waiting for them to be
+    /// finalized. Reorgs of indexed blocks are detected by their parent hashes, and the affected
+    /// heights are reindexed.
+    #[serde(default)]
+    pub confirmations: Option<u64>,
+
+    /// How many of the blocks indexed ahead of finality to track for reorgs.
+    #[serde(default = "default_max_reorg_depth")]
+    pub max_reorg_depth: u64,
+
+    /// Persist the last fully indexed height, and alert when indexing lags behind the chain.
+    #[serde(default)]
+    pub checkpoint: Option<CheckpointConfig>,
 }
 
 fn default_chunk_block_fetch_size() -> u64 {
     10
 }
 
+fn default_subscription_buffer_blocks() -> u64 {
+    10_000
+}
+
+fn default_max_reorg_depth() -> u64 {
+    256
+}
+
 impl Plugin for Module {
     type Call = ModuleCall;
     type Callback = Never;
@@ -135,8 +196,10 @@ impl Module {
         let provider = DynProvider::new(
             ProviderBuilder::new()
                 .layer(CacheLayer::new(config.max_cache_size))
-                .connect(&config.rpc_url)
-                .await?,
+                .connect_client(
+                    rpc_failover::alloy::connect_client(&config.rpc_url, &config.rpc_failover)
+                        .await?,
+                ),
         );
 
         let chain_id = ChainId::new(provider.get_chain_id().await?.to_string());
@@ -149,12 +212,58 @@ impl Module {
             );
         }
 
+        let subscription = config.ws_url.map(|ws_url| {
+            let buffer = Arc::new(SubscriptionBuffer::new(config.subscription_buffer_blocks));
+
+            tokio::spawn(subscribe(
+                ws_url,
+                config.ibc_handler_address,
+                provider.clone(),
+                buffer.clone(),
+            ));
+
+            buffer
+        });
+
+        let index_progress = match config.checkpoint {
+            Some(checkpoint_config) => {
+                let index_progress =
+                    Arc::new(IndexProgress::new(chain_id.to_string(), &checkpoint_config).await?);
+
+                if let (Some(rest_url), Some(checkpoint)) = (
+                    checkpoint_config.resume_rest_url,
+                    index_progress.checkpoint(),
+                ) {
+                    info!(%checkpoint, "resuming indexing from checkpoint");
+
+                    let op = call::<VoyagerMessage>(Index {
+                        chain_id: chain_id.clone(),
+                        start_height: Height::new(checkpoint + 1),
+                    });
+
+                    let idempotency_key =
+                        index_checkpoint::resume_idempotency_key(chain_id.as_str(), checkpoint);
+
+                    tokio::spawn(async move {
+                        index_checkpoint::enqueue(&rest_url, &op, &idempotency_key).await
+                    });
+                }
+
+                Some(index_progress)
+            }
+            None => None,
+        };
+
         Ok(Self {
             chain_id,
             ibc_handler_address: config.ibc_handler_address,
             index_trivial_events: config.index_trivial_events,
             chunk_block_fetch_size: config.chunk_block_fetch_size,
             provider,
+            subscription,
+            confirmations: config.confirmations,
+            indexed_blocks: Arc::new(IndexedBlocks::new(config.max_reorg_depth)),
+            index_progress,
         })
     }
 
@@ -251,16 +360,21 @@ impl PluginServer<ModuleCall, Never> for Module {
                             }),
                         ))
                     }
-                    Op::Call(Call::IndexRange(fetch)) if fetch.chain_id == self.chain_id => {
-                        call(PluginMessage::new(
-                            self.plugin_name(),
-                            ModuleCall::from(FetchBlocks {
-                                block_number: fetch.range.from_height().height(),
-                                until: Some(fetch.range.to_height().height()),
-                            }),
-                        ))
+                    mut op => {
+                        // also handles ranges nested in other messages, i.e. a backfill promise
+                        IndexRangeHook::new(&self.chain_id, |fetch| {
+                            Call::Plugin(PluginMessage::new(
+                                self.plugin_name(),
+                                ModuleCall::from(FetchBlocks {
+                                    block_number: fetch.range.from_height().height(),
+                                    until: Some(fetch.range.to_height().height()),
+                                }),
+                            ))
+                        })
+                        .visit_op(&mut op);
+
+                        op
                     }
-                    op => op,
                 })
                 .enumerate()
                 .map(|(i, op)| (vec![i], op))
@@ -301,6 +415,131 @@ impl PluginServer<ModuleCall, Never> for Module {
             }
         }
     }
+
+    #[instrument(skip_all, fields(chain_id = %self.chain_id))]
+    async fn custom(&self, _: &Extensions, method: String, _: Vec<Value>) -> RpcResult<Value> {
+        match method.as_str() {
+            "indexingStatus" => Ok(into_value(
+                self.index_progress
+                    .as_ref()
+                    .map(|index_progress| index_progress.status()),
+            )),
+            _ => Err(RpcError::fatal_from_message(format!(
+                "unknown method {method}"
+            ))),
+        }
+    }
+}
+
+/// Keep `buffer` filled with the logs of `ibc_handler_address` pushed over `ws_url`, resubscribing
+/// whenever the websocket reconnects.
+async fn subscribe(
+    ws_url: String,
+    ibc_handler_address: H160,
+    provider: DynProvider,
+    buffer: Arc<SubscriptionBuffer<Log>>,
+) {
+    let client = reconnecting_jsonrpc_ws_client::Client::new({
+        let ws_url = ws_url.clone();
+        move || WsClientBuilder::default().build(ws_url.clone())
+    });
+
+    let filter = json!({ "address": alloy::primitives::Address::from(ibc_handler_address.get()) });
+
+    loop {
+        buffer.disconnected();
+
+        if let Err(err) = client.wait_until_connected(Duration::from_secs(10)).await {
+            warn!(err = %ErrorReporter(err), %ws_url, "websocket not connected");
+            continue;
+        }
+
+        let (heads, logs) = match (
+            client
+                .subscribe::<Header, _>("eth_subscribe", rpc_params!["newHeads"], "eth_unsubscribe")
+                .await,
+            client
+                .subscribe::<Log, _>(
+                    "eth_subscribe",
+                    rpc_params!["logs", filter.clone()],
+                    "eth_unsubscribe",
+                )
+                .await,
+        ) {
+            (Ok(heads), Ok(logs)) => (heads, logs),
+            (Err(err), _) | (_, Err(err)) => {
+                error!(err = %ErrorReporter(err), %ws_url, "error subscribing");
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                continue;
+            }
+        };
+
+        // reorgs of blocks that were not yet finalized may have been missed while disconnected
+        let finalized_height = match provider
+            .get_block(BlockId::Number(BlockNumberOrTag::Finalized))
+            .await
+        {
+            Ok(block) => block.map(|block| block.header.number),
+            Err(err) => {
+                warn!(
+                    err = %ErrorReporter(err),
+                    "error fetching finalized height, previously subscribed logs will not be used"
+                );
+                None
+            }
+        };
+
+        // subscribe before querying the latest height, such that no blocks are missed in between
+        match provider.get_block_number().await {
+            Ok(latest_height) => buffer.connected(latest_height, finalized_height),
+            Err(err) => {
+                error!(err = %ErrorReporter(err), "error fetching latest height");
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                continue;
+            }
+        }
+
+        let mut heads = heads.fuse();
+        let mut logs = logs.fuse();
+
+        loop {
+            tokio::select! {
+                // logs are pushed before the head of the next block, so they must be handled first
+                biased;
+                log = logs.next() => match log {
+                    Some(Ok(log)) => {
+                        let Some(block_number) = log.block_number else {
+                            continue;
+                        };
+
+                        if log.removed {
+                            debug!(%block_number, "log removed by reorg");
+
+                            buffer.remove(block_number, |buffered| {
+                                buffered.block_hash == log.block_hash
+                                    && buffered.log_index == log.log_index
+                            });
+                        } else {
+                            buffer.insert(block_number, log);
+                        }
+                    }
+                    Some(Err(err)) => {
+                        warn!(err = %ErrorReporter(err), "invalid log");
+                    }
+                    None => break,
+                },
+                head = heads.next() => match head {
+                    Some(Ok(head)) => buffer.head(head.number),
+                    Some(Err(err)) => {
+                        warn!(err = %ErrorReporter(err), "invalid head");
+                    }
+                    None => break,
+                },
+            }
+        }
+
+        debug!(%ws_url, "subscription ended");
+    }
 }
 
 impl Module {
@@ -325,20 +564,31 @@ impl Module {
             }
         }
 
-        let latest_height = voyager_client
-            .query_latest_height(self.chain_id.clone(), true)
-            .await?
-            .height();
+        let latest_height = match self.confirmations {
+            Some(confirmations) => voyager_client
+                .query_latest_height(self.chain_id.clone(), false)
+                .await?
+                .height()
+                .saturating_sub(confirmations),
+            None => voyager_client
+                .query_latest_height(self.chain_id.clone(), true)
+                .await?
+                .height(),
+        };
 
         info!(%latest_height, %block_number, "fetching blocks");
 
+        if let Some(index_progress) = &self.index_progress {
+            index_progress.latest_height(latest_height);
+        }
+
         let continuation = |next_height: u64| {
             seq([
                 // TODO: Make this a config param
                 call(WaitForHeight {
                     chain_id: self.chain_id.clone(),
-                    height: Height::new(next_height),
-                    finalized: true,
+                    height: Height::new(next_height + self.confirmations.unwrap_or_default()),
+                    finalized: self.confirmations.is_none(),
                 }),
                 call(PluginMessage::new(
                     self.plugin_name(),
@@ -367,6 +617,14 @@ impl Module {
                     "batch fetching blocks in range {block_number}..{next_height}"
                 );
 
+                // only the unbounded unfold is checkpointed, ranged fetches are backfills
+                let gap = match self.index_progress.as_ref().filter(|_| until.is_none()) {
+                    Some(index_progress) => {
+                        index_progress.schedule(block_number..next_height).await
+                    }
+                    None => None,
+                };
+
                 Ok(conc(
                     (block_number..next_height)
                         .map(|block_number| {
@@ -375,6 +633,15 @@ impl Module {
                                 ModuleCall::from(FetchGetLogs { block_number }),
                             ))
                         })
+                        .chain(gap.map(|gap| {
+                            call(PluginMessage::new(
+                                self.plugin_name(),
+                                ModuleCall::from(FetchBlocks {
+                                    block_number: gap.start,
+                                    until: Some(gap.end - 1),
+                                }),
+                            ))
+                        }))
                         .chain([continuation(next_height)]),
                 ))
             }
@@ -392,22 +659,52 @@ impl Module {
 
     #[instrument(skip_all, fields(%block_number))]
     async fn fetch_get_logs(&self, block_number: u64) -> RpcResult<Op<VoyagerMessage>> {
-        debug!("fetching logs in execution block");
+        // blocks indexed ahead of finality are pinned to their hash, such that reorgs can be detected
+        let (block_hash, reorged) = match self.confirmations {
+            Some(_) => {
+                let (block_hash, reorged) = self.check_reorg(block_number).await?;
+                (Some(block_hash), reorged)
+            }
+            None => (None, vec![]),
+        };
 
-        let logs = self
-            .provider
-            .get_logs(
-                &Filter::new()
-                    .address(alloy::primitives::Address::from(
-                        self.ibc_handler_address.get(),
-                    ))
-                    .from_block(block_number)
-                    .to_block(block_number),
-            )
-            .await
-            .map_err(RpcError::retryable(format!(
-                "error fetching logs in block {block_number}",
-            )))?;
+        let filter = Filter::new().address(alloy::primitives::Address::from(
+            self.ibc_handler_address.get(),
+        ));
+
+        let filter = match block_hash {
+            Some(block_hash) => {
+                filter.at_block_hash(alloy::primitives::B256::from(block_hash.get()))
+            }
+            None => filter.from_block(block_number).to_block(block_number),
+        };
+
+        let logs = match self
+            .subscription
+            .as_ref()
+            .and_then(|subscription| subscription.get(block_number))
+            .filter(|logs| {
+                block_hash.is_none_or(|block_hash| {
+                    logs.iter()
+                        .all(|log| log.block_hash.map(H256::from) == Some(block_hash))
+                })
+            }) {
+            Some(logs) => {
+                debug!("using subscribed logs in execution block");
+
+                logs
+            }
+            None => {
+                debug!("fetching logs in execution block");
+
+                self.provider
+                    .get_logs(&filter)
+                    .await
+                    .map_err(RpcError::retryable(format!(
+                        "error fetching logs in block {block_number}",
+                    )))?
+            }
+        };
 
         info!(logs_count = logs.len(), "found logs");
 
@@ -539,7 +836,77 @@ impl Module {
             })
         });
 
-        Ok(conc(events))
+        if let Some(index_progress) = &self.index_progress {
+            index_progress.complete(block_number).await;
+        }
+
+        Ok(conc(events.chain(reorged.into_iter().map(
+            |block_number| {
+                call(PluginMessage::new(
+                    self.plugin_name(),
+                    ModuleCall::from(FetchGetLogs { block_number }),
+                ))
+            },
+        ))))
+    }
+
+    /// Record the hash of the block at `block_number`, and walk back the previously indexed blocks
+    /// if its parent is not the block indexed at `block_number - 1`. Returns the hash of the block,
+    /// and the heights that were reorged out and must be reindexed.
+    ///
+    /// Events already emitted for the reorged out blocks cannot be retracted; any messages built from
+    /// them will fail to verify on the counterparty.
+    async fn check_reorg(&self, block_number: u64) -> RpcResult<(H256, Vec<u64>)> {
+        let header = self.block_header(block_number).await?;
+        let block_hash = H256::from(header.hash);
+
+        if !self
+            .indexed_blocks
+            .record(block_number, block_hash, header.parent_hash.into())
+        {
+            return Ok((block_hash, vec![]));
+        }
+
+        let mut reorged = vec![];
+
+        let mut number = block_number;
+        while let Some(parent_number) = number.checked_sub(1) {
+            number = parent_number;
+
+            let Some(indexed_hash) = self.indexed_blocks.get(number) else {
+                break;
+            };
+
+            let canonical_hash = H256::from(self.block_header(number).await?.hash);
+
+            if indexed_hash == canonical_hash {
+                break;
+            }
+
+            warn!(
+                %number,
+                %indexed_hash,
+                %canonical_hash,
+                "indexed block was reorged out, reindexing"
+            );
+
+            self.indexed_blocks.remove(number);
+            reorged.push(number);
+        }
+
+        Ok((block_hash, reorged))
+    }
+
+    async fn block_header(&self, block_number: u64) -> RpcResult<Header> {
+        Ok(self
+            .provider
+            .get_block_by_number(block_number.into())
+            .await
+            .map_err(RpcError::retryable(format!(
+                "error fetching block {block_number}"
+            )))?
+            .ok_or_else(|| RpcError::missing_state(format_args!("block {block_number} not found")))?
+            .header)
     }
 
     #[instrument(skip_all, fields(%block_number, %tx_hash))]
@@ -0,0 +1,60 @@
+use std::{collections::BTreeMap, sync::Mutex};
+
+use unionlabs::primitives::H256;
+
+/// The hashes of the blocks indexed ahead of finality, used to detect reorgs of already indexed
+/// heights.
+#[derive(Debug)]
+pub struct IndexedBlocks {
+    hashes: Mutex<BTreeMap<u64, H256>>,
+    max_depth: u64,
+}
+
+impl IndexedBlocks {
+    /// Track the hashes of the last `max_depth` indexed blocks. Reorgs deeper than this are not
+    /// detected.
+    pub fn new(max_depth: u64) -> Self {
+        Self {
+            hashes: Mutex::new(BTreeMap::new()),
+            max_depth,
+        }
+    }
+
+    /// Record the block `hash` indexed at `number`. Returns `true` if the parent of the block is
+    /// not the block that was previously indexed at `number - 1`, i.e. if the chain has been
+    /// reorged since.
+    pub fn record(&self, number: u64, hash: H256, parent_hash: H256) -> bool {
+        let mut hashes = self.hashes.lock().expect("mutex is not poisoned; qed;");
+
+        let reorged = number
+            .checked_sub(1)
+            .and_then(|parent_number| hashes.get(&parent_number))
+            .is_some_and(|indexed_parent_hash| *indexed_parent_hash != parent_hash);
+
+        hashes.insert(number, hash);
+
+        if let Some(latest) = hashes.last_key_value().map(|(number, _)| *number) {
+            *hashes = hashes.split_off(&latest.saturating_sub(self.max_depth));
+        }
+
+        reorged
+    }
+
+    /// The hash of the block indexed at `number`, if it is still tracked.
+    pub fn get(&self, number: u64) -> Option<H256> {
+        self.hashes
+            .lock()
+            .expect("mutex is not poisoned; qed;")
+            .get(&number)
+            .copied()
+    }
+
+    /// Forget the block indexed at `number`, such that it is not compared against once it is
+    /// reindexed.
+    pub fn remove(&self, number: u64) {
+        self.hashes
+            .lock()
+            .expect("mutex is not poisoned; qed;")
+            .remove(&number);
+    }
+}
@@ -24,7 +24,7 @@ use unionlabs::{ibc::core::client::height::Height, never::Never, primitives::H25
 use voyager_sdk::{
     ExtensionsExt, VoyagerClient,
     anyhow::{self, bail},
-    hook::simple_take_filter,
+    hook::{IndexRangeHook, simple_take_filter},
     into_value,
     message::{
         PluginMessage, VoyagerMessage,
@@ -34,7 +34,7 @@ use voyager_sdk::{
     plugin::Plugin,
     primitives::{ChainId, ClientType, QueryHeight},
     rpc::{PluginServer, RpcError, RpcErrorExt, RpcResult, types::PluginInfo},
-    vm::{Op, call, conc, data, noop, pass::PassResult, seq},
+    vm::{Op, Visit, call, conc, data, noop, pass::PassResult, seq},
 };
 
 use crate::{
@@ -187,16 +187,21 @@ impl PluginServer<ModuleCall, Never> for Module {
                             }),
                         ))
                     }
-                    Op::Call(Call::IndexRange(fetch)) if fetch.chain_id == self.chain_id => {
-                        call(PluginMessage::new(
-                            self.plugin_name(),
-                            ModuleCall::from(FetchBlocks {
-                                height: fetch.range.from_height(),
-                                until: Some(fetch.range.to_height()),
-                            }),
-                        ))
+                    mut op => {
+                        // also handles ranges nested in other messages, i.e. a backfill promise
+                        IndexRangeHook::new(&self.chain_id, |fetch| {
+                            Call::Plugin(PluginMessage::new(
+                                self.plugin_name(),
+                                ModuleCall::from(FetchBlocks {
+                                    height: fetch.range.from_height(),
+                                    until: Some(fetch.range.to_height()),
+                                }),
+                            ))
+                        })
+                        .visit_op(&mut op);
+
+                        op
                     }
-                    op => op,
                 })
                 .enumerate()
                 .map(|(i, op)| (vec![i], op))
@@ -1,8 +1,9 @@
 // #![warn(clippy::unwrap_used)]
 
-use std::{cmp::Ordering, collections::VecDeque};
+use std::{cmp::Ordering, collections::VecDeque, sync::Arc};
 
 use cainome_cairo_serde::CairoSerde;
+use index_checkpoint::{CheckpointConfig, IndexProgress};
 use jsonrpsee::{Extensions, core::async_trait};
 use serde::{Deserialize, Serialize};
 use starknet::{
@@ -15,17 +16,18 @@ use unionlabs::{ibc::core::client::height::Height, never::Never, primitives::H25
 use voyager_sdk::{
     ExtensionsExt, VoyagerClient,
     anyhow::{self, bail},
-    hook::simple_take_filter,
+    hook::{IndexRangeHook, simple_take_filter},
     into_value,
     message::{
         PluginMessage, VoyagerMessage,
-        call::{Call, WaitForHeight},
+        call::{Call, Index, WaitForHeight},
         data::{Data, EventProvableHeight},
     },
     plugin::Plugin,
     primitives::ChainId,
     rpc::{PluginServer, RpcError, RpcResult, types::PluginInfo},
-    vm::{Op, call, conc, pass::PassResult, seq},
+    serde_json::Value,
+    vm::{Op, Visit, call, conc, pass::PassResult, seq},
 };
 
 use crate::{
@@ -53,6 +55,8 @@ pub struct Module {
     pub index_trivial_events: bool,
 
     pub ibc_host_contract_address: Felt,
+
+    pub index_progress: Option<Arc<IndexProgress>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -71,6 +75,10 @@ pub struct Config {
 
     #[serde(default)]
     pub ibc_host_contract_address: Felt,
+
+    /// Persist the last fully indexed height, and alert when indexing lags behind the chain.
+    #[serde(default)]
+    pub checkpoint: Option<CheckpointConfig>,
 }
 
 fn default_chunk_block_fetch_size() -> u64 {
@@ -103,12 +111,42 @@ impl Plugin for Module {
             );
         }
 
+        let index_progress = match config.checkpoint {
+            Some(checkpoint_config) => {
+                let index_progress =
+                    Arc::new(IndexProgress::new(chain_id.to_string(), &checkpoint_config).await?);
+
+                if let (Some(rest_url), Some(checkpoint)) = (
+                    checkpoint_config.resume_rest_url,
+                    index_progress.checkpoint(),
+                ) {
+                    info!(%checkpoint, "resuming indexing from checkpoint");
+
+                    let op = call::<VoyagerMessage>(Index {
+                        chain_id: chain_id.clone(),
+                        start_height: Height::new(checkpoint + 1),
+                    });
+
+                    let idempotency_key =
+                        index_checkpoint::resume_idempotency_key(chain_id.as_str(), checkpoint);
+
+                    tokio::spawn(async move {
+                        index_checkpoint::enqueue(&rest_url, &op, &idempotency_key).await
+                    });
+                }
+
+                Some(index_progress)
+            }
+            None => None,
+        };
+
         Ok(Self {
             client,
             chain_id,
             chunk_block_fetch_size: config.chunk_block_fetch_size,
             index_trivial_events: config.index_trivial_events,
             ibc_host_contract_address: config.ibc_host_contract_address,
+            index_progress,
         })
     }
 
@@ -176,16 +214,21 @@ impl PluginServer<ModuleCall, Never> for Module {
                             }),
                         ))
                     }
-                    Op::Call(Call::IndexRange(fetch)) if fetch.chain_id == self.chain_id => {
-                        call(PluginMessage::new(
-                            self.plugin_name(),
-                            ModuleCall::from(FetchBlocks {
-                                height: fetch.range.from_height().height(),
-                                until: Some(fetch.range.to_height().height()),
-                            }),
-                        ))
+                    mut op => {
+                        // also handles ranges nested in other messages, i.e. a backfill promise
+                        IndexRangeHook::new(&self.chain_id, |fetch| {
+                            Call::Plugin(PluginMessage::new(
+                                self.plugin_name(),
+                                ModuleCall::from(FetchBlocks {
+                                    height: fetch.range.from_height().height(),
+                                    until: Some(fetch.range.to_height().height()),
+                                }),
+                            ))
+                        })
+                        .visit_op(&mut op);
+
+                        op
                     }
-                    op => op,
                 })
                 .enumerate()
                 .map(|(i, op)| (vec![i], op))
@@ -209,7 +252,15 @@ impl PluginServer<ModuleCall, Never> for Module {
             ModuleCall::FetchBlocks(FetchBlocks { height, until }) => {
                 self.fetch_blocks(e.voyager_client()?, height, until).await
             }
-            ModuleCall::FetchBlock(FetchBlock { height }) => self.fetch_block(height).await,
+            ModuleCall::FetchBlock(FetchBlock { height }) => {
+                let op = self.fetch_block(height).await?;
+
+                if let Some(index_progress) = &self.index_progress {
+                    index_progress.complete(height).await;
+                }
+
+                Ok(op)
+            }
             ModuleCall::MakeChainEvent(MakeChainEvent {
                 block_number,
                 tx_hash,
@@ -220,6 +271,20 @@ impl PluginServer<ModuleCall, Never> for Module {
             }
         }
     }
+
+    #[instrument(skip_all, fields(chain_id = %self.chain_id))]
+    async fn custom(&self, _: &Extensions, method: String, _: Vec<Value>) -> RpcResult<Value> {
+        match method.as_str() {
+            "indexingStatus" => Ok(into_value(
+                self.index_progress
+                    .as_ref()
+                    .map(|index_progress| index_progress.status()),
+            )),
+            _ => Err(RpcError::fatal_from_message(format!(
+                "unknown method {method}"
+            ))),
+        }
+    }
 }
 
 impl Module {
@@ -251,6 +316,10 @@ impl Module {
 
         info!(%latest_height, %height, ?until, "fetching blocks");
 
+        if let Some(index_progress) = &self.index_progress {
+            index_progress.latest_height(latest_height);
+        }
+
         let continuation = |next_height: u64| {
             seq([
                 // TODO: Make this a config param
@@ -286,6 +355,12 @@ impl Module {
                     "batch fetching blocks in range {height}..{next_height}"
                 );
 
+                // only the unbounded unfold is checkpointed, ranged fetches are backfills
+                let gap = match self.index_progress.as_ref().filter(|_| until.is_none()) {
+                    Some(index_progress) => index_progress.schedule(height..next_height).await,
+                    None => None,
+                };
+
                 Ok(conc(
                     (height..next_height)
                         .map(|h| {
@@ -294,6 +369,15 @@ impl Module {
                                 ModuleCall::from(FetchBlock { height: h }),
                             ))
                         })
+                        .chain(gap.map(|gap| {
+                            call(PluginMessage::new(
+                                self.plugin_name(),
+                                ModuleCall::from(FetchBlocks {
+                                    height: gap.start,
+                                    until: Some(gap.end - 1),
+                                }),
+                            ))
+                        }))
                         .chain([continuation(next_height)]),
                 ))
             }
@@ -0,0 +1,34 @@
+use galois_rpc::prove_request::ProveRequest;
+use ibc_union_spec::ClientId;
+use macros::model;
+use serde_json::Value;
+use unionlabs::{ibc::core::client::height::Height, primitives::Bytes};
+use voyager_sdk::primitives::{ClientType, IbcInterface};
+
+#[model]
+#[allow(clippy::large_enum_variant)]
+pub enum ModuleCall {
+    CheckHeader(CheckHeader),
+    ProveTrackedHeader(ProveTrackedHeader),
+}
+
+/// Check a header that was relayed to `client_id` against the tracked chain.
+#[model]
+pub struct CheckHeader {
+    pub client_id: ClientId,
+    /// The encoded header, as submitted in the `MsgUpdateClient`.
+    pub client_message: Bytes,
+}
+
+/// Prove the header of the tracked chain that conflicts with `header_a`, and submit both as
+/// misbehaviour evidence to `client_id`. Polls the prover until the proof is generated.
+#[model]
+pub struct ProveTrackedHeader {
+    pub client_id: ClientId,
+    pub client_type: ClientType,
+    pub ibc_interface: IbcInterface,
+    pub trusted_height: Height,
+    /// The decoded header that was relayed to `client_id`.
+    pub header_a: Value,
+    pub request: ProveRequest,
+}
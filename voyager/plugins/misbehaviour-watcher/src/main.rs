@@ -0,0 +1,1039 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    num::NonZeroU64,
+};
+
+use alloy::{
+    network::{AnyNetwork, AnyRpcBlock},
+    providers::{DynProvider, Provider, ProviderBuilder},
+};
+use cometbft_types::{
+    crypto::public_key::PublicKey,
+    types::{
+        canonical_block_id::CanonicalBlockId, canonical_part_set_header::CanonicalPartSetHeader,
+        commit::Commit, commit_sig::CommitSig, signed_msg_type::SignedMsgType,
+        simple_validator::SimpleValidator, validator::Validator,
+    },
+};
+use cometbls_light_client_types::LightHeader;
+use galois_rpc::{
+    canonical_vote::CanonicalVote,
+    poll_request::PollRequest,
+    poll_response::{PollResponse, ProveRequestDone, ProveRequestFailed},
+    prove_request::ProveRequest,
+    validator_set_commit::ValidatorSetCommit,
+};
+use ibc_union_spec::{
+    ClientId, IbcUnion,
+    datagram::{Datagram, MsgMisbehaviour},
+};
+use jsonrpsee::{Extensions, core::async_trait};
+use num_bigint::BigUint;
+use parlia_types::ParliaHeader;
+use rpc_failover::FailoverConfig;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{debug, info, instrument, warn};
+use unionlabs::{
+    bounded::BoundedI64,
+    google::protobuf::timestamp::Timestamp,
+    ibc::core::client::height::Height,
+    never::Never,
+    primitives::{Bytes, H256, encoding::HexUnprefixed},
+};
+use voyager_sdk::{
+    DefaultCmd, ExtensionsExt, VoyagerClient, anyhow, into_value,
+    message::{
+        PluginMessage, VoyagerMessage,
+        call::{Call, SubmitTx},
+        data::{Data, IbcDatagram},
+    },
+    plugin::Plugin,
+    primitives::{ChainId, ClientType, IbcInterface, IbcSpec},
+    rpc::{PluginServer, RpcError, RpcResult, types::PluginInfo},
+    vm::{Op, Visit, call, conc, defer, noop, now, pass::PassResult, seq},
+};
+
+use crate::call::{CheckHeader, ModuleCall, ProveTrackedHeader};
+
+pub mod call;
+
+#[tokio::main]
+async fn main() {
+    Module::run().await
+}
+
+#[derive(Debug, Clone)]
+pub struct Module {
+    pub chain_id: ChainId,
+    pub client_ids: Vec<ClientId>,
+    pub tracked_chain: TrackedChain,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// The chain that the watched clients are on.
+    pub chain_id: ChainId,
+    /// The ibc-union clients on `chain_id` to watch. All of these clients must track
+    /// `tracked_chain`.
+    pub client_ids: Vec<ClientId>,
+    /// The chain tracked by the watched clients. Relayed headers are checked against, and the
+    /// misbehaviour evidence is built from, the endpoints configured here, so they should be
+    /// independent of the endpoints used by the client update plugin for that chain.
+    pub tracked_chain: TrackedChainConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type", deny_unknown_fields)]
+pub enum TrackedChainConfig {
+    /// A CometBFT chain, tracked by cometbls and tendermint clients. Evidence is only submitted
+    /// to cometbls clients, conflicting headers relayed to tendermint clients fail with a fatal
+    /// error.
+    Cometbft {
+        rpc_url: String,
+        /// Fallback endpoints, health checks and quorum reads for `rpc_url`.
+        #[serde(default)]
+        rpc_failover: FailoverConfig,
+        /// Galois prover endpoints, used to prove the tracked header for the misbehaviour
+        /// evidence of cometbls clients.
+        #[serde(default)]
+        prover_endpoints: Vec<String>,
+    },
+    /// A parlia chain, tracked by parlia clients.
+    Parlia {
+        rpc_url: String,
+        /// Fallback endpoints, health checks and quorum reads for `rpc_url`.
+        #[serde(default)]
+        rpc_failover: FailoverConfig,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub enum TrackedChain {
+    Cometbft {
+        client: cometbft_rpc::Client,
+        prover_endpoints: Vec<String>,
+    },
+    Parlia {
+        provider: DynProvider<AnyNetwork>,
+    },
+}
+
+impl Plugin for Module {
+    type Call = ModuleCall;
+    type Callback = Never;
+
+    type Config = Config;
+    type Cmd = DefaultCmd;
+
+    async fn new(config: Self::Config) -> anyhow::Result<Self> {
+        let tracked_chain = match config.tracked_chain {
+            TrackedChainConfig::Cometbft {
+                rpc_url,
+                rpc_failover,
+                prover_endpoints,
+            } => TrackedChain::Cometbft {
+                client: cometbft_rpc::Client::new_with_failover(rpc_url, &rpc_failover).await?,
+                prover_endpoints,
+            },
+            TrackedChainConfig::Parlia {
+                rpc_url,
+                rpc_failover,
+            } => TrackedChain::Parlia {
+                provider: DynProvider::new(
+                    ProviderBuilder::new()
+                        .network::<AnyNetwork>()
+                        .connect_client(
+                            rpc_failover::alloy::connect_client(&rpc_url, &rpc_failover).await?,
+                        ),
+                ),
+            },
+        };
+
+        Ok(Self {
+            chain_id: config.chain_id,
+            client_ids: config.client_ids,
+            tracked_chain,
+        })
+    }
+
+    fn info(config: Self::Config) -> PluginInfo {
+        let client_ids = config
+            .client_ids
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(",");
+
+        PluginInfo {
+            name: plugin_name(&config.chain_id),
+            // copy all transactions that update one of the watched clients, the transaction plugin
+            // still submits them as usual
+            interest_filter: format!(
+                r#"
+if [
+    .. | . as $o
+    | $o."@type"? == "submit_tx"
+        and $o."@value".chain_id == "{chain_id}"
+        and ([
+            $o."@value".datagrams[]
+            | select(.ibc_spec_id == "{ibc_union_id}" and .datagram."@type" == "update_client")
+            | .datagram."@value".client_id
+        ] | any(. as $c | [{client_ids}] | any(. == $c)))
+] | any
+then
+    false
+else
+    null
+end
+"#,
+                chain_id = config.chain_id,
+                ibc_union_id = IbcUnion::ID,
+            ),
+        }
+    }
+
+    async fn cmd(_config: Self::Config, cmd: Self::Cmd) {
+        match cmd {}
+    }
+}
+
+fn plugin_name(chain_id: &ChainId) -> String {
+    pub const PLUGIN_NAME: &str = env!("CARGO_PKG_NAME");
+
+    format!("{PLUGIN_NAME}/{}", chain_id)
+}
+
+impl Module {
+    fn plugin_name(&self) -> String {
+        plugin_name(&self.chain_id)
+    }
+
+    fn cometbft_client(&self) -> RpcResult<&cometbft_rpc::Client> {
+        match &self.tracked_chain {
+            TrackedChain::Cometbft { client, .. } => Ok(client),
+            TrackedChain::Parlia { .. } => Err(RpcError::fatal_from_message(
+                "the tracked chain is not a cometbft chain",
+            )),
+        }
+    }
+
+    fn parlia_provider(&self) -> RpcResult<&DynProvider<AnyNetwork>> {
+        match &self.tracked_chain {
+            TrackedChain::Parlia { provider } => Ok(provider),
+            TrackedChain::Cometbft { .. } => Err(RpcError::fatal_from_message(
+                "the tracked chain is not a parlia chain",
+            )),
+        }
+    }
+
+    /// Fetch the block at `number` from the tracked parlia chain.
+    async fn tracked_parlia_header(&self, number: u64) -> RpcResult<ParliaHeader> {
+        self.parlia_provider()?
+            .get_block(number.into())
+            .await
+            .map_err(RpcError::retryable("error fetching block"))?
+            .ok_or_else(|| RpcError::missing_state("error fetching block: block not found"))
+            .and_then(convert_header)
+    }
+
+    #[instrument(skip_all, fields(chain_id = %self.chain_id, %client_id))]
+    async fn check_header(
+        &self,
+        voyager_client: &VoyagerClient,
+        client_id: ClientId,
+        client_message: Bytes,
+    ) -> RpcResult<Op<VoyagerMessage>> {
+        let client_info = voyager_client
+            .client_info::<IbcUnion>(self.chain_id.clone(), client_id)
+            .await?;
+
+        let header = voyager_client
+            .decode_header::<IbcUnion, Value>(
+                client_info.client_type.clone(),
+                client_info.ibc_interface.clone(),
+                client_message,
+            )
+            .await?;
+
+        match parse_header(&client_info.client_type, &header)? {
+            Some(RelayedHeader::Cometbft {
+                summary,
+                trusted_height,
+            }) => {
+                let tracked = self
+                    .cometbft_client()?
+                    .commit(Some(non_zero_height(summary.height)?))
+                    .await
+                    .map_err(RpcError::retryable("error fetching commit"))?
+                    .signed_header;
+
+                if summary == HeaderSummary::from(&tracked.header) {
+                    debug!(
+                        height = summary.height,
+                        "relayed header matches the tracked chain"
+                    );
+
+                    return Ok(noop());
+                }
+
+                warn!(
+                    height = summary.height,
+                    relayed = ?summary,
+                    tracked = ?HeaderSummary::from(&tracked.header),
+                    "relayed header conflicts with the tracked chain"
+                );
+
+                // the tendermint light client does not support misbehaviour, fail such that the
+                // conflict is kept in the failed queue for an operator instead of only being logged
+                if client_info.client_type.as_str() != ClientType::COMETBLS_GROTH16 {
+                    return Err(RpcError::fatal_from_message(format!(
+                        "relayed header at height {} conflicts with the tracked chain, but \
+                        misbehaviour is not supported by client type {}",
+                        summary.height, client_info.client_type
+                    )));
+                }
+
+                let request = self.prove_request(trusted_height, tracked).await?;
+
+                Ok(call(PluginMessage::new(
+                    self.plugin_name(),
+                    ModuleCall::ProveTrackedHeader(ProveTrackedHeader {
+                        client_id,
+                        client_type: client_info.client_type,
+                        ibc_interface: client_info.ibc_interface,
+                        trusted_height,
+                        header_a: header,
+                        request,
+                    }),
+                )))
+            }
+            Some(RelayedHeader::Parlia(relayed)) => {
+                let [source, target, attestation] = attestation_chain(&relayed)?;
+
+                let mut tracked = vec![];
+                for header in [source, target, attestation] {
+                    tracked.push(self.tracked_parlia_header(block_number(header)?).await?);
+                }
+
+                let tracked = <[ParliaHeader; 3]>::try_from(tracked)
+                    .expect("exactly three headers were fetched; qed;");
+
+                let Some(header_b) = parlia_conflict(&relayed, tracked) else {
+                    debug!(
+                        number = block_number(source)?,
+                        "relayed header matches the tracked chain"
+                    );
+
+                    return Ok(noop());
+                };
+
+                warn!(
+                    number = block_number(source)?,
+                    relayed = %source.hash(),
+                    tracked = %header_b.chain[0].hash(),
+                    "relayed header conflicts with the tracked chain"
+                );
+
+                self.submit_misbehaviour(
+                    voyager_client,
+                    client_id,
+                    client_info.client_type,
+                    client_info.ibc_interface,
+                    header,
+                    into_value(header_b),
+                )
+                .await
+            }
+            None => {
+                warn!(
+                    client_type = %client_info.client_type,
+                    "client type is not supported by the misbehaviour watcher, ignoring header"
+                );
+
+                Ok(noop())
+            }
+        }
+    }
+
+    /// Build the request to prove `signed_header` of the tracked chain from `trusted_height`.
+    async fn prove_request(
+        &self,
+        trusted_height: Height,
+        signed_header: cometbft_types::types::signed_header::SignedHeader,
+    ) -> RpcResult<ProveRequest> {
+        let client = self.cometbft_client()?;
+
+        let trusted_validators = client
+            .all_validators(Some(non_zero_height(trusted_height.increment().height())?))
+            .await
+            .map_err(RpcError::retryable("error fetching trusted validators"))?
+            .validators;
+
+        let untrusted_validators = client
+            .all_validators(Some(non_zero_height(
+                signed_header.header.height.inner() as u64
+            )?))
+            .await
+            .map_err(RpcError::retryable("error fetching untrusted validators"))?
+            .validators;
+
+        Ok(ProveRequest {
+            vote: CanonicalVote {
+                ty: SignedMsgType::Precommit,
+                height: signed_header.commit.height,
+                round: BoundedI64::new_const(signed_header.commit.round.inner().into())
+                    .expect("0..=i32::MAX can be converted to 0..=i64::MAX safely"),
+                block_id: CanonicalBlockId {
+                    hash: signed_header.commit.block_id.hash.unwrap_or_default(),
+                    part_set_header: CanonicalPartSetHeader {
+                        total: signed_header.commit.block_id.part_set_header.total,
+                        hash: signed_header
+                            .commit
+                            .block_id
+                            .part_set_header
+                            .hash
+                            .unwrap_or_default(),
+                    },
+                },
+                chain_id: signed_header.header.chain_id.clone(),
+            },
+            trusted_commit: validator_set_commit(trusted_validators, &signed_header.commit)?,
+            untrusted_commit: validator_set_commit(untrusted_validators, &signed_header.commit)?,
+            untrusted_header: signed_header.header,
+        })
+    }
+
+    #[instrument(
+        skip_all,
+        fields(
+            chain_id = %self.chain_id,
+            client_id = %msg.client_id,
+            height = %msg.request.untrusted_header.height,
+        )
+    )]
+    async fn prove_tracked_header(
+        &self,
+        voyager_client: &VoyagerClient,
+        msg: ProveTrackedHeader,
+    ) -> RpcResult<Op<VoyagerMessage>> {
+        let TrackedChain::Cometbft {
+            prover_endpoints, ..
+        } = &self.tracked_chain
+        else {
+            return Err(RpcError::fatal_from_message(
+                "the tracked chain is not a cometbft chain",
+            ));
+        };
+
+        if prover_endpoints.is_empty() {
+            return Err(RpcError::fatal_from_message(
+                "no prover endpoints are configured, unable to prove the tracked header",
+            ));
+        }
+
+        let prover_endpoint = &prover_endpoints
+            [msg.request.untrusted_header.height.inner() as usize % prover_endpoints.len()];
+
+        let response = galois_rpc::Client::connect(prover_endpoint)
+            .await
+            .map_err(RpcError::retryable("error connecting to prover endpoint"))?
+            .poll(PollRequest {
+                request: msg.request.clone(),
+            })
+            .await;
+
+        match response {
+            Ok(PollResponse::Pending) => {}
+            Err(status) if status.message() == "busy_building" => {}
+            Err(status) => return Err(RpcError::retryable("prove request failed")(status)),
+            Ok(PollResponse::Failed(ProveRequestFailed { message })) => {
+                return Err(RpcError::fatal_from_message(format!(
+                    "prove request failed: {message}"
+                )));
+            }
+            Ok(PollResponse::Done(ProveRequestDone { response })) => {
+                info!(prover = %prover_endpoint, "proof generated");
+
+                let header_b = cometbls_header(
+                    &msg.request,
+                    msg.trusted_height,
+                    response.proof.evm_proof.into(),
+                );
+
+                return self
+                    .submit_misbehaviour(
+                        voyager_client,
+                        msg.client_id,
+                        msg.client_type,
+                        msg.ibc_interface,
+                        msg.header_a,
+                        into_value(header_b),
+                    )
+                    .await;
+            }
+        }
+
+        debug!("proof pending");
+
+        Ok(seq([
+            defer(now() + 1),
+            call(PluginMessage::new(
+                self.plugin_name(),
+                ModuleCall::ProveTrackedHeader(msg),
+            )),
+        ]))
+    }
+
+    /// Encode the misbehaviour evidence for `client_id` from the relayed `header_a` and the
+    /// tracked `header_b`, and submit it to the host chain.
+    async fn submit_misbehaviour(
+        &self,
+        voyager_client: &VoyagerClient,
+        client_id: ClientId,
+        client_type: ClientType,
+        ibc_interface: IbcInterface,
+        header_a: Value,
+        header_b: Value,
+    ) -> RpcResult<Op<VoyagerMessage>> {
+        let client_message = voyager_client
+            .encode_misbehaviour::<IbcUnion>(client_type, ibc_interface, header_a, header_b)
+            .await?;
+
+        info!(%client_id, "submitting misbehaviour");
+
+        Ok(call(SubmitTx {
+            chain_id: self.chain_id.clone(),
+            datagrams: vec![IbcDatagram::new::<IbcUnion>(MsgMisbehaviour {
+                client_id,
+                client_message,
+            })],
+        }))
+    }
+}
+
+fn non_zero_height(height: u64) -> RpcResult<NonZeroU64> {
+    NonZeroU64::new(height)
+        .ok_or_else(|| RpcError::fatal_from_message("header height must be non-zero"))
+}
+
+/// A header relayed to one of the watched clients.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RelayedHeader {
+    /// A cometbls or tendermint header, along with the height it is trusted from.
+    Cometbft {
+        summary: HeaderSummary,
+        trusted_height: Height,
+    },
+    Parlia(parlia_light_client_types::Header),
+}
+
+/// The fields of a CometBFT header that both the cometbls and tendermint light clients commit to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderSummary {
+    pub height: u64,
+    pub time: Timestamp,
+    pub validators_hash: H256<HexUnprefixed>,
+    pub next_validators_hash: H256<HexUnprefixed>,
+    pub app_hash: H256<HexUnprefixed>,
+}
+
+impl From<&cometbft_types::types::header::Header> for HeaderSummary {
+    fn from(header: &cometbft_types::types::header::Header) -> Self {
+        Self {
+            height: header.height.inner() as u64,
+            time: header.time,
+            validators_hash: header.validators_hash,
+            next_validators_hash: header.next_validators_hash,
+            app_hash: header.app_hash,
+        }
+    }
+}
+
+impl From<&LightHeader> for HeaderSummary {
+    fn from(header: &LightHeader) -> Self {
+        Self {
+            height: header.height.inner() as u64,
+            time: header.time,
+            validators_hash: header.validators_hash,
+            next_validators_hash: header.next_validators_hash,
+            app_hash: header.app_hash,
+        }
+    }
+}
+
+/// Parse a header decoded by the client module of `client_type`. Returns `None` if the client type
+/// is not supported by the watcher. This includes ethereum clients, since the beacon api only
+/// serves the latest finality update, so the conflicting update for the relayed slot can not be
+/// rebuilt from the tracked chain.
+fn parse_header(client_type: &ClientType, header: &Value) -> RpcResult<Option<RelayedHeader>> {
+    match client_type.as_str() {
+        ClientType::COMETBLS_GROTH16 => {
+            let header =
+                serde_json::from_value::<cometbls_light_client_types::Header>(header.clone())
+                    .map_err(RpcError::fatal("unable to deserialize cometbls header"))?;
+
+            Ok(Some(RelayedHeader::Cometbft {
+                summary: HeaderSummary::from(&header.signed_header),
+                trusted_height: header.trusted_height,
+            }))
+        }
+        ClientType::TENDERMINT => {
+            let header =
+                serde_json::from_value::<tendermint_light_client_types::Header>(header.clone())
+                    .map_err(RpcError::fatal("unable to deserialize tendermint header"))?;
+
+            Ok(Some(RelayedHeader::Cometbft {
+                summary: HeaderSummary::from(&header.signed_header.header),
+                trusted_height: header.trusted_height,
+            }))
+        }
+        ClientType::PARLIA => serde_json::from_value(header.clone())
+            .map(|header| Some(RelayedHeader::Parlia(header)))
+            .map_err(RpcError::fatal("unable to deserialize parlia header")),
+        _ => Ok(None),
+    }
+}
+
+/// The `[source, target, attestation]` headers at the end of the chain of a parlia header.
+fn attestation_chain(header: &parlia_light_client_types::Header) -> RpcResult<[&ParliaHeader; 3]> {
+    match header.chain.as_slice() {
+        [.., source, target, attestation] => Ok([source, target, attestation]),
+        _ => Err(RpcError::fatal_from_message(
+            "header chain must contain at least the source, target and attestation",
+        )),
+    }
+}
+
+fn block_number(header: &ParliaHeader) -> RpcResult<u64> {
+    header
+        .number
+        .try_into()
+        .map_err(|_| RpcError::fatal_from_message("block number overflows u64"))
+}
+
+/// Compare the attestation chain of a `relayed` parlia header against the `tracked` blocks at the
+/// same heights. If the relayed header finalizes a different source or target, returns the header
+/// built from the tracked blocks, which together with `relayed` is the misbehaviour evidence.
+fn parlia_conflict(
+    relayed: &parlia_light_client_types::Header,
+    tracked: [ParliaHeader; 3],
+) -> Option<parlia_light_client_types::Header> {
+    let [source, target, _] = attestation_chain(relayed).ok()?;
+
+    (source.hash() != tracked[0].hash() || target.hash() != tracked[1].hash()).then(|| {
+        parlia_light_client_types::Header {
+            trusted_valset_epoch_number: relayed.trusted_valset_epoch_number,
+            chain: tracked.into(),
+        }
+    })
+}
+
+/// Build the cometbls header for the untrusted header of `request`, proven by
+/// `zero_knowledge_proof`.
+fn cometbls_header(
+    request: &ProveRequest,
+    trusted_height: Height,
+    zero_knowledge_proof: Bytes,
+) -> cometbls_light_client_types::Header {
+    cometbls_light_client_types::Header {
+        signed_header: LightHeader {
+            height: request.untrusted_header.height,
+            time: request.untrusted_header.time,
+            validators_hash: request.untrusted_header.validators_hash,
+            next_validators_hash: request.untrusted_header.next_validators_hash,
+            app_hash: request.untrusted_header.app_hash,
+        },
+        trusted_height,
+        zero_knowledge_proof,
+    }
+}
+
+/// The commitment of `validators` to the signatures of `commit`, as expected by the prover.
+fn validator_set_commit(
+    mut validators: Vec<Validator>,
+    commit: &Commit,
+) -> RpcResult<ValidatorSetCommit> {
+    // validators must be sorted to match the root, by voting power then address
+    validators.sort_by(|a, b| {
+        b.voting_power
+            .cmp(&a.voting_power)
+            .then_with(|| a.address.cmp(&b.address))
+    });
+
+    let validators_map = validators
+        .iter()
+        .enumerate()
+        .map(|(i, v)| (v.address, i))
+        .collect::<HashMap<_, _>>();
+
+    // the bitmap is a public input of the circuit, it must fit in Fr (scalar field) bn254
+    let mut bitmap = BigUint::default();
+    let mut signatures = Vec::<Vec<u8>>::new();
+
+    for sig in &commit.signatures {
+        if let CommitSig::Commit {
+            validator_address,
+            signature,
+            ..
+        } = sig
+        {
+            // the validator set may have drifted, in which case the signature is skipped
+            if let Some(validator_index) = validators_map.get(validator_address.as_encoding()) {
+                bitmap.set_bit(*validator_index as u64, true);
+                signatures.push(signature.clone().into());
+            }
+        }
+    }
+
+    let validators = validators
+        .into_iter()
+        .map(|v| match v.pub_key {
+            PublicKey::Bn254(key) => Ok(SimpleValidator {
+                pub_key: PublicKey::Bn254(key),
+                voting_power: v.voting_power.into(),
+            }),
+            _ => Err(RpcError::fatal_from_message(format!(
+                "validator {} does not have a bn254 key",
+                v.address
+            ))),
+        })
+        .collect::<RpcResult<Vec<_>>>()?;
+
+    Ok(ValidatorSetCommit {
+        validators,
+        signatures,
+        bitmap: bitmap.to_bytes_be(),
+    })
+}
+
+fn convert_header(block: AnyRpcBlock) -> RpcResult<ParliaHeader> {
+    let header = block.0.into_inner().header.inner;
+
+    let missing = |field: &str| {
+        RpcError::fatal_from_message(format!("block is missing {field}, not a parlia block"))
+    };
+
+    Ok(ParliaHeader {
+        parent_hash: header.parent_hash.into(),
+        sha3_uncles: header.ommers_hash.into(),
+        miner: header.beneficiary.into(),
+        state_root: header.state_root.into(),
+        transactions_root: header.transactions_root.into(),
+        receipts_root: header.receipts_root.into(),
+        logs_bloom: Box::new(header.logs_bloom.0.into()),
+        difficulty: header.difficulty.into(),
+        number: header.number.into(),
+        gas_limit: header.gas_limit,
+        gas_used: header.gas_used,
+        timestamp: header.timestamp,
+        extra_data: header.extra_data.into(),
+        mix_hash: header.mix_hash.ok_or_else(|| missing("mix_hash"))?.into(),
+        nonce: header.nonce.ok_or_else(|| missing("nonce"))?.into(),
+        base_fee_per_gas: header
+            .base_fee_per_gas
+            .ok_or_else(|| missing("base_fee_per_gas"))?
+            .into(),
+        withdrawals_root: header
+            .withdrawals_root
+            .ok_or_else(|| missing("withdrawals_root"))?
+            .into(),
+        blob_gas_used: header
+            .blob_gas_used
+            .ok_or_else(|| missing("blob_gas_used"))?,
+        excess_blob_gas: header
+            .excess_blob_gas
+            .ok_or_else(|| missing("excess_blob_gas"))?,
+        parent_beacon_block_root: header
+            .parent_beacon_block_root
+            .ok_or_else(|| missing("parent_beacon_block_root"))?
+            .into(),
+        requests_hash: header.requests_hash.map(Into::into).into(),
+    })
+}
+
+/// Collects the headers of all `update_client` datagrams for the watched clients that are
+/// submitted on the host chain.
+struct UpdateClientVisitor<'a> {
+    chain_id: &'a ChainId,
+    client_ids: &'a [ClientId],
+    headers: Vec<CheckHeader>,
+}
+
+impl Visit<VoyagerMessage> for UpdateClientVisitor<'_> {
+    fn visit_call(&mut self, c: &mut Call) {
+        match c {
+            Call::SubmitTx(submit_tx) if &submit_tx.chain_id == self.chain_id => {
+                for datagram in &submit_tx.datagrams {
+                    match datagram.decode_datagram::<IbcUnion>() {
+                        Some(Ok(Datagram::UpdateClient(msg)))
+                            if self.client_ids.contains(&msg.client_id) =>
+                        {
+                            self.headers.push(CheckHeader {
+                                client_id: msg.client_id,
+                                client_message: msg.client_message,
+                            });
+                        }
+                        Some(Err(err)) => {
+                            warn!(%err, "unable to decode datagram");
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[async_trait]
+impl PluginServer<ModuleCall, Never> for Module {
+    #[instrument(skip_all, fields(chain_id = %self.chain_id))]
+    async fn run_pass(
+        &self,
+        _: &Extensions,
+        msgs: Vec<Op<VoyagerMessage>>,
+    ) -> RpcResult<PassResult<VoyagerMessage>> {
+        let ready = msgs
+            .into_iter()
+            .enumerate()
+            .map(|(idx, mut op)| {
+                let mut visitor = UpdateClientVisitor {
+                    chain_id: &self.chain_id,
+                    client_ids: &self.client_ids,
+                    headers: vec![],
+                };
+
+                visitor.visit_op(&mut op);
+
+                (
+                    vec![idx],
+                    conc(visitor.headers.into_iter().map(|check_header| {
+                        call(PluginMessage::new(
+                            self.plugin_name(),
+                            ModuleCall::CheckHeader(check_header),
+                        ))
+                    })),
+                )
+            })
+            .collect();
+
+        Ok(PassResult {
+            optimize_further: vec![],
+            ready,
+        })
+    }
+
+    #[instrument(skip_all, fields(chain_id = %self.chain_id))]
+    async fn call(&self, e: &Extensions, msg: ModuleCall) -> RpcResult<Op<VoyagerMessage>> {
+        match msg {
+            ModuleCall::CheckHeader(CheckHeader {
+                client_id,
+                client_message,
+            }) => {
+                self.check_header(e.voyager_client()?, client_id, client_message)
+                    .await
+            }
+            ModuleCall::ProveTrackedHeader(msg) => {
+                self.prove_tracked_header(e.voyager_client()?, msg).await
+            }
+        }
+    }
+
+    #[instrument(skip_all, fields(chain_id = %self.chain_id))]
+    async fn callback(
+        &self,
+        _: &Extensions,
+        cb: Never,
+        _data: VecDeque<Data>,
+    ) -> RpcResult<Op<VoyagerMessage>> {
+        match cb {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use unionlabs::primitives::{H64, H160, H2048, U256};
+
+    use super::*;
+
+    fn parlia_header(number: u64, extra_data: &[u8]) -> ParliaHeader {
+        ParliaHeader {
+            parent_hash: H256::default(),
+            sha3_uncles: H256::default(),
+            miner: H160::default(),
+            state_root: H256::default(),
+            transactions_root: H256::default(),
+            receipts_root: H256::default(),
+            logs_bloom: Box::new(H2048::default()),
+            difficulty: U256::from(2u64),
+            number: U256::from(number),
+            gas_limit: 0,
+            gas_used: 0,
+            timestamp: number,
+            extra_data: extra_data.to_vec().into(),
+            mix_hash: H256::default(),
+            nonce: H64::default(),
+            base_fee_per_gas: U256::from(0u64),
+            withdrawals_root: H256::default(),
+            blob_gas_used: 0,
+            excess_blob_gas: 0,
+            parent_beacon_block_root: H256::default(),
+            requests_hash: None.into(),
+        }
+    }
+
+    fn relayed_parlia_header() -> parlia_light_client_types::Header {
+        parlia_light_client_types::Header {
+            trusted_valset_epoch_number: 500,
+            chain: vec![
+                parlia_header(9, b""),
+                parlia_header(10, b""),
+                parlia_header(11, b""),
+                parlia_header(12, b""),
+            ],
+        }
+    }
+
+    #[test]
+    fn parse_cometbls_header() {
+        let light_header = LightHeader {
+            height: BoundedI64::new_const(10).unwrap(),
+            time: Timestamp::default(),
+            validators_hash: [1; 32].into(),
+            next_validators_hash: [2; 32].into(),
+            app_hash: [3; 32].into(),
+        };
+
+        let header = into_value(cometbls_light_client_types::Header {
+            signed_header: light_header.clone(),
+            trusted_height: Height::new(5),
+            zero_knowledge_proof: Bytes::default(),
+        });
+
+        assert_eq!(
+            parse_header(&ClientType::new(ClientType::COMETBLS_GROTH16), &header).unwrap(),
+            Some(RelayedHeader::Cometbft {
+                summary: HeaderSummary::from(&light_header),
+                trusted_height: Height::new(5),
+            })
+        );
+    }
+
+    #[test]
+    fn parse_parlia_header() {
+        let header = relayed_parlia_header();
+
+        assert_eq!(
+            parse_header(&ClientType::new(ClientType::PARLIA), &into_value(&header)).unwrap(),
+            Some(RelayedHeader::Parlia(header))
+        );
+    }
+
+    #[test]
+    fn parse_unsupported_header() {
+        assert_eq!(
+            parse_header(&ClientType::new(ClientType::ETHEREUM), &Value::Null).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn parse_malformed_header() {
+        assert!(parse_header(&ClientType::new(ClientType::PARLIA), &Value::Null).is_err());
+    }
+
+    #[test]
+    fn parlia_no_conflict() {
+        let relayed = relayed_parlia_header();
+
+        let tracked = [
+            parlia_header(10, b""),
+            parlia_header(11, b""),
+            parlia_header(12, b""),
+        ];
+
+        assert_eq!(parlia_conflict(&relayed, tracked), None);
+    }
+
+    #[test]
+    fn parlia_conflicting_attestation_is_ignored() {
+        let relayed = relayed_parlia_header();
+
+        // only the source and target are finalized by the attestation
+        let tracked = [
+            parlia_header(10, b""),
+            parlia_header(11, b""),
+            parlia_header(12, b"fork"),
+        ];
+
+        assert_eq!(parlia_conflict(&relayed, tracked), None);
+    }
+
+    #[test]
+    fn parlia_conflicting_target() {
+        let relayed = relayed_parlia_header();
+
+        let tracked = [
+            parlia_header(10, b""),
+            parlia_header(11, b"fork"),
+            parlia_header(12, b"fork"),
+        ];
+
+        let header_b = parlia_conflict(&relayed, tracked.clone()).unwrap();
+
+        assert_eq!(
+            header_b,
+            parlia_light_client_types::Header {
+                trusted_valset_epoch_number: relayed.trusted_valset_epoch_number,
+                chain: tracked.into(),
+            }
+        );
+
+        // the evidence must be verifiable as a misbehaviour by the parlia client module, which
+        // requires both headers to be trusted from the same valset and to attest to the same
+        // heights
+        let [source_a, target_a, attestation_a] = attestation_chain(&relayed).unwrap();
+        let [source_b, target_b, attestation_b] = attestation_chain(&header_b).unwrap();
+
+        assert_eq!(source_a.number, source_b.number);
+        assert_eq!(target_a.number, target_b.number);
+        assert_eq!(attestation_a.number, attestation_b.number);
+        assert_ne!(target_a.hash(), target_b.hash());
+    }
+
+    #[test]
+    fn parlia_conflicting_source() {
+        let relayed = relayed_parlia_header();
+
+        let tracked = [
+            parlia_header(10, b"fork"),
+            parlia_header(11, b""),
+            parlia_header(12, b""),
+        ];
+
+        assert!(parlia_conflict(&relayed, tracked).is_some());
+    }
+
+    #[test]
+    fn parlia_short_chain() {
+        let relayed = parlia_light_client_types::Header {
+            trusted_valset_epoch_number: 500,
+            chain: vec![parlia_header(11, b""), parlia_header(12, b"")],
+        };
+
+        assert!(attestation_chain(&relayed).is_err());
+        assert_eq!(
+            parlia_conflict(
+                &relayed,
+                [
+                    parlia_header(10, b""),
+                    parlia_header(11, b""),
+                    parlia_header(12, b""),
+                ]
+            ),
+            None
+        );
+    }
+}
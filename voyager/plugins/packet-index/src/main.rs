@@ -12,8 +12,8 @@ use ibc_union_spec::{
 };
 use jsonrpsee::{Extensions, core::async_trait};
 use serde::{Deserialize, Serialize};
-use tracing::{info, instrument, warn};
-use unionlabs::{ibc::core::client::height::Height, never::Never, primitives::H256};
+use tracing::{debug, info, instrument, warn};
+use unionlabs::{ibc::core::client::height::Height, primitives::H256};
 use voyager_sdk::{
     DefaultCmd, ExtensionsExt, VoyagerClient, anyhow,
     message::{
@@ -22,12 +22,15 @@ use voyager_sdk::{
         data::{ChainEvent, Data, EventProvableHeight, IbcDatagram},
     },
     plugin::Plugin,
-    primitives::{ChainId, QueryHeight},
-    rpc::{PluginServer, RpcResult, types::PluginInfo},
-    vm::{Op, call, data, noop, pass::PassResult},
+    primitives::{ChainId, IbcSpec, QueryHeight},
+    rpc::{PluginServer, RpcError, RpcResult, types::PluginInfo},
+    vm::{Op, call, conc, data, noop, pass::PassResult},
 };
 
-use crate::call::{MakePacketEvent, ModuleCall};
+use crate::{
+    call::{MakePacketEvent, ModuleCall},
+    callback::{FilterUnrelayed, ModuleCallback},
+};
 
 pub mod call {
     use enumorph::Enumorph;
@@ -58,6 +61,30 @@ pub mod call {
     }
 }
 
+pub mod callback {
+    use enumorph::Enumorph;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Enumorph)]
+    #[serde(
+        tag = "@type",
+        content = "@value",
+        rename_all = "snake_case",
+        deny_unknown_fields
+    )]
+    pub enum ModuleCallback {
+        FilterUnrelayed(FilterUnrelayed),
+    }
+
+    /// Filter the aggregated events of a backfilled block range down to the packets that still
+    /// need to be relayed, i.e. packet sends that have not been received on the destination and
+    /// acknowledgements that have not been written back to the source. All other events are
+    /// dropped.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case", deny_unknown_fields)]
+    pub struct FilterUnrelayed {}
+}
+
 #[tokio::main]
 async fn main() {
     Module::run().await
@@ -72,7 +99,7 @@ pub struct Config {}
 
 impl Plugin for Module {
     type Call = ModuleCall;
-    type Callback = Never;
+    type Callback = ModuleCallback;
 
     type Config = Config;
     type Cmd = DefaultCmd;
@@ -102,6 +129,76 @@ impl Module {
         PLUGIN_NAME.to_owned()
     }
 
+    #[instrument(skip_all, fields(events = datas.len()))]
+    async fn filter_unrelayed(
+        &self,
+        voyager_client: &VoyagerClient,
+        datas: VecDeque<Data>,
+    ) -> RpcResult<Op<VoyagerMessage>> {
+        let mut unrelayed = vec![];
+
+        for chain_event in datas.into_iter().filter_map(Data::into_ibc_event) {
+            if chain_event.ibc_spec_id != IbcUnion::ID {
+                debug!(ibc_spec_id = %chain_event.ibc_spec_id, "ignoring non ibc-union event");
+                continue;
+            }
+
+            let event = chain_event
+                .decode_event::<IbcUnion>()
+                .expect("ibc spec id is checked above; qed;")
+                .map_err(RpcError::fatal("unable to decode ibc-union event"))?;
+
+            let relay = match &event {
+                // the packet is relayed to the counterparty, unless it has already been received
+                FullEvent::PacketSend(packet_send) => {
+                    let packet = packet_send.packet();
+
+                    let receipt = voyager_client
+                        .maybe_query_ibc_state(
+                            chain_event.counterparty_chain_id.clone(),
+                            QueryHeight::Latest,
+                            BatchReceiptsPath::from_packet(&packet),
+                        )
+                        .await?;
+
+                    receipt.state.is_none()
+                }
+                // the acknowledgement is relayed back to the source, unless the packet commitment
+                // has already been acknowledged (or timed out)
+                FullEvent::WriteAck(write_ack) => {
+                    let packet = write_ack.packet();
+
+                    let commitment = voyager_client
+                        .maybe_query_ibc_state(
+                            chain_event.counterparty_chain_id.clone(),
+                            QueryHeight::Latest,
+                            BatchPacketsPath::from_packet(&packet),
+                        )
+                        .await?;
+
+                    commitment.state == Some(COMMITMENT_MAGIC)
+                }
+                _ => false,
+            };
+
+            debug!(
+                event = %event.name(),
+                chain_id = %chain_event.chain_id,
+                tx_hash = ?chain_event.tx_hash,
+                relay,
+                "backfilled event"
+            );
+
+            if relay {
+                unrelayed.push(data(chain_event));
+            }
+        }
+
+        info!(unrelayed = unrelayed.len(), "filtered backfilled events");
+
+        Ok(conc(unrelayed))
+    }
+
     #[instrument(skip_all, fields(%chain_id, %channel_id, %packet_hash, %intent))]
     async fn make_packet_event(
         &self,
@@ -340,7 +437,7 @@ impl Module {
 }
 
 #[async_trait]
-impl PluginServer<ModuleCall, Never> for Module {
+impl PluginServer<ModuleCall, ModuleCallback> for Module {
     #[instrument(skip_all)]
     async fn run_pass(
         &self,
@@ -371,13 +468,17 @@ impl PluginServer<ModuleCall, Never> for Module {
         }
     }
 
-    #[instrument]
+    #[instrument(skip_all)]
     async fn callback(
         &self,
-        _: &Extensions,
-        cb: Never,
-        _data: VecDeque<Data>,
+        e: &Extensions,
+        cb: ModuleCallback,
+        datas: VecDeque<Data>,
     ) -> RpcResult<Op<VoyagerMessage>> {
-        match cb {}
+        match cb {
+            ModuleCallback::FilterUnrelayed(FilterUnrelayed {}) => {
+                self.filter_unrelayed(e.voyager_client()?, datas).await
+            }
+        }
     }
 }
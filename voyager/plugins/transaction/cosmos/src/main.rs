@@ -3,30 +3,38 @@
 
 use std::{
     collections::{BTreeMap, HashMap, HashSet, VecDeque},
-    num::NonZeroU32,
+    num::{NonZeroU32, NonZeroU64},
     ops::Deref,
     panic::AssertUnwindSafe,
     sync::{Arc, LazyLock},
+    time::{SystemTime, UNIX_EPOCH},
 };
 
-use cometbft_rpc::rpc_types::GrpcAbciQueryError;
-use concurrent_keyring::{ConcurrentKeyring, KeyringConfig, KeyringEntry};
+use cometbft_rpc::rpc_types::{GrpcAbciQueryError, TxResponse};
+use concurrent_keyring::{
+    ChainKeyring, ConcurrentKeyring, KeyringConfig, KeyringEntry, SignerBalance,
+    monitor::{BalanceMonitorConfig, monitor_balances},
+    nonce::NonceManager,
+};
 use cosmos_client::{
     BroadcastTxCommitError, TxClient,
-    gas::{GasFillerT, any, feemarket, fixed, osmosis_eip1559_feemarket},
+    gas::{GasFillerT, any, feemarket, fixed, oracle, osmosis_eip1559_feemarket},
     rpc::{Rpc, RpcT},
-    wallet::{LocalSigner, WalletT},
+    wallet::{Wallet, WalletT},
 };
 use ibc_union::ContractErrorKind;
 use ibc_union_spec::datagram::Datagram;
 use jsonrpsee::{Extensions, MethodsError, core::async_trait, proc_macros::rpc};
 use prost::Message;
+use relayer_profitability::{ProfitabilityConfig, ProfitabilityPolicy, Verdict};
+use rpc_failover::FailoverConfig;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{Value, json};
 use tracing::{debug, error, info, info_span, instrument, trace, warn};
+use tx_audit_log::{AuditLog, AuditLogConfig, MessageOutcome, MessageRecord, TxRecord};
 use unionlabs::{
     self, ErrorReporter,
-    cosmos::base::coin::Coin,
+    cosmos::{auth::base_account::BaseAccount, bank::msg_send::MsgSend, base::coin::Coin},
     google::protobuf::any::mk_any,
     never::Never,
     primitives::{Bech32, Bytes, H160, H256},
@@ -40,7 +48,7 @@ use voyager_sdk::{
     plugin::Plugin,
     primitives::ChainId,
     rpc::{PluginServer, RpcError, RpcResult, types::PluginInfo},
-    vm::{BoxDynError, Op, Visit, call, defer_relative, noop, pass::PassResult, seq},
+    vm::{BoxDynError, Op, Visit, call, conc, defer_relative, noop, pass::PassResult, seq},
 };
 
 use crate::call::{IbcMessage, ModuleCall};
@@ -59,7 +67,7 @@ pub struct Module(Arc<ModuleInner>);
 pub struct ModuleInner {
     pub chain_id: ChainId,
     pub ibc_host_contract_address: Bech32<H256>,
-    pub keyring: ConcurrentKeyring<Bech32<H160>, LocalSigner>,
+    pub keyring: ConcurrentKeyring<Bech32<H160>, Wallet>,
     pub rpc: Rpc,
     pub gas_config: any::GasFiller,
     pub bech32_prefix: String,
@@ -67,6 +75,12 @@ pub struct ModuleInner {
     pub gas_station_config: Vec<Coin>,
     pub fee_recipient: Option<Bech32<Bytes>>,
     pub max_tx_size: u32,
+    /// The wallet that low keyring signers are refilled from, if configured.
+    pub treasury: Option<Wallet>,
+    /// The next sequence and the account number of every signer.
+    pub sequences: NonceManager<Bech32<H160>, u64>,
+    pub profitability: Option<ProfitabilityPolicy>,
+    pub audit_log: Option<AuditLog>,
 }
 
 impl Deref for Module {
@@ -84,6 +98,9 @@ pub struct Config {
     pub ibc_host_contract_address: Bech32<H256>,
     pub keyring: KeyringConfig,
     pub rpc_url: String,
+    /// Fallback endpoints, health checks and quorum reads for `rpc_url`.
+    #[serde(default)]
+    pub rpc_failover: FailoverConfig,
     pub gas_config: GasFillerConfig,
     /// A list of (codespace, code) tuples that are to be considered non-recoverable.
     #[serde(default)]
@@ -93,6 +110,18 @@ pub struct Config {
     #[serde(default)]
     pub fee_recipient: Option<Bech32<Bytes>>,
     pub max_tx_size: u32,
+    /// Periodically poll the balances of the signers, excluding signers with a low balance from
+    /// rotation and optionally refilling them from a treasury key.
+    #[serde(default)]
+    pub balance_monitor: Option<BalanceMonitorConfig>,
+    /// Only submit batches that receive packets if the relayer fees of the packets cover the gas
+    /// cost of the batch. Fees are paid to `fee_recipient`.
+    #[serde(default)]
+    pub profitability: Option<ProfitabilityConfig>,
+    /// Write a record of every included transaction, such that it can later be looked up which
+    /// signer relayed a packet and how much it cost.
+    #[serde(default)]
+    pub audit_log: Option<AuditLogConfig>,
 }
 
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
@@ -102,6 +131,9 @@ pub enum GasFillerConfig {
     Fixed(fixed::GasFiller),
     Feemarket(FeemarketConfig),
     OsmosisEip1559Feemarket(OsmosisEip1559FeemarketConfig),
+    /// Price gas with one or more gas price sources, taking the median of all sources. Sources
+    /// without an rpc url use the rpc url of the plugin.
+    Oracle(oracle::Config),
 }
 
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
@@ -149,12 +181,18 @@ impl GasFillerConfig {
                     .await?,
                 )
             }
+            GasFillerConfig::Oracle(config) => {
+                any::GasFiller::Oracle(oracle::GasFiller::new(config, &rpc_url).await?)
+            }
         })
     }
 }
 
 const FATAL_ERRORS: &[(&str, NonZeroU32)] = &[];
 
+/// How long to wait before resubmitting a message that failed simulation on its own.
+const FAILED_MSG_RETRY_DELAY_SECONDS: u64 = 10;
+
 static ACCOUNT_SEQUENCE_ERRORS: LazyLock<HashSet<(&str, NonZeroU32)>> = LazyLock::new(|| {
     [
         // ("sdk", NonZeroU32::new(6).unwrap()),
@@ -172,7 +210,7 @@ impl Plugin for Module {
     type Cmd = DefaultCmd;
 
     async fn new(config: Self::Config) -> anyhow::Result<Self> {
-        let rpc = Rpc::new(config.rpc_url.clone()).await?;
+        let rpc = Rpc::new_with_failover(config.rpc_url.clone(), &config.rpc_failover).await?;
 
         let chain_id = rpc.client().status().await?.node_info.network;
 
@@ -197,21 +235,43 @@ impl Plugin for Module {
             .unwrap()
             .bech32_prefix;
 
-        Ok(Self(Arc::new(ModuleInner {
+        let mut keys = vec![];
+        for entry in &config.keyring.keys {
+            let signer = Wallet::from_config(entry, bech32_prefix.clone()).await?;
+
+            keys.push(KeyringEntry {
+                address: signer.address(),
+                signer,
+            });
+        }
+
+        let treasury = match config
+            .balance_monitor
+            .as_ref()
+            .and_then(|balance_monitor| balance_monitor.refill.as_ref())
+        {
+            Some(refill) => {
+                Some(Wallet::from_config(&refill.treasury, bech32_prefix.clone()).await?)
+            }
+            None => None,
+        };
+
+        let audit_log = match config.audit_log {
+            Some(audit_log) => Some(AuditLog::new(audit_log).await?),
+            None => None,
+        };
+
+        let module = Self(Arc::new(ModuleInner {
             ibc_host_contract_address: config.ibc_host_contract_address,
-            keyring: ConcurrentKeyring::new(
+            keyring: ConcurrentKeyring::new_with_max_in_flight(
                 config.keyring.name,
-                config.keyring.keys.into_iter().map(|entry| {
-                    let signer =
-                        LocalSigner::new(entry.value().try_into().unwrap(), bech32_prefix.clone());
-
-                    KeyringEntry {
-                        address: signer.address(),
-                        signer,
-                    }
-                }),
+                keys.into_iter(),
+                config.keyring.max_in_flight,
             ),
             rpc,
+            profitability: config
+                .profitability
+                .map(|profitability| ProfitabilityPolicy::new(chain_id.clone(), profitability)),
             chain_id: ChainId::new(chain_id),
             gas_config: config
                 .gas_config
@@ -231,7 +291,16 @@ impl Plugin for Module {
             gas_station_config: config.gas_station_config,
             fee_recipient: config.fee_recipient,
             max_tx_size: config.max_tx_size,
-        })))
+            treasury,
+            sequences: NonceManager::new(),
+            audit_log,
+        }));
+
+        if let Some(balance_monitor) = config.balance_monitor {
+            tokio::spawn(monitor_balances(module.clone(), balance_monitor));
+        }
+
+        Ok(module)
     }
 
     fn info(config: Self::Config) -> PluginInfo {
@@ -266,38 +335,74 @@ impl TransactionPluginServer for Module {
         let mut out = BTreeMap::new();
 
         for address in self.keyring.keys() {
-            let balance = self
-                .rpc
-                .client()
-                .grpc_abci_query::<_, protos::cosmos::bank::v1beta1::QueryBalanceResponse>(
-                    "/cosmos.bank.v1beta1.Query/Balance",
-                    &protos::cosmos::bank::v1beta1::QueryBalanceRequest {
-                        address: address.to_string(),
-                        denom: self.gas_config.mk_fee(0).await.amount[0].denom.clone(),
-                    },
-                    None,
-                    false,
-                )
-                .await
-                .map_err(RpcError::retryable("error fetching balance"))?
-                .into_result()
-                .map_err(RpcError::retryable("error fetching balance"))?
-                .ok_or_else(|| {
-                    RpcError::retryable_from_message("empty response when fetching balance")
-                })?
-                .balance
-                .ok_or_else(|| {
-                    RpcError::retryable_from_message("empty balance when fetching balance")
-                })?
-                .amount;
-
-            out.insert(address.clone(), balance);
+            out.insert(address.clone(), self.balance(address).await?);
         }
 
         Ok(out)
     }
 }
 
+impl ChainKeyring for Module {
+    type Address = Bech32<H160>;
+    type Signer = Wallet;
+
+    fn keyring(&self) -> &ConcurrentKeyring<Bech32<H160>, Wallet> {
+        &self.keyring
+    }
+
+    async fn balances(&self) -> Vec<SignerBalance<Bech32<H160>>> {
+        let denom = self.gas_config.denom();
+
+        let mut out = vec![];
+
+        for address in self.keyring.keys() {
+            match self.balance(address).await.map(|balance| balance.parse()) {
+                Ok(Ok(balance)) => out.push(SignerBalance {
+                    key_name: self.keyring.name.to_string(),
+                    address: address.clone(),
+                    balance,
+                    denom: denom.to_owned(),
+                }),
+                Ok(Err(err)) => {
+                    warn!(%address, err = %ErrorReporter(err), "invalid signer balance")
+                }
+                Err(err) => {
+                    warn!(%address, err = %ErrorReporter(err), "error fetching signer balance")
+                }
+            }
+        }
+
+        out
+    }
+
+    async fn refill(
+        &self,
+        address: &Bech32<H160>,
+        amount: u128,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let treasury = self.treasury.as_ref().ok_or("no treasury configured")?;
+
+        let (tx_hash, _) = TxClient::new(treasury, &self.rpc, &self.gas_config)
+            .tx(
+                MsgSend {
+                    from_address: treasury.address().map_data(Into::into),
+                    to_address: address.clone().map_data(Into::into),
+                    amount: vec![Coin {
+                        denom: self.gas_config.denom().to_owned(),
+                        amount,
+                    }],
+                },
+                "",
+                true,
+            )
+            .await?;
+
+        debug!(%tx_hash, "refill tx included");
+
+        Ok(())
+    }
+}
+
 fn plugin_name(chain_id: &ChainId) -> String {
     pub const PLUGIN_NAME: &str = env!("CARGO_PKG_NAME");
 
@@ -309,10 +414,210 @@ impl Module {
         plugin_name(&self.chain_id)
     }
 
+    /// The balance of `address` in the fee denom.
+    async fn balance(&self, address: &Bech32<H160>) -> RpcResult<String> {
+        Ok(self
+            .rpc
+            .client()
+            .grpc_abci_query::<_, protos::cosmos::bank::v1beta1::QueryBalanceResponse>(
+                "/cosmos.bank.v1beta1.Query/Balance",
+                &protos::cosmos::bank::v1beta1::QueryBalanceRequest {
+                    address: address.to_string(),
+                    denom: self.gas_config.denom().to_owned(),
+                },
+                None,
+                false,
+            )
+            .await
+            .map_err(RpcError::retryable("error fetching balance"))?
+            .into_result()
+            .map_err(RpcError::retryable("error fetching balance"))?
+            .ok_or_else(|| {
+                RpcError::retryable_from_message("empty response when fetching balance")
+            })?
+            .balance
+            .ok_or_else(|| RpcError::retryable_from_message("empty balance when fetching balance"))?
+            .amount)
+    }
+
+    /// Update the locally tracked sequence of `address` after a tx signed with `sequence` failed.
+    fn handle_sequence_error(
+        &self,
+        address: &Bech32<H160>,
+        sequence: u64,
+        err: &BroadcastTxCommitError,
+    ) {
+        match err {
+            BroadcastTxCommitError::Query(GrpcAbciQueryError {
+                error_code,
+                codespace,
+                log,
+            })
+            | BroadcastTxCommitError::TxFailed {
+                codespace,
+                error_code,
+                log,
+                ..
+            } if ACCOUNT_SEQUENCE_ERRORS.contains(&(codespace.as_str(), *error_code))
+                || log.contains("account sequence mismatch") =>
+            {
+                match parse_expected_sequence(log) {
+                    Some(expected) => self.sequences.set(address, expected),
+                    None => self.sequences.resync(address),
+                }
+            }
+            // the tx was never broadcast
+            BroadcastTxCommitError::NoResponse
+            | BroadcastTxCommitError::Query(_)
+            | BroadcastTxCommitError::AccountDecode(_)
+            | BroadcastTxCommitError::Sign(_)
+            | BroadcastTxCommitError::GasPrice(_) => self.sequences.release(address, sequence),
+            // the tx may or may not have been included
+            _ => self.sequences.resync(address),
+        }
+    }
+
+    /// Log the failure of a single message, returning whether the message may succeed if it is
+    /// retried.
+    fn classify_msg_failure(&self, codespace: &str, error_code: NonZeroU32, log: &str) -> bool {
+        match self.fatal_errors.get(&(codespace.to_owned(), error_code)) {
+            // no msg
+            Some(None) => {
+                error!(codespace, error_code, %log, "fatal error");
+                false
+            }
+            // provided msg
+            Some(Some(msg)) => {
+                error!(codespace, error_code, %log, "fatal error: {msg}");
+                false
+            }
+            // unknown error, retry
+            None => match parse_wasm_failure(log) {
+                Some(err) => match err {
+                    ContractErrorKind::ReceivedTimedOutPacketHeight => {
+                        info!("packet timed out (height)");
+                        false
+                    }
+                    ContractErrorKind::ReceivedTimedOutPacketTimestamp => {
+                        info!("packet timed out (timestamp)");
+                        false
+                    }
+                    ContractErrorKind::AlreadyAcknowledged => {
+                        info!("packet already acknowledged");
+                        false
+                    }
+                    ContractErrorKind::PacketCommitmentNotFound => {
+                        info!("packet commitment not found");
+                        false
+                    }
+                    _ => {
+                        warn!("ibc-union error ({err}): {log}");
+                        true
+                    }
+                },
+                None => {
+                    warn!("error submitting transaction ({codespace}, {error_code}): {log}");
+                    true
+                }
+            },
+        }
+    }
+
+    /// Turn the messages that failed simulation into follow-up ops. Messages that may succeed if
+    /// retried are resubmitted individually after a delay, unless they were already submitted on
+    /// their own, in which case they are dropped (as with messages that will never succeed).
+    fn handle_simulation_failures(
+        &self,
+        failures: Vec<SimulationFailure>,
+        batch_size: usize,
+    ) -> Op<VoyagerMessage> {
+        let mut ops = vec![];
+
+        for SimulationFailure { msg, error } in failures {
+            let _span = info_span!("cosmos msg failed simulation", msg = msg.name()).entered();
+
+            let retry = self.classify_msg_failure(&error.codespace, error.error_code, &error.log);
+
+            if matches!(msg, IbcMessage::IbcUnion(Datagram::UpdateClient(_))) {
+                warn!("update client failed, this may cause other messages to fail as well");
+            }
+
+            if retry && batch_size > 1 {
+                info!(msg = %into_value(&msg), "requeueing failed msg");
+
+                ops.push(seq([
+                    defer_relative(FAILED_MSG_RETRY_DELAY_SECONDS),
+                    call(PluginMessage::new(
+                        self.plugin_name(),
+                        ModuleCall::SubmitTransaction(vec![msg]),
+                    )),
+                ]));
+            } else {
+                warn!(msg = %into_value(msg), "dropping failed msg");
+            }
+        }
+
+        if ops.is_empty() { noop() } else { conc(ops) }
+    }
+
+    /// Write the audit log record of an included tx, if the audit log is enabled. All messages
+    /// share the `outcome` of the tx, since a cosmos tx is reverted as a whole if any of its
+    /// messages fail.
+    async fn record_tx(
+        &self,
+        signer: &Bech32<H160>,
+        tx_response: &TxResponse,
+        msgs: &[(IbcMessage, protos::google::protobuf::Any)],
+        outcome: &MessageOutcome,
+    ) {
+        let Some(audit_log) = &self.audit_log else {
+            return;
+        };
+
+        let fee = tx_response
+            .tx_result
+            .events
+            .iter()
+            .filter(|event| event.ty == "tx")
+            .flat_map(|event| &event.attributes)
+            .find(|attribute| attribute.key == "fee")
+            .and_then(|attribute| parse_fee(&attribute.value));
+
+        if fee.is_none() {
+            warn!(tx_hash = %tx_response.hash, "unable to find the fee paid in the tx events");
+        }
+
+        let (fee, fee_denom) = fee.map_or((0, None), |(amount, denom)| (amount, Some(denom)));
+
+        let record = TxRecord {
+            chain_id: self.chain_id.to_string(),
+            signer: signer.to_string(),
+            tx_hash: tx_response.hash.into_encoding(),
+            height: tx_response.height.map_or(0, NonZeroU64::get),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            gas_used: tx_response.tx_result.gas_used.inner().unsigned_abs(),
+            fee,
+            fee_denom,
+            messages: msgs
+                .iter()
+                .map(|(IbcMessage::IbcUnion(datagram), _)| {
+                    MessageRecord::new(datagram, outcome.clone())
+                })
+                .collect(),
+        };
+
+        if let Err(err) = audit_log.record(&record).await {
+            error!(err = %ErrorReporter(err), "error writing tx audit log record");
+        }
+    }
+
     pub async fn do_send_transaction(
         &self,
         msgs: Vec<IbcMessage>,
-    ) -> Option<Result<Option<Op<VoyagerMessage>>, BroadcastTxCommitError>> {
+    ) -> Option<Result<Option<Op<VoyagerMessage>>, SendTransactionError>> {
         self.keyring
             .with(|signer| {
                 let msgs = msgs.clone();
@@ -344,9 +649,6 @@ impl Module {
 
                 let tx_client = TxClient::new(signer, &self.rpc, &self.gas_config);
 
-                let batch_size = msgs.len();
-                let msg_names = msgs.iter().map(|x| x.0.name()).collect::<Vec<_>>();
-
                 let approximate_size = msgs.iter().map(|x| x.1.encoded_len()).sum::<usize>();
 
                 info!(
@@ -396,11 +698,86 @@ impl Module {
                         }
                     };
 
+                    let address = tx_client.wallet().address();
+
+                    let (sequence, account_number) = self
+                        .sequences
+                        .reserve(&address, || async {
+                            tx_client
+                                .account_info(address.clone())
+                                .await
+                                .map(Option::unwrap_or_default)
+                                .map(|account| (account.sequence, account.account_number))
+                        })
+                        .await?;
+
+                    info!(%sequence, %account_number, "reserved sequence");
+
+                    let tx_client = tx_client.with_account(BaseAccount {
+                        address: address.to_string(),
+                        pub_key: None,
+                        account_number,
+                        sequence,
+                    });
+
+                    let original_batch_size = msgs.len();
+
+                    let (msgs, failures, gas_used) = simulate_batch(&tx_client, msgs, &memo)
+                        .await
+                        .inspect_err(|err| self.handle_sequence_error(&address, sequence, err))?;
+
+                    let failed = (!failures.is_empty())
+                        .then(|| self.handle_simulation_failures(failures, original_batch_size));
+
+                    if msgs.is_empty() {
+                        info!("no msgs left to submit after dropping msgs that failed simulation");
+                        self.sequences.release(&address, sequence);
+                        return Ok(failed);
+                    }
+
+                    if let Some(profitability) = &self.profitability {
+                        let cost = self
+                            .gas_config
+                            .mk_fee(gas_used)
+                            .await
+                            .map_err(BroadcastTxCommitError::from)
+                            .inspect_err(|err| self.handle_sequence_error(&address, sequence, err))?
+                            .amount
+                            .first()
+                            .map_or(0, |coin| coin.amount);
+
+                        let verdict = profitability.evaluate(
+                            msgs.iter().map(|x| x.0.clone()).collect(),
+                            |IbcMessage::IbcUnion(datagram)| Some(datagram),
+                            cost,
+                        );
+
+                        info!(
+                            unprofitable = verdict.unprofitable.len(),
+                            batch.size = msgs.len(),
+                            "evaluated batch profitability"
+                        );
+
+                        if !verdict.unprofitable.is_empty() {
+                            self.sequences.release(&address, sequence);
+
+                            return Err(SendTransactionError::Unprofitable {
+                                verdict,
+                                failed: failed.map(Box::new),
+                            });
+                        }
+                    }
+
+                    let batch_size = msgs.len();
+                    let msg_names = msgs.iter().map(|x| x.0.name()).collect::<Vec<_>>();
+
+                    // the remaining batch was just simulated successfully, so the gas used by that
+                    // simulation is reused instead of simulating the batch again
                     match tx_client
-                        .broadcast_tx_commit(
+                        .broadcast_tx_commit_with_gas(
                             msgs.iter().map(move |x| x.1.clone()).collect::<Vec<_>>(),
                             memo,
-                            true,
+                            gas_used,
                         )
                         .await
                     {
@@ -417,11 +794,44 @@ impl Module {
                                 info!(tx_hash = %tx_response.hash, %msg, "cosmos msg");
                             }
 
-                            Ok(None)
+                            self.record_tx(&address, &tx_response, &msgs, &MessageOutcome::Success)
+                                .await;
+
+                            if let Some(profitability) = &self.profitability {
+                                profitability.record_submitted(
+                                    msgs.iter()
+                                        .map(|(IbcMessage::IbcUnion(datagram), _)| datagram),
+                                );
+                            }
+
+                            Ok(failed)
                         }
                         Err(err) => {
                             info!(error = %ErrorReporter(&err), "cosmos tx failed");
-                            Err(err)
+
+                            // the fee of a tx that failed after it was included is still paid
+                            if let BroadcastTxCommitError::TxFailed {
+                                codespace,
+                                error_code,
+                                log,
+                                included: Some(tx_response),
+                            } = &err
+                            {
+                                self.record_tx(
+                                    &address,
+                                    tx_response,
+                                    &msgs,
+                                    &MessageOutcome::Reverted {
+                                        reason: format!(
+                                            "code={error_code}, codespace={codespace}, log={log}"
+                                        ),
+                                    },
+                                )
+                                .await;
+                            }
+
+                            self.handle_sequence_error(&address, sequence, &err);
+                            Err(err.into())
                         }
                     }
                 })
@@ -490,135 +900,118 @@ impl PluginServer<ModuleCall, Never> for Module {
                         Ok(noop())
                     }
                     Some(Ok(Some(op))) => Ok(op),
-                    Some(Err(err)) => {
-                        match err {
-                            _ if let Some(err) = err.as_json_rpc_error() => {
-                                return Err(RpcError::retryable("jsonrpc error")(err));
-                            }
+                    Some(Err(SendTransactionError::Unprofitable { verdict, failed })) => self
+                        .profitability
+                        .as_ref()
+                        .expect("only returned if a profitability policy is configured; qed;")
+                        .handle_unprofitable(
+                            verdict,
+                            |IbcMessage::IbcUnion(datagram)| Some(datagram),
+                            failed.map(|failed| *failed),
+                            |msgs| {
+                                call(PluginMessage::new(
+                                    self.plugin_name(),
+                                    ModuleCall::SubmitTransaction(msgs),
+                                ))
+                            },
+                        ),
+                    Some(Err(SendTransactionError::Broadcast(err))) => match err {
+                        _ if let Some(err) = err.as_json_rpc_error() => {
+                            return Err(RpcError::retryable("jsonrpc error")(err));
+                        }
 
-                            BroadcastTxCommitError::Query(GrpcAbciQueryError {
-                                error_code,
-                                codespace,
-                                log,
-                            })
-                            | BroadcastTxCommitError::TxFailed {
-                                codespace,
-                                error_code,
-                                log,
-                            } if ACCOUNT_SEQUENCE_ERRORS.contains(&(&codespace, error_code))
-                                || log.contains("account sequence mismatch") =>
-                            {
-                                return Err(RpcError::retryable_from_message(format!(
-                                    "account sequence mismatch ({codespace}, {error_code}): {log}"
-                                )));
-                            }
+                        BroadcastTxCommitError::Query(GrpcAbciQueryError {
+                            error_code,
+                            codespace,
+                            log,
+                        })
+                        | BroadcastTxCommitError::TxFailed {
+                            codespace,
+                            error_code,
+                            log,
+                            ..
+                        } if ACCOUNT_SEQUENCE_ERRORS.contains(&(&codespace, error_code))
+                            || log.contains("account sequence mismatch") =>
+                        {
+                            return Err(RpcError::retryable_from_message(format!(
+                                "account sequence mismatch ({codespace}, {error_code}): {log}"
+                            )));
+                        }
 
-                            BroadcastTxCommitError::Query(GrpcAbciQueryError {
-                                error_code,
-                                codespace,
-                                log,
-                            })
-                            | BroadcastTxCommitError::TxFailed {
-                                codespace,
-                                error_code,
-                                log,
-                            } => {
-                                info!(%log, "error submitting cosmos tx");
-
-                                if let Some((msg_idx, log)) = parse_msg_idx_from_log(&log) {
-                                    let _span = info_span!("cosmos msg failed", msg_idx).entered();
-                                    info!(%log, "tx log");
-
-                                    match self.fatal_errors.get(&(codespace.clone(), error_code)) {
-                                        // no msg
-                                        Some(None) => {
-                                            error!(codespace, error_code, %log, "fatal error");
-                                        }
-                                        // provided msg
-                                        Some(Some(msg)) => {
-                                            error!(codespace, error_code, %log, "fatal error: {msg}");
-                                        }
-                                        // unknown error, retry
-                                        None => match parse_wasm_failure(log) {
-                                            Some(err) => match err {
-                                                ContractErrorKind::ReceivedTimedOutPacketHeight => {
-                                                    info!("packet timed out (height)");
-                                                }
-                                                ContractErrorKind::ReceivedTimedOutPacketTimestamp => {
-                                                    info!("packet timed out (timestamp)");
-                                                }
-                                                ContractErrorKind::AlreadyAcknowledged => {
-                                                    info!("packet already acknowledged");
-                                                }
-                                                ContractErrorKind::PacketCommitmentNotFound => {
-                                                    info!("packet commitment not found");
-                                                }
-                                                _ => {
-                                                    warn!("ibc-union error ({err}): {log}");
-                                                }
-                                            },
-                                            None => {
-                                                warn!("error submitting transaction ({codespace}, {error_code}): {log}");
-                                            }
-                                        },
-                                    }
+                        BroadcastTxCommitError::Query(GrpcAbciQueryError {
+                            error_code,
+                            codespace,
+                            log,
+                        })
+                        | BroadcastTxCommitError::TxFailed {
+                            codespace,
+                            error_code,
+                            log,
+                            ..
+                        } => {
+                            info!(%log, "error submitting cosmos tx");
 
-                                    if msgs.len() == 1 {
-                                        warn!(msg = %into_value(msgs.pop().unwrap()), "cosmos msg failed");
+                            if let Some((msg_idx, log)) = parse_msg_idx_from_log(&log) {
+                                let _span = info_span!("cosmos msg failed", msg_idx).entered();
+                                info!(%log, "tx log");
 
-                                        Ok(noop())
-                                    } else {
-                                        let failed_msg = msgs.remove(msg_idx);
-
-                                        if matches!(
-                                            failed_msg,
-                                            IbcMessage::IbcUnion(Datagram::UpdateClient(_))
-                                        ) {
-                                            warn!(
-                                                "update client failed, this may cause other messages to fail as well"
-                                            );
-                                        }
-
-                                        warn!(msg = %into_value(failed_msg), "dropping failed msg");
-
-                                        if msgs.is_empty() {
-                                            info!(
-                                                "no messages to submit after dropping failed messages"
-                                            );
-
-                                            Ok(noop())
-                                        } else {
-                                            Ok(call(PluginMessage::new(
-                                                self.plugin_name(),
-                                                ModuleCall::SubmitTransaction(msgs),
-                                            )))
-                                        }
-                                    }
-                                } else if log.contains("insufficient funds") {
-                                    warn!("out of gas");
+                                self.classify_msg_failure(&codespace, error_code, log);
 
-                                    return Err(RpcError::retryable_from_message("out of gas"));
+                                if msgs.len() == 1 {
+                                    warn!(msg = %into_value(msgs.pop().unwrap()), "cosmos msg failed");
+
+                                    Ok(noop())
                                 } else {
-                                    warn!(
-                                        "unable to parse message index from tx failure ({codespace}, {error_code}): {log}"
-                                    );
+                                    let failed_msg = msgs.remove(msg_idx);
+
+                                    if matches!(
+                                        failed_msg,
+                                        IbcMessage::IbcUnion(Datagram::UpdateClient(_))
+                                    ) {
+                                        warn!(
+                                            "update client failed, this may cause other messages to fail as well"
+                                        );
+                                    }
+
+                                    warn!(msg = %into_value(failed_msg), "dropping failed msg");
+
+                                    if msgs.is_empty() {
+                                        info!(
+                                            "no messages to submit after dropping failed messages"
+                                        );
 
-                                    if msgs.len() == 1 {
-                                        warn!(msg = %into_value(msgs.pop().unwrap()), "cosmos msg failed");
                                         Ok(noop())
                                     } else {
-                                        Ok(seq(msgs.into_iter().map(|msg| {
-                                            call(PluginMessage::new(
-                                                self.plugin_name(),
-                                                ModuleCall::SubmitTransaction(vec![msg]),
-                                            ))
-                                        })))
+                                        Ok(call(PluginMessage::new(
+                                            self.plugin_name(),
+                                            ModuleCall::SubmitTransaction(msgs),
+                                        )))
                                     }
                                 }
+                            } else if log.contains("insufficient funds") {
+                                warn!("out of gas");
+
+                                return Err(RpcError::retryable_from_message("out of gas"));
+                            } else {
+                                warn!(
+                                    "unable to parse message index from tx failure ({codespace}, {error_code}): {log}"
+                                );
+
+                                if msgs.len() == 1 {
+                                    warn!(msg = %into_value(msgs.pop().unwrap()), "cosmos msg failed");
+                                    Ok(noop())
+                                } else {
+                                    Ok(seq(msgs.into_iter().map(|msg| {
+                                        call(PluginMessage::new(
+                                            self.plugin_name(),
+                                            ModuleCall::SubmitTransaction(vec![msg]),
+                                        ))
+                                    })))
+                                }
                             }
-                            err => Err(RpcError::retryable("error submitting tx")(err)),
                         }
-                    }
+                        err => Err(RpcError::retryable("error submitting tx")(err)),
+                    },
                 }
             }
         }
@@ -653,7 +1046,7 @@ impl PluginServer<ModuleCall, Never> for Module {
 
 fn process_msgs(
     msgs: Vec<IbcMessage>,
-    signer: &LocalSigner,
+    signer: &Wallet,
     ibc_host_contract_address: Bech32<H256>,
     _gas_station_config: Vec<Coin>,
     fee_recipient: Option<&Bech32<Bytes>>,
@@ -698,6 +1091,14 @@ fn process_msgs(
                                     .map_or(signer.to_string(), |s| s.to_string()),
                             }))
                         }
+                        Datagram::Misbehaviour(msg_misbehaviour) => {
+                            mk_msg(RestrictedExecuteMsg::Misbehaviour(MsgMisbehaviour {
+                                client_id: msg_misbehaviour.client_id,
+                                client_message: msg_misbehaviour.client_message,
+                                relayer: fee_recipient
+                                    .map_or(signer.to_string(), |s| s.to_string()),
+                            }))
+                        }
                         Datagram::ConnectionOpenInit(msg_connection_open_init) => mk_msg(
                             RestrictedExecuteMsg::ConnectionOpenInit(MsgConnectionOpenInit {
                                 client_id: msg_connection_open_init.client_id,
@@ -861,6 +1262,143 @@ fn parse_wasm_failure(log: &str) -> Option<ContractErrorKind> {
     log.split(' ').find_map(ContractErrorKind::parse)
 }
 
+/// The error returned by [`Module::do_send_transaction`].
+pub enum SendTransactionError {
+    Broadcast(BroadcastTxCommitError),
+    /// The batch was simulated successfully, but some of its packet receipts are not profitable.
+    /// The messages that failed the simulation have already been handled in `failed`.
+    Unprofitable {
+        verdict: Verdict<IbcMessage>,
+        failed: Option<Box<Op<VoyagerMessage>>>,
+    },
+}
+
+impl From<BroadcastTxCommitError> for SendTransactionError {
+    fn from(err: BroadcastTxCommitError) -> Self {
+        Self::Broadcast(err)
+    }
+}
+
+/// A message that failed simulation.
+struct SimulationFailure {
+    msg: IbcMessage,
+    error: GrpcAbciQueryError,
+}
+
+/// Simulate `msgs`, removing every message that fails until the remaining batch simulates
+/// successfully. The gas used by the remaining batch is returned along with it.
+///
+/// The failing message is taken from the message index in the simulation log if present. Otherwise,
+/// the batch is bisected to find the shortest failing prefix, since later messages may depend on
+/// earlier ones (i.e. a packet on the client update before it). Errors that aren't caused by a
+/// single message (i.e. account sequence mismatches) are returned as-is.
+async fn simulate_batch(
+    tx_client: &TxClient<impl WalletT, impl RpcT, impl GasFillerT>,
+    mut msgs: Vec<(IbcMessage, protos::google::protobuf::Any)>,
+    memo: &str,
+) -> Result<
+    (
+        Vec<(IbcMessage, protos::google::protobuf::Any)>,
+        Vec<SimulationFailure>,
+        u64,
+    ),
+    BroadcastTxCommitError,
+> {
+    let mut failures = vec![];
+    let mut gas_used = 0;
+
+    while !msgs.is_empty() {
+        let error = match simulate(tx_client, &msgs, memo).await? {
+            Ok(gas) => {
+                gas_used = gas;
+                break;
+            }
+            Err(error) => error,
+        };
+
+        let (idx, error) = match parse_msg_idx_from_log(&error.log) {
+            Some((idx, _)) if idx < msgs.len() => (idx, error),
+            _ => {
+                // find the shortest prefix that fails; the last message of that prefix is the
+                // failing one
+                let (mut lo, mut hi, mut error) = (1, msgs.len(), error);
+
+                while lo < hi {
+                    let mid = lo + (hi - lo) / 2;
+
+                    match simulate(tx_client, &msgs[..mid], memo).await? {
+                        Err(prefix_error) => {
+                            hi = mid;
+                            error = prefix_error;
+                        }
+                        Ok(_) => lo = mid + 1,
+                    }
+                }
+
+                (hi - 1, error)
+            }
+        };
+
+        let (msg, _) = msgs.remove(idx);
+
+        debug!(
+            msg = msg.name(),
+            %idx,
+            log = %error.log,
+            "msg failed simulation"
+        );
+
+        failures.push(SimulationFailure { msg, error });
+    }
+
+    Ok((msgs, failures, gas_used))
+}
+
+/// Simulate `msgs`, returning the gas used if the simulation succeeded, or the error if the
+/// simulation failed due to one of the messages.
+async fn simulate(
+    tx_client: &TxClient<impl WalletT, impl RpcT, impl GasFillerT>,
+    msgs: &[(IbcMessage, protos::google::protobuf::Any)],
+    memo: &str,
+) -> Result<Result<u64, GrpcAbciQueryError>, BroadcastTxCommitError> {
+    match tx_client
+        .simulate_tx(msgs.iter().map(|x| x.1.clone()).collect::<Vec<_>>(), memo)
+        .await
+    {
+        Ok((_, _, gas_info)) => Ok(Ok(gas_info.gas_used)),
+        Err(BroadcastTxCommitError::Query(error))
+            if !(ACCOUNT_SEQUENCE_ERRORS
+                .contains(&(error.codespace.as_str(), error.error_code))
+                || error.log.contains("account sequence mismatch")
+                || error.log.contains("insufficient funds")) =>
+        {
+            Ok(Err(error))
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Parse the expected sequence out of an account sequence mismatch error, i.e.
+/// `account sequence mismatch, expected 10, got 9: incorrect account sequence`.
+fn parse_expected_sequence(log: &str) -> Option<u64> {
+    let (_, expected) = log.split_once("account sequence mismatch, expected ")?;
+
+    expected
+        .split(|c: char| !c.is_ascii_digit())
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// Parse the fee paid by a tx out of the `fee` attribute of its `tx` event, i.e. `1234muno`. If the
+/// fee consists of multiple coins, only the first one is returned.
+fn parse_fee(fee: &str) -> Option<(u128, String)> {
+    let coin = fee.split(',').next()?;
+    let (amount, denom) = coin.split_at(coin.find(|c: char| !c.is_ascii_digit())?);
+
+    Some((amount.parse().ok()?, denom.to_owned()))
+}
+
 fn parse_msg_idx_from_log(log: &str) -> Option<(usize, &str)> {
     let (_, log) = log.split_once("message index: ")?;
     let (idx, log) = log.split_once(':')?;
@@ -869,10 +1407,39 @@ fn parse_msg_idx_from_log(log: &str) -> Option<(usize, &str)> {
 
 #[cfg(test)]
 mod tests {
+    use std::num::NonZeroUsize;
+
     use concurrent_keyring::KeyringConfigEntry;
 
     use super::*;
 
+    #[test]
+    fn test_parse_expected_sequence() {
+        assert_eq!(
+            parse_expected_sequence(
+                "account sequence mismatch, expected 10, got 9: incorrect account sequence"
+            ),
+            Some(10)
+        );
+
+        assert_eq!(parse_expected_sequence("out of gas"), None);
+    }
+
+    #[test]
+    fn test_parse_fee() {
+        assert_eq!(parse_fee("1234muno"), Some((1234, "muno".to_owned())));
+        assert_eq!(
+            parse_fee(
+                "10ibc/27394FB092D2ECCD56123C74F36E4C1F926001CEADA9CA97EA622B25F41E5EB2,5muno"
+            ),
+            Some((
+                10,
+                "ibc/27394FB092D2ECCD56123C74F36E4C1F926001CEADA9CA97EA622B25F41E5EB2".to_owned()
+            ))
+        );
+        assert_eq!(parse_fee(""), None);
+    }
+
     #[test]
     fn test_parse_wasm_failure() {
         let (idx, log) = parse_msg_idx_from_log("rpc error: code = Unknown desc = failed to execute message; message index: 0: IBC_UNION_ERR_PACKET_COMMITMENT_NOT_FOUND packet commitment not found: execute wasm contract failed [CosmWasm/wasmd@v0.53.2/x/wasm/keeper/keeper.go:436] with gas used: '287090'").unwrap();
@@ -921,9 +1488,11 @@ mod tests {
                     keys: vec![KeyringConfigEntry::Raw {
                         name: "name".to_string(),
                         key: vec![0; 32],
-                    }]
+                    }],
+                    max_in_flight: NonZeroUsize::MIN,
                 },
                 rpc_url: "rpc_url".to_string(),
+                rpc_failover: FailoverConfig::default(),
                 gas_config: GasFillerConfig::Feemarket(FeemarketConfig {
                     max_gas: 123456789,
                     gas_multiplier: Some(1.4),
@@ -932,7 +1501,10 @@ mod tests {
                 fatal_errors: HashMap::default(),
                 gas_station_config: vec![],
                 fee_recipient: None,
-                max_tx_size: 1000000
+                max_tx_size: 1000000,
+                balance_monitor: None,
+                profitability: None,
+                audit_log: None,
             }
         );
     }
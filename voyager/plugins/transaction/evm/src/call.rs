@@ -5,4 +5,16 @@ use macros::model;
 #[derive(Enumorph)]
 pub enum ModuleCall {
     SubmitMulticall(Vec<ibc_union_spec::datagram::Datagram>),
+    SubmitMessage(SubmitMessage),
+}
+
+/// Resubmit a single message that failed as part of a multicall batch.
+///
+/// The message is simulated before it is submitted, and if it fails again it is either retried with
+/// backoff or the op fails with the decoded revert reason.
+#[model]
+pub struct SubmitMessage {
+    pub datagram: ibc_union_spec::datagram::Datagram,
+    /// The amount of times this message has been resubmitted individually.
+    pub attempt: u32,
 }
@@ -0,0 +1,133 @@
+//! Handling of individual messages that failed within a multicall batch.
+
+use std::fmt;
+
+use alloy::{primitives::Bytes, sol_types::SolInterface};
+use ibc_solidity::Ibc::IbcErrors;
+use ibc_union_spec::datagram::Datagram;
+
+/// A message in a multicall batch that reverted.
+#[derive(Debug, Clone)]
+pub struct MessageFailure {
+    pub datagram: Datagram,
+    pub revert: Revert,
+}
+
+#[derive(Debug, Clone)]
+pub enum Revert {
+    /// A well-known error of the IBC handler.
+    Known(IbcErrors),
+    /// A 0x revert, likely an ABI issue. This is also returned if a call runs out of gas, so it is
+    /// retried (up to the configured retry limit) rather than treated as fatal.
+    Empty,
+    /// Revert data that could not be decoded.
+    Unknown(Bytes),
+}
+
+/// What to do with a failed message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// The message has already been handled (i.e. the packet has already been acknowledged), and
+    /// can be dropped.
+    Done,
+    /// The message may succeed if it is retried later.
+    Retry,
+    /// The message will never succeed without operator intervention.
+    Fatal,
+}
+
+impl Revert {
+    pub fn decode(return_data: &Bytes) -> Self {
+        if return_data.is_empty() {
+            Self::Empty
+        } else if let Ok(known_revert) = IbcErrors::abi_decode_validate(return_data) {
+            Self::Known(known_revert)
+        } else {
+            Self::Unknown(return_data.clone())
+        }
+    }
+
+    pub fn outcome(&self) -> Outcome {
+        match self {
+            Self::Known(
+                // the acknowledgement has already been written
+                IbcErrors::ErrAcknowledgementAlreadyExists(_)
+                // the packet has already been acknowledged or timed out
+                | IbcErrors::ErrPacketCommitmentNotFound(_)
+                // the packet timed out, the timeout will be relayed back to the source instead
+                | IbcErrors::ErrHeightTimeout(_)
+                | IbcErrors::ErrTimestampTimeout(_)
+                // the client has already been updated to this height or a later one
+                | IbcErrors::ErrUntrustedHeightLTETrustedHeight(_)
+                | IbcErrors::ErrUntrustedTimestampLTETrustedTimestamp(_)
+                // the handshake step has already been executed
+                | IbcErrors::ErrInvalidConnectionState(_)
+                | IbcErrors::ErrInvalidChannelState(_)
+                | IbcErrors::ErrClientTypeAlreadyExists(_),
+            ) => Outcome::Done,
+            Self::Known(
+                IbcErrors::ErrUnauthorized(_)
+                | IbcErrors::ErrNotIBC(_)
+                | IbcErrors::ErrModuleNotFound(_)
+                | IbcErrors::ErrClientTypeNotFound(_)
+                | IbcErrors::ErrTimeoutMustBeSet(_)
+                | IbcErrors::ErrAcknowledgementIsEmpty(_)
+                | IbcErrors::ErrHeaderExpired(_)
+                | IbcErrors::ErrClientFrozen(_)
+                | IbcErrors::ErrInvalidZKP(_)
+                | IbcErrors::ErrInvalidUntrustedValidatorsHash(_)
+                | IbcErrors::ErrInvalidMisbehaviourHeadersSequence(_)
+                | IbcErrors::ErrInvalidMisbehaviour(_)
+                | IbcErrors::ErrInvalidInitialConsensusState(_),
+            ) => Outcome::Fatal,
+            Self::Known(_) | Self::Empty | Self::Unknown(_) => Outcome::Retry,
+        }
+    }
+}
+
+impl fmt::Display for Revert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Known(known_revert) => write!(f, "{known_revert:?}"),
+            Self::Empty => f.write_str("0x"),
+            Self::Unknown(return_data) => write!(f, "{return_data}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ibc_solidity::Ibc;
+
+    use super::*;
+
+    #[test]
+    fn decode_and_classify() {
+        let revert = Revert::decode(
+            &IbcErrors::ErrAcknowledgementAlreadyExists(Ibc::ErrAcknowledgementAlreadyExists {})
+                .abi_encode()
+                .into(),
+        );
+        assert_eq!(revert.outcome(), Outcome::Done);
+
+        let revert = Revert::decode(
+            &IbcErrors::ErrTimeoutTimestampNotReached(Ibc::ErrTimeoutTimestampNotReached {})
+                .abi_encode()
+                .into(),
+        );
+        assert_eq!(revert.outcome(), Outcome::Retry);
+
+        let revert = Revert::decode(
+            &IbcErrors::ErrClientFrozen(Ibc::ErrClientFrozen {})
+                .abi_encode()
+                .into(),
+        );
+        assert_eq!(revert.outcome(), Outcome::Fatal);
+
+        assert_eq!(Revert::decode(&Bytes::new()).outcome(), Outcome::Retry);
+
+        let revert = Revert::decode(&Bytes::from_static(&[0xde, 0xad, 0xbe, 0xef]));
+        assert_eq!(revert.outcome(), Outcome::Retry);
+        assert_eq!(revert.to_string(), "0xdeadbeef");
+    }
+}
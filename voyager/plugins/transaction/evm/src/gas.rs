@@ -0,0 +1,174 @@
+use alloy::{
+    eips::BlockNumberOrTag,
+    network::AnyNetwork,
+    providers::{DynProvider, Provider, ProviderBuilder},
+};
+use gas_oracle::{GasOracle, GasOracleConfig, GasOracleError, GasPrice, median};
+use serde::{Deserialize, Serialize};
+use voyager_sdk::anyhow;
+
+/// A source of gas prices for an evm chain.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type", deny_unknown_fields)]
+pub enum GasSourceConfig {
+    /// A fixed gas price, for chains where none of the gas price endpoints can be relied on.
+    Fixed {
+        #[serde(with = "::serde_utils::string")]
+        gas_price: u128,
+    },
+    /// The gas price as returned by `eth_gasPrice`.
+    GasPrice {
+        /// Defaults to the rpc url of the plugin.
+        #[serde(default)]
+        rpc_url: Option<String>,
+    },
+    /// The base fee of the next block and the median of the priority fees paid at
+    /// `reward_percentile` in the last `block_count` blocks, as returned by `eth_feeHistory`.
+    FeeHistory {
+        /// Defaults to the rpc url of the plugin.
+        #[serde(default)]
+        rpc_url: Option<String>,
+        #[serde(default = "default_block_count")]
+        block_count: u64,
+        #[serde(with = "::serde_utils::string", default = "default_reward_percentile")]
+        reward_percentile: f64,
+    },
+}
+
+const fn default_block_count() -> u64 {
+    10
+}
+
+const fn default_reward_percentile() -> f64 {
+    20.0
+}
+
+impl GasSourceConfig {
+    /// The gas oracle configuration equivalent to the deprecated `fixed_gas_price`, `legacy` and
+    /// `max_gas_price` options, used if no gas oracle is configured.
+    pub fn from_legacy_config(
+        fixed_gas_price: Option<u128>,
+        legacy: bool,
+        max_gas_price: Option<u128>,
+    ) -> GasOracleConfig<Self> {
+        let source = match fixed_gas_price {
+            Some(gas_price) => Self::Fixed { gas_price },
+            None if legacy => Self::GasPrice { rpc_url: None },
+            None => Self::FeeHistory {
+                rpc_url: None,
+                block_count: default_block_count(),
+                reward_percentile: default_reward_percentile(),
+            },
+        };
+
+        GasOracleConfig {
+            sources: vec![source],
+            cache_seconds: 0,
+            max_gas_price: max_gas_price.map(|max_gas_price| max_gas_price as f64),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum GasSource {
+    Fixed(GasPrice),
+    GasPrice(DynProvider<AnyNetwork>),
+    FeeHistory {
+        provider: DynProvider<AnyNetwork>,
+        block_count: u64,
+        reward_percentile: f64,
+    },
+}
+
+impl GasSource {
+    /// Build the source, using `provider` for any sources that do not specify their own rpc url.
+    pub async fn new(
+        config: GasSourceConfig,
+        provider: &DynProvider<AnyNetwork>,
+    ) -> anyhow::Result<Self> {
+        Ok(match config {
+            GasSourceConfig::Fixed { gas_price } => Self::Fixed(GasPrice {
+                base_fee: gas_price as f64,
+                priority_fee: 0.0,
+            }),
+            GasSourceConfig::GasPrice { rpc_url } => {
+                Self::GasPrice(connect(rpc_url, provider).await?)
+            }
+            GasSourceConfig::FeeHistory {
+                rpc_url,
+                block_count,
+                reward_percentile,
+            } => Self::FeeHistory {
+                provider: connect(rpc_url, provider).await?,
+                block_count,
+                reward_percentile,
+            },
+        })
+    }
+}
+
+async fn connect(
+    rpc_url: Option<String>,
+    provider: &DynProvider<AnyNetwork>,
+) -> anyhow::Result<DynProvider<AnyNetwork>> {
+    Ok(match rpc_url {
+        Some(rpc_url) => DynProvider::new(
+            ProviderBuilder::new()
+                .network::<AnyNetwork>()
+                .connect(&rpc_url)
+                .await?,
+        ),
+        None => provider.clone(),
+    })
+}
+
+impl GasOracle for GasSource {
+    async fn gas_price(&self) -> Result<GasPrice, GasOracleError> {
+        match self {
+            Self::Fixed(gas_price) => Ok(*gas_price),
+            Self::GasPrice(provider) => {
+                let gas_price = provider
+                    .get_gas_price()
+                    .await
+                    .map_err(GasOracleError::other)?;
+
+                Ok(GasPrice {
+                    base_fee: gas_price as f64,
+                    priority_fee: 0.0,
+                })
+            }
+            Self::FeeHistory {
+                provider,
+                block_count,
+                reward_percentile,
+            } => {
+                let fee_history = provider
+                    .get_fee_history(
+                        *block_count,
+                        BlockNumberOrTag::Latest,
+                        &[*reward_percentile],
+                    )
+                    .await
+                    .map_err(GasOracleError::other)?;
+
+                let base_fee = fee_history
+                    .next_block_base_fee()
+                    .ok_or_else(|| GasOracleError::other("fee history contains no base fee"))?;
+
+                let priority_fee = median(
+                    fee_history
+                        .reward
+                        .iter()
+                        .flatten()
+                        .filter_map(|rewards| rewards.first())
+                        .map(|reward| *reward as f64),
+                );
+
+                Ok(GasPrice {
+                    base_fee: base_fee as f64,
+                    priority_fee,
+                })
+            }
+        }
+    }
+}
@@ -3,31 +3,41 @@ use std::{
     ops::Deref,
     panic::AssertUnwindSafe,
     sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use alloy::{
-    contract::{Error, RawCallBuilder},
-    network::{AnyNetwork, EthereumWallet},
+    contract::{CallBuilder, CallDecoder, Error, RawCallBuilder},
+    network::{
+        AnyNetwork, AnyTransactionReceipt, EthereumWallet, Network, TransactionBuilder, TxSigner,
+    },
     primitives::Address,
     providers::{
-        DynProvider, PendingTransactionError, Provider, ProviderBuilder,
-        fillers::RecommendedFillers, layers::CacheLayer,
+        DynProvider, PendingTransactionBuilder, PendingTransactionError, Provider, ProviderBuilder,
+        WatchTxError, fillers::RecommendedFillers, layers::CacheLayer,
     },
     signers::local::LocalSigner,
-    sol_types::{SolEvent, SolInterface},
+    sol_types::SolEvent,
     transports::TransportError,
 };
-use bip32::secp256k1::ecdsa::{self, SigningKey};
 use clap::Subcommand;
-use concurrent_keyring::{ConcurrentKeyring, KeyringConfig, KeyringEntry};
-use ibc_solidity::Ibc::{self, IbcErrors};
+use concurrent_keyring::{
+    ChainKeyring, ConcurrentKeyring, KeyringConfig, KeyringEntry, SignerBalance,
+    monitor::{BalanceMonitorConfig, monitor_balances},
+    nonce::NonceManager,
+};
+use gas_oracle::{GasOracle, GasOracleConfig, GasOracleError, GasOracleStack};
+use ibc_solidity::Ibc;
 use ibc_union_spec::{IbcUnion, datagram::Datagram};
 use jsonrpsee::{
     Extensions, MethodsError, core::async_trait, proc_macros::rpc, types::ErrorObjectOwned,
 };
+use relayer_profitability::{ProfitabilityConfig, ProfitabilityPolicy, Verdict};
+use rpc_failover::FailoverConfig;
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
 use tracing::{Instrument, error, info, info_span, instrument, trace, warn};
+use tx_audit_log::{AuditLog, AuditLogConfig, MessageOutcome, MessageRecord, TxRecord};
 use unionlabs::{
     ErrorReporter,
     never::Never,
@@ -41,15 +51,23 @@ use voyager_sdk::{
     plugin::Plugin,
     primitives::ChainId,
     rpc::{PluginServer, RpcError, RpcResult, types::PluginInfo},
-    vm::{Op, Visit, call, defer, now, pass::PassResult, seq},
+    vm::{Op, Visit, call, conc, defer, now, pass::PassResult, seq},
 };
 
 use crate::{
-    call::ModuleCall,
+    call::{ModuleCall, SubmitMessage},
+    failure::{MessageFailure, Outcome, Revert},
+    gas::{GasSource, GasSourceConfig},
     multicall::{Call3, Multicall, MulticallResult},
+    pending::{Fees, PendingTransaction, PendingTransactions},
+    signer::EvmSigner,
 };
 
 pub mod call;
+pub mod failure;
+pub mod gas;
+pub mod pending;
+pub mod signer;
 
 #[tokio::main]
 async fn main() {
@@ -78,17 +96,37 @@ pub struct ModuleInner {
 
     pub provider: DynProvider<AnyNetwork>,
 
-    pub keyring: ConcurrentKeyring<alloy::primitives::Address, LocalSigner<SigningKey>>,
+    pub keyring: ConcurrentKeyring<alloy::primitives::Address, EvmSigner>,
 
-    pub max_gas_price: Option<u128>,
-
-    pub fixed_gas_price: Option<u128>,
+    pub gas_oracle: GasOracleStack<GasSource>,
 
     pub gas_multiplier: f64,
 
     pub legacy: bool,
 
     pub fee_recipient: Option<alloy::primitives::Address>,
+
+    pub stuck_tx_timeout: Option<Duration>,
+
+    pub fee_bump_percent: u64,
+
+    pub max_fee_bumps: u32,
+
+    pub pending_transactions: PendingTransactions,
+
+    pub max_message_retries: u32,
+
+    pub message_retry_backoff_seconds: u64,
+
+    /// The signer that low keyring signers are refilled from, if configured.
+    pub treasury: Option<EvmSigner>,
+
+    /// The next nonce of every signer.
+    pub nonces: NonceManager<Address>,
+
+    pub profitability: Option<ProfitabilityPolicy>,
+
+    pub audit_log: Option<AuditLog>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -105,15 +143,25 @@ pub struct Config {
     /// The RPC endpoint for the execution chain.
     pub rpc_url: String,
 
+    /// Fallback endpoints, health checks and quorum reads for `rpc_url`.
+    #[serde(default)]
+    pub rpc_failover: FailoverConfig,
+
     pub keyring: KeyringConfig,
 
+    /// Deprecated, use `gas_oracle.max_gas_price` instead.
     #[serde(default)]
     pub max_gas_price: Option<u128>,
 
-    /// Temporary fix for 0g until they fix their eth_feeHistory endpoint
+    /// Deprecated, use a `fixed` gas oracle source instead.
     #[serde(default)]
     pub fixed_gas_price: Option<u128>,
 
+    /// The sources that the gas price is fetched from. If not set, the gas price is fetched with
+    /// `eth_feeHistory` (or `eth_gasPrice` if `legacy` is set).
+    #[serde(default)]
+    pub gas_oracle: Option<GasOracleConfig<GasSourceConfig>>,
+
     #[serde(with = "::serde_utils::string")]
     pub gas_multiplier: f64,
 
@@ -125,6 +173,63 @@ pub struct Config {
 
     #[serde(default)]
     pub fee_recipient: Option<alloy::primitives::Address>,
+
+    /// How long to wait for a submitted transaction to be included before it is considered stuck
+    /// and replaced with a transaction with bumped fees. If not set, transactions are waited on
+    /// indefinitely.
+    #[serde(default)]
+    pub stuck_tx_timeout_seconds: Option<u64>,
+
+    /// The percentage by which the fees of a stuck transaction are increased when it is replaced.
+    /// Note that most nodes require an increase of at least 10% for a replacement to be accepted.
+    #[serde(default = "default_fee_bump_percent")]
+    pub fee_bump_percent: u64,
+
+    /// The maximum amount of times a stuck transaction is replaced before it is cancelled with a
+    /// zero-value self-transfer. A transaction is also cancelled if the bumped fees would exceed
+    /// the maximum gas price of the gas oracle.
+    #[serde(default = "default_max_fee_bumps")]
+    pub max_fee_bumps: u32,
+
+    /// The maximum amount of times a message that failed with a retryable revert is resubmitted
+    /// individually before it is moved to the failed queue.
+    #[serde(default = "default_max_message_retries")]
+    pub max_message_retries: u32,
+
+    /// The base delay before a failed message is resubmitted. This is doubled for every attempt.
+    #[serde(default = "default_message_retry_backoff_seconds")]
+    pub message_retry_backoff_seconds: u64,
+
+    /// Periodically poll the balances of the signers, excluding signers with a low balance from
+    /// rotation and optionally refilling them from a treasury key.
+    #[serde(default)]
+    pub balance_monitor: Option<BalanceMonitorConfig>,
+
+    /// Only submit batches that receive packets if the relayer fees of the packets cover the gas
+    /// cost of the batch. Fees are paid to `fee_recipient`.
+    #[serde(default)]
+    pub profitability: Option<ProfitabilityConfig>,
+
+    /// Write a record of every included transaction, such that it can later be looked up which
+    /// signer relayed a packet and how much it cost.
+    #[serde(default)]
+    pub audit_log: Option<AuditLogConfig>,
+}
+
+const fn default_fee_bump_percent() -> u64 {
+    20
+}
+
+const fn default_max_fee_bumps() -> u32 {
+    3
+}
+
+const fn default_max_message_retries() -> u32 {
+    5
+}
+
+const fn default_message_retry_backoff_seconds() -> u64 {
+    12
 }
 
 #[derive(Subcommand)]
@@ -145,8 +250,10 @@ impl Plugin for Module {
             ProviderBuilder::new()
                 .network::<AnyNetwork>()
                 .layer(CacheLayer::new(config.max_cache_size))
-                .connect(&config.rpc_url)
-                .await?,
+                .connect_client(
+                    rpc_failover::alloy::connect_client(&config.rpc_url, &config.rpc_failover)
+                        .await?,
+                ),
         );
 
         let raw_chain_id = provider.get_chain_id().await?;
@@ -160,34 +267,78 @@ impl Plugin for Module {
             );
         }
 
-        Ok(Self(Arc::new(ModuleInner {
-            chain_id,
+        let mut keys = vec![];
+        for entry in &config.keyring.keys {
+            let signer = EvmSigner::from_config(entry).await?;
+
+            keys.push(KeyringEntry {
+                address: signer.address(),
+                signer,
+            });
+        }
+
+        let treasury = match config
+            .balance_monitor
+            .as_ref()
+            .and_then(|balance_monitor| balance_monitor.refill.as_ref())
+        {
+            Some(refill) => Some(EvmSigner::from_config(&refill.treasury).await?),
+            None => None,
+        };
+
+        let gas_oracle = config.gas_oracle.unwrap_or_else(|| {
+            GasSourceConfig::from_legacy_config(
+                config.fixed_gas_price,
+                config.legacy,
+                config.max_gas_price,
+            )
+        });
+
+        let mut gas_sources = vec![];
+        for source in gas_oracle.sources.clone() {
+            gas_sources.push(GasSource::new(source, &provider).await?);
+        }
+
+        let audit_log = match config.audit_log {
+            Some(audit_log) => Some(AuditLog::new(audit_log).await?),
+            None => None,
+        };
+
+        let module = Self(Arc::new(ModuleInner {
+            chain_id: chain_id.clone(),
             additional_chain_ids: config.additional_chain_ids,
             ibc_handler_address: config.ibc_handler_address,
             multicall_address: config.multicall_address,
             provider,
-            keyring: ConcurrentKeyring::new(
+            keyring: ConcurrentKeyring::new_with_max_in_flight(
                 config.keyring.name,
-                config.keyring.keys.into_iter().map(|config| {
-                    let signing_key = <ecdsa::SigningKey as bip32::PrivateKey>::from_bytes(
-                        &config.value().as_slice().try_into().unwrap(),
-                    )
-                    .unwrap();
-
-                    let signer = LocalSigner::from_signing_key(signing_key);
-
-                    KeyringEntry {
-                        address: signer.address(),
-                        signer,
-                    }
-                }),
+                keys.into_iter(),
+                config.keyring.max_in_flight,
             ),
-            max_gas_price: config.max_gas_price,
-            fixed_gas_price: config.fixed_gas_price,
-            legacy: config.legacy,
+            gas_oracle: GasOracleStack::from_config(&gas_oracle, gas_sources),
+            // a fixed gas price was previously always sent as a legacy transaction
+            legacy: config.legacy || config.fixed_gas_price.is_some(),
             gas_multiplier: config.gas_multiplier,
             fee_recipient: config.fee_recipient,
-        })))
+            stuck_tx_timeout: config.stuck_tx_timeout_seconds.map(Duration::from_secs),
+            fee_bump_percent: config.fee_bump_percent,
+            max_fee_bumps: config.max_fee_bumps,
+            pending_transactions: PendingTransactions::new(chain_id),
+            max_message_retries: config.max_message_retries,
+            message_retry_backoff_seconds: config.message_retry_backoff_seconds,
+            treasury,
+            nonces: NonceManager::new(),
+            profitability: config.profitability.map(|profitability| {
+                ProfitabilityPolicy::new(config.chain_id.to_string(), profitability)
+            }),
+            audit_log,
+        }));
+
+        if let Some(balance_monitor) = config.balance_monitor {
+            tokio::spawn(monitor_balances(module.clone(), balance_monitor));
+        }
+
+        Ok(module)
     }
 
     fn info(config: Self::Config) -> PluginInfo {
@@ -230,6 +381,9 @@ trait TransactionPlugin {
 
     #[method(name = "signerBalances")]
     async fn signer_balances(&self) -> RpcResult<BTreeMap<Address, U256>>;
+
+    #[method(name = "pendingTransactions")]
+    async fn pending_transactions(&self) -> RpcResult<Vec<PendingTransaction>>;
 }
 
 #[async_trait]
@@ -253,6 +407,116 @@ impl TransactionPluginServer for Module {
 
         Ok(out)
     }
+
+    async fn pending_transactions(&self) -> RpcResult<Vec<PendingTransaction>> {
+        Ok(self.pending_transactions.all())
+    }
+}
+
+impl ChainKeyring for Module {
+    type Address = Address;
+    type Signer = EvmSigner;
+
+    fn keyring(&self) -> &ConcurrentKeyring<Address, EvmSigner> {
+        &self.keyring
+    }
+
+    async fn balances(&self) -> Vec<SignerBalance<Address>> {
+        let mut out = vec![];
+
+        for address in self.keyring.keys() {
+            match self.provider.get_balance(*address).await {
+                Ok(balance) => out.push(SignerBalance {
+                    key_name: self.keyring.name.to_string(),
+                    address: *address,
+                    balance: balance.try_into().unwrap_or(u128::MAX),
+                    denom: "wei".to_owned(),
+                }),
+                Err(err) => {
+                    warn!(%address, err = %ErrorReporter(err), "error fetching signer balance")
+                }
+            }
+        }
+
+        out
+    }
+
+    async fn refill(
+        &self,
+        address: &Address,
+        amount: u128,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let treasury = self.treasury.as_ref().ok_or("no treasury configured")?;
+
+        let signer = DynProvider::new(
+            ProviderBuilder::new()
+                .network::<AnyNetwork>()
+                .filler(AnyNetwork::recommended_fillers())
+                .wallet(EthereumWallet::new(treasury.clone()))
+                .connect_provider(self.provider.clone()),
+        );
+
+        // refills are subject to the same gas price limits as any other transaction
+        let fees = self.current_fees().await?;
+
+        let from = treasury.address();
+
+        let (nonce, ()) = self
+            .nonces
+            .reserve(&from, || async {
+                Ok::<_, TransportError>((
+                    self.provider.get_transaction_count(from).pending().await?,
+                    (),
+                ))
+            })
+            .await?;
+
+        info!(%from, to = %address, %amount, %nonce, ?fees, "refilling signer");
+
+        let request = |fees| {
+            with_request_fees(
+                <AnyNetwork as Network>::TransactionRequest::default()
+                    .with_from(from)
+                    .with_to(*address)
+                    .with_value(alloy::primitives::U256::from(amount))
+                    .with_nonce(nonce)
+                    .with_gas_limit(21_000),
+                fees,
+            )
+        };
+
+        let pending_tx = signer
+            .send_transaction(request(fees))
+            .await
+            .map_err(Error::from)
+            .inspect_err(|err| self.handle_send_error(&from, nonce, err))?;
+
+        let receipt = match self
+            .wait_for_inclusion(&signer, from, nonce, fees, pending_tx, |fees| {
+                let request = request(fees);
+                let signer = signer.clone();
+                async move { signer.send_transaction(request).await.map_err(Error::from) }
+            })
+            .await
+            // it is unknown whether the nonce was consumed by this transaction
+            .inspect_err(|_| self.nonces.resync(&from))?
+        {
+            Inclusion::Included(receipt) => receipt,
+            Inclusion::Cancelled { replacements, .. } => {
+                return Err(TxSubmitError::Cancelled {
+                    nonce,
+                    replacements,
+                }
+                .into());
+            }
+        };
+
+        if !receipt.status() {
+            return Err(format!("refill reverted (tx hash: {})", receipt.transaction_hash).into());
+        }
+
+        Ok(())
+    }
 }
 
 fn plugin_name(chain_id: &ChainId) -> String {
@@ -287,6 +551,28 @@ pub enum TxSubmitError {
     RpcError(#[from] ErrorObjectOwned),
     #[error("batch too large")]
     BatchTooLarge,
+    #[error("transaction with nonce {nonce} was cancelled after {replacements} replacements")]
+    Cancelled { nonce: u64, replacements: u32 },
+    #[error("nonce {nonce} was used by a transaction not sent by this plugin")]
+    NonceConsumed { nonce: u64 },
+    #[error("{} packets in the batch are not profitable", .0.unprofitable.len())]
+    Unprofitable(Verdict<Datagram>),
+    #[error("error fetching gas price")]
+    GasOracle(#[source] GasOracleError),
+    #[error(transparent)]
+    Transport(#[from] TransportError),
+}
+
+impl From<GasOracleError> for TxSubmitError {
+    fn from(err: GasOracleError) -> Self {
+        match err {
+            GasOracleError::TooHigh { price, max } => Self::GasPriceTooHigh {
+                max: max as u128,
+                price: price as u128,
+            },
+            err => Self::GasOracle(err),
+        }
+    }
 }
 
 #[async_trait]
@@ -334,52 +620,9 @@ impl PluginServer<ModuleCall, Never> for Module {
     #[instrument(skip_all, fields(chain_id = %self.chain_id))]
     async fn call(&self, _: &Extensions, msg: ModuleCall) -> RpcResult<Op<VoyagerMessage>> {
         match msg {
-            ModuleCall::SubmitMulticall(mut msgs) => {
-                let res = self
-                    .keyring
-                    .with({
-                        let msgs = msgs.clone();
-                        move |wallet| -> _ {
-                            // let call = if self.legacy { call.legacy() } else { call };
-                            AssertUnwindSafe(self.submit_transaction(wallet, msgs))
-                        }
-                    })
-                    .await;
-
-                match res {
-                    Some(Ok(())) => Ok(Op::Noop),
-                    Some(Err(TxSubmitError::GasPriceTooHigh { max, price })) => Err(
-                        RpcError::retryable_from_message("gas price too high").with_data(json!({
-                            "max": max,
-                            "price": price
-                        })),
-                    ),
-                    Some(Err(TxSubmitError::OutOfGas)) => {
-                        Err(RpcError::retryable_from_message("out of gas"))
-                    }
-                    Some(Err(TxSubmitError::EmptyRevert(msgs))) => Ok(seq([
-                        defer(now() + 12),
-                        call(PluginMessage::new(
-                            self.plugin_name(),
-                            ModuleCall::SubmitMulticall(msgs),
-                        )),
-                    ])),
-                    Some(Err(TxSubmitError::BatchTooLarge)) => {
-                        let new = msgs.split_off(msgs.len() / 2);
-                        Ok(seq([
-                            call(PluginMessage::new(
-                                self.plugin_name(),
-                                ModuleCall::SubmitMulticall(msgs),
-                            )),
-                            call(PluginMessage::new(
-                                self.plugin_name(),
-                                ModuleCall::SubmitMulticall(new),
-                            )),
-                        ]))
-                    }
-                    Some(Err(err)) => Err(RpcError::retryable("error submittin transaction")(err)),
-                    None => Err(RpcError::retryable_from_message("no signers available")),
-                }
+            ModuleCall::SubmitMulticall(msgs) => self.submit_multicall(msgs, None).await,
+            ModuleCall::SubmitMessage(SubmitMessage { datagram, attempt }) => {
+                self.submit_multicall(vec![datagram], Some(attempt)).await
             }
         }
     }
@@ -412,11 +655,167 @@ impl PluginServer<ModuleCall, Never> for Module {
 }
 
 impl Module {
+    /// Submit `msgs` as a multicall batch. `attempt` is set if this is an individual resubmission of
+    /// a message that previously failed.
+    async fn submit_multicall(
+        &self,
+        mut msgs: Vec<Datagram>,
+        attempt: Option<u32>,
+    ) -> RpcResult<Op<VoyagerMessage>> {
+        let res = self
+            .keyring
+            .with({
+                let msgs = msgs.clone();
+                move |wallet| -> _ {
+                    // let call = if self.legacy { call.legacy() } else { call };
+                    AssertUnwindSafe(self.submit_transaction(wallet, msgs, attempt.is_some()))
+                }
+            })
+            .await;
+
+        match res {
+            Some(Ok(failures)) => self.handle_failures(failures, attempt),
+            Some(Err(TxSubmitError::GasPriceTooHigh { max, price })) => Err(
+                RpcError::retryable_from_message("gas price too high").with_data(json!({
+                    "max": max,
+                    "price": price
+                })),
+            ),
+            Some(Err(TxSubmitError::OutOfGas)) => {
+                Err(RpcError::retryable_from_message("out of gas"))
+            }
+            Some(Err(TxSubmitError::EmptyRevert(msgs))) => Ok(seq([
+                defer(now() + 12),
+                call(PluginMessage::new(
+                    self.plugin_name(),
+                    ModuleCall::SubmitMulticall(msgs),
+                )),
+            ])),
+            Some(Err(TxSubmitError::BatchTooLarge)) => {
+                let new = msgs.split_off(msgs.len() / 2);
+                Ok(seq([
+                    call(PluginMessage::new(
+                        self.plugin_name(),
+                        ModuleCall::SubmitMulticall(msgs),
+                    )),
+                    call(PluginMessage::new(
+                        self.plugin_name(),
+                        ModuleCall::SubmitMulticall(new),
+                    )),
+                ]))
+            }
+            Some(Err(TxSubmitError::Cancelled {
+                nonce,
+                replacements,
+            })) => Err(RpcError::retryable_from_message(
+                "transaction was stuck and has been cancelled",
+            )
+            .with_data(json!({
+                "nonce": nonce,
+                "replacements": replacements,
+            }))),
+            Some(Err(TxSubmitError::Unprofitable(verdict))) => self
+                .profitability
+                .as_ref()
+                .expect("only returned if a profitability policy is configured; qed;")
+                .handle_unprofitable(verdict, Some, [], |msgs| {
+                    call(PluginMessage::new(
+                        self.plugin_name(),
+                        ModuleCall::SubmitMulticall(msgs),
+                    ))
+                }),
+            Some(Err(err)) => Err(RpcError::retryable("error submittin transaction")(err)),
+            None => Err(RpcError::retryable_from_message("no signers available")),
+        }
+    }
+
+    /// Turn the failed messages of a batch into follow-up ops.
+    ///
+    /// Messages that have already been handled are dropped. All other messages are resubmitted
+    /// individually, with backoff if the failure is retryable. If this is already an individual
+    /// resubmission (`attempt` is set), a fatal failure or a retryable failure that has exhausted
+    /// `max_message_retries` fails the op, which moves it to the failed queue with the decoded
+    /// revert reason.
+    fn handle_failures(
+        &self,
+        failures: Vec<MessageFailure>,
+        attempt: Option<u32>,
+    ) -> RpcResult<Op<VoyagerMessage>> {
+        let mut ops = vec![];
+
+        for MessageFailure { datagram, revert } in failures {
+            let outcome = revert.outcome();
+
+            let next_attempt = match (outcome, attempt) {
+                (Outcome::Done, _) => {
+                    info!(
+                        msg = datagram.name(),
+                        %revert,
+                        "message has already been handled, dropping"
+                    );
+
+                    continue;
+                }
+                (Outcome::Fatal | Outcome::Retry, None) => 0,
+                (Outcome::Retry, Some(attempt)) if attempt < self.max_message_retries => {
+                    attempt + 1
+                }
+                (Outcome::Fatal | Outcome::Retry, Some(attempt)) => {
+                    return Err(RpcError::fatal_from_message(format!(
+                        "message {} failed: {revert}",
+                        datagram.name()
+                    ))
+                    .with_data(json!({
+                        "revert": revert.to_string(),
+                        "outcome": format!("{outcome:?}"),
+                        "attempt": attempt,
+                        "datagram": datagram,
+                    })));
+                }
+            };
+
+            let resubmit = call(PluginMessage::new(
+                self.plugin_name(),
+                ModuleCall::SubmitMessage(SubmitMessage {
+                    datagram,
+                    attempt: next_attempt,
+                }),
+            ));
+
+            ops.push(match outcome {
+                // the simulation of the resubmission will fail immediately
+                Outcome::Fatal => resubmit,
+                _ => seq([
+                    defer(now() + self.message_retry_backoff(next_attempt)),
+                    resubmit,
+                ]),
+            });
+        }
+
+        if ops.is_empty() {
+            Ok(Op::Noop)
+        } else {
+            Ok(conc(ops))
+        }
+    }
+
+    /// Exponential backoff for individual message resubmissions, capped at one hour.
+    fn message_retry_backoff(&self, attempt: u32) -> u64 {
+        self.message_retry_backoff_seconds
+            .saturating_mul(1 << attempt.min(16))
+            .min(60 * 60)
+    }
+
+    /// Submit `ibc_messages` in a single multicall transaction, returning the messages that failed.
+    ///
+    /// If `simulate` is set, the batch is simulated first and any messages that fail the simulation
+    /// are not submitted.
     async fn submit_transaction(
         &self,
-        wallet: &LocalSigner<SigningKey>,
+        wallet: &EvmSigner,
         ibc_messages: Vec<Datagram>,
-    ) -> Result<(), TxSubmitError> {
+        simulate: bool,
+    ) -> Result<Vec<MessageFailure>, TxSubmitError> {
         let signer = DynProvider::new(
             ProviderBuilder::new()
                 .network::<AnyNetwork>()
@@ -427,30 +826,15 @@ impl Module {
                 .connect_provider(self.provider.clone()),
         );
 
-        if let Some(max_gas_price) = self.max_gas_price {
-            let gas_price = self
-                .provider
-                .get_gas_price()
-                .await
-                .expect("unable to fetch gas price");
-
-            if gas_price > max_gas_price {
-                warn!(%max_gas_price, %gas_price, "gas price is too high");
+        let fees = self.current_fees().await?;
 
-                return Err(TxSubmitError::GasPriceTooHigh {
-                    max: max_gas_price,
-                    price: gas_price,
-                });
-            } else {
-                info!(%gas_price, "gas price");
-            }
-        }
+        info!(?fees, "gas price");
 
         let multicall = Multicall::new(self.multicall_address.into(), signer.clone());
 
         let ibc = Ibc::new(self.ibc_handler_address.into(), &self.provider);
 
-        let msgs = process_msgs(
+        let mut msgs = process_msgs(
             &ibc,
             ibc_messages,
             self.fee_recipient.unwrap_or(wallet.address()).into(),
@@ -458,22 +842,56 @@ impl Module {
 
         trace!(?msgs);
 
+        let call3 = |call: &RawCallBuilder<_, AnyNetwork>| Call3 {
+            target: self.ibc_handler_address.into(),
+            allowFailure: true,
+            callData: call.calldata().clone(),
+        };
+
+        let mut failures = vec![];
+
+        if simulate {
+            let results = multicall
+                .multicall(msgs.iter().map(|(_, call)| call3(call)).collect())
+                .call()
+                .await
+                .map_err(TxSubmitError::Estimate)?;
+
+            let (ok, failed): (Vec<_>, Vec<_>) = msgs
+                .into_iter()
+                .zip(results)
+                .partition(|(_, result)| result.success);
+
+            for ((msg, _), result) in failed {
+                let revert = Revert::decode(&result.returnData);
+
+                warn!(
+                    msg = msg.name(),
+                    %revert,
+                    outcome = ?revert.outcome(),
+                    "evm message failed simulation"
+                );
+
+                failures.push(MessageFailure {
+                    datagram: msg,
+                    revert,
+                });
+            }
+
+            msgs = ok.into_iter().map(|(msg, _)| msg).collect();
+
+            if msgs.is_empty() {
+                return Ok(failures);
+            }
+        }
+
         let msg_names = msgs
             .iter()
             // .map(|x| (x.0.clone(), x.1.function.name.clone()))
             .map(|x| (x.0.clone(), x.0.name()))
             .collect::<Vec<_>>();
 
-        let mut call = multicall.multicall(
-            msgs.clone()
-                .into_iter()
-                .map(|(_, call)| Call3 {
-                    target: self.ibc_handler_address.into(),
-                    allowFailure: true,
-                    callData: call.calldata().clone(),
-                })
-                .collect(),
-        );
+        let call = multicall.multicall(msgs.iter().map(|(_, call)| call3(call)).collect());
 
         info!("submitting evm tx");
 
@@ -497,16 +915,98 @@ impl Module {
             "gas estimatation successful"
         );
 
-        if let Some(fixed_gas_price) = self.fixed_gas_price {
-            call = call.gas_price(fixed_gas_price);
+        // individual resubmissions were already accepted as part of their original batch
+        if let Some(profitability) = self.profitability.as_ref().filter(|_| !simulate) {
+            let verdict = profitability.evaluate(
+                msgs.iter().map(|(msg, _)| msg.clone()).collect(),
+                Some,
+                u128::from(gas_to_use).saturating_mul(fees.max_price()),
+            );
+
+            info!(
+                unprofitable = verdict.unprofitable.len(),
+                batch.size = msgs.len(),
+                "evaluated batch profitability"
+            );
+
+            if !verdict.unprofitable.is_empty() {
+                return Err(TxSubmitError::Unprofitable(verdict));
+            }
         }
 
-        match call.gas(gas_to_use).send().await {
+        let address = wallet.address();
+
+        let (nonce, ()) = self
+            .nonces
+            .reserve(&address, || async {
+                Ok::<_, TransportError>((
+                    self.provider
+                        .get_transaction_count(address)
+                        .pending()
+                        .await?,
+                    (),
+                ))
+            })
+            .await?;
+
+        info!(%nonce, ?fees, "fees");
+
+        let call = call.gas(gas_to_use).nonce(nonce);
+
+        let result = with_fees(call.clone(), fees).send().await;
+
+        if let Err(err) = &result {
+            self.handle_send_error(&address, nonce, err);
+        }
+
+        match result {
             Ok(ok) => {
-                let tx_hash = <H256>::from(*ok.tx_hash());
-                async move {
-                    let receipt = ok.get_receipt().await?;
+                let receipt = match self
+                    .wait_for_inclusion(&signer, address, nonce, fees, ok, |fees| {
+                        let call = with_fees(call.clone(), fees);
+                        async move { call.send().await }
+                    })
+                    .await
+                    // it is unknown whether the nonce was consumed by this transaction
+                    .inspect_err(|_| self.nonces.resync(&address))?
+                {
+                    Inclusion::Included(receipt) => receipt,
+                    Inclusion::Cancelled {
+                        receipt,
+                        replacements,
+                    } => {
+                        warn!(
+                            tx_hash = %<H256>::from(receipt.transaction_hash),
+                            %nonce,
+                            replacements,
+                            "stuck transaction was cancelled"
+                        );
+
+                        // the cancellation paid the fees of the batch
+                        self.record_tx(
+                            address,
+                            &receipt,
+                            msg_names
+                                .iter()
+                                .map(|(msg, _)| {
+                                    MessageRecord::new(
+                                        msg,
+                                        MessageOutcome::Cancelled { replacements },
+                                    )
+                                })
+                                .collect(),
+                        )
+                        .await;
+
+                        return Err(TxSubmitError::Cancelled {
+                            nonce,
+                            replacements,
+                        });
+                    }
+                };
 
+                let tx_hash = <H256>::from(receipt.transaction_hash);
+                async move {
                     let block_number = receipt.block_number;
 
                     info!(%tx_hash, block_number, "tx included");
@@ -528,49 +1028,74 @@ impl Module {
                         "submitted batched evm messages"
                     );
 
+                    let mut messages = vec![];
+
                     for (idx, (result, (msg, msg_name))) in
                         result._0.into_iter().zip(msg_names).enumerate()
                     {
                         if result.success {
+                            messages.push(MessageRecord::new(&msg, MessageOutcome::Success));
+
+                            if let Some(profitability) = &self.profitability {
+                                profitability.record_submitted([&msg]);
+                            }
+
                             info!(
                                 msg = msg_name,
                                 %idx,
                                 data = %into_value(&msg),
                                 "evm tx",
                             );
-                        } else if let Ok(known_revert) =
-                            IbcErrors::abi_decode_validate(&result.returnData)
-                        {
-                            error!(
-                                msg = %msg_name,
-                                %idx,
-                                revert = ?known_revert,
-                                well_known = true,
-                                data = %into_value(&msg),
-                                "evm message failed",
-                            );
-                        } else if result.returnData.is_empty() {
-                            error!(
-                                msg = %msg_name,
-                                %idx,
-                                revert = %result.returnData,
-                                well_known = false,
-                                data = %into_value(&msg),
-                                "evm message failed with 0x revert, likely an ABI issue",
-                            );
                         } else {
-                            error!(
-                                msg = %msg_name,
-                                %idx,
-                                revert = %result.returnData,
-                                well_known = false,
-                                data = %into_value(&msg),
-                                "evm message failed",
-                            );
+                            let revert = Revert::decode(&result.returnData);
+
+                            match &revert {
+                                Revert::Known(known_revert) => error!(
+                                    msg = %msg_name,
+                                    %idx,
+                                    revert = ?known_revert,
+                                    well_known = true,
+                                    outcome = ?revert.outcome(),
+                                    data = %into_value(&msg),
+                                    "evm message failed",
+                                ),
+                                Revert::Empty => error!(
+                                    msg = %msg_name,
+                                    %idx,
+                                    revert = %result.returnData,
+                                    well_known = false,
+                                    outcome = ?revert.outcome(),
+                                    data = %into_value(&msg),
+                                    "evm message failed with 0x revert, likely an ABI issue",
+                                ),
+                                Revert::Unknown(_) => error!(
+                                    msg = %msg_name,
+                                    %idx,
+                                    revert = %result.returnData,
+                                    well_known = false,
+                                    outcome = ?revert.outcome(),
+                                    data = %into_value(&msg),
+                                    "evm message failed",
+                                ),
+                            }
+
+                            messages.push(MessageRecord::new(
+                                &msg,
+                                MessageOutcome::Reverted {
+                                    reason: revert.to_string(),
+                                },
+                            ));
+
+                            failures.push(MessageFailure {
+                                datagram: msg,
+                                revert,
+                            });
                         }
                     }
 
-                    Ok(())
+                    self.record_tx(address, &receipt, messages).await;
+
+                    Ok(failures)
                 }
                 .instrument(info_span!(
                     "evm tx",
@@ -601,7 +1126,7 @@ impl Module {
             {
                 if msgs.len() == 1 {
                     error!(error = %e.message, msg = ?msgs[0], "message is too large");
-                    Ok(()) // drop the message
+                    Ok(failures) // drop the message
                 } else {
                     warn!(error = %e.message, "batch is too large");
                     Err(TxSubmitError::BatchTooLarge)
@@ -610,6 +1135,322 @@ impl Module {
             Err(err) => Err(TxSubmitError::Error(err)),
         }
     }
+
+    /// Write the audit log record of an included transaction, if the audit log is enabled.
+    async fn record_tx(
+        &self,
+        signer: Address,
+        receipt: &AnyTransactionReceipt,
+        messages: Vec<MessageRecord>,
+    ) {
+        let Some(audit_log) = &self.audit_log else {
+            return;
+        };
+
+        let record = TxRecord {
+            chain_id: self.chain_id.to_string(),
+            signer: signer.to_string(),
+            tx_hash: receipt.transaction_hash.into(),
+            height: receipt.block_number.unwrap_or_default(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            gas_used: receipt.gas_used,
+            fee: u128::from(receipt.gas_used).saturating_mul(receipt.effective_gas_price),
+            fee_denom: None,
+            messages,
+        };
+
+        if let Err(err) = audit_log.record(&record).await {
+            error!(err = %ErrorReporter(err), "error writing tx audit log record");
+        }
+    }
+
+    /// Update the nonce of `address` after sending a transaction with `nonce` failed.
+    ///
+    /// The nonce is only released if the transaction was definitely rejected. Otherwise (i.e. if
+    /// the request timed out), the transaction may still have been accepted, so the nonce is
+    /// resynced from the chain.
+    fn handle_send_error(&self, address: &Address, nonce: u64, err: &Error) {
+        if is_rejected(err) {
+            // the tx was rejected, so the nonce is still unused
+            self.nonces.release(address, nonce);
+        } else {
+            warn!(
+                %nonce,
+                err = %ErrorReporter(err),
+                "unknown whether the nonce was consumed, resyncing"
+            );
+            self.nonces.resync(address);
+        }
+    }
+
+    /// The fees to use for a new transaction, based on the current state of the chain.
+    async fn current_fees(&self) -> Result<Fees, GasOracleError> {
+        let gas_price = self.gas_oracle.gas_price().await?;
+
+        if self.legacy {
+            Ok(Fees::Legacy {
+                gas_price: gas_price.total().ceil() as u128,
+            })
+        } else {
+            // leave room for the base fee to double before the transaction is included
+            Ok(Fees::Eip1559 {
+                max_fee_per_gas: (2.0 * gas_price.base_fee + gas_price.priority_fee).ceil() as u128,
+                max_priority_fee_per_gas: gas_price.priority_fee.ceil() as u128,
+            })
+        }
+    }
+
+    /// Wait for `pending_tx` to be included. If a stuck transaction timeout is configured, the
+    /// transaction is tracked and replaced with bumped fees (via `replace`) every time the timeout
+    /// elapses.
+    async fn wait_for_inclusion<F, Fut>(
+        &self,
+        signer: &DynProvider<AnyNetwork>,
+        address: Address,
+        nonce: u64,
+        fees: Fees,
+        pending_tx: PendingTransactionBuilder<AnyNetwork>,
+        replace: F,
+    ) -> Result<Inclusion, TxSubmitError>
+    where
+        F: Fn(Fees) -> Fut,
+        Fut: Future<Output = Result<PendingTransactionBuilder<AnyNetwork>, Error>>,
+    {
+        let Some(timeout) = self.stuck_tx_timeout else {
+            return Ok(Inclusion::Included(pending_tx.get_receipt().await?));
+        };
+
+        let mut tx = PendingTransaction::new(address, nonce, *pending_tx.tx_hash(), fees);
+
+        self.pending_transactions.insert(&tx);
+
+        let res = self
+            .replace_until_included(signer, timeout, &mut tx, pending_tx, replace)
+            .await;
+
+        self.pending_transactions.remove(&tx);
+
+        res
+    }
+
+    async fn replace_until_included<F, Fut>(
+        &self,
+        signer: &DynProvider<AnyNetwork>,
+        timeout: Duration,
+        tx: &mut PendingTransaction,
+        pending_tx: PendingTransactionBuilder<AnyNetwork>,
+        replace: F,
+    ) -> Result<Inclusion, TxSubmitError>
+    where
+        F: Fn(Fees) -> Fut,
+        Fut: Future<Output = Result<PendingTransactionBuilder<AnyNetwork>, Error>>,
+    {
+        let mut pending_tx = Some(pending_tx);
+
+        loop {
+            match pending_tx.take() {
+                Some(pending_tx) => {
+                    let tx_hash = *pending_tx.tx_hash();
+
+                    match pending_tx.with_timeout(Some(timeout)).get_receipt().await {
+                        Ok(receipt) => return Ok(tx.inclusion(&tx_hash, receipt)),
+                        Err(PendingTransactionError::TxWatcher(WatchTxError::Timeout)) => {}
+                        Err(err) => return Err(err.into()),
+                    }
+                }
+                // the last replacement was rejected, give the previous transaction some more time
+                None => tokio::time::sleep(timeout).await,
+            }
+
+            // a previous transaction with this nonce may have been included while waiting on the
+            // latest one
+            if let Some(inclusion) = find_inclusion(signer, tx).await? {
+                return Ok(inclusion);
+            }
+
+            let bumped = tx.fees.bump(self.fee_bump_percent);
+
+            let (fees, too_high) = match self.current_fees().await {
+                Ok(current_fees) => (bumped.max(current_fees), false),
+                Err(GasOracleError::TooHigh { price, max }) => {
+                    warn!(%price, %max, "gas price is too high");
+
+                    (bumped, true)
+                }
+                Err(err) => return Err(err.into()),
+            };
+
+            let cancel = tx.is_cancelling()
+                || too_high
+                || tx.replacements >= self.max_fee_bumps
+                || self
+                    .gas_oracle
+                    .max_gas_price()
+                    .is_some_and(|max_gas_price| fees.max_price() as f64 > max_gas_price);
+
+            warn!(
+                nonce = tx.nonce,
+                replacements = tx.replacements,
+                ?fees,
+                cancel,
+                "transaction is stuck, replacing"
+            );
+
+            let res = if cancel {
+                send_cancellation(signer, tx.signer, tx.nonce, fees).await
+            } else {
+                replace(fees).await
+            };
+
+            match res {
+                Ok(new_pending_tx) => {
+                    info!(
+                        tx_hash = %<H256>::from(*new_pending_tx.tx_hash()),
+                        nonce = tx.nonce,
+                        "replaced stuck transaction"
+                    );
+
+                    self.pending_transactions
+                        .replace(tx, *new_pending_tx.tx_hash(), fees, cancel);
+
+                    pending_tx = Some(new_pending_tx);
+                }
+                Err(err) if error_message(&err).is_some_and(|m| m.contains("nonce too low")) => {
+                    // one of the previous transactions was included after the receipts were
+                    // checked
+                    return match find_inclusion(signer, tx).await? {
+                        Some(inclusion) => Ok(inclusion),
+                        None => Err(TxSubmitError::NonceConsumed { nonce: tx.nonce }),
+                    };
+                }
+                Err(err) if error_message(&err).is_some_and(|m| m.contains("underpriced")) => {
+                    // bump from the rejected fees on the next attempt
+                    warn!(error = %ErrorReporter(&err), "replacement transaction was underpriced");
+
+                    tx.fees = fees;
+                }
+                Err(err) => return Err(TxSubmitError::Error(err)),
+            }
+        }
+    }
+}
+
+/// The outcome of waiting for a transaction to be included.
+enum Inclusion {
+    /// The transaction, or one of its fee-bumped replacements, was included.
+    Included(AnyTransactionReceipt),
+    /// The transaction got stuck and a cancellation was included in its place.
+    Cancelled {
+        receipt: AnyTransactionReceipt,
+        replacements: u32,
+    },
+}
+
+impl PendingTransaction {
+    fn inclusion(
+        &self,
+        tx_hash: &alloy::primitives::TxHash,
+        receipt: AnyTransactionReceipt,
+    ) -> Inclusion {
+        if self.is_cancellation(tx_hash) {
+            Inclusion::Cancelled {
+                receipt,
+                replacements: self.replacements,
+            }
+        } else {
+            Inclusion::Included(receipt)
+        }
+    }
+}
+
+/// Check if any of the transactions sent for `tx` have been included.
+async fn find_inclusion(
+    signer: &DynProvider<AnyNetwork>,
+    tx: &PendingTransaction,
+) -> Result<Option<Inclusion>, TransportError> {
+    for tx_hash in &tx.tx_hashes {
+        if let Some(receipt) = signer.get_transaction_receipt(*tx_hash).await? {
+            return Ok(Some(tx.inclusion(tx_hash, receipt)));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Free up `nonce` by replacing the transaction with a zero-value transfer to the signer itself.
+async fn send_cancellation(
+    signer: &DynProvider<AnyNetwork>,
+    address: Address,
+    nonce: u64,
+    fees: Fees,
+) -> Result<PendingTransactionBuilder<AnyNetwork>, Error> {
+    let request = <AnyNetwork as Network>::TransactionRequest::default()
+        .with_from(address)
+        .with_to(address)
+        .with_value(alloy::primitives::U256::ZERO)
+        .with_nonce(nonce)
+        .with_gas_limit(21_000);
+
+    Ok(signer
+        .send_transaction(with_request_fees(request, fees))
+        .await?)
+}
+
+fn with_request_fees(
+    request: <AnyNetwork as Network>::TransactionRequest,
+    fees: Fees,
+) -> <AnyNetwork as Network>::TransactionRequest {
+    match fees {
+        Fees::Legacy { gas_price } => request.with_gas_price(gas_price),
+        Fees::Eip1559 {
+            max_fee_per_gas,
+            max_priority_fee_per_gas,
+        } => request
+            .with_max_fee_per_gas(max_fee_per_gas)
+            .with_max_priority_fee_per_gas(max_priority_fee_per_gas),
+    }
+}
+
+fn with_fees<P: Provider<AnyNetwork>, D: CallDecoder>(
+    call: CallBuilder<P, D, AnyNetwork>,
+    fees: Fees,
+) -> CallBuilder<P, D, AnyNetwork> {
+    match fees {
+        Fees::Legacy { gas_price } => call.gas_price(gas_price),
+        Fees::Eip1559 {
+            max_fee_per_gas,
+            max_priority_fee_per_gas,
+        } => call
+            .max_fee_per_gas(max_fee_per_gas)
+            .max_priority_fee_per_gas(max_priority_fee_per_gas),
+    }
+}
+
+/// Errors returned for transactions that will never be included, and as such don't consume their
+/// nonce.
+const REJECTED_TX_ERRORS: &[&str] = &[
+    "insufficient funds",
+    "intrinsic gas too low",
+    "exceeds block gas limit",
+    "max fee per gas less than block base fee",
+];
+
+/// Whether sending a transaction failed because the node rejected it.
+fn is_rejected(error: &Error) -> bool {
+    error_message(error).is_some_and(|m| REJECTED_TX_ERRORS.iter().any(|e| m.contains(e)))
+}
+
+fn error_message(error: &Error) -> Option<&str> {
+    match error {
+        Error::PendingTransactionError(PendingTransactionError::TransportError(
+            TransportError::ErrorResp(e),
+        ))
+        | Error::TransportError(TransportError::ErrorResp(e)) => Some(&*e.message),
+        _ => None,
+    }
 }
 
 #[allow(clippy::type_complexity)]
@@ -647,6 +1488,16 @@ fn process_msgs<'a>(
                     })
                     .clear_decoder(),
             ),
+            Datagram::Misbehaviour(data) => (
+                msg,
+                ibc_handler
+                    .misbehaviour(ibc_solidity::MsgMisbehaviour {
+                        client_id: data.client_id.raw(),
+                        client_message: data.client_message.into(),
+                        relayer: relayer.into(),
+                    })
+                    .clear_decoder(),
+            ),
             Datagram::ConnectionOpenInit(data) => (
                 msg,
                 ibc_handler
@@ -836,10 +1687,40 @@ mod tests {
     use alloy::{
         hex,
         primitives::{LogData, fixed_bytes},
+        rpc::json_rpc::ErrorPayload,
+        transports::TransportErrorKind,
     };
 
     use super::*;
 
+    fn error_resp(message: &'static str) -> Error {
+        Error::TransportError(TransportError::ErrorResp(ErrorPayload {
+            code: -32000,
+            message: message.into(),
+            data: None,
+        }))
+    }
+
+    #[test]
+    fn rejected_tx() {
+        assert!(is_rejected(&error_resp(
+            "insufficient funds for gas * price + value: have 1 want 2"
+        )));
+        assert!(is_rejected(&error_resp("intrinsic gas too low")));
+
+        // the nonce is out of sync, it has to be resynced
+        assert!(!is_rejected(&error_resp("nonce too low")));
+        assert!(!is_rejected(&error_resp("nonce too high")));
+
+        // the transaction may have been accepted by the node before the request timed out
+        assert!(!is_rejected(&Error::TransportError(
+            TransportErrorKind::custom_str("request timed out")
+        )));
+        assert!(!is_rejected(&Error::PendingTransactionError(
+            PendingTransactionError::TxWatcher(WatchTxError::Timeout)
+        )));
+    }
+
     #[test]
     fn multicall_result_decode() {
         let bz = hex::decode("0x0000000000000000000000000000000000000000000000000000000000000020000000000000000000000000000000000000000000000000000000000000000100000000000000000000000000000000000000000000000000000000000000200000000000000000000000000000000000000000000000000000000000000001000000000000000000000000000000000000000000000000000000000000004000000000000000000000000000000000000000000000000000000000000000200000000000000000000000000000000000000000000000000000000000000004").unwrap();
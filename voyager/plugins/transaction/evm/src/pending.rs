@@ -0,0 +1,263 @@
+//! Tracking of pending transactions per signer nonce.
+//!
+//! A transaction that is underpriced (i.e. during a gas spike) can sit in the mempool indefinitely,
+//! holding its signer's keyring slot. If a timeout is configured, the transaction is replaced with
+//! a fee-bumped transaction with the same nonce every time the timeout elapses, until either one of
+//! the sent transactions is included or the bump cap is hit, at which point the nonce is freed up
+//! with a zero-value self-transfer.
+
+use std::{collections::BTreeMap, sync::Mutex};
+
+use alloy::primitives::{Address, TxHash};
+use opentelemetry::{KeyValue, metrics::Counter};
+use serde::{Deserialize, Serialize};
+use voyager_sdk::{primitives::ChainId, vm::now};
+
+/// The fees of a transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Fees {
+    Legacy {
+        gas_price: u128,
+    },
+    Eip1559 {
+        max_fee_per_gas: u128,
+        max_priority_fee_per_gas: u128,
+    },
+}
+
+impl Fees {
+    /// Increase all fees by `percent`, rounding up. Fees are always increased by at least 1 wei,
+    /// since nodes will reject a replacement that doesn't increase the fees.
+    #[must_use]
+    pub fn bump(self, percent: u64) -> Self {
+        let bump =
+            |fee: u128| fee.saturating_add(fee.saturating_mul(percent.into()).div_ceil(100).max(1));
+
+        match self {
+            Self::Legacy { gas_price } => Self::Legacy {
+                gas_price: bump(gas_price),
+            },
+            Self::Eip1559 {
+                max_fee_per_gas,
+                max_priority_fee_per_gas,
+            } => Self::Eip1559 {
+                max_fee_per_gas: bump(max_fee_per_gas),
+                max_priority_fee_per_gas: bump(max_priority_fee_per_gas),
+            },
+        }
+    }
+
+    /// The fieldwise maximum of `self` and `other`. If the fees are of different types, `self` is
+    /// returned.
+    #[must_use]
+    pub fn max(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::Legacy { gas_price: a }, Self::Legacy { gas_price: b }) => Self::Legacy {
+                gas_price: a.max(b),
+            },
+            (
+                Self::Eip1559 {
+                    max_fee_per_gas: a_max_fee_per_gas,
+                    max_priority_fee_per_gas: a_max_priority_fee_per_gas,
+                },
+                Self::Eip1559 {
+                    max_fee_per_gas: b_max_fee_per_gas,
+                    max_priority_fee_per_gas: b_max_priority_fee_per_gas,
+                },
+            ) => Self::Eip1559 {
+                max_fee_per_gas: a_max_fee_per_gas.max(b_max_fee_per_gas),
+                max_priority_fee_per_gas: a_max_priority_fee_per_gas
+                    .max(b_max_priority_fee_per_gas),
+            },
+            _ => self,
+        }
+    }
+
+    /// The highest price per gas that can be paid with these fees.
+    pub fn max_price(&self) -> u128 {
+        match self {
+            Self::Legacy { gas_price } => *gas_price,
+            Self::Eip1559 {
+                max_fee_per_gas, ..
+            } => *max_fee_per_gas,
+        }
+    }
+}
+
+/// A nonce of a signer that has a transaction in the mempool.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PendingTransaction {
+    pub signer: Address,
+    pub nonce: u64,
+    /// The fees of the most recently sent transaction.
+    pub fees: Fees,
+    /// The hashes of all transactions sent with this nonce, oldest first.
+    pub tx_hashes: Vec<TxHash>,
+    /// The hashes of all cancellations sent with this nonce, oldest first. These are also
+    /// contained in `tx_hashes`.
+    pub cancel_tx_hashes: Vec<TxHash>,
+    /// The number of times the original transaction was replaced with a higher fee.
+    pub replacements: u32,
+    /// Unix timestamp (in seconds) at which the original transaction was sent.
+    pub first_sent_at: u64,
+}
+
+impl PendingTransaction {
+    pub fn new(signer: Address, nonce: u64, tx_hash: TxHash, fees: Fees) -> Self {
+        Self {
+            signer,
+            nonce,
+            fees,
+            tx_hashes: vec![tx_hash],
+            cancel_tx_hashes: vec![],
+            replacements: 0,
+            first_sent_at: now(),
+        }
+    }
+
+    pub fn is_cancelling(&self) -> bool {
+        !self.cancel_tx_hashes.is_empty()
+    }
+
+    pub fn is_cancellation(&self, tx_hash: &TxHash) -> bool {
+        self.cancel_tx_hashes.contains(tx_hash)
+    }
+}
+
+#[derive(Debug)]
+pub struct PendingTransactions {
+    chain_id: ChainId,
+    transactions: Mutex<BTreeMap<(Address, u64), PendingTransaction>>,
+
+    replaced_counter_metric: Counter<u64>,
+    cancelled_counter_metric: Counter<u64>,
+}
+
+impl PendingTransactions {
+    pub fn new(chain_id: ChainId) -> Self {
+        Self {
+            chain_id,
+            transactions: Mutex::new(BTreeMap::new()),
+            replaced_counter_metric: opentelemetry::global::meter("voyager")
+                .u64_counter("evm_transaction.replaced")
+                .build(),
+            cancelled_counter_metric: opentelemetry::global::meter("voyager")
+                .u64_counter("evm_transaction.cancelled")
+                .build(),
+        }
+    }
+
+    /// Start or update tracking of `tx`.
+    pub fn insert(&self, tx: &PendingTransaction) {
+        self.transactions
+            .lock()
+            .expect("mutex is not poisoned; qed;")
+            .insert((tx.signer, tx.nonce), tx.clone());
+    }
+
+    /// Record that `tx` was replaced by a new transaction with `tx_hash` and `fees`.
+    pub fn replace(&self, tx: &mut PendingTransaction, tx_hash: TxHash, fees: Fees, cancel: bool) {
+        tx.fees = fees;
+        tx.tx_hashes.push(tx_hash);
+
+        let attributes = [
+            KeyValue::new("chain_id", self.chain_id.to_string()),
+            KeyValue::new("signer", tx.signer.to_string()),
+        ];
+
+        if cancel {
+            // only the first cancellation is counted, any further ones are replacements of the
+            // cancellation
+            if !tx.is_cancelling() {
+                self.cancelled_counter_metric.add(1, &attributes);
+            }
+
+            tx.cancel_tx_hashes.push(tx_hash);
+        } else {
+            tx.replacements += 1;
+
+            self.replaced_counter_metric.add(1, &attributes);
+        }
+
+        self.insert(tx);
+    }
+
+    pub fn remove(&self, tx: &PendingTransaction) {
+        self.transactions
+            .lock()
+            .expect("mutex is not poisoned; qed;")
+            .remove(&(tx.signer, tx.nonce));
+    }
+
+    /// All currently pending transactions, ordered by signer and nonce.
+    pub fn all(&self) -> Vec<PendingTransaction> {
+        self.transactions
+            .lock()
+            .expect("mutex is not poisoned; qed;")
+            .values()
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bump_rounds_up() {
+        assert_eq!(
+            Fees::Eip1559 {
+                max_fee_per_gas: 1001,
+                max_priority_fee_per_gas: 0,
+            }
+            .bump(10),
+            Fees::Eip1559 {
+                max_fee_per_gas: 1102,
+                max_priority_fee_per_gas: 1,
+            }
+        );
+
+        assert_eq!(
+            Fees::Legacy { gas_price: 100 }.bump(0),
+            Fees::Legacy { gas_price: 101 }
+        );
+
+        assert_eq!(
+            Fees::Legacy {
+                gas_price: u128::MAX
+            }
+            .bump(20),
+            Fees::Legacy {
+                gas_price: u128::MAX
+            }
+        );
+    }
+
+    #[test]
+    fn max_is_fieldwise() {
+        assert_eq!(
+            Fees::Eip1559 {
+                max_fee_per_gas: 10,
+                max_priority_fee_per_gas: 5,
+            }
+            .max(Fees::Eip1559 {
+                max_fee_per_gas: 8,
+                max_priority_fee_per_gas: 7,
+            }),
+            Fees::Eip1559 {
+                max_fee_per_gas: 10,
+                max_priority_fee_per_gas: 7,
+            }
+        );
+
+        assert_eq!(
+            Fees::Legacy { gas_price: 1 }.max(Fees::Eip1559 {
+                max_fee_per_gas: 8,
+                max_priority_fee_per_gas: 7,
+            }),
+            Fees::Legacy { gas_price: 1 }
+        );
+    }
+}
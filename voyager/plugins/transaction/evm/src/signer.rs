@@ -0,0 +1,89 @@
+//! Transaction signers backing the keyring.
+
+use alloy::{
+    consensus::SignableTransaction,
+    network::TxSigner,
+    primitives::{Address, Signature},
+    signers::{self, local::LocalSigner, utils::public_key_to_address},
+};
+use bip32::secp256k1::ecdsa::{SigningKey, VerifyingKey};
+use concurrent_keyring::{KeyringConfigEntry, remote::RemoteSigner};
+use jsonrpsee::core::async_trait;
+
+#[derive(Debug, Clone)]
+pub enum EvmSigner {
+    Local(LocalSigner<SigningKey>),
+    Remote {
+        signer: RemoteSigner,
+        address: Address,
+    },
+}
+
+impl EvmSigner {
+    /// Build the signer for a keyring entry. Remote entries are queried for their public key.
+    pub async fn from_config(entry: &KeyringConfigEntry) -> Result<Self, signers::Error> {
+        match entry.remote() {
+            Some(remote) => Self::remote(remote).await,
+            None => Ok(Self::Local(LocalSigner::from_signing_key(
+                SigningKey::from_slice(&entry.value()).map_err(signers::Error::other)?,
+            ))),
+        }
+    }
+
+    /// Fetch the public key of `signer` and derive its address.
+    pub async fn remote(signer: RemoteSigner) -> Result<Self, signers::Error> {
+        let public_key = signer.public_key().await.map_err(signers::Error::other)?;
+
+        let verifying_key =
+            VerifyingKey::from_sec1_bytes(&public_key).map_err(signers::Error::other)?;
+
+        Ok(Self::Remote {
+            signer,
+            address: public_key_to_address(&verifying_key),
+        })
+    }
+}
+
+#[async_trait]
+impl TxSigner<Signature> for EvmSigner {
+    fn address(&self) -> Address {
+        match self {
+            Self::Local(signer) => signer.address(),
+            Self::Remote { address, .. } => *address,
+        }
+    }
+
+    async fn sign_transaction(
+        &self,
+        tx: &mut dyn SignableTransaction<Signature>,
+    ) -> signers::Result<Signature> {
+        match self {
+            Self::Local(signer) => signer.sign_transaction(tx).await,
+            Self::Remote { signer, address } => {
+                let hash = tx.signature_hash();
+
+                let signature = signer
+                    .sign(hash.as_slice())
+                    .await
+                    .map_err(signers::Error::other)?;
+
+                let signature = Signature::from_raw(&signature).map_err(signers::Error::other)?;
+
+                // guard against a misconfigured key id, since a transaction signed by the wrong
+                // key would otherwise only fail once it is broadcast
+                let recovered = signature
+                    .recover_address_from_prehash(&hash)
+                    .map_err(signers::Error::other)?;
+
+                if recovered != *address {
+                    return Err(signers::Error::message(format!(
+                        "remote signer {} returned a signature for {recovered}, expected {address}",
+                        signer.url()
+                    )));
+                }
+
+                Ok(signature)
+            }
+        }
+    }
+}
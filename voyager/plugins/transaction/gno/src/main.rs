@@ -527,6 +527,11 @@ func main() {{
                         relayer,
                     )
                 }
+                Datagram::Misbehaviour(_msg) => {
+                    return Err(RpcError::fatal_from_message(
+                        "Misbehaviour is not supported on gno",
+                    ));
+                }
                 Datagram::ConnectionOpenInit(msg) => {
                     format!(
                         r#"
@@ -893,6 +898,8 @@ fn gno_packet(packet: &Packet) -> String {
 
 #[cfg(test)]
 mod tests {
+    use std::num::NonZeroUsize;
+
     use concurrent_keyring::KeyringConfigEntry;
     use gno_rpc::rpc_types::TxFee;
 
@@ -933,7 +940,8 @@ mod tests {
                     keys: vec![KeyringConfigEntry::Raw {
                         name: "name".to_string(),
                         key: vec![0; 32],
-                    }]
+                    }],
+                    max_in_flight: NonZeroUsize::MIN,
                 },
                 rpc_url: "rpc_url".to_string(),
                 fee_recipient: None,
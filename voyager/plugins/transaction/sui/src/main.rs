@@ -6,7 +6,7 @@ use std::{
     sync::Arc,
 };
 
-use concurrent_keyring::{ConcurrentKeyring, KeyringConfig, KeyringEntry};
+use concurrent_keyring::{ConcurrentKeyring, KeyringConfig, KeyringConfigEntry, KeyringEntry};
 use ibc_union_spec::{IbcUnion, datagram::Datagram, path::ChannelPath};
 use jsonrpsee::{Extensions, core::async_trait};
 use serde::{Deserialize, Serialize};
@@ -21,10 +21,9 @@ use sui_sdk::{
     },
 };
 use tracing::instrument;
-use unionlabs::primitives::Bytes;
 use voyager_sdk::{
     DefaultCmd, ExtensionsExt, VoyagerClient,
-    anyhow::{self},
+    anyhow::{self, bail},
     hook::SubmitTxHook,
     message::{PluginMessage, VoyagerMessage, data::Data},
     plugin::Plugin,
@@ -117,6 +116,17 @@ impl Plugin for Module {
         )
         .unwrap();
 
+        // the keypair is passed to the ibc app plugins to build their part of the ptb, so the
+        // private key has to be available locally
+        if let Some(entry) = config
+            .keyring
+            .keys
+            .iter()
+            .find(|entry| matches!(entry, KeyringConfigEntry::Remote { .. }))
+        {
+            bail!("remote signers are not supported for sui keyrings: {entry:?}");
+        }
+
         Ok(Self {
             chain_id: ChainId::new(chain_id.to_string()),
             ibc_contract,
@@ -126,7 +136,6 @@ impl Plugin for Module {
             keyring: ConcurrentKeyring::new(
                 config.keyring.name,
                 config.keyring.keys.into_iter().map(|config| {
-                    println!("{}", <Bytes>::new(config.value()));
                     let pk = SuiKeyPair::decode(
                         &String::from_utf8(config.value()).expect("priv keys are utf8 strings"),
                     )
@@ -197,6 +206,9 @@ impl Module {
                 Datagram::UpdateClient(data) => {
                     move_api::update_client(&mut ptb_builder, self, data)?
                 }
+                Datagram::Misbehaviour(data) => {
+                    move_api::misbehaviour(&mut ptb_builder, self, data)?
+                }
                 Datagram::ConnectionOpenInit(data) => {
                     move_api::connection_open_init(&mut ptb_builder, self, data)?
                 }
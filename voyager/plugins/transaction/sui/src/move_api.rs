@@ -3,7 +3,8 @@ use ibc_union_spec::{
     datagram::{
         MsgChannelOpenAck, MsgChannelOpenConfirm, MsgChannelOpenInit, MsgChannelOpenTry,
         MsgCommitPacketTimeout, MsgConnectionOpenAck, MsgConnectionOpenConfirm,
-        MsgConnectionOpenInit, MsgConnectionOpenTry, MsgCreateClient, MsgUpdateClient,
+        MsgConnectionOpenInit, MsgConnectionOpenTry, MsgCreateClient, MsgMisbehaviour,
+        MsgUpdateClient,
     },
 };
 use move_core_types::{ident_str, identifier::IdentStr};
@@ -75,6 +76,30 @@ pub fn update_client(
     .map_err(RpcError::fatal_from_message)
 }
 
+pub fn misbehaviour(
+    ptb: &mut ProgrammableTransactionBuilder,
+    module: &Module,
+    data: MsgMisbehaviour,
+) -> RpcResult<()> {
+    ptb.move_call(
+        module.ibc_contract.into(),
+        IBC_IDENT.into(),
+        ident_str!("misbehaviour").into(),
+        vec![],
+        vec![
+            CallArg::Object(ObjectArg::SharedObject {
+                id: module.ibc_store.into(),
+                initial_shared_version: module.ibc_store_initial_seq,
+                mutability: SharedObjectMutability::Mutable,
+            }),
+            data.client_id.raw().into(),
+            (&data.client_message.into_vec()).into(),
+            CallArg::Pure(<H256>::default().into_bytes().to_vec()),
+        ],
+    )
+    .map_err(RpcError::fatal_from_message)
+}
+
 pub fn connection_open_init(
     ptb: &mut ProgrammableTransactionBuilder,
     module: &Module,
@@ -1,6 +1,7 @@
 use std::{
     ffi::{OsStr, OsString},
     fs::read_to_string,
+    num::{NonZeroU32, NonZeroU64, NonZeroUsize},
     path::PathBuf,
     str::FromStr,
 };
@@ -8,7 +9,9 @@ use std::{
 use anyhow::{Context, anyhow};
 use clap::{self, Parser, Subcommand};
 use ibc_union_spec::IbcUnion;
-use unionlabs::{self, bounded::BoundedI64, ibc::core::client::height::Height, result_unwrap};
+use unionlabs::{
+    self, bounded::BoundedI64, ibc::core::client::height::Height, primitives::H256, result_unwrap,
+};
 use voyager_message::VoyagerMessage;
 use voyager_primitives::{ChainId, ClientType, IbcInterface, IbcSpec, IbcSpecId, QueryHeight};
 use voyager_types::RawClientId;
@@ -113,6 +116,16 @@ pub enum Command {
         /// Index a specific block.
         #[arg(long, conflicts_with_all(["from", "to"]))]
         exact: Option<Height>,
+        /// Backfill the range from..=to, splitting it into chunks of this many blocks that are indexed concurrently.
+        ///
+        /// Only packets that have not yet been received (or acknowledged) on the counterparty chain are relayed, all other events in the range are dropped. This requires the packet-index plugin to be loaded.
+        #[arg(long, value_name = "CHUNK_SIZE", requires = "to")]
+        backfill: Option<NonZeroU64>,
+        /// The amount of backfill chunks that are indexed at once, the next chunks are only started once all of these are done. Only used with `--backfill`.
+        ///
+        /// The events of a chunk are held in its op until the whole chunk is indexed, so this and the chunk size bound the memory used by the backfill.
+        #[arg(long, default_value = "8")]
+        concurrency: NonZeroUsize,
         /// Automatically enqueue the op.
         #[arg(long, short = 'e', default_value_t = false)]
         enqueue: bool,
@@ -132,11 +145,20 @@ pub enum Command {
     Rpc {
         #[arg(long, short = 'r', global = true)]
         rpc_url: Option<String>,
+        /// The url of the admin rpc, used for pausing and resuming plugins and chains.
+        #[arg(long, global = true)]
+        admin_url: Option<String>,
         #[command(subcommand)]
         cmd: RpcCmd,
     },
     #[command(subcommand)]
     Msg(MsgCmd),
+    /// Capture and replay the handling of a single op.
+    #[command(subcommand)]
+    Replay(ReplayCmd),
+    /// Query the audit log of the transactions submitted by the transaction plugins.
+    #[command(subcommand)]
+    AuditLog(AuditLogCmd),
 }
 
 #[derive(Debug, Subcommand)]
@@ -147,10 +169,61 @@ pub enum ConfigCmd {
     Default,
     /// Print the JSON Schema for the voyager config, to be used in the top-level `$schema` field.
     Schema,
+    /// Reload the plugin and module config of a running voyager instance from its config file.
+    ///
+    /// Only plugins and modules whose config changed are restarted. Plugins can be added and
+    /// removed, but adding or removing modules requires a full restart.
+    Reload {
+        #[arg(long)]
+        admin_url: Option<String>,
+    },
 }
 
 type Pg64 = BoundedI64<1, { i64::MAX }>;
 
+#[derive(Debug, Subcommand)]
+pub enum AuditLogCmd {
+    /// Find all transactions that relayed the packet with the provided hash, along with the signer
+    /// that submitted them and the fees paid.
+    ///
+    /// By default, the audit log is read from the database of the queue. This requires the
+    /// transaction plugins to write their audit log to the same database.
+    Packet {
+        packet_hash: H256,
+        /// Read the audit log from this file instead of the queue database.
+        #[arg(long)]
+        file: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ReplayCmd {
+    /// Capture an op from the queue, along with every response returned by the plugins and modules
+    /// while handling it.
+    ///
+    /// The op is handled once outside of the queue, against the plugins and modules in the config. This is a dry run: any op that would submit a transaction is not handled, and is instead included in the capture as halted.
+    Capture {
+        /// The id of the item to capture. This can be an item in any table.
+        id: Pg64,
+        /// The file to write the captured op to.
+        #[arg(long, short = 'o')]
+        output: PathBuf,
+        /// The maximum number of items to handle before stopping.
+        #[arg(long, default_value_t = 1000)]
+        max_steps: usize,
+    },
+    /// Replay a captured op offline, serving all plugin and module responses from the capture.
+    ///
+    /// No plugin or module processes are started. This will exit with an error if any request is made that was not recorded in the capture, or if the replay diverges from the capture.
+    Run {
+        /// The file containing the captured op.
+        fixture: PathBuf,
+        /// The maximum number of items to handle before stopping.
+        #[arg(long, default_value_t = 1000)]
+        max_steps: usize,
+    },
+}
+
 #[derive(Debug, Subcommand)]
 #[allow(clippy::large_enum_variant)]
 pub enum QueueCmd {
@@ -159,6 +232,9 @@ pub enum QueueCmd {
     Enqueue {
         #[arg(value_parser(|s: &str| serde_json::from_str::<Op<VoyagerMessage>>(s)))]
         op: Op<VoyagerMessage>,
+        /// Only enqueue the op if no op with the same idempotency key has been enqueued within the queue's idempotency window.
+        #[arg(long)]
+        idempotency_key: Option<String>,
         #[arg(long, global = true)]
         rest_url: Option<String>,
     },
@@ -188,11 +264,18 @@ pub enum QueueCmd {
         failed: bool,
     },
 
-    // History {
-    //     id: PgId,
-    //     #[arg(long, default_value_t = result_unwrap!(Pg32::new(10)))]
-    //     max_depth: Pg32,
-    // },
+    /// Print the lineage of an item, walking its parents through the queue, done and failed tables.
+    ///
+    /// This is useful to find out how a failed item came to be enqueued in the first place.
+    History {
+        id: Pg64,
+        /// The maximum number of parent levels to walk.
+        #[arg(long, default_value_t = 10)]
+        max_depth: u32,
+        /// Print the raw history items as JSON instead of rendering a tree.
+        #[arg(long)]
+        json: bool,
+    },
     /// Query all failed messages.
     QueryFailed {
         #[arg(long, default_value_t = result_unwrap!(Pg64::new_const(1)))]
@@ -219,6 +302,36 @@ pub enum QueueCmd {
         #[arg(long = "message-filter", short = 'm')]
         message_filters: Vec<String>,
     },
+    /// Move all failed messages matching the provided filters back into the queue.
+    ///
+    /// Requeued messages keep their original ID, so their history is preserved.
+    RequeueFailed {
+        /// SQL filters for the item, see `query-failed`.
+        ///
+        /// This can be specified multiple times to specify multiple filters.
+        #[arg(long = "item-filter", short = 'i')]
+        item_filters: Vec<String>,
+        /// SQL filters for failure message.
+        ///
+        /// This can be specified multiple times to specify multiple filters.
+        #[arg(long = "message-filter", short = 'm')]
+        message_filters: Vec<String>,
+        /// Only print the number of messages that would be requeued.
+        #[arg(long)]
+        dry_run: bool,
+        /// The number of messages to requeue per transaction.
+        #[arg(long, default_value_t = result_unwrap!(Pg64::new_const(100)))]
+        batch_size: Pg64,
+        /// The maximum number of messages to requeue per second.
+        #[arg(long)]
+        rate_limit: Option<NonZeroU32>,
+        /// A jq filter to apply to each message before it is requeued, i.e. to bump a height.
+        ///
+        /// The filter must return exactly one value, which must be a valid op. Messages that fail to
+        /// be transformed are left in the failed table.
+        #[arg(long)]
+        transform: Option<String>,
+    },
     /// Query a failed message by it's ID.
     QueryFailedById {
         id: Pg64,
@@ -227,6 +340,31 @@ pub enum QueueCmd {
         #[arg(long)]
         rest_url: Option<String>,
     },
+    /// Print the number of items waiting in the optimize table per tag, along with their age.
+    ///
+    /// Items that keep getting older are a sign that a plugin's optimization pass is holding them
+    /// back, i.e. waiting for a batch that will never fill up.
+    OptimizeStats,
+    /// Query the items waiting in the optimize table, oldest first.
+    QueryOptimize {
+        /// Only return items with this tag (the name of the plugin that will optimize them).
+        #[arg(long)]
+        tag: Option<String>,
+        #[arg(long, default_value_t = result_unwrap!(Pg64::new_const(1)))]
+        page: Pg64,
+        #[arg(long, default_value_t = result_unwrap!(Pg64::new_const(10)))]
+        per_page: Pg64,
+    },
+    /// Move all items with the provided tag out of the optimize table and directly into the queue,
+    /// bypassing the plugin's optimization pass.
+    ///
+    /// Flushed items keep their original ID, so their history is preserved.
+    FlushOptimize {
+        tag: String,
+        /// Only print the number of items that would be flushed.
+        #[arg(long)]
+        dry_run: bool,
+    },
 }
 
 #[derive(Debug, Subcommand)]
@@ -403,6 +541,27 @@ pub enum RpcCmd {
         #[arg(trailing_var_arg = true, allow_hyphen_values = true, hide = true)]
         args: Vec<String>,
     },
+    /// Pause a plugin. Calls to the plugin stay in the queue until it is resumed.
+    PausePlugin {
+        name: String,
+    },
+    /// Resume a paused plugin.
+    ResumePlugin {
+        name: String,
+    },
+    /// Pause all calls interacting with a chain. The calls stay in the queue until the chain is
+    /// resumed.
+    PauseChain {
+        #[arg(value_parser(|s: &str| ok(ChainId::new(s.to_owned()))))]
+        chain_id: ChainId,
+    },
+    /// Resume a paused chain.
+    ResumeChain {
+        #[arg(value_parser(|s: &str| ok(ChainId::new(s.to_owned()))))]
+        chain_id: ChainId,
+    },
+    /// List the paused plugins and chains.
+    Paused,
 }
 
 #[derive(Debug, Subcommand)]
@@ -472,6 +631,9 @@ pub enum MsgCmd {
         /// Automatically enqueue the op.
         #[arg(long, short = 'e', default_value_t = false)]
         enqueue: bool,
+        /// Only enqueue the op if no op with the same idempotency key has been enqueued within the queue's idempotency window.
+        #[arg(long, requires = "enqueue")]
+        idempotency_key: Option<String>,
         #[arg(long, global = true)]
         rest_url: Option<String>,
         #[arg(long, global = true)]
@@ -499,6 +661,9 @@ pub enum MsgCmd {
         /// Automatically enqueue the op.
         #[arg(long, short = 'e', default_value_t = false)]
         enqueue: bool,
+        /// Only enqueue the op if no op with the same idempotency key has been enqueued within the queue's idempotency window.
+        #[arg(long, requires = "enqueue")]
+        idempotency_key: Option<String>,
         #[arg(long, global = true)]
         rest_url: Option<String>,
         #[arg(long, global = true)]
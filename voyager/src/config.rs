@@ -4,8 +4,8 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use voyager_core::{
     context::{ModulesConfig, PluginConfig},
-    default_ipc_client_request_timeout, default_optimizer_delay_milliseconds, default_rest_laddr,
-    default_rpc_laddr, default_trace_ratio,
+    default_admin_laddr, default_ipc_client_request_timeout, default_optimizer_delay_milliseconds,
+    default_rest_laddr, default_rpc_laddr, default_trace_ratio,
     equivalent_chain_ids::EquivalentChainIds,
 };
 
@@ -32,6 +32,10 @@ pub struct VoyagerConfig {
     pub rest_laddr: SocketAddr,
     #[serde(default = "default_rpc_laddr")]
     pub rpc_laddr: SocketAddr,
+    /// The address to serve the admin rpc (config reloads, pausing and resuming) on. This should
+    /// not be publicly reachable.
+    #[serde(default = "default_admin_laddr")]
+    pub admin_laddr: SocketAddr,
     #[serde(default = "default_trace_ratio")]
     pub trace_ratio: Option<f64>,
     pub queue: QueueConfig,
@@ -41,4 +45,6 @@ pub struct VoyagerConfig {
     #[serde(default = "default_ipc_client_request_timeout")]
     pub ipc_client_request_timeout: Duration,
     pub cache: voyager_core::cache::Config,
+    #[serde(default)]
+    pub chain_limits: voyager_core::chain_limits::Config,
 }
@@ -1,28 +1,45 @@
 #![allow(clippy::type_complexity)]
 
-use std::fmt::Debug;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{Debug, Write},
+};
 
+use anyhow::anyhow;
 use futures::Future;
-use pg_queue::{PgQueue, PgQueueConfig};
+use pg_queue::{PgQueue, PgQueueConfig, Tables};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlite_queue::{SqliteQueue, SqliteQueueConfig};
 use voyager_message::VoyagerMessage;
 use voyager_vm::{
-    Captures, EnqueueResult, ItemId, Op, Queue, QueueError, filter::InterestFilter,
-    in_memory::InMemoryQueue, pass::Pass,
+    Captures, EnqueueResult, HistoryItem, ItemId, ItemStatus, Op, Queue, QueueError,
+    filter::InterestFilter,
+    in_memory::{InMemoryQueue, InMemoryQueueConfig},
+    pass::Pass,
 };
 
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "kebab-case", tag = "type")]
 pub enum QueueConfig {
-    InMemory,
+    InMemory {
+        /// See [`InMemoryQueueConfig::idempotency_window_seconds`].
+        #[serde(default)]
+        idempotency_window_seconds: u64,
+        /// See [`InMemoryQueueConfig::anti_starvation_interval`].
+        #[serde(default = "voyager_vm::in_memory::default_anti_starvation_interval")]
+        anti_starvation_interval: u32,
+    },
     PgQueue(PgQueueConfig),
+    SqliteQueue(SqliteQueueConfig),
 }
 
 #[derive(Debug, Clone)]
 pub enum QueueImpl {
     InMemory(InMemoryQueue<VoyagerMessage>),
     PgQueue(PgQueue<VoyagerMessage>),
+    SqliteQueue(SqliteQueue<VoyagerMessage>),
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -30,6 +47,7 @@ pub enum QueueImpl {
 pub enum AnyQueueError {
     InMemory(std::convert::Infallible),
     PgQueue(sqlx::Error),
+    SqliteQueue(sqlx::Error),
 }
 
 impl Queue<VoyagerMessage> for QueueImpl {
@@ -38,14 +56,24 @@ impl Queue<VoyagerMessage> for QueueImpl {
 
     async fn new(cfg: Self::Config) -> Result<Self, Self::Error> {
         match cfg {
-            QueueConfig::InMemory => InMemoryQueue::new(())
-                .await
-                .map_err(AnyQueueError::InMemory)
-                .map(Self::InMemory),
+            QueueConfig::InMemory {
+                idempotency_window_seconds,
+                anti_starvation_interval,
+            } => InMemoryQueue::new(InMemoryQueueConfig {
+                idempotency_window_seconds,
+                anti_starvation_interval,
+            })
+            .await
+            .map_err(AnyQueueError::InMemory)
+            .map(Self::InMemory),
             QueueConfig::PgQueue(cfg) => PgQueue::new(cfg)
                 .await
                 .map_err(AnyQueueError::PgQueue)
                 .map(Self::PgQueue),
+            QueueConfig::SqliteQueue(cfg) => SqliteQueue::new(cfg)
+                .await
+                .map_err(AnyQueueError::SqliteQueue)
+                .map(Self::SqliteQueue),
         }
     }
 
@@ -63,6 +91,10 @@ impl Queue<VoyagerMessage> for QueueImpl {
                 .enqueue(item, filter)
                 .await
                 .map_err(AnyQueueError::PgQueue),
+            QueueImpl::SqliteQueue(queue) => queue
+                .enqueue(item, filter)
+                .await
+                .map_err(AnyQueueError::SqliteQueue),
         }
     }
 
@@ -86,6 +118,10 @@ impl Queue<VoyagerMessage> for QueueImpl {
                 .process(filter, f)
                 .await
                 .map_err(AnyQueueError::PgQueue),
+            QueueImpl::SqliteQueue(queue) => queue
+                .process(filter, f)
+                .await
+                .map_err(AnyQueueError::SqliteQueue),
         }
     }
 
@@ -104,6 +140,454 @@ impl Queue<VoyagerMessage> for QueueImpl {
                 .optimize(tag, filter, optimizer)
                 .await
                 .map_err(|e| e.map_left(AnyQueueError::PgQueue)),
+            QueueImpl::SqliteQueue(queue) => queue
+                .optimize(tag, filter, optimizer)
+                .await
+                .map_err(|e| e.map_left(AnyQueueError::SqliteQueue)),
+        }
+    }
+
+    async fn history(
+        &self,
+        id: ItemId,
+        max_depth: u32,
+    ) -> Result<Vec<HistoryItem<VoyagerMessage>>, Self::Error> {
+        match self {
+            QueueImpl::InMemory(queue) => queue
+                .history(id, max_depth)
+                .await
+                .map_err(AnyQueueError::InMemory),
+            QueueImpl::PgQueue(queue) => queue
+                .history(id, max_depth)
+                .await
+                .map_err(AnyQueueError::PgQueue),
+            QueueImpl::SqliteQueue(queue) => queue
+                .history(id, max_depth)
+                .await
+                .map_err(AnyQueueError::SqliteQueue),
+        }
+    }
+}
+
+/// A queue backend with persistent storage, used by the `voyager queue` subcommands.
+#[derive(Debug, Clone)]
+pub enum PersistentQueue {
+    PgQueue(PgQueue<VoyagerMessage>),
+    SqliteQueue(SqliteQueue<VoyagerMessage>),
+}
+
+#[derive(Debug, Serialize)]
+pub struct Stats {
+    pub total: i64,
+    pub ready: i64,
+    pub optimize: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FailedRecord {
+    pub id: i64,
+    pub parents: Vec<i64>,
+    pub item: Op<VoyagerMessage>,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OptimizeStats {
+    pub tag: String,
+    pub count: i64,
+    pub max_age_seconds: f64,
+    pub avg_age_seconds: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OptimizeItem {
+    pub id: i64,
+    pub parents: Vec<i64>,
+    pub tag: String,
+    pub item: Op<VoyagerMessage>,
+    pub age_seconds: f64,
+}
+
+#[derive(Debug)]
+pub struct RequeueBatch<E> {
+    pub requeued: Vec<i64>,
+    pub errors: Vec<(i64, E)>,
+    pub last_id: Option<i64>,
+}
+
+impl From<pg_queue::FailedRecord<VoyagerMessage>> for FailedRecord {
+    fn from(record: pg_queue::FailedRecord<VoyagerMessage>) -> Self {
+        Self {
+            id: record.id,
+            parents: record.parents,
+            item: record.item.0,
+            message: record.message,
+        }
+    }
+}
+
+impl From<sqlite_queue::FailedRecord<VoyagerMessage>> for FailedRecord {
+    fn from(record: sqlite_queue::FailedRecord<VoyagerMessage>) -> Self {
+        Self {
+            id: record.id,
+            parents: record.parents.0,
+            item: record.item.0,
+            message: record.message,
+        }
+    }
+}
+
+impl From<pg_queue::OptimizeStats> for OptimizeStats {
+    fn from(stats: pg_queue::OptimizeStats) -> Self {
+        Self {
+            tag: stats.tag,
+            count: stats.count,
+            max_age_seconds: stats.max_age_seconds,
+            avg_age_seconds: stats.avg_age_seconds,
+        }
+    }
+}
+
+impl From<sqlite_queue::OptimizeStats> for OptimizeStats {
+    fn from(stats: sqlite_queue::OptimizeStats) -> Self {
+        Self {
+            tag: stats.tag,
+            count: stats.count,
+            max_age_seconds: stats.max_age_seconds,
+            avg_age_seconds: stats.avg_age_seconds,
         }
     }
 }
+
+impl From<pg_queue::OptimizeItem<VoyagerMessage>> for OptimizeItem {
+    fn from(item: pg_queue::OptimizeItem<VoyagerMessage>) -> Self {
+        Self {
+            id: item.id,
+            parents: item.parents,
+            tag: item.tag,
+            item: item.item.0,
+            age_seconds: item.age_seconds,
+        }
+    }
+}
+
+impl From<sqlite_queue::OptimizeItem<VoyagerMessage>> for OptimizeItem {
+    fn from(item: sqlite_queue::OptimizeItem<VoyagerMessage>) -> Self {
+        Self {
+            id: item.id,
+            parents: item.parents.0,
+            tag: item.tag,
+            item: item.item.0,
+            age_seconds: item.age_seconds,
+        }
+    }
+}
+
+impl PersistentQueue {
+    pub async fn new(cfg: QueueConfig) -> anyhow::Result<Self> {
+        match cfg {
+            QueueConfig::PgQueue(cfg) => Ok(Self::PgQueue(
+                PgQueue::new(PgQueueConfig {
+                    // only one connection is needed for the queue commands
+                    min_connections: 1,
+                    max_connections: 1,
+                    ..cfg
+                })
+                .await?,
+            )),
+            QueueConfig::SqliteQueue(cfg) => Ok(Self::SqliteQueue(
+                SqliteQueue::new(SqliteQueueConfig {
+                    max_connections: 1,
+                    ..cfg
+                })
+                .await?,
+            )),
+            QueueConfig::InMemory { .. } => Err(anyhow!(
+                "no database set in config, queue commands require \
+                either the `pg-queue` or `sqlite-queue` backend"
+            )),
+        }
+    }
+
+    pub async fn stats(&self) -> anyhow::Result<Stats> {
+        Ok(match self {
+            Self::PgQueue(q) => {
+                let stats = q.stats().await?;
+                Stats {
+                    total: stats.total,
+                    ready: stats.ready,
+                    optimize: stats.optimize.0,
+                }
+            }
+            Self::SqliteQueue(q) => {
+                let stats = q.stats().await?;
+                Stats {
+                    total: stats.total,
+                    ready: stats.ready,
+                    optimize: stats.optimize.0,
+                }
+            }
+        })
+    }
+
+    pub async fn optimize_stats(&self) -> anyhow::Result<Vec<OptimizeStats>> {
+        Ok(match self {
+            Self::PgQueue(q) => q
+                .optimize_stats()
+                .await?
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+            Self::SqliteQueue(q) => q
+                .optimize_stats()
+                .await?
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+        })
+    }
+
+    pub async fn query_optimize(
+        &self,
+        tag: Option<String>,
+        page: i64,
+        per_page: i64,
+    ) -> anyhow::Result<Vec<OptimizeItem>> {
+        Ok(match self {
+            Self::PgQueue(q) => q
+                .query_optimize(tag, page, per_page)
+                .await?
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+            Self::SqliteQueue(q) => q
+                .query_optimize(tag, page, per_page)
+                .await?
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+        })
+    }
+
+    pub async fn flush_optimize(&self, tag: &str) -> anyhow::Result<Vec<i64>> {
+        Ok(match self {
+            Self::PgQueue(q) => q.flush_optimize(tag).await?,
+            Self::SqliteQueue(q) => q.flush_optimize(tag).await?,
+        })
+    }
+
+    pub async fn truncate(&self, tables: Tables) -> anyhow::Result<()> {
+        match self {
+            Self::PgQueue(q) => q.truncate(tables).await?,
+            Self::SqliteQueue(q) => q.truncate(sqlite_tables(tables)).await?,
+        }
+
+        Ok(())
+    }
+
+    pub async fn vacuum(&self, tables: Tables) -> anyhow::Result<()> {
+        match self {
+            Self::PgQueue(q) => q.vacuum(tables).await?,
+            Self::SqliteQueue(q) => q.vacuum(sqlite_tables(tables)).await?,
+        }
+
+        Ok(())
+    }
+
+    pub async fn query_failed(
+        &self,
+        page: i64,
+        per_page: i64,
+        item_filters: Vec<String>,
+        message_filters: Vec<String>,
+    ) -> anyhow::Result<Vec<FailedRecord>> {
+        Ok(match self {
+            Self::PgQueue(q) => q
+                .query_failed(page, per_page, item_filters, message_filters)
+                .await?
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+            Self::SqliteQueue(q) => q
+                .query_failed(page, per_page, item_filters, message_filters)
+                .await?
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+        })
+    }
+
+    pub async fn query_failed_by_id(&self, id: i64) -> anyhow::Result<Option<FailedRecord>> {
+        Ok(match self {
+            Self::PgQueue(q) => q.query_failed_by_id(id).await?.map(Into::into),
+            Self::SqliteQueue(q) => q.query_failed_by_id(id).await?.map(Into::into),
+        })
+    }
+
+    pub async fn count_failed(
+        &self,
+        item_filters: Vec<String>,
+        message_filters: Vec<String>,
+    ) -> anyhow::Result<i64> {
+        Ok(match self {
+            Self::PgQueue(q) => q.count_failed(item_filters, message_filters).await?,
+            Self::SqliteQueue(q) => q.count_failed(item_filters, message_filters).await?,
+        })
+    }
+
+    pub async fn requeue_failed<E>(
+        &self,
+        after: i64,
+        limit: i64,
+        item_filters: Vec<String>,
+        message_filters: Vec<String>,
+        transform: impl FnMut(Op<VoyagerMessage>) -> Result<Op<VoyagerMessage>, E>,
+    ) -> anyhow::Result<RequeueBatch<E>> {
+        Ok(match self {
+            Self::PgQueue(q) => {
+                let batch = q
+                    .requeue_failed(after, limit, item_filters, message_filters, transform)
+                    .await?;
+                RequeueBatch {
+                    requeued: batch.requeued,
+                    errors: batch.errors,
+                    last_id: batch.last_id,
+                }
+            }
+            Self::SqliteQueue(q) => {
+                let batch = q
+                    .requeue_failed(after, limit, item_filters, message_filters, transform)
+                    .await?;
+                RequeueBatch {
+                    requeued: batch.requeued,
+                    errors: batch.errors,
+                    last_id: batch.last_id,
+                }
+            }
+        })
+    }
+
+    pub async fn history(
+        &self,
+        id: ItemId,
+        max_depth: u32,
+    ) -> anyhow::Result<Vec<HistoryItem<VoyagerMessage>>> {
+        Ok(match self {
+            Self::PgQueue(q) => q.history(id, max_depth).await?,
+            Self::SqliteQueue(q) => q.history(id, max_depth).await?,
+        })
+    }
+}
+
+/// Render the lineage returned by [`PersistentQueue::history`] as a tree, with the requested item
+/// at the root and its parents as children.
+pub fn render_history(history: &[HistoryItem<VoyagerMessage>], max_depth: u32) -> String {
+    struct Renderer<'a> {
+        items: BTreeMap<ItemId, &'a HistoryItem<VoyagerMessage>>,
+        rendered: BTreeSet<ItemId>,
+        max_depth: u32,
+        out: String,
+    }
+
+    impl Renderer<'_> {
+        fn render(&mut self, id: ItemId, depth: u32, prefix: &str, connector: &str, indent: &str) {
+            let _ = write!(self.out, "{prefix}{connector}{}", id.raw());
+
+            let Some(item) = self.items.get(&id).copied() else {
+                if depth > self.max_depth {
+                    let _ = writeln!(self.out, " ...");
+                } else {
+                    let _ = writeln!(self.out, " (not found)");
+                }
+                return;
+            };
+
+            if !self.rendered.insert(id) {
+                let _ = writeln!(self.out, " (see above)");
+                return;
+            }
+
+            let status = match item.status {
+                ItemStatus::Queued => "queued",
+                ItemStatus::Optimize => "optimize",
+                ItemStatus::Done => "done",
+                ItemStatus::Failed => "failed",
+            };
+
+            let _ = write!(self.out, " [{status}] {}", op_summary(&item.item));
+
+            match &item.message {
+                Some(message) => {
+                    let _ = writeln!(self.out, ": {message}");
+                }
+                None => {
+                    let _ = writeln!(self.out);
+                }
+            }
+
+            let prefix = format!("{prefix}{indent}");
+
+            for (idx, parent) in item.parents.iter().enumerate() {
+                if idx == item.parents.len() - 1 {
+                    self.render(*parent, depth + 1, &prefix, "└── ", "    ");
+                } else {
+                    self.render(*parent, depth + 1, &prefix, "├── ", "│   ");
+                }
+            }
+        }
+    }
+
+    let Some(root) = history.iter().find(|item| item.depth == 0) else {
+        return String::new();
+    };
+
+    let mut renderer = Renderer {
+        items: history.iter().map(|item| (item.id, item)).collect(),
+        rendered: BTreeSet::new(),
+        max_depth,
+        out: String::new(),
+    };
+
+    renderer.render(root.id, 0, "", "", "");
+
+    renderer.out
+}
+
+/// A short description of an op, i.e. `call/plugin(voyager-plugin-packet-batch)`.
+fn op_summary(op: &Op<VoyagerMessage>) -> String {
+    let value = serde_json::to_value(op).unwrap_or_default();
+
+    let mut parts = vec![];
+    let mut value = &value;
+
+    while let Some(ty) = value.get("@type").and_then(Value::as_str) {
+        if ty == "plugin" {
+            parts.push(format!(
+                "plugin({})",
+                value["@value"]["plugin"].as_str().unwrap_or_default()
+            ));
+            break;
+        }
+
+        parts.push(ty.to_owned());
+        value = &value["@value"];
+    }
+
+    parts.join("/")
+}
+
+fn sqlite_tables(
+    Tables {
+        queue,
+        optimize,
+        done,
+        failed,
+    }: Tables,
+) -> sqlite_queue::Tables {
+    sqlite_queue::Tables {
+        queue,
+        optimize,
+        done,
+        failed,
+    }
+}